@@ -35,6 +35,20 @@ pub enum OptionError {
     InvalidExpiryDate,
     #[msg("Option cannot be closed at current price")]
     InvalidCloseCondition,
+    #[msg("Option premium exceeds the maximum accepted premium")]
+    PremiumExceedsMax,
+    #[msg("Option settlement price has not been recorded")]
+    SettlementPriceNotRecorded,
+    #[msg("Underlying, locked and premium custodies are not an allowed combination")]
+    InvalidCustodyCombination,
+    #[msg("Premium refund accounts are required to cancel a pending limit option")]
+    PremiumRefundAccountMissing,
+    #[msg("Option notional at this strike and expiry would exceed the pool cap")]
+    StrikeExpiryCapExceeded,
+    #[msg("European options can only be settled at expiry")]
+    EarlyExerciseNotAllowed,
+    #[msg("Option strike or expiry is not on the pool's listed grid")]
+    UnsupportedStrikeOrExpiry,
 }
 
 // Perpetual-specific errors only
@@ -124,6 +138,40 @@ pub enum PerpetualError {
     PositionNotClosed,
     #[msg("TP/SL order not triggered at current price")]
     TpSlNotTriggered,
+    #[msg("Limit order book is full")]
+    LimitOrderBookFull,
+    #[msg("Invalid stop-limit order: stop requires a limit order with a limit price on the correct side")]
+    InvalidStopLimitOrder,
+    #[msg("Health factor would be at or below 1.0 after this operation")]
+    HealthFactorTooLow,
+    #[msg("Position size exceeds the pool's per-position liquidity cap")]
+    PositionTooLargeForPool,
+    #[msg("Borrow fees must be settled before open interest changes")]
+    BorrowFeesNotSettled,
+    #[msg("Operation is not supported for positions with LP token collateral")]
+    LpCollateralUnsupported,
+    #[msg("LP token mint and LP collateral accounts are required")]
+    LpCollateralAccountsMissing,
+    #[msg("Position is within its liquidation cooldown and still solvent")]
+    LiquidationCooldown,
+    #[msg("Limit trigger direction does not match the side and current price")]
+    InvalidTriggerDirection,
+    #[msg("Option does not hedge this position")]
+    InvalidHedge,
+    #[msg("Limit order has already been executed")]
+    LimitOrderAlreadyExecuted,
+    #[msg("Limit order expiry must be in the future and is only valid for limit orders")]
+    InvalidLimitExpiry,
+    #[msg("Limit order has expired")]
+    LimitOrderExpired,
+    #[msg("Limit order has not expired")]
+    LimitOrderNotExpired,
+    #[msg("Max loss must be below collateral with its price inside the liquidation price")]
+    InvalidMaxLoss,
+    #[msg("Positions with max-loss protection cannot be resized")]
+    MaxLossResizeUnsupported,
+    #[msg("Limit order rests in the limit order book, which must be passed")]
+    LimitOrderBookRequired,
 }
 
 // General trading errors that apply to both options and perpetuals
@@ -189,6 +237,18 @@ pub enum TradingError {
     PositionNotEmpty,
     #[msg("Position must be closed before closing account")]
     PositionNotClosed,
+    #[msg("Position cannot be transferred while it has resting orders")]
+    OwnershipTransferNotAllowed,
+    #[msg("Receiving account mint does not match the asset being paid out")]
+    ReceivingAccountMintMismatch,
+    #[msg("Referral account is required when a referrer is set")]
+    ReferralAccountMissing,
+    #[msg("Owner cannot refer their own position")]
+    SelfReferral,
+    #[msg("Active take-profit orders would close more than 100% of the position")]
+    TakeProfitOverAllocated,
+    #[msg("Referrer is not registered for this pool")]
+    ReferralNotRegistered,
 }
 
 // Pool-specific errors
@@ -220,6 +280,20 @@ pub enum PoolError {
     TokenRatioOutOfRange,
     #[msg("Token is not supported")]
     UnsupportedToken,
+    #[msg("Pool AUM is stale, pass all custodies and oracles to reconcile")]
+    AumReconcileRequired,
+    #[msg("Not enough free liquidity to fill the withdrawal request")]
+    WithdrawalNotFillable,
+    #[msg("Custody accounting invariant violated")]
+    CustodyInvariantViolated,
+    #[msg("Custody is paused, no new positions or deposits")]
+    CustodyTradingPaused,
+    #[msg("Queued withdrawals already hold back the most this custody allows")]
+    WithdrawalReserveExceeded,
+    #[msg("Withdrawal request is still inside its minimum escrow period")]
+    WithdrawalCancelTooEarly,
+    #[msg("Pool still has open positions")]
+    PoolNotEmpty,
 }
 
 // Contract-specific errors
@@ -251,6 +325,24 @@ pub enum ContractError {
     PermissionlessOracleSignerMismatch,
     #[msg("Signed message does not match instruction params")]
     PermissionlessOracleMessageMismatch,
+    #[msg("Contract is paused, new positions cannot be opened")]
+    ContractPaused,
+    #[msg("Batch exceeds the maximum number of items")]
+    BatchTooLarge,
+    #[msg("Batch contains no items")]
+    EmptyBatch,
+    #[msg("Invalid contract config")]
+    InvalidContractConfig,
+    #[msg("Oracle price exponent is outside the supported range")]
+    InvalidOracleExponent,
+    #[msg("Account cannot be migrated")]
+    AccountNotMigratable,
+    #[msg("Global notional limit reached")]
+    GlobalNotionalLimitReached,
+    #[msg("Every pool of the contract must be passed once, in order")]
+    InvalidPoolList,
+    #[msg("Clock is behind the last time recorded on the pool")]
+    ClockWentBackwards,
 }
 
 // Mathematical operation errors
@@ -334,4 +426,8 @@ pub enum FutureError {
     NoTriggerPrice,
     #[msg("Trigger condition not met")]
     TriggerConditionNotMet,
+    #[msg("Requested settlement asset does not match the future's settlement custody")]
+    SettlementCustodyMismatch,
+    #[msg("Future still has an unclaimed settlement")]
+    SettlementNotClaimed,
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
 
+use crate::state::TokenRatios;
+
 // Option related events - containing ALL fields from msg! calls
 #[event]
 pub struct OptionOpened {
@@ -25,6 +27,21 @@ pub struct OptionOpened {
     pub take_profit_price: Option<u64>,
     pub stop_loss_price: Option<u64>,
     pub bump: u8,
+    pub premium_markup_bps: u64,
+    // Black-Scholes inputs the premium was priced with, enough to reproduce fair_premium_usd
+    pub pricing_spot_price: u64,
+    pub pricing_time_to_expiry: f64, // years
+    pub pricing_volatility: f64,     // annual
+    pub pricing_borrow_rate: f64,    // annual risk-free rate from the locked custody's borrow curve
+    pub pricing_token_locked: u64,   // locked custody utilization at pricing
+    pub pricing_token_owned: u64,
+    pub fair_premium_usd: u64,       // per contract, before the buy markup
+    pub size_impact_bps: u64,        // size-dependent markup on top of the buy markup
+    pub size_impact_usd: u64,        // total paid for size impact
+    pub strike_expiry_notional_usd: u64,     // bucket total including this option
+    pub max_strike_expiry_notional_usd: u64, // 0 = no cap
+    pub referrer: Option<Pubkey>,
+    pub referral_fee_usd: u64,
 }
 
 #[event]
@@ -51,6 +68,7 @@ pub struct OptionClosed {
     pub take_profit_price: Option<u64>,
     pub stop_loss_price: Option<u64>,
     pub close_quantity: u64,
+    pub refund_markdown_bps: u64, // rate taken off the refund, the time-to-expiry close fee tier when tiers are set
 }
 
 #[event]
@@ -80,6 +98,26 @@ pub struct OptionExercised {
     pub profit: u64,
 }
 
+#[event]
+pub struct OptionRolled {
+    pub owner: Pubkey,
+    pub index: u64,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub quantity: u64,
+    pub old_strike_price: u64,
+    pub new_strike_price: u64,
+    pub old_expired_date: i64,
+    pub new_expired_date: i64,
+    pub old_value_usd: u64, // fair value of the old terms
+    pub new_value_usd: u64, // fair value of the new terms
+    pub roll_fee_usd: u64,
+    pub additional_premium: u64, // paid by the owner, in premium_asset tokens
+    pub refund_amount: u64,      // paid to the owner, in premium_asset tokens
+    pub premium_asset: Pubkey,
+    pub rolled_at: i64,
+}
+
 #[event]
 pub struct OptionTpSlSet {
     pub owner: Pubkey,
@@ -122,6 +160,7 @@ pub struct LimitOptionOpened {
     pub custody: Pubkey,
     pub premium: u64,
     pub premium_asset: Pubkey,
+    pub max_premium: u64,
     pub limit_price: u64,
     pub executed: bool,
     pub entry_price: u64,
@@ -183,8 +222,20 @@ pub struct PerpPositionOpened {
     pub collateral_amount: u64,
     pub trigger_price: Option<u64>,
     pub trigger_above_threshold: bool,
+    pub stop_price: Option<u64>,
     pub max_slippage: u64,
+    pub trade_fee_bps: u64,
+    pub lp_collateral_amount: u64,
+    pub borrow_size_usd: u64,
     pub bump: u8,
+    pub referrer: Option<Pubkey>,
+    pub referral_fee_usd: u64,
+    pub confidence_fee_usd: u64, // part of trade_fees charged for oracle confidence
+    pub oracle_confidence_bps: u64,
+    pub max_loss_usd: u64, // 0 = no protection
+    pub max_loss_price: u64,
+    pub max_loss_premium_usd: u64, // part of trade_fees charged for the protection
+    pub max_loss_expiry: i64,
 }
 
 #[event]
@@ -219,9 +270,29 @@ pub struct PerpPositionClosed {
     pub close_percentage: u64,
     pub realized_pnl: i64,
     pub settlement_tokens: u64,
+    pub lp_collateral_returned: u64,
+    pub lp_collateral_burned: u64,
+    pub borrow_size_usd: u64, // borrowed notional repaid by the closed portion
+    pub settlement_haircut: u64, // tokens kept by the pool for draining the receiving custody
+    pub hedge_fee_discount_usd: u64, // borrow fees waived over the position's life by an option hedge
+    pub lp_amount_minted: u64, // LP tokens minted for the settlement with receive_as_lp
+    pub referral_fee_usd: u64, // referrer's share of the trade fee collected on this close
 }
 
 // Limit order events - containing ALL fields from msg! calls
+#[event]
+pub struct StopLimitActivated {
+    pub pub_key: Pubkey,
+    pub index: u64,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub side: u8,
+    pub stop_price: u64,
+    pub limit_price: u64,
+    pub activation_price: u64,
+    pub activated_at: i64,
+}
+
 #[event]
 pub struct LimitOrderExecuted {
     pub index: u64,
@@ -251,6 +322,23 @@ pub struct LimitOrderExecuted {
     pub execution_price: u64,
 }
 
+#[event]
+pub struct LimitOrderExpired {
+    pub pub_key: Pubkey,
+    pub index: u64,
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub keeper: Pubkey,
+    pub side: u8,
+    pub size_usd: u64,
+    pub collateral_custody: Pubkey,
+    pub collateral_amount: u64,
+    pub refund_amount: u64,   // collateral returned, net of the keeper fee
+    pub keeper_fee_usd: u64,  // credited to the keeper's rewards
+    pub expiry_time: i64,
+    pub expired_at: i64,
+}
+
 #[event]
 pub struct LimitOrderCanceled {
     pub index: u64,
@@ -300,6 +388,7 @@ pub struct PositionLiquidated {
     pub update_time: i64,
     pub last_borrow_fees_update_time: i64,
     pub liquidation_price: u64,
+    pub bankruptcy_price: u64,
     pub cumulative_interest_snapshot: u128,
     pub trade_fees: u64,
     pub trade_fees_paid: u64,
@@ -314,6 +403,15 @@ pub struct PositionLiquidated {
     pub settlement_tokens: u64,
     pub liquidator_reward_tokens: u64,
     pub liquidator: Pubkey,
+    pub reward_waived: bool, // liquidator was the position owner
+    pub bad_debt_usd: u64,
+    pub lp_collateral_burned: u64,
+    pub borrow_size_usd: u64, // borrowed notional at liquidation
+    pub cooldown_bypassed: bool, // liquidated inside the cooldown because it was insolvent
+    pub settlement_price: u64, // live price bounded around the EMA, used for pnl
+    pub maintenance_margin_bps: u64, // size tier applied to the margin check
+    pub max_loss_triggered: bool, // closed at the max-loss floor rather than for margin
+    pub referral_fee_usd: u64,
 }
 
 // Liquidity events - containing ALL fields from msg! calls
@@ -325,9 +423,29 @@ pub struct LiquidityAdded {
     pub amount_in: u64,
     pub deposit_amount: u64,
     pub lp_amount: u64,
+    pub bonus_lp_amount: u64,
     pub fee_amount: u64,
     pub token_amount_usd: u64,
     pub pool_aum_usd: u128,
+    // Valuation inputs, enough to reproduce lp_amount
+    pub token_price: u64,
+    pub token_price_exponent: i32,
+    pub lp_share_price_usd: u64, // one whole LP token, at the AUM before the deposit
+}
+
+#[event]
+pub struct BalancedLiquidityAdded {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub amounts_in: Vec<u64>,
+    pub token_amounts_usd: Vec<u64>,
+    pub deposit_usd: u64,
+    pub lp_amount: u64,
+    pub pool_aum_usd: u128,
+    // Valuation inputs, enough to reproduce lp_amount
+    pub token_prices: Vec<u64>,
+    pub token_price_exponents: Vec<i32>,
+    pub lp_share_price_usd: u64, // one whole LP token, at the AUM before the deposit
 }
 
 #[event]
@@ -340,6 +458,53 @@ pub struct LiquidityRemoved {
     pub fee_amount: u64,
     pub withdrawal_amount: u64,
     pub pool_aum_usd: u128,
+    // Valuation inputs, enough to reproduce withdrawal_amount
+    pub token_price: u64,
+    pub token_price_exponent: i32,
+    pub lp_share_price_usd: u64, // one whole LP token, at the AUM before the withdrawal
+}
+
+#[event]
+pub struct WithdrawalRequested {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub request: Pubkey,
+    pub lp_amount: u64,
+    pub reserved_amount: u64,
+    pub queue_index: u64,
+    pub request_time: i64,
+}
+
+#[event]
+pub struct WithdrawalFulfilled {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub request: Pubkey,
+    pub keeper: Pubkey,
+    pub lp_amount_filled: u64,
+    pub lp_amount_remaining: u64,
+    pub transfer_amount: u64,
+    pub fee_amount: u64,
+    pub withdrawal_amount: u64,
+    pub pool_aum_usd: u128,
+    pub fill_time: i64,
+    // Valuation inputs, enough to reproduce withdrawal_amount
+    pub token_price: u64,
+    pub token_price_exponent: i32,
+    pub lp_share_price_usd: u64, // one whole LP token, at the AUM before the fill
+}
+
+#[event]
+pub struct WithdrawalCancelled {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub request: Pubkey,
+    pub lp_amount_returned: u64,
+    pub reserved_released: u64,
+    pub cancelled_at: i64,
 }
 
 // Pool management events - containing ALL fields from msg! calls
@@ -363,6 +528,7 @@ pub struct TpSlOrderbookInitialized {
     pub owner: Pubkey,
     pub position: Pubkey,
     pub contract_type: u8,
+    pub max_orders: u8, // per side
     pub bump: u8,
 }
 
@@ -454,6 +620,7 @@ pub struct TpSlOrderExecuted {
     pub liquidation_price: u64,
     pub cumulative_interest_snapshot: u128,
     pub is_full_close: bool,
+    pub referral_fee_usd: u64,
 }
 
 #[event]
@@ -473,6 +640,50 @@ pub struct PositionAccountClosed {
     pub rent_refunded: u64,
 }
 
+#[event]
+pub struct OpenInterestReconciled {
+    pub pool: Pubkey,
+    pub previous_long_open_interest_usd: u128,
+    pub previous_short_open_interest_usd: u128,
+    pub long_open_interest_usd: u128,
+    pub short_open_interest_usd: u128,
+    pub positions_passed: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CustodyLockedReconciled {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub previous_token_locked: u64,
+    pub token_locked: u64,
+    pub token_locked_perp: u64,
+    pub token_locked_option: u64,
+    pub token_locked_future: u64,
+    pub token_owned: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GlobalLimitReached {
+    pub global_notional_usd: u64,
+    pub max_global_notional_usd: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OwnershipTransferred {
+    pub contract_type: u8, // 0 = Perp, 1 = Option, 2 = Future
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub old_account: Pubkey,
+    pub new_account: Pubkey,
+    pub old_index: u64,
+    pub new_index: u64,
+    pub pool: Pubkey,
+    pub transferred_at: i64,
+}
+
 // Collateral management events
 #[event]
 pub struct CollateralAdded {
@@ -486,6 +697,7 @@ pub struct CollateralAdded {
     pub side: u8,
     pub collateral_amount_added: u64,
     pub collateral_usd_added: u64,
+    pub adjust_fee_amount: u64,
     pub price: u64,
     pub new_collateral_amount: u64,
     pub new_collateral_usd: u64,
@@ -513,6 +725,7 @@ pub struct CollateralRemoved {
     pub new_leverage: f64,
     pub new_liquidation_price: u64,
     pub withdrawal_tokens: u64,
+    pub adjust_fee_amount: u64,
     pub withdrawal_asset: Pubkey,
     pub update_time: i64,
     pub accrued_borrow_fees: u64,
@@ -542,6 +755,21 @@ pub struct PositionSizeUpdated {
     pub update_time: i64,
 }
 
+#[event]
+pub struct PositionHedgeUpdated {
+    pub pub_key: Pubkey,
+    pub owner: Pubkey,
+    pub position_index: u64,
+    pub pool: Pubkey,
+    pub option: Pubkey,
+    pub hedged: bool,            // false = hedge removed
+    pub hedged_usd: u64,         // option notional covering the position
+    pub hedge_discount_bps: u64,
+    pub hedge_expiry: i64,
+    pub hedge_fee_discount_usd: u64,
+    pub update_time: i64,
+}
+
 #[event]
 pub struct BorrowFeesUpdated {
     pub pub_key: Pubkey,
@@ -559,6 +787,60 @@ pub struct BorrowFeesUpdated {
     pub previous_interest_snapshot: u128,
     pub new_interest_snapshot: u128,
     pub update_time: i64,
+    pub keeper_reward_usd: u64,
+    pub applied_rate_bps: u64, // annual rate charged over the period, after cap, hedge discount and floor
+}
+
+#[event]
+pub struct KeeperRewardPaid {
+    pub keeper: Pubkey,
+    pub pool: Pubkey,
+    pub reward_usd: u64,
+    pub reward_tokens: u64,
+    pub total_claimed_usd: u64,
+    pub claimed_at: i64,
+    pub remaining_usd: u64, // still accrued, waiting on the keeper reward budget
+}
+
+#[event]
+pub struct ReferralRegistered {
+    pub referrer: Pubkey,
+    pub pool: Pubkey,
+    pub active: bool,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct ReferralFeesClaimed {
+    pub referrer: Pubkey,
+    pub pool: Pubkey,
+    pub fee_usd: u64,
+    pub fee_tokens: u64,
+    pub total_claimed_usd: u64,
+    pub claimed_at: i64,
+}
+
+#[event]
+pub struct LpFeesClaimed {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub lp_balance: u64, // staked balance recorded at this checkpoint
+    pub fee_amount: u64, // custody tokens paid
+    pub fees_owed: u64,  // left unpaid when the reserve ran short
+    pub total_claimed: u64,
+    pub epoch: u64,
+    pub claimed_at: i64,
+}
+
+#[event]
+pub struct LpStakeUpdated {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub staked: bool, // false = unstaked
+    pub amount: u64,
+    pub staked_amount: u64, // owner's stake after the update
+    pub updated_at: i64,
 }
 
 // Future trading events
@@ -570,10 +852,12 @@ pub struct FutureOpened {
     pub pool: Pubkey,
     pub custody: Pubkey,
     pub collateral_custody: Pubkey,
+    pub settlement_custody: Pubkey,
     pub side: u8,
     pub size_usd: u64,
     pub collateral_usd: u64,
     pub collateral_amount: u64,
+    pub lp_collateral_amount: u64,
     pub entry_price: u64,
     pub future_price: u64,
     pub fixed_interest_rate_bps: u32,
@@ -581,6 +865,8 @@ pub struct FutureOpened {
     pub liquidation_price: u64,
     pub locked_amount: u64,
     pub open_time: i64,
+    pub referrer: Option<Pubkey>,
+    pub referral_fee_usd: u64,
 }
 
 #[event]
@@ -589,7 +875,7 @@ pub struct FutureClosed {
     pub future_key: Pubkey,
     pub index: u64,
     pub side: u8,
-    pub close_percentage: u64,
+    pub close_percentage: u64, // Future::FULL_CLOSE = 100%
     pub closed_size_usd: u64,
     pub collateral_usd: u64,
     pub collateral_amount: u64,
@@ -599,6 +885,8 @@ pub struct FutureClosed {
     pub remaining_size_usd: u64,
     pub settlement_amount: u64,
     pub settlement_tokens: u64,
+    pub lp_collateral_returned: u64,
+    pub lp_collateral_burned: u64,
     pub pnl: i64,
     pub current_spot_price: u64,
     pub close_time: i64,
@@ -616,10 +904,22 @@ pub struct FutureSettled {
     pub pnl: i64,
     pub settlement_amount: u64,
     pub settlement_tokens: u64,
+    pub lp_collateral_returned: u64,
+    pub lp_collateral_burned: u64,
     pub expiry_time: i64,
     pub settlement_time: i64,
 }
 
+#[event]
+pub struct FutureExpiryMarked {
+    pub owner: Pubkey,
+    pub future_key: Pubkey,
+    pub index: u64,
+    pub settlement_price: u64,
+    pub expiry_time: i64,
+    pub marked_at: i64,
+}
+
 #[event]
 pub struct FutureClaimed {
     pub owner: Pubkey,
@@ -649,6 +949,7 @@ pub struct LimitFutureOpened {
     pub pool: Pubkey,
     pub custody: Pubkey,
     pub collateral_custody: Pubkey,
+    pub settlement_custody: Pubkey,
     pub side: u8,
     pub size_usd: u64,
     pub collateral_usd: u64,
@@ -679,4 +980,116 @@ pub struct LimitFutureExecuted {
     pub execution_time: i64,
     pub expiry_time: i64,
     pub locked_amount: u64,
-}
\ No newline at end of file
+}
+#[event]
+pub struct CloseSimulation {
+    pub position_key: Pubkey,
+    pub position_index: u64,
+    pub owner: Pubkey,
+    pub close_percentage: u64,
+    pub receive_sol: bool,
+    pub price: u64,
+    pub settlement_usd: u64,
+    pub settlement_tokens: u64,
+    pub realized_pnl: i64,
+    pub borrow_fees: u64,
+    pub trade_fees: u64,
+    pub simulated_at: i64,
+    pub receive_as_lp: bool,
+    pub borrow_size_usd: u64,
+    pub settlement_haircut: u64,
+    pub hedge_fee_discount_usd: u64,
+    pub lp_collateral_returned: u64,
+    pub lp_collateral_burned: u64,
+    pub lp_amount_minted: u64,
+}
+#[event]
+pub struct FutureMark {
+    pub future_key: Pubkey,
+    pub future_index: u64,
+    pub owner: Pubkey,
+    pub spot_price: u64,
+    pub mark_price: u64, // spot compounded at the fixed rate for the remaining time
+    pub future_price: u64, // locked at open
+    pub unrealized_pnl: i64,
+    pub equity_usd: u64,
+    pub current_leverage: f64, // size over current equity, 0 when insolvent
+    pub liquidation_price: u64,
+    pub liquidation_distance_bps: u64, // adverse spot move left before liquidation, 0 when past it
+    pub time_to_expiry: i64,
+    pub marked_at: i64,
+}
+#[event]
+pub struct CustodyTradingPaused {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub mint: Pubkey,
+    pub paused: bool, // false when trading is resumed
+    pub updated_at: i64,
+}
+#[event]
+pub struct RequiredCollateralComputed {
+    pub pool: Pubkey,
+    pub pay_sol: bool,
+    pub size_usd: u64,
+    pub size_amount: u64, // size in collateral tokens, as open_perp_position takes it
+    pub target_leverage: f64,
+    pub price: u64,
+    pub margin_usd: u64,
+    pub trade_fee_usd: u64,
+    pub confidence_fee_usd: u64,
+    pub max_loss_premium_usd: u64, // paid out of the collateral by the open
+    pub collateral_usd: u64,       // margin plus open fees plus max-loss premium
+    pub collateral_amount: u64,
+    pub computed_at: i64,
+}
+#[event]
+pub struct BatchProcessed {
+    pub instruction: String, // batch instruction name
+    pub requested: u8,
+    pub processed: u8,
+    pub skipped: u8, // left for the next batch when compute ran short
+    pub keeper: Pubkey, // signer that sent the batch
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AllPositionsClosed {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub requested: u8,
+    pub closed: u8,
+    pub skipped: u8, // ineligible, or left over when compute ran short
+    pub price: u64,
+    pub total_size_usd: u64,
+    pub total_settlement_usd: u64,
+    pub total_settlement_tokens: u64,
+    pub rent_refunded: u64,
+    pub closed_at: i64,
+    pub total_referral_fee_usd: u64,
+    pub futures_closed: u8, // of `closed`
+    pub options_closed: u8, // of `closed`
+}
+
+#[event]
+pub struct UserPrefsUpdated {
+    pub owner: Pubkey,
+    pub default_receive_sol: bool,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct PoolRatiosUpdated {
+    pub pool: Pubkey,
+    pub old_ratios: Vec<TokenRatios>,
+    pub new_ratios: Vec<TokenRatios>,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub old_len: u64,
+    pub new_len: u64,
+    pub migrated_at: i64,
+}
@@ -1,308 +1,314 @@
-use crate::{
-    errors::{PerpetualError, TradingError},
-    events::CollateralAdded,
-    math::{self, f64_to_scaled_price},
-    utils::risk_management::*,
-    state::{Contract, Custody, OraclePrice, Pool, Position, OrderType},
-};
-use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct AddCollateralParams {
-    pub position_index: u64,
-    pub pool_name: String,
-    pub collateral_amount: u64,  // Amount of collateral to add
-    pub pay_sol: bool,           // true = add SOL, false = add USDC
-}
-
-pub fn add_collateral(
-    ctx: Context<AddCollateral>,
-    params: &AddCollateralParams
-) -> Result<()> {
-    msg!("Adding collateral to perpetual position");
-    
-    let contract = &ctx.accounts.contract;
-    let pool = &mut ctx.accounts.pool;
-    let position = &mut ctx.accounts.position;
-    let sol_custody = &mut ctx.accounts.sol_custody;
-    let usdc_custody = &mut ctx.accounts.usdc_custody;
-    
-    // Validation
-    require_keys_eq!(position.owner, ctx.accounts.owner.key(), TradingError::Unauthorized);
-    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
-    require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
-    require!(params.collateral_amount > 0, TradingError::InvalidAmount);
-    
-    // Check user has sufficient balance
-    require_gte!(
-        ctx.accounts.funding_account.amount,
-        params.collateral_amount,
-        TradingError::InsufficientBalance
-    );
-    
-    // Get current prices
-    let current_time = contract.get_time()?;
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
-    
-    let sol_price_value = sol_price.get_price();
-    let usdc_price_value = usdc_price.get_price();
-    
-    msg!("SOL Price: {}", sol_price_value);
-    msg!("USDC Price: {}", usdc_price_value);
-    msg!("Adding {} tokens as collateral", params.collateral_amount);
-    
-    // Determine collateral asset and calculate USD value
-    let (collateral_decimals, collateral_price) = 
-        if params.pay_sol {
-            (sol_custody.decimals, sol_price_value)
-        } else {
-            (usdc_custody.decimals, usdc_price_value)
-        };
-    
-    // Calculate USD value of added collateral (scaled to 6 decimals)
-    let collateral_usd_to_add = math::checked_as_u64(math::checked_float_mul(
-        params.collateral_amount as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
-        collateral_price
-    )? * 1_000_000.0)?;
-    
-    msg!("Collateral USD to add: {}", collateral_usd_to_add);
-    msg!("Current collateral USD: {}", position.collateral_usd);
-    
-    // Transfer collateral from user to pool
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            SplTransfer {
-                from: ctx.accounts.funding_account.to_account_info(),
-                to: if params.pay_sol {
-                    ctx.accounts.sol_custody_token_account.to_account_info()
-                } else {
-                    ctx.accounts.usdc_custody_token_account.to_account_info()
-                },
-                authority: ctx.accounts.owner.to_account_info(),
-            },
-        ),
-        params.collateral_amount,
-    )?;
-    
-    // Update custody stats based on what asset was actually added
-    if params.pay_sol {
-        sol_custody.token_owned = math::checked_add(
-            sol_custody.token_owned,
-            params.collateral_amount
-        )?;
-    } else {
-        usdc_custody.token_owned = math::checked_add(
-            usdc_custody.token_owned,
-            params.collateral_amount
-        )?;
-    }
-    
-    
-    // Update position collateral
-    position.collateral_usd = math::checked_add(
-        position.collateral_usd,
-        collateral_usd_to_add
-    )?;
-    
-    // Convert and add to collateral_amount based on position's collateral custody
-    if position.collateral_custody == sol_custody.key() {
-        // Position stores collateral in SOL
-        let sol_amount_to_add = if params.pay_sol {
-            // Adding SOL to SOL position - direct add
-            params.collateral_amount
-        } else {
-            // Adding USDC to SOL position - convert USDC to SOL using integer math
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(collateral_usd_to_add as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?  
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
-        };
-        position.collateral_amount = math::checked_add(
-            position.collateral_amount,
-            sol_amount_to_add
-        )?;
-    } else {
-        // Position stores collateral in USDC
-        let usdc_amount_to_add = if params.pay_sol {
-            // Adding SOL to USDC position - convert SOL to USDC using integer math
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(collateral_usd_to_add as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
-        } else {
-            // Adding USDC to USDC position - direct add
-            params.collateral_amount
-        };
-        position.collateral_amount = math::checked_add(
-            position.collateral_amount,
-            usdc_amount_to_add
-        )?;
-    }
-    
-    // Recalculate margin requirements based on new collateral
-    let new_leverage = math::checked_float_div(position.size_usd as f64, position.collateral_usd as f64)?.max(1.0);
-    
-    // Recalculate liquidation price with new margin
-    let new_liquidation_price = calculate_liquidation_price(
-        position.entry_price,
-        new_leverage,
-        position.side
-    )?;
-    
-    // Update accrued borrow fees before modifying position
-    pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
-    
-    position.liquidation_price = new_liquidation_price;
-    position.update_time = current_time;
-    
-    msg!("Successfully added collateral");
-    msg!("New collateral amount: {}", position.collateral_amount);
-    msg!("New collateral USD: {}", position.collateral_usd);
-    msg!("New leverage: {}x", new_leverage);
-    msg!("New liquidation price: {}", position.liquidation_price);
-    
-    emit!(CollateralAdded {
-        pub_key: position.key(),
-        owner: ctx.accounts.owner.key(),
-        position_index: params.position_index,
-        pool: pool.key(),
-        custody: position.custody,
-        collateral_custody: position.collateral_custody,
-        order_type: position.order_type as u8,
-        side: position.side as u8,
-        collateral_amount_added: params.collateral_amount,
-        collateral_usd_added: collateral_usd_to_add,
-        new_collateral_amount: position.collateral_amount,
-        new_collateral_usd: position.collateral_usd,
-        price: f64_to_scaled_price(sol_price_value)?,
-        new_leverage,
-        new_liquidation_price: position.liquidation_price,
-        accrued_borrow_fees: position.accrued_borrow_fees,
-        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
-        update_time: current_time,
-    });
-    
-    Ok(())
-}
-
-#[derive(Accounts)]
-#[instruction(params: AddCollateralParams)]
-pub struct AddCollateral<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
-    #[account(
-        mut,
-        has_one = owner
-    )]
-    pub funding_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        seeds = [b"contract"],
-        bump = contract.bump
-    )]
-    pub contract: Box<Account<'info, Contract>>,
-
-    #[account(
-        mut,
-        seeds = [b"pool", params.pool_name.as_bytes()],
-        bump = pool.bump
-    )]
-    pub pool: Box<Account<'info, Pool>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"position",
-            owner.key().as_ref(),
-            params.position_index.to_le_bytes().as_ref(),
-            pool.key().as_ref()
-        ],
-        bump = position.bump
-    )]
-    pub position: Box<Account<'info, Position>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
-        bump = sol_custody.bump
-    )]
-    pub sol_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
-        bump = usdc_custody.bump
-    )]
-    pub usdc_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            sol_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            usdc_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = sol_oracle_account.key() == sol_custody.oracle
-    )]
-    pub sol_oracle_account: AccountInfo<'info>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = usdc_oracle_account.key() == usdc_custody.oracle
-    )]
-    pub usdc_oracle_account: AccountInfo<'info>,
-
-    #[account(mut)]
-    pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-
-    pub token_program: Program<'info, Token>,
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::CollateralAdded,
+    math::{self, f64_to_scaled_price},
+    utils::risk_management::*,
+    state::{Contract, Custody, Pool, Position, OrderType},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AddCollateralParams {
+    pub position_index: u64,
+    pub pool_name: String,
+    pub collateral_amount: u64,  // Amount of collateral to add
+    pub pay_sol: bool,           // true = add SOL, false = add USDC
+}
+
+pub fn add_collateral(
+    ctx: Context<AddCollateral>,
+    params: &AddCollateralParams
+) -> Result<()> {
+    msg!("Adding collateral to perpetual position");
+    
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let position = &mut ctx.accounts.position;
+    let sol_custody = &mut ctx.accounts.sol_custody;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    
+    // Validation
+    require_keys_eq!(position.owner, ctx.accounts.owner.key(), TradingError::Unauthorized);
+    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
+    require!(params.collateral_amount > 0, TradingError::InvalidAmount);
+    
+    // Check user has sufficient balance
+    require_gte!(
+        ctx.accounts.funding_account.amount,
+        params.collateral_amount,
+        TradingError::InsufficientBalance
+    );
+    
+    // Get current prices
+    let current_time = pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+    
+    let sol_price_value = sol_price.get_price();
+    let usdc_price_value = usdc_price.get_price();
+    
+    msg!("SOL Price: {}", sol_price_value);
+    msg!("USDC Price: {}", usdc_price_value);
+    msg!("Adding {} tokens as collateral", params.collateral_amount);
+    
+    
+    // Settle accrued borrow fees before the collateral changes
+    pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
+    
+    // Adjustment fee stays in the custody for LPs, the rest is credited as collateral
+    let adjust_fee_amount = math::checked_div(
+        math::checked_mul(params.collateral_amount, pool.collateral_adjust_fee_bps)?,
+        10_000,
+    )?;
+    let net_collateral_amount = math::checked_sub(params.collateral_amount, adjust_fee_amount)?;
+    require!(net_collateral_amount > 0, TradingError::InvalidAmount);
+    
+    // Determine collateral asset and calculate USD value
+    let (collateral_decimals, collateral_price) = 
+        if params.pay_sol {
+            (sol_custody.decimals, sol_price_value)
+        } else {
+            (usdc_custody.decimals, usdc_price_value)
+        };
+    
+    // Calculate USD value of added collateral (scaled to 6 decimals)
+    let collateral_usd_to_add = math::checked_as_u64(math::checked_float_mul(
+        net_collateral_amount as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
+        collateral_price
+    )? * Contract::USD_SCALE as f64)?;
+    
+    msg!("Collateral USD to add: {}", collateral_usd_to_add);
+    msg!("Current collateral USD: {}", position.collateral_usd);
+    
+    // Transfer collateral from user to pool
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.funding_account.to_account_info(),
+                to: if params.pay_sol {
+                    ctx.accounts.sol_custody_token_account.to_account_info()
+                } else {
+                    ctx.accounts.usdc_custody_token_account.to_account_info()
+                },
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+        ),
+        params.collateral_amount,
+    )?;
+    
+    // Update custody stats based on what asset was actually added
+    if params.pay_sol {
+        sol_custody.token_owned = math::checked_add(
+            sol_custody.token_owned,
+            params.collateral_amount
+        )?;
+    } else {
+        usdc_custody.token_owned = math::checked_add(
+            usdc_custody.token_owned,
+            params.collateral_amount
+        )?;
+    }
+    
+    
+    // Update position collateral
+    position.collateral_usd = math::checked_add(
+        position.collateral_usd,
+        collateral_usd_to_add
+    )?;
+    
+    // Convert and add to collateral_amount based on position's collateral custody
+    if position.collateral_custody == sol_custody.key() {
+        // Position stores collateral in SOL
+        let sol_amount_to_add = if params.pay_sol {
+            // Adding SOL to SOL position - direct add
+            net_collateral_amount
+        } else {
+            // Adding USDC to SOL position - convert USDC to SOL using integer math
+            math::usd_to_token_amount(collateral_usd_to_add, &sol_price, sol_custody.decimals)?
+        };
+        position.collateral_amount = math::checked_add(
+            position.collateral_amount,
+            sol_amount_to_add
+        )?;
+    } else {
+        // Position stores collateral in USDC
+        let usdc_amount_to_add = if params.pay_sol {
+            // Adding SOL to USDC position - convert SOL to USDC using integer math
+            math::usd_to_token_amount(collateral_usd_to_add, &usdc_price, usdc_custody.decimals)?
+        } else {
+            // Adding USDC to USDC position - direct add
+            net_collateral_amount
+        };
+        position.collateral_amount = math::checked_add(
+            position.collateral_amount,
+            usdc_amount_to_add
+        )?;
+    }
+    
+    // Recalculate margin requirements based on new collateral
+    let new_leverage = math::checked_float_div(position.size_usd as f64, position.collateral_usd as f64)?.max(1.0);
+    
+    // Recalculate liquidation price with new margin
+    let new_liquidation_price = calculate_liquidation_price(
+        position.entry_price,
+        new_leverage,
+        position.side,
+        sol_custody.get_maintenance_margin_bps(position.size_usd),
+    )?;
+    
+    position.liquidation_price = new_liquidation_price;
+    position.bankruptcy_price = calculate_bankruptcy_price(
+        position.entry_price,
+        new_leverage,
+        position.side
+    )?;
+    position.update_time = current_time;
+    
+    msg!("Successfully added collateral");
+    msg!("New collateral amount: {}", position.collateral_amount);
+    msg!("New collateral USD: {}", position.collateral_usd);
+    msg!("New leverage: {}x", new_leverage);
+    msg!("New liquidation price: {}", position.liquidation_price);
+    
+    emit!(CollateralAdded {
+        pub_key: position.key(),
+        owner: ctx.accounts.owner.key(),
+        position_index: params.position_index,
+        pool: pool.key(),
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        order_type: position.order_type as u8,
+        side: position.side as u8,
+        collateral_amount_added: params.collateral_amount,
+        collateral_usd_added: collateral_usd_to_add,
+        adjust_fee_amount,
+        new_collateral_amount: position.collateral_amount,
+        new_collateral_usd: position.collateral_usd,
+        price: f64_to_scaled_price(sol_price_value)?,
+        new_leverage,
+        new_liquidation_price: position.liquidation_price,
+        accrued_borrow_fees: position.accrued_borrow_fees,
+        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
+        update_time: current_time,
+    });
+    
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: AddCollateralParams)]
+pub struct AddCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -1,12 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
 
-use crate::state::{Contract, Custody, Multisig, Pool};
+use crate::state::{Contract, Custody, Multisig, OptionCustodyCombo, Pool};
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct AddCustodyParams {
     pub oracle: Pubkey,
-    pub pool_name : String
+    pub pool_name : String,
+    pub option_custody_combos: Vec<OptionCustodyCombo>, // appended to the pool allowlist
 }
 
 pub fn add_custody<'info>(
@@ -33,12 +34,20 @@ pub fn add_custody<'info>(
     let pool =&mut ctx.accounts.pool;
     require_keys_eq!(*pool.custodies.last().unwrap(), ctx.accounts.custody.key());
 
+    pool.add_option_custody_combos(&params.option_custody_combos)?;
+
     // record custody data
     let custody =&mut ctx.accounts.custody;
     custody.mint = ctx.accounts.custody_token_mint.key();
     custody.token_account = ctx.accounts.custody_token_account.key();
     custody.decimals = ctx.accounts.custody_token_mint.decimals;
     custody.oracle = params.oracle;
+    custody.oracle_secondary = Pubkey::default();
+    custody.oracle_type_primary = Custody::ORACLE_TYPE_PYTH;
+    custody.oracle_type_secondary = Custody::ORACLE_TYPE_PYTH;
+    custody.option_buy_markup_bps = 0;
+    custody.option_sell_markdown_bps = Custody::DEFAULT_OPTION_SELL_MARKDOWN_BPS;
+    custody.reserved_for_settlement = 0;
     
     // record bumps
     custody.bump = ctx.bumps.custody;
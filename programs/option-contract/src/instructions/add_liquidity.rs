@@ -2,8 +2,8 @@
 
 use {
     crate::{
-        errors::ContractError, events::LiquidityAdded, math, state::{
-            custody::Custody, oracle::OraclePrice, Contract, Pool
+        errors::{ContractError, PoolError}, events::LiquidityAdded, math, state::{
+            custody::Custody, Contract, Pool
         }
     },
     anchor_lang::prelude::*,
@@ -117,9 +117,16 @@ pub struct AddLiquidity<'info> {
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 
-    // remaining accounts:
+    // remaining accounts (optional, required once pool.aum_usd is stale):
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -146,18 +153,16 @@ pub fn add_liquidity<'info>(ctx: Context<'_, '_, 'info, 'info, AddLiquidity<'inf
     let custody = ctx.accounts.custody.as_mut();
     let pool = ctx.accounts.pool.as_mut();
     let token_id = pool.get_token_id(&custody.key())?;
+    require!(!custody.trading_paused, PoolError::CustodyTradingPaused);
 
     // calculate fee
-    let curtime = contract.get_time()?;
+    let curtime = pool.get_time(contract)?;
     // Refresh pool.aum_usm to adapt to token price change
-    pool.aum_usd =
-        pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+    let incremental = pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
 
-    let token_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account.to_account_info(),
-        curtime,
-        false,
-    )?;
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let token_price = custody.get_oracle_price(&ctx.accounts.custody_oracle_account, custody_oracle_secondary.as_ref(), curtime)?;
 
     let fee_amount =
         pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_price)?;
@@ -177,8 +182,8 @@ pub fn add_liquidity<'info>(ctx: Context<'_, '_, 'info, 'info, AddLiquidity<'inf
 
     // compute assets under management
     msg!("Compute assets under management");
-    let pool_amount_usd =
-        pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    let pool_amount_usd = pool.aum_usd;
+    let lp_share_price_usd = pool.get_lp_share_price_usd(ctx.accounts.lp_token_mint.supply)?;
 
     // compute amount of lp tokens to mint
     let no_fee_amount = math::checked_sub(params.amount_in, fee_amount)?;
@@ -203,21 +208,39 @@ pub fn add_liquidity<'info>(ctx: Context<'_, '_, 'info, 'info, AddLiquidity<'inf
     };
     msg!("LP tokens to mint: {}", lp_amount);
 
+    // deposits restoring an under-represented custody earn a bonus from the pool budget
+    let bonus_lp_amount =
+        pool.get_rebalance_bonus(token_id, no_fee_amount, lp_amount, custody, &token_price)?;
+    if bonus_lp_amount > 0 {
+        pool.rebalance_incentive_budget =
+            math::checked_sub(pool.rebalance_incentive_budget, bonus_lp_amount)?;
+        msg!("Rebalance bonus LP tokens: {}", bonus_lp_amount);
+    }
+
     // mint lp tokens
     contract.mint_tokens(
         ctx.accounts.lp_token_mint.to_account_info(),
         ctx.accounts.lp_token_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
-        lp_amount,
+        math::checked_add(lp_amount, bonus_lp_amount)?,
     )?;
     custody.token_owned = math::checked_add(custody.token_owned, deposit_amount)?;
+    // The fee stays in the token account outside token_owned; with epochs on it goes to LPs
+    if let Some(epoch) = pool.get_lp_fee_epoch(curtime) {
+        custody.accrue_lp_fee(fee_amount, epoch, ctx.accounts.lp_token_mint.supply)?;
+    }
 
     // update pool stats
     msg!("Update pool stats");
     custody.exit(&crate::ID)?;
-    pool.aum_usd =
-        pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    if incremental {
+        // only this custody changed, so adjust by the deposit alone
+        pool.aum_usd = math::checked_add(pool.aum_usd, token_amount_usd as u128)?;
+    } else {
+        pool.aum_usd =
+            pool.get_assets_under_management_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
+    }
 
     emit!(LiquidityAdded {
         owner: ctx.accounts.owner.key(),
@@ -226,11 +249,17 @@ pub fn add_liquidity<'info>(ctx: Context<'_, '_, 'info, 'info, AddLiquidity<'inf
         amount_in: params.amount_in,
         deposit_amount,
         lp_amount,
+        bonus_lp_amount,
         fee_amount,
         token_amount_usd,
         pool_aum_usd: pool.aum_usd,
+        token_price: token_price.price,
+        token_price_exponent: token_price.exponent,
+        lp_share_price_usd,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
     Ok(())
 }
 
@@ -0,0 +1,220 @@
+//! AddLiquidityBalanced instruction handler
+
+use {
+    crate::{
+        errors::{ContractError, PoolError}, events::BalancedLiquidityAdded, math, state::{
+            custody::Custody, Contract, Pool
+        }
+    },
+    anchor_lang::prelude::*,
+    anchor_spl::{
+        associated_token::AssociatedToken,
+        token::{Mint, Token, TokenAccount},
+    },
+};
+
+#[derive(Accounts)]
+#[instruction(params: AddLiquidityBalancedParams)]
+pub struct AddLiquidityBalanced<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = lp_token_mint,
+        associated_token::authority = owner,
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint",
+                pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    // remaining accounts:
+    //   pool.tokens.len() custody accounts (writable, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() custody token accounts (writable, unsigned)
+    //   pool.tokens.len() funding accounts (writable, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddLiquidityBalancedParams {
+    amounts_in: Vec<u64>, // One amount per pool custody, in pool.custodies order
+    min_lp_amount_out: u64,
+    pool_name: String,
+}
+
+pub fn add_liquidity_balanced<'info>(
+    ctx: Context<'_, '_, 'info, 'info, AddLiquidityBalanced<'info>>,
+    params: &AddLiquidityBalancedParams,
+) -> Result<()> {
+    let contract = ctx.accounts.contract.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let token_count = pool.custodies.len();
+
+    if params.amounts_in.len() != token_count {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    if ctx.remaining_accounts.len() < token_count * 4 {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+
+    let curtime = pool.get_time(contract)?;
+    // Refresh pool.aum_usd to adapt to token price change, always the full path here
+    let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 4);
+    pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
+    let pool_amount_usd = pool.aum_usd;
+    let lp_share_price_usd = pool.get_lp_share_price_usd(ctx.accounts.lp_token_mint.supply)?;
+
+    // value every leg of the deposit
+    let mut token_amounts_usd: Vec<u64> = Vec::with_capacity(token_count);
+    let mut token_prices: Vec<u64> = Vec::with_capacity(token_count);
+    let mut token_price_exponents: Vec<i32> = Vec::with_capacity(token_count);
+    let mut deposit_usd: u64 = 0;
+    for idx in 0..token_count {
+        let custody = Account::<Custody>::try_from(&ctx.remaining_accounts[idx])?;
+        let oracle_info = &ctx.remaining_accounts[token_count + idx];
+        require!(
+            params.amounts_in[idx] == 0 || !custody.trading_paused,
+            PoolError::CustodyTradingPaused
+        );
+
+        let token_price = custody.get_oracle_price(oracle_info, failover_oracles.get(idx), curtime)?;
+        let token_amount_usd =
+            token_price.get_asset_amount_usd(params.amounts_in[idx], custody.decimals)?;
+
+        token_amounts_usd.push(token_amount_usd);
+        token_prices.push(token_price.price);
+        token_price_exponents.push(token_price.exponent);
+        deposit_usd = math::checked_add(deposit_usd, token_amount_usd)?;
+    }
+    require_gte!(deposit_usd, 1u64, ContractError::InsufficientAmountReturned);
+
+    // each leg must match the pool's target ratio, so the composition isn't skewed
+    for (token_amount_usd, ratio) in token_amounts_usd.iter().zip(pool.ratios.iter()) {
+        let share_bps = math::checked_as_u64(math::checked_div(
+            math::checked_mul(*token_amount_usd as u128, Contract::BPS_POWER)?,
+            deposit_usd as u128,
+        )?)?;
+        let target_bps = math::checked_mul(ratio.target, 100)?;
+        require_gte!(
+            Pool::BALANCED_DEPOSIT_TOLERANCE_BPS,
+            share_bps.abs_diff(target_bps),
+            PoolError::TokenRatioOutOfRange
+        );
+    }
+
+    // transfer tokens
+    msg!("Transfer tokens");
+    for idx in 0..token_count {
+        let amount = params.amounts_in[idx];
+        if amount == 0 {
+            continue;
+        }
+
+        let mut custody = Account::<Custody>::try_from(&ctx.remaining_accounts[idx])?;
+        let custody_token_account_info = &ctx.remaining_accounts[token_count * 2 + idx];
+        let funding_account_info = &ctx.remaining_accounts[token_count * 3 + idx];
+
+        let (expected_custody_token_account, _) = Pubkey::find_program_address(
+            &[b"custody_token_account", pool.key().as_ref(), custody.mint.as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(custody_token_account_info.key(), expected_custody_token_account);
+
+        let funding_account = Account::<TokenAccount>::try_from(funding_account_info)?;
+        require_keys_eq!(funding_account.owner, ctx.accounts.owner.key());
+        require_keys_eq!(funding_account.mint, custody.mint);
+
+        contract.transfer_tokens_from_user(
+            funding_account_info.clone(),
+            custody_token_account_info.clone(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            amount,
+        )?;
+
+        custody.token_owned = math::checked_add(custody.token_owned, amount)?;
+        Custody::check_invariants(&[&custody])?;
+        custody.exit(&crate::ID)?;
+    }
+
+    // compute amount of lp tokens to mint, no ratio fee since the composition is unchanged
+    let lp_amount = if pool_amount_usd == 0 {
+        deposit_usd
+    } else {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                deposit_usd as u128,
+                ctx.accounts.lp_token_mint.supply as u128,
+            )?,
+            pool_amount_usd,
+        )?)?
+    };
+    msg!("LP tokens to mint: {}", lp_amount);
+    require_gte!(
+        lp_amount,
+        params.min_lp_amount_out,
+        ContractError::InsufficientAmountReturned
+    );
+
+    // mint lp tokens
+    contract.mint_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount,
+    )?;
+
+    // update pool stats
+    msg!("Update pool stats");
+    pool.aum_usd =
+        pool.get_assets_under_management_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
+
+    emit!(BalancedLiquidityAdded {
+        owner: ctx.accounts.owner.key(),
+        pool: pool.key(),
+        amounts_in: params.amounts_in.clone(),
+        token_amounts_usd,
+        deposit_usd,
+        lp_amount,
+        pool_aum_usd: pool.aum_usd,
+        token_prices,
+        token_price_exponents,
+        lp_share_price_usd,
+    });
+
+    Ok(())
+}
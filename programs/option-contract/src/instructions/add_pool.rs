@@ -3,12 +3,17 @@ use anchor_spl::token::{Mint, Token};
 
 use crate::{
     events::PoolAdded,
-    state::{Contract, multisig::{Multisig, AdminInstruction}, Pool}
+    state::{Contract, multisig::{Multisig, AdminInstruction}, Pool, Position}
 };
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct AddPoolParams {
     pub name: String,
+    pub sol_mint: Pubkey,  // Base asset used by perps and futures
+    pub usdc_mint: Pubkey, // Quote asset used by perps and futures
+    pub min_future_duration_sec: Option<i64>, // None = Pool::DEFAULT_MIN_FUTURE_DURATION_SEC
+    pub max_future_duration_sec: Option<i64>, // None = Pool::DEFAULT_MAX_FUTURE_DURATION_SEC
+    pub perp_trade_fee_bps: Option<u64>,      // None = Position::EXITING_FEE_BPS
 }
 
 pub fn add_pool<'info>(ctx: Context<'_, '_, '_, 'info, AddPool<'info>>, params: &AddPoolParams) -> Result<u8> {
@@ -16,6 +21,22 @@ pub fn add_pool<'info>(ctx: Context<'_, '_, '_, 'info, AddPool<'info>>, params:
     if params.name.is_empty() || params.name.len() > 64 {
         return Err(ProgramError::InvalidArgument.into());
     }
+    if params.sol_mint == params.usdc_mint {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let min_future_duration_sec = params
+        .min_future_duration_sec
+        .unwrap_or(Pool::DEFAULT_MIN_FUTURE_DURATION_SEC);
+    let max_future_duration_sec = params
+        .max_future_duration_sec
+        .unwrap_or(Pool::DEFAULT_MAX_FUTURE_DURATION_SEC);
+    if min_future_duration_sec <= 0 || max_future_duration_sec <= min_future_duration_sec {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    let perp_trade_fee_bps = params.perp_trade_fee_bps.unwrap_or(Position::EXITING_FEE_BPS);
+    if perp_trade_fee_bps > Pool::MAX_PERP_TRADE_FEE_BPS {
+        return Err(ProgramError::InvalidArgument.into());
+    }
 
     // validate signatures
     let mut multisig = ctx.accounts.multisig.load_mut()?;
@@ -43,6 +64,11 @@ pub fn add_pool<'info>(ctx: Context<'_, '_, '_, 'info, AddPool<'info>>, params:
     pool.name = params.name.clone();
     pool.bump = ctx.bumps.pool;
     pool.lp_token_bump = ctx.bumps.lp_token_mint;
+    pool.sol_mint = params.sol_mint;
+    pool.usdc_mint = params.usdc_mint;
+    pool.min_future_duration_sec = min_future_duration_sec;
+    pool.max_future_duration_sec = max_future_duration_sec;
+    pool.perp_trade_fee_bps = perp_trade_fee_bps;
     
     // Initialize borrow rate curve with default parameters
     pool.initialize_borrow_rate_curve()?;
@@ -51,6 +77,7 @@ pub fn add_pool<'info>(ctx: Context<'_, '_, '_, 'info, AddPool<'info>>, params:
     pool.cumulative_interest_rate_long = 0;
     pool.cumulative_interest_rate_short = 0;
     pool.last_rate_update = Clock::get()?.unix_timestamp;
+    pool.borrow_index_start_time = pool.last_rate_update;
     
     // Initialize open interest tracking
     pool.long_open_interest_usd = 0;
@@ -92,7 +119,7 @@ pub struct AddPool<'info> {
 
     #[account(
         mut, 
-        realloc= Contract::LEN +(contract.pools.len() + 1) * std::mem::size_of::<Pubkey>(),
+        realloc = Contract::get_space(contract.pools.len() + 1),
         realloc::payer = signer,
         realloc::zero = false,
         seeds = [b"contract"],
@@ -1,7 +1,7 @@
 use crate::{
     errors::{OptionError, TradingError},
-    math::{self, scaled_price_to_f64},
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    math,
+    state::{Contract, Custody, LockedProduct, OptionDetail, Pool, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::{
@@ -26,6 +26,7 @@ pub fn auto_exercise(
     let custody: &mut Box<Account<'_, Custody>> = &mut ctx.accounts.custody;
     let locked_custody = &mut ctx.accounts.locked_custody;
     let locked_oracle = &ctx.accounts.locked_oracle;
+    let custody_oracle = &ctx.accounts.custody_oracle;
 
     // CRITICAL VALIDATION CHECKS - Add these at the beginning
     require_gte!(user.option_index, params.option_index);
@@ -51,7 +52,7 @@ pub fn auto_exercise(
     );
 
     // Current Unix timestamp
-    let current_timestamp = contract.get_time()?;
+    let current_timestamp = ctx.accounts.pool.get_time(contract)?;
 
     // Auto-exercise should only work AFTER expiry (opposite of manual exercise)
     require_gte!(
@@ -60,57 +61,70 @@ pub fn auto_exercise(
         OptionError::InvalidTimeError
     );
 
-    let token_price =
-        OraclePrice::new_from_oracle(locked_oracle, current_timestamp, false)?;
-    let oracle_price = token_price.get_price();
-
+    // Moneyness is decided by the underlying custody price, payout is denominated in the locked
+    // asset. Both are read as published at expiry, so a late run settles the same as a prompt one.
+    let locked_oracle_secondary = ctx.accounts.locked_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let locked_price_update = ctx.accounts.locked_price_update.as_ref().map(|a| a.to_account_info());
+    let custody_price_update = ctx.accounts.custody_price_update.as_ref().map(|a| a.to_account_info());
+    let locked_token_price = locked_custody
+        .get_oracle_price_at(
+            locked_oracle,
+            locked_oracle_secondary.as_ref(),
+            locked_price_update.as_ref(),
+            option_detail.expired_date,
+        )?
+        .get_price();
+    let oracle_price = custody
+        .get_oracle_price_at(
+            custody_oracle,
+            custody_oracle_secondary.as_ref(),
+            custody_price_update.as_ref(),
+            option_detail.expired_date,
+        )?
+        .get_price();
+
+    let locked_amount = option_detail.get_locked_amount(option_detail.quantity)?;
     require_gte!(
         locked_custody.token_locked,
-        option_detail.amount,
+        locked_amount,
         TradingError::InvalidLockedBalanceError
     );
 
-    if custody.key() == locked_custody.key() {
-        // call option - only exercise if profitable
-        let strike_price_f64 = scaled_price_to_f64(option_detail.strike_price)?;
-        if oracle_price > strike_price_f64 {
-            // Calculate Sol Amount from Option Detail Value : call / covered sol
-            // Use more precise calculation to minimize rounding
-            let price_diff = oracle_price - strike_price_f64;
-            let intrinsic_value = price_diff * (option_detail.quantity as f64);
-            let amount = intrinsic_value / oracle_price;
-
-            option_detail.profit = math::checked_as_u64(amount.round())?;
-            option_detail.claimed = math::checked_as_u64(amount.round())?;
-        } else {
-            // Option expired out of the money - no profit
-            option_detail.claimed = 0;
-            option_detail.profit = 0;
-        }
+    // Freeze the expiry price so later price moves can't change the payout
+    let settlement_price = math::f64_to_scaled_price(oracle_price)?;
+    option_detail.settlement_price = Some(settlement_price);
+
+    // Calls lock the underlying itself, so value the payout at the same frozen price
+    let payout_token_price = if custody.key() == locked_custody.key() {
+        oracle_price
     } else {
-        // put option - only exercise if profitable
-        let strike_price_f64 = scaled_price_to_f64(option_detail.strike_price)?;
-        if strike_price_f64 > oracle_price {
-            // Calculate Profit amount with option detail values: put / cash-secured usdc
-            let price_diff = strike_price_f64 - oracle_price;
-            let amount = price_diff * (option_detail.quantity as f64);
-
-            option_detail.profit = math::checked_as_u64(amount.round())?;
-            option_detail.claimed = amount as u64;
-        } else {
-            // Option expired out of the money - no profit
-            option_detail.claimed = 0;
-            option_detail.profit = 0;
-        }
-    }
+        locked_token_price
+    };
+    let payout = option_detail.settlement_payout(payout_token_price)?;
+    option_detail.profit = payout;
+    option_detail.claimed = payout;
 
     // Mark option as exercised and invalid
     option_detail.exercised = current_timestamp as u64;
     option_detail.valid = false;
+    msg!("Settlement price: {}", settlement_price);
+    let notional_usd = option_detail.get_notional_usd(option_detail.quantity)?;
+    ctx.accounts.pool.remove_strike_expiry_notional(
+        option_detail.strike_price,
+        option_detail.expired_date,
+        option_detail.is_call(),
+        notional_usd,
+    );
+    ctx.accounts.pool.remove_notional(notional_usd);
+
+    let option_key = option_detail.key();
+    ctx.accounts.pool.sync_option_hedge(option_detail, option_key, ctx.accounts.hedge_position.as_deref())?;
 
-    // Update locked custody balance
-    locked_custody.token_locked =
-        math::checked_sub(locked_custody.token_locked, option_detail.amount)?;
+    // Release the option's remaining lock; partial closes have already shrunk `locked_amount`
+    locked_custody.remove_locked(LockedProduct::Option, locked_amount)?;
+
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.locked_custody])?;
 
     Ok(())
 }
@@ -183,7 +197,35 @@ pub struct AutoExerciseOption<'info> {
     )]
     pub locked_oracle: AccountInfo<'info>,
 
+    /// CHECK: oracle account for the underlying asset
+    #[account(
+        constraint = custody_oracle.key() == custody.oracle
+    )]
+    pub custody_oracle: AccountInfo<'info>,
+
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = locked_oracle_secondary.key() == locked_custody.oracle_secondary
+    )]
+    pub locked_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Price update posted for the expiry, checked against the locked oracle's feed
+    pub locked_price_update: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Price update posted for the expiry, checked against the underlying oracle's feed
+    pub custody_price_update: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use crate::{
     errors::{PerpetualError, TradingError},
     events::{LimitOrderCanceled, PositionAccountClosed, TpSlOrderbookClosed},
     math,
-    state::{Contract, Custody, OraclePrice, OrderType, Pool, Position, TpSlOrderbook},
+    state::{Contract, Custody, LimitOrderBook, OrderType, Pool, Position, TpSlOrderbook, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -13,7 +13,7 @@ pub struct CancelLimitOrderParams {
     pub pool_name: String,
     pub contract_type: u8,
     pub close_percentage: u64, // 1-100,000,000: 100,000,000 = full close (6 decimal precision)
-    pub receive_sol: bool,    // true = receive SOL, false = receive USDC
+    pub receive_sol: Option<bool>, // true = receive SOL, false = receive USDC, None = user default
 }
 
 pub fn cancel_limit_order(
@@ -22,6 +22,10 @@ pub fn cancel_limit_order(
 ) -> Result<()> {
     msg!("Canceling {}% of limit order", params.close_percentage);
 
+    let receive_sol = params.receive_sol.unwrap_or(
+        ctx.accounts.user.as_ref().is_some_and(|user| user.default_receive_sol),
+    );
+
     let contract = &ctx.accounts.contract;
     let position = &mut ctx.accounts.position;
     let sol_custody = &mut ctx.accounts.sol_custody;
@@ -38,13 +42,14 @@ pub fn cancel_limit_order(
         position.order_type == OrderType::Limit,
         PerpetualError::NotLimitOrder
     );
+    require!(position.execution_time.is_none(), PerpetualError::LimitOrderAlreadyExecuted);
     require!(position.size_usd > 0, PerpetualError::InvalidPositionSize);
     require!(
         params.close_percentage > 0 && params.close_percentage <= 100_000_000,
         TradingError::InvalidAmount
     );
 
-    let current_time = contract.get_time()?;
+    let current_time = ctx.accounts.pool.get_time(contract)?;
     let is_full_close = params.close_percentage == 100_000_000;
 
     msg!("Canceling limit order for position:");
@@ -54,7 +59,14 @@ pub fn cancel_limit_order(
     msg!("Position side: {:?}", position.side);
     msg!(
         "User chose to receive: {}",
-        if params.receive_sol { "SOL" } else { "USDC" }
+        if receive_sol { "SOL" } else { "USDC" }
+    );
+    // Receiving account must hold the asset being paid out
+    let payout_mint = if receive_sol { sol_custody.mint } else { usdc_custody.mint };
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        payout_mint,
+        TradingError::ReceivingAccountMintMismatch
     );
     msg!("Close percentage: {}%", params.close_percentage);
 
@@ -107,61 +119,23 @@ pub fn cancel_limit_order(
     let sol_custody_key = sol_custody.key();
     let _usdc_custody_key = usdc_custody.key();
 
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
     
     // Convert USD to tokens using integer math only
     // collateral_usd_to_refund has 6 decimals (e.g., $100 = 100_000_000)
-    let settlement_tokens = if params.receive_sol {
-        // Scale SOL price to 6 decimals for consistent math
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        
-        // USD amount / SOL price = SOL amount (both with 6 decimals)
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(collateral_usd_to_refund as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        // Scale from 6 decimals to SOL token decimals (usually 9)
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
+    let settlement_tokens = if receive_sol {
+        math::usd_to_token_amount(collateral_usd_to_refund, &sol_price, sol_custody.decimals)?
     } else {
-        // Scale USDC price to 6 decimals
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        
-        // USD amount / USDC price = USDC amount
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(collateral_usd_to_refund as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        // Scale from 6 decimals to USDC token decimals (usually 6)
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(collateral_usd_to_refund, &usdc_price, usdc_custody.decimals)?
     };
 
     // Transfer collateral back to user
     if collateral_amount_to_refund > 0 {
         // Determine which token account to use for transfer
-        let original_token_account = if params.receive_sol {
+        let original_token_account = if receive_sol {
             &ctx.accounts.sol_custody_token_account
         } else {
             &ctx.accounts.usdc_custody_token_account
@@ -255,6 +229,17 @@ pub fn cancel_limit_order(
 
     position.update_time = current_time;
 
+    // Keep the shared book in sync with the remaining order size
+    position.require_limit_order_book(ctx.accounts.limit_order_book.is_some())?;
+    if let Some(book) = ctx.accounts.limit_order_book.as_mut() {
+        if is_full_close {
+            book.remove(&position_key);
+            position.in_limit_order_book = false;
+        } else {
+            book.update_size(&position_key, position.size_usd);
+        }
+    }
+
     // No fees for canceling limit orders since they were never active positions
 
     emit!(LimitOrderCanceled {
@@ -281,7 +266,7 @@ pub fn cancel_limit_order(
         trigger_price: position.trigger_price,
         trigger_above_threshold: position.trigger_above_threshold,
         bump: position.bump,
-        close_percentage: params.close_percentage as u64,
+        close_percentage: params.close_percentage,
         refunded_collateral: collateral_amount_to_refund,
         refunded_collateral_usd: collateral_usd_to_refund,
     });
@@ -345,6 +330,8 @@ pub fn cancel_limit_order(
         msg!("TP/SL orderbook and position accounts automatically closed - all rent returned to user");
     }
 
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -354,6 +341,12 @@ pub struct CancelLimitOrder<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
 
+    #[account(
+        seeds = [b"user_v3", owner.key().as_ref()],
+        bump,
+    )]
+    pub user: Option<Box<Account<'info, User>>>, // settlement preference when receive_sol is unset
+
     #[account(
         mut,
         has_one = owner
@@ -440,13 +433,39 @@ pub struct CancelLimitOrder<'info> {
     )]
     pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
     /// CHECK: Optional TP/SL orderbook account - may not exist if user never set TP/SL
     pub tp_sl_orderbook: Option<AccountInfo<'info>>,
 
+    // Shared limit order book, required when position.in_limit_order_book is set
+    #[account(
+        mut,
+        seeds = [b"limit_order_book", pool.key().as_ref()],
+        bump = limit_order_book.bump
+    )]
+    pub limit_order_book: Option<Box<Account<'info, LimitOrderBook>>>,
+
     pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
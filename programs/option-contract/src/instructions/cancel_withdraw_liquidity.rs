@@ -0,0 +1,142 @@
+use crate::{
+    errors::PoolError,
+    events::WithdrawalCancelled,
+    math,
+    state::{Contract, Custody, Pool, WithdrawalRequest},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CancelWithdrawLiquidityParams {
+    pub pool_name: String,
+    pub queue_index: u64, // Position of the request in the custody's withdrawal queue
+}
+
+/// Withdraw a queued request once MIN_CANCEL_DELAY_SEC has passed: the unfilled LP tokens
+/// go back to the owner and the custody reservation is released. The request at the head of
+/// the queue is closed; one further back stays as an empty placeholder so the queue keeps
+/// its order, and fulfill closes it when it reaches the head.
+pub fn cancel_withdraw_liquidity(
+    ctx: Context<CancelWithdrawLiquidity>,
+    _params: &CancelWithdrawLiquidityParams,
+) -> Result<()> {
+    msg!("Cancelling queued liquidity withdrawal");
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let custody = &mut ctx.accounts.custody;
+    let request = &mut ctx.accounts.withdrawal_request;
+
+    let current_time = pool.get_time(contract)?;
+    require!(request.can_cancel(current_time)?, PoolError::WithdrawalCancelTooEarly);
+    let lp_amount_returned = request.lp_amount;
+    let reserved_released = request.reserved_amount;
+
+    if request.lp_amount > 0 {
+        contract.transfer_tokens(
+            ctx.accounts.lp_escrow_account.to_account_info(),
+            ctx.accounts.lp_token_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            request.lp_amount,
+        )?;
+    }
+
+    custody.reserved_for_withdrawals =
+        custody.reserved_for_withdrawals.saturating_sub(reserved_released);
+    pool.pending_withdrawal_lp = math::checked_sub(pool.pending_withdrawal_lp, lp_amount_returned)?;
+    request.lp_amount = 0;
+    request.reserved_amount = 0;
+
+    emit!(WithdrawalCancelled {
+        owner: request.owner,
+        pool: request.pool,
+        custody: request.custody,
+        request: request.key(),
+        lp_amount_returned,
+        reserved_released,
+        cancelled_at: current_time,
+    });
+
+    // The head leaves the queue now, rent goes back to the owner
+    if request.queue_index == custody.withdrawal_queue_head {
+        custody.withdrawal_queue_head = math::checked_add(custody.withdrawal_queue_head, 1)?;
+        WithdrawalRequest::close_account(
+            &ctx.accounts.withdrawal_request.to_account_info(),
+            &ctx.accounts.owner.to_account_info(),
+        )?;
+    }
+
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: CancelWithdrawLiquidityParams)]
+pub struct CancelWithdrawLiquidity<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lp_escrow_account.mint == lp_token_mint.key(),
+        constraint = lp_escrow_account.owner == transfer_authority.key()
+    )]
+    pub lp_escrow_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdrawal_request",
+            custody.key().as_ref(),
+            params.queue_index.to_le_bytes().as_ref()
+        ],
+        bump = withdrawal_request.bump,
+        has_one = owner
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    pub token_program: Program<'info, Token>,
+}
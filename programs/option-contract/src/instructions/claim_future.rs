@@ -2,7 +2,7 @@ use crate::{
     errors::{FutureError, TradingError},
     events::{FutureClaimed, FutureAccountClosed},
     math,
-    state::{Contract, Custody, Future, FutureStatus, OraclePrice, Pool},
+    state::{Contract, Custody, Future, FutureStatus, Pool},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -42,58 +42,28 @@ pub fn claim_future(ctx: Context<ClaimFuture>, params: &ClaimFutureParams) -> Re
         FutureError::NothingToClaim
     );
 
-    let current_time = contract.get_time()?;
+    let current_time = ctx.accounts.pool.get_time(contract)?;
 
     // Get current prices for conversion (use settlement price if available, otherwise current)
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
 
     // Convert settlement amount to tokens
-    let claim_tokens = if future.collateral_custody == sol_custody.key() {
+    let claim_tokens = if future.get_settlement_custody() == sol_custody.key() {
         // Claim in SOL
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_amount as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(settlement_amount, &sol_price, sol_custody.decimals)?
     } else {
         // Claim in USDC
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_amount as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(settlement_amount, &usdc_price, usdc_custody.decimals)?
     };
 
     msg!("Claiming {} tokens", claim_tokens);
 
     // Transfer tokens to user
     if claim_tokens > 0 {
-        let claim_token_account = if future.collateral_custody == sol_custody.key() {
+        let claim_token_account = if future.get_settlement_custody() == sol_custody.key() {
             &ctx.accounts.sol_custody_token_account
         } else {
             &ctx.accounts.usdc_custody_token_account
@@ -108,7 +78,7 @@ pub fn claim_future(ctx: Context<ClaimFuture>, params: &ClaimFutureParams) -> Re
         )?;
 
         // Update custody balance
-        if future.collateral_custody == sol_custody.key() {
+        if future.get_settlement_custody() == sol_custody.key() {
             sol_custody.token_owned = math::checked_sub(
                 sol_custody.token_owned,
                 claim_tokens
@@ -172,6 +142,9 @@ pub fn claim_future(ctx: Context<ClaimFuture>, params: &ClaimFutureParams) -> Re
     }
 
     msg!("Future claim completed successfully");
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -267,10 +240,28 @@ pub struct ClaimFuture<'info> {
     )]
     pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
     pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
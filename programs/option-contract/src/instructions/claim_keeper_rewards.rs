@@ -0,0 +1,143 @@
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::KeeperRewardPaid,
+    math,
+    state::{Contract, Custody, KeeperRewards, Pool},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClaimKeeperRewardsParams {
+    pub pool_name: String,
+}
+
+/// Pay out the keeper rewards accrued in a pool in USDC from the pool's USDC custody, up to
+/// the pool's keeper reward budget. Whatever the budget can't cover stays accrued.
+pub fn claim_keeper_rewards(
+    ctx: Context<ClaimKeeperRewards>,
+    params: &ClaimKeeperRewardsParams,
+) -> Result<()> {
+    msg!("Claiming keeper rewards in pool {}", params.pool_name);
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    let keeper_rewards = &mut ctx.accounts.keeper_rewards;
+
+    let reward_usd = keeper_rewards.accrued_usd.min(pool.keeper_reward_budget_usd);
+    require!(reward_usd > 0, TradingError::InvalidAmount);
+
+    let current_time = pool.get_time(contract)?;
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_price =
+        usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+    let reward_tokens = math::usd_to_token_amount(reward_usd, &usdc_price, usdc_custody.decimals)?;
+
+    // Rewards are paid from free liquidity only, never from backing of open positions
+    require_gte!(
+        usdc_custody.available_for_open(),
+        reward_tokens,
+        PerpetualError::CustodyAmountLimit
+    );
+
+    contract.transfer_tokens(
+        ctx.accounts.usdc_custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        reward_tokens,
+    )?;
+    usdc_custody.token_owned = math::checked_sub(usdc_custody.token_owned, reward_tokens)?;
+
+    keeper_rewards.accrued_usd = math::checked_sub(keeper_rewards.accrued_usd, reward_usd)?;
+    pool.keeper_reward_budget_usd = math::checked_sub(pool.keeper_reward_budget_usd, reward_usd)?;
+    keeper_rewards.total_claimed_usd = math::checked_add(keeper_rewards.total_claimed_usd, reward_usd)?;
+    keeper_rewards.last_claim_time = current_time;
+
+    emit!(KeeperRewardPaid {
+        keeper: keeper_rewards.keeper,
+        pool: keeper_rewards.pool,
+        reward_usd,
+        reward_tokens,
+        total_claimed_usd: keeper_rewards.total_claimed_usd,
+        claimed_at: current_time,
+        remaining_usd: keeper_rewards.accrued_usd,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ClaimKeeperRewardsParams)]
+pub struct ClaimKeeperRewards<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == usdc_custody.mint @ TradingError::ReceivingAccountMintMismatch,
+        constraint = receiving_account.owner == keeper.key()
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), pool.usdc_mint.as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"keeper_rewards", keeper.key().as_ref(), pool.key().as_ref()],
+        bump = keeper_rewards.bump
+    )]
+    pub keeper_rewards: Box<Account<'info, KeeperRewards>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
@@ -0,0 +1,149 @@
+use crate::{
+    errors::{PoolError, TradingError},
+    events::LpFeesClaimed,
+    math,
+    state::{Contract, Custody, LpFeeAccount, LpStake, Pool},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClaimLpFeesParams {
+    pub pool_name: String,
+}
+
+/// Checkpoint the owner's staked LP balance for a custody's epoch fee distribution and pay out
+/// what closed epochs have earned, in the custody's token. The first call only starts the
+/// clock, and LPs should call it again after staking more so the new balance starts earning.
+pub fn claim_lp_fees(ctx: Context<ClaimLpFees>, params: &ClaimLpFeesParams) -> Result<()> {
+    msg!("Claiming LP fees in pool {}", params.pool_name);
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let custody = &mut ctx.accounts.custody;
+    let lp_fee_account = &mut ctx.accounts.lp_fee_account;
+
+    let current_time = pool.get_time(contract)?;
+    let epoch = pool
+        .get_lp_fee_epoch(current_time)
+        .ok_or(PoolError::InvalidPoolConfig)?;
+
+    if lp_fee_account.owner == Pubkey::default() {
+        lp_fee_account.owner = ctx.accounts.owner.key();
+        lp_fee_account.pool = pool.key();
+        lp_fee_account.custody = custody.key();
+        lp_fee_account.bump = ctx.bumps.lp_fee_account;
+    }
+
+    custody.advance_lp_fee_epoch(epoch, ctx.accounts.lp_token_mint.supply)?;
+    lp_fee_account.checkpoint(
+        custody,
+        pool.lp_fee_epoch_duration_sec,
+        current_time,
+        ctx.accounts.lp_stake.amount,
+    )?;
+
+    // Pots are split over the whole LP supply and only escrowed tokens earn, each counted by
+    // one stake, so the reserve covers every claim; the cap only guards against rounding
+    let fee_amount = lp_fee_account.fees_owed.min(custody.lp_fee_reserve);
+    if fee_amount > 0 {
+        contract.transfer_tokens(
+            ctx.accounts.custody_token_account.to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            fee_amount,
+        )?;
+        custody.lp_fee_reserve = math::checked_sub(custody.lp_fee_reserve, fee_amount)?;
+        lp_fee_account.fees_owed = math::checked_sub(lp_fee_account.fees_owed, fee_amount)?;
+        lp_fee_account.total_claimed = math::checked_add(lp_fee_account.total_claimed, fee_amount)?;
+    }
+
+    emit!(LpFeesClaimed {
+        owner: lp_fee_account.owner,
+        pool: lp_fee_account.pool,
+        custody: lp_fee_account.custody,
+        lp_balance: lp_fee_account.lp_balance,
+        fee_amount,
+        fees_owed: lp_fee_account.fees_owed,
+        total_claimed: lp_fee_account.total_claimed,
+        epoch,
+        claimed_at: current_time,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ClaimLpFeesParams)]
+pub struct ClaimLpFees<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint @ TradingError::ReceivingAccountMintMismatch,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account", pool.key().as_ref(), custody.mint.as_ref()],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = LpFeeAccount::LEN,
+        seeds = [b"lp_fees", owner.key().as_ref(), custody.key().as_ref()],
+        bump
+    )]
+    pub lp_fee_account: Box<Account<'info, LpFeeAccount>>,
+
+    #[account(
+        has_one = owner,
+        seeds = [b"lp_stake", owner.key().as_ref(), pool.key().as_ref()],
+        bump = lp_stake.bump
+    )]
+    pub lp_stake: Box<Account<'info, LpStake>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
@@ -1,5 +1,5 @@
 use crate::{
-    errors::TradingError,
+    errors::{OptionError, TradingError},
     math, 
     state::{Contract, Custody, OptionDetail, Pool, User}
 };
@@ -39,6 +39,12 @@ pub fn claim_option(ctx: Context<ClaimOption>, params: &ClaimOptionParams) -> Re
     // Option must be invalid (exercised/expired)
     require_eq!(option_detail.valid, false);
     
+    // Payout is only valid once the settlement price has been frozen at expiry
+    require!(
+        option_detail.settlement_price.is_some(),
+        OptionError::SettlementPriceNotRecorded
+    );
+
     // Must have claimable amount
     require_gt!(option_detail.claimed, 0);
 
@@ -65,6 +71,8 @@ pub fn claim_option(ctx: Context<ClaimOption>, params: &ClaimOptionParams) -> Re
         claim_amount,
     )?;
 
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -0,0 +1,138 @@
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::ReferralFeesClaimed,
+    math,
+    state::{Contract, Custody, Pool, Referral},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClaimReferralFeesParams {
+    pub pool_name: String,
+}
+
+/// Pay out the referral fees owed by a pool in USDC from the pool's USDC custody
+pub fn claim_referral_fees(
+    ctx: Context<ClaimReferralFees>,
+    params: &ClaimReferralFeesParams,
+) -> Result<()> {
+    msg!("Claiming referral fees in pool {}", params.pool_name);
+
+    let contract = &ctx.accounts.contract;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    let referral = &mut ctx.accounts.referral;
+
+    let fee_usd = referral.referral_fees_owed;
+    require!(fee_usd > 0, TradingError::InvalidAmount);
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_price =
+        usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+    let fee_tokens = math::usd_to_token_amount(fee_usd, &usdc_price, usdc_custody.decimals)?;
+
+    // Referral fees are paid from free liquidity only, never from backing of open positions
+    require_gte!(
+        usdc_custody.available_for_open(),
+        fee_tokens,
+        PerpetualError::CustodyAmountLimit
+    );
+
+    contract.transfer_tokens(
+        ctx.accounts.usdc_custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        fee_tokens,
+    )?;
+    usdc_custody.token_owned = math::checked_sub(usdc_custody.token_owned, fee_tokens)?;
+
+    referral.referral_fees_owed = 0;
+    referral.total_claimed_usd = math::checked_add(referral.total_claimed_usd, fee_usd)?;
+    referral.last_claim_time = current_time;
+
+    emit!(ReferralFeesClaimed {
+        referrer: referral.referrer,
+        pool: referral.pool,
+        fee_usd,
+        fee_tokens,
+        total_claimed_usd: referral.total_claimed_usd,
+        claimed_at: current_time,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ClaimReferralFeesParams)]
+pub struct ClaimReferralFees<'info> {
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == usdc_custody.mint @ TradingError::ReceivingAccountMintMismatch,
+        constraint = receiving_account.owner == referrer.key()
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), pool.usdc_mint.as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"referral", referrer.key().as_ref(), pool.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Box<Account<'info, Referral>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
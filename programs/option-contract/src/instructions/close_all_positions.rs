@@ -0,0 +1,461 @@
+use crate::{
+    errors::TradingError,
+    events::{AllPositionsClosed, BatchProcessed},
+    math::{self, f64_to_scaled_price},
+    state::{
+        Contract, Custody, Future, FutureStatus, LockedProduct, OptionDetail, Pool, Position,
+        Referral, Side, User,
+    },
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CloseAllPositionsParams {
+    pub pool_name: String,
+    pub receive_sol: Option<bool>,  // true = receive SOL, false = receive USDC, None = user default
+    pub min_settlement_tokens: u64, // slippage bound on the combined payout, 0 = none
+}
+
+/// Fully close every perp position, future and option passed in remaining accounts at the
+/// current oracle price and pay out the combined settlement in one transfer; each account is
+/// told apart by its discriminator. Positions that are not an open market position of the
+/// owner in this pool, or that still have a TP/SL orderbook or LP token collateral, or a
+/// referrer other than the passed referral account's, are skipped. So are futures and options
+/// that do not pay out in the requested asset, LP-collateralised futures, and limit, hedged or
+/// TP/SL options. Processing stops early when compute runs short; the rest are reported as
+/// skipped so they can be sent again.
+pub fn close_all_positions<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CloseAllPositions<'info>>,
+    params: &CloseAllPositionsParams,
+) -> Result<()> {
+    msg!("Closing all positions");
+
+    let requested = ctx.remaining_accounts.len();
+    Contract::check_batch_size(requested)?;
+    let receive_sol = params.receive_sol.unwrap_or(
+        ctx.accounts.user.as_ref().is_some_and(|user| user.default_receive_sol),
+    );
+
+    let pool = &mut ctx.accounts.pool;
+    let sol_custody = &mut ctx.accounts.sol_custody;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    let owner_key = ctx.accounts.owner.key();
+    let pool_key = pool.key();
+
+    let current_time = pool.get_time(&ctx.accounts.contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+    let current_price_scaled = f64_to_scaled_price(sol_price.get_price())?;
+
+    // Receiving account must hold the asset being paid out
+    let payout_mint = if receive_sol { sol_custody.mint } else { usdc_custody.mint };
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        payout_mint,
+        TradingError::ReceivingAccountMintMismatch
+    );
+
+    let payout_custody_key = if receive_sol { sol_custody.key() } else { usdc_custody.key() };
+    let payout_price = if receive_sol { &sol_price } else { &usdc_price };
+
+    let mut closed: u8 = 0;
+    let mut futures_closed: u8 = 0;
+    let mut options_closed: u8 = 0;
+    let mut skipped: u8 = 0;
+    let mut total_size_usd: u64 = 0;
+    let mut total_settlement_usd: u64 = 0;
+    let mut perp_settlement_usd: u64 = 0;
+    let mut future_settlement_tokens: u64 = 0;
+    let mut option_refund_tokens: u64 = 0;
+    let mut total_rent_refunded: u64 = 0;
+    let mut total_referral_fee_usd: u64 = 0;
+    let referrer = ctx.accounts.referral.as_ref().map(|referral| referral.referrer);
+
+    let mut deferred: u8 = 0;
+    for (index, position_info) in ctx.remaining_accounts.iter().enumerate() {
+        if !Contract::has_batch_budget() {
+            msg!("Compute budget low, leaving remaining positions for the next batch");
+            skipped = math::checked_sub(requested as u8, closed)?;
+            deferred = (requested - index) as u8;
+            break;
+        }
+
+        if position_info.owner != ctx.program_id || !position_info.is_writable {
+            skipped += 1;
+            continue;
+        }
+        let discriminator: [u8; 8] = match position_info.try_borrow_data()?.get(..8) {
+            Some(data) => data.try_into().unwrap(),
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        if discriminator == Position::DISCRIMINATOR {
+            let Ok(mut position) = Account::<Position>::try_from(position_info) else {
+                skipped += 1;
+                continue;
+            };
+            if position.owner != owner_key
+                || position.pool != pool_key
+                || position.is_liquidated
+                || !position.is_executed()
+                || position.tp_sl_orderbook.is_some()
+                || position.lp_collateral_amount > 0
+                || position.referrer.is_some_and(|key| Some(key) != referrer)
+            {
+                skipped += 1;
+                continue;
+            }
+
+            let settlement = pool.compute_close_settlement(
+                &mut position,
+                Position::FULL_CLOSE_PERCENTAGE,
+                current_price_scaled,
+                current_time,
+                sol_custody,
+                usdc_custody,
+            )?;
+            let settlement_usd = settlement.settlement_usd;
+            pool.fund_keeper_rewards(&ctx.accounts.contract, settlement.get_collected_borrow_fees())?;
+            let referral_fee_usd = Referral::accrue_perp_close(
+                ctx.accounts.referral.as_deref_mut().map(|r| &mut **r),
+                &position,
+                &settlement,
+                pool,
+                &ctx.accounts.contract,
+            )?;
+            total_referral_fee_usd = math::checked_add(total_referral_fee_usd, referral_fee_usd)?;
+
+            // Release the locked backing and the collateral held for this position
+            if position.side == Side::Long {
+                sol_custody.unlock_funds(LockedProduct::Perp, position.locked_amount)?;
+            } else {
+                usdc_custody.unlock_funds(LockedProduct::Perp, position.locked_amount)?;
+            }
+            pool.update_open_interest(&position, position.size_usd, false, current_time)?;
+            if position.collateral_custody == sol_custody.key() {
+                sol_custody.token_owned =
+                    math::checked_sub(sol_custody.token_owned, position.collateral_amount)?;
+            } else {
+                usdc_custody.token_owned =
+                    math::checked_sub(usdc_custody.token_owned, position.collateral_amount)?;
+            }
+
+            total_size_usd = math::checked_add(total_size_usd, position.size_usd)?;
+            perp_settlement_usd = math::checked_add(perp_settlement_usd, settlement_usd)?;
+            total_settlement_usd = math::checked_add(total_settlement_usd, settlement_usd)?;
+            msg!("Closed position {} with settlement {} USD", position.index, settlement_usd);
+        } else if discriminator == Future::DISCRIMINATOR {
+            let Ok(future) = Account::<Future>::try_from(position_info) else {
+                skipped += 1;
+                continue;
+            };
+            // Futures pay out in the asset chosen at open, so only those settling in the
+            // batch payout asset can be closed here
+            if future.owner != owner_key
+                || future.pool != pool_key
+                || future.status != FutureStatus::Active
+                || future.is_expired(current_time)
+                || future.lp_collateral_amount > 0
+                || future.get_settlement_custody() != payout_custody_key
+            {
+                skipped += 1;
+                continue;
+            }
+
+            let settlement = pool.compute_future_close_settlement(
+                &future,
+                Future::FULL_CLOSE,
+                current_price_scaled,
+                current_time,
+            )?;
+            let settlement_usd = settlement.settlement_usd;
+
+            // Release locked liquidity; the payout is checked against what is free after the loop
+            if future.side == Side::Long {
+                sol_custody.remove_locked(LockedProduct::Future, settlement.locked_amount)?;
+            } else {
+                usdc_custody.remove_locked(LockedProduct::Future, settlement.locked_amount)?;
+            }
+            pool.remove_future_position(settlement.size_usd, future.time_to_expiry(current_time), current_time)?;
+
+            let settlement_tokens = math::usd_to_token_amount(
+                settlement_usd,
+                payout_price,
+                if receive_sol { sol_custody.decimals } else { usdc_custody.decimals },
+            )?;
+            future_settlement_tokens = math::checked_add(future_settlement_tokens, settlement_tokens)?;
+            total_size_usd = math::checked_add(total_size_usd, settlement.size_usd)?;
+            total_settlement_usd = math::checked_add(total_settlement_usd, settlement_usd)?;
+            futures_closed += 1;
+            msg!("Closed future {} with settlement {} USD", future.index, settlement_usd);
+        } else if discriminator == OptionDetail::DISCRIMINATOR {
+            let Ok(option_detail) = Account::<OptionDetail>::try_from(position_info) else {
+                skipped += 1;
+                continue;
+            };
+            // Market options on the SOL underlying whose refund is paid in the batch payout
+            // asset; limit orders, hedged options and those with TP/SL are closed on their own
+            if option_detail.owner != owner_key
+                || option_detail.pool != pool_key
+                || !option_detail.valid
+                || option_detail.limit_price > 0
+                || option_detail.tp_sl_orderbook.is_some()
+                || option_detail.hedge_position.is_some()
+                || option_detail.custody != sol_custody.key()
+                || option_detail.locked_asset != payout_custody_key
+                || current_time >= option_detail.expired_date
+            {
+                skipped += 1;
+                continue;
+            }
+
+            let quantity = option_detail.quantity;
+            let (refund_amount, _) = option_detail.get_buyback_refund(
+                quantity,
+                current_time,
+                sol_price.get_price(),
+                sol_custody,
+                if receive_sol { sol_custody } else { usdc_custody },
+                payout_price,
+            )?;
+            let unlock_amount = option_detail.get_locked_amount(quantity)?;
+            if receive_sol {
+                sol_custody.release_option_refund(unlock_amount, refund_amount)?;
+            } else {
+                usdc_custody.release_option_refund(unlock_amount, refund_amount)?;
+            }
+
+            let notional_usd = option_detail.get_notional_usd(quantity)?;
+            pool.remove_strike_expiry_notional(
+                option_detail.strike_price,
+                option_detail.expired_date,
+                option_detail.is_call(),
+                notional_usd,
+            );
+            pool.remove_notional(notional_usd);
+
+            option_refund_tokens = math::checked_add(option_refund_tokens, refund_amount)?;
+            options_closed += 1;
+            msg!("Closed option {} with refund {}", option_detail.index, refund_amount);
+        } else {
+            skipped += 1;
+            continue;
+        }
+
+        // Close the account, rent goes back to the owner
+        let position_rent = position_info.lamports();
+        **position_info.try_borrow_mut_lamports()? = 0;
+        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? = ctx.accounts.owner
+            .to_account_info()
+            .lamports()
+            .checked_add(position_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        position_info.try_borrow_mut_data()?.fill(0);
+        total_rent_refunded = math::checked_add(total_rent_refunded, position_rent)?;
+
+        closed += 1;
+    }
+
+    pool.remove_notional(total_size_usd);
+
+    let (perp_settlement_tokens, settlement_haircut) = if receive_sol {
+        let token_id = pool.get_token_id(&sol_custody.key())?;
+        pool.get_settlement_payout(token_id, perp_settlement_usd, sol_custody, &sol_price)?
+    } else {
+        let token_id = pool.get_token_id(&usdc_custody.key())?;
+        pool.get_settlement_payout(token_id, perp_settlement_usd, usdc_custody, &usdc_price)?
+    };
+    msg!("Settlement haircut: {}", settlement_haircut);
+    let total_settlement_tokens = math::checked_add(
+        math::checked_add(perp_settlement_tokens, future_settlement_tokens)?,
+        option_refund_tokens,
+    )?;
+    require_gte!(
+        total_settlement_tokens,
+        params.min_settlement_tokens,
+        TradingError::SlippageExceededError
+    );
+
+    // Option refunds were taken out of the custody as each was released
+    let settlement_custody = if receive_sol { &mut **sol_custody } else { &mut **usdc_custody };
+    let settlement_tokens = math::checked_add(perp_settlement_tokens, future_settlement_tokens)?;
+    require_gte!(
+        settlement_custody.available_for_payout(),
+        settlement_tokens,
+        TradingError::InsufficientPoolLiquidity
+    );
+    settlement_custody.token_owned = math::checked_sub(settlement_custody.token_owned, future_settlement_tokens)?;
+
+    if total_settlement_tokens > 0 {
+        ctx.accounts.contract.transfer_tokens(
+            if receive_sol {
+                ctx.accounts.sol_custody_token_account.to_account_info()
+            } else {
+                ctx.accounts.usdc_custody_token_account.to_account_info()
+            },
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            total_settlement_tokens,
+        )?;
+    }
+
+    emit!(AllPositionsClosed {
+        owner: owner_key,
+        pool: pool_key,
+        requested: requested as u8,
+        closed,
+        skipped,
+        price: current_price_scaled,
+        total_size_usd,
+        total_settlement_usd,
+        total_settlement_tokens,
+        rent_refunded: total_rent_refunded,
+        closed_at: current_time,
+        total_referral_fee_usd,
+        futures_closed,
+        options_closed,
+    });
+    emit!(BatchProcessed {
+        instruction: "close_all_positions".to_string(),
+        requested: requested as u8,
+        processed: requested as u8 - deferred,
+        skipped: deferred,
+        keeper: owner_key,
+        timestamp: current_time,
+    });
+
+    msg!("Closed {} positions, skipped {}", closed, skipped);
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseAllPositionsParams)]
+pub struct CloseAllPositions<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"user_v3", owner.key().as_ref()],
+        bump,
+    )]
+    pub user: Option<Box<Account<'info, User>>>, // settlement preference when receive_sol is unset
+
+    #[account(
+        mut,
+        has_one = owner,
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+
+    // remaining accounts:
+    //   up to Contract::MAX_BATCH_SIZE of the owner's position, future and option accounts in
+    //   this pool (writable)
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    // Registration of the referrer on the positions, positions referred by anyone else are skipped
+    #[account(
+        mut,
+        constraint = referral.pool == pool.key()
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+}
@@ -1,8 +1,8 @@
 use crate::{
-    errors::{FutureError, TradingError},
-    events::FutureClosed,
+    errors::{FutureError, PerpetualError, TradingError},
+    events::{FutureAccountClosed, FutureClosed},
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, Future, FutureStatus, OraclePrice, Pool, Side},
+    state::{Contract, Custody, LockedProduct, Future, FutureStatus, Pool, Side},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -11,14 +11,14 @@ use anchor_spl::token::{Mint, Token, TokenAccount};
 pub struct CloseFutureParams {
     pub future_index: u64,            // Index of future to close
     pub pool_name: String,            // Pool name for seeds
-    pub close_percentage: u64,        // Percentage to close (100_000_000 = 100%)
-    pub receive_sol: bool,            // Settlement preference
+    pub close_percentage: u64,        // Portion to close, Future::FULL_CLOSE = 100%
+    pub receive_sol: Option<bool>,    // Must match the settlement asset chosen at open, None = accept it
     pub max_slippage_bps: u64,        // Maximum slippage tolerance
 }
 
 pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Result<()> {
     msg!("Closing future position");
-    msg!("Close percentage: {}%", params.close_percentage as f64 / 1_000_000.0);
+    msg!("Close percentage: {}%", Future::close_percentage_display(params.close_percentage));
 
     // Get keys first to avoid borrowing conflicts
     let sol_custody_key = ctx.accounts.sol_custody.key();
@@ -40,21 +40,46 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
         FutureError::FutureNotActive
     );
     require!(
-        params.close_percentage > 0 && params.close_percentage <= 100_000_000,
+        params.close_percentage > 0 && params.close_percentage <= Future::FULL_CLOSE,
         TradingError::InvalidAmount
     );
 
-    let current_time = contract.get_time()?;
-    let is_full_close = params.close_percentage == 100_000_000;
+    let current_time = pool.get_time(contract)?;
+    let is_full_close = params.close_percentage == Future::FULL_CLOSE;
+
+    // LP collateral is returned in one piece
+    require!(
+        future.lp_collateral_amount == 0 || is_full_close,
+        PerpetualError::LpCollateralUnsupported
+    );
 
     // Check if future has expired
     if future.is_expired(current_time) {
         return Err(FutureError::FutureExpired.into());
     }
 
+    // Settlement asset is fixed at open; a requested asset must agree with it
+    let receive_sol = future.get_settlement_custody() == sol_custody_key;
+    require!(
+        params.receive_sol.unwrap_or(receive_sol) == receive_sol,
+        FutureError::SettlementCustodyMismatch
+    );
+    // Receiving account must hold the asset being paid out
+    let payout_mint = if receive_sol { sol_custody.mint } else { usdc_custody.mint };
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        payout_mint,
+        TradingError::ReceivingAccountMintMismatch
+    );
+
+    // Collateral recorded at open must be one of the custodies passed in
+    let sol_collateral = future.is_sol_collateral(&sol_custody_key, &usdc_custody.key())?;
+
     // Get current oracle prices
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
     
     let current_sol_price = sol_price.get_price();
     let current_sol_price_scaled = f64_to_scaled_price(current_sol_price)?;
@@ -63,164 +88,66 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
     let pnl = future.calculate_pnl(current_sol_price_scaled, current_time)?;
     
     msg!("Current spot price: {}", current_sol_price);
-    msg!("Future price: {}", (future.future_price as f64) / 1_000_000.0);
+    msg!("Future price: {}", (future.future_price as f64) / math::PRICE_SCALE as f64);
     msg!("P&L: {}", pnl);
 
-    // Calculate amounts to close (proportional to percentage)
-    let size_usd_to_close = if is_full_close {
-        future.size_usd
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(future.size_usd as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    let collateral_amount_to_close = if is_full_close {
-        future.collateral_amount
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(future.collateral_amount as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    let collateral_usd_to_close = if is_full_close {
-        future.collateral_usd
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(future.collateral_usd as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
+    // Net settlement of the closed portion (collateral + PnL - fees)
+    let settlement = pool.compute_future_close_settlement(
+        future,
+        params.close_percentage,
+        current_sol_price_scaled,
+        current_time,
+    )?;
+    let size_usd_to_close = settlement.size_usd;
+    let collateral_amount_to_close = settlement.collateral_amount;
+    let collateral_usd_to_close = settlement.collateral_usd;
+    let locked_amount_to_release = settlement.locked_amount;
+    let pnl_for_closed_portion = settlement.realized_pnl;
+    let closing_fee = settlement.close_fee_usd;
+    let settlement_usd = settlement.settlement_usd;
 
-    let locked_amount_to_release = if is_full_close {
-        future.locked_amount
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(future.locked_amount as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
+    msg!("Settlement USD: {}", settlement_usd);
+    msg!("Closing fee: {}", closing_fee);
 
-    // Calculate P&L for closed portion
-    let pnl_for_closed_portion = if is_full_close {
-        pnl
-    } else {
-        if pnl >= 0 {
-            math::checked_as_i64(math::checked_div(
-                math::checked_mul(pnl as u128, params.close_percentage as u128)?,
-                100_000_000u128
-            )?)?
-        } else {
-            -math::checked_as_i64(math::checked_div(
-                math::checked_mul((-pnl) as u128, params.close_percentage as u128)?,
-                100_000_000u128
-            )?)?
-        }
-    };
+    // LP collateral goes back in kind: a profit is paid in the settlement asset, a loss is
+    // covered by burning LP tokens worth the shortfall at the current AUM
+    let lp_supply = ctx.accounts.lp_token_mint.as_ref().map(|mint| mint.supply);
+    let (payout_usd, lp_collateral_returned, lp_collateral_burned) = pool.get_lp_collateral_settlement(
+        future.lp_collateral_amount,
+        future.lp_collateral_usd,
+        settlement_usd,
+        lp_supply,
+    )?;
 
-    // Calculate net settlement (collateral + PnL - fees)
-    let closing_fee = math::checked_div(
-        math::checked_mul(size_usd_to_close as u128, Future::SETTLEMENT_FEE_BPS as u128)?,
-        10_000u128,
-    )? as u64;
-
-    let net_settlement = (collateral_usd_to_close as i64) + pnl_for_closed_portion - (closing_fee as i64);
-    let settlement_usd = if net_settlement > 0 { net_settlement as u64 } else { 0 };
-
-    let native_exit_mount = if settlement_usd > 0 {
-        if future.side == Side::Long {
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
+    // Calculate settlement tokens in the future's settlement asset
+    let settlement_tokens = if payout_usd > 0 {
+        if receive_sol {
+            math::usd_to_token_amount(payout_usd, &sol_price, sol_custody.decimals)?
         } else {
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
+            math::usd_to_token_amount(payout_usd, &usdc_price, usdc_custody.decimals)?
         }
     } else {
         0
     };
-    
 
-    msg!("Settlement USD: {}", settlement_usd);
-    msg!("Closing fee: {}", closing_fee);
+    // Release locked liquidity first so the payout can draw on it
+    if future.side == Side::Long {
+        sol_custody.remove_locked(LockedProduct::Future, locked_amount_to_release)?;
+    } else {
+        usdc_custody.remove_locked(LockedProduct::Future, locked_amount_to_release)?;
+    }
 
-    // Calculate settlement tokens
-    let settlement_tokens = if settlement_usd > 0 {
-        if params.receive_sol {
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
-        } else {
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
-        }
+    // Fail clearly when the settlement custody can't cover the payout
+    let payout_available = if receive_sol {
+        sol_custody.available_for_payout()
     } else {
-        0
+        usdc_custody.available_for_payout()
     };
+    require_gte!(payout_available, settlement_tokens, TradingError::InsufficientPoolLiquidity);
 
     // Transfer settlement to user
     if settlement_tokens > 0 {
-        let settlement_token_account = if params.receive_sol {
+        let settlement_token_account = if receive_sol {
             &ctx.accounts.sol_custody_token_account
         } else {
             &ctx.accounts.usdc_custody_token_account
@@ -235,7 +162,7 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
         )?;
 
         // Update settlement custody balance
-        if params.receive_sol {
+        if receive_sol {
             sol_custody.token_owned = math::checked_sub(
                 sol_custody.token_owned,
                 settlement_tokens
@@ -248,76 +175,81 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
         }
     }
 
-    // Release locked liquidity
-    if future.side == Side::Long {
-        sol_custody.token_locked = math::checked_sub(
-            sol_custody.token_locked,
-            locked_amount_to_release
-        )?;
-    } else {
-        usdc_custody.token_locked = math::checked_sub(
-            usdc_custody.token_locked,
-            locked_amount_to_release
-        )?;
+    // Return the LP collateral and burn the part that covered a loss
+    if future.lp_collateral_amount > 0 {
+        let (Some(lp_token_mint), Some(lp_collateral_account), Some(lp_receiving_account)) = (
+            ctx.accounts.lp_token_mint.as_ref(),
+            ctx.accounts.lp_collateral_account.as_ref(),
+            ctx.accounts.lp_receiving_account.as_ref(),
+        ) else {
+            return err!(PerpetualError::LpCollateralAccountsMissing);
+        };
+
+        if lp_collateral_returned > 0 {
+            contract.transfer_tokens(
+                lp_collateral_account.to_account_info(),
+                lp_receiving_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                lp_collateral_returned,
+            )?;
+        }
+        if lp_collateral_burned > 0 {
+            contract.burn_owned_tokens(
+                lp_token_mint.to_account_info(),
+                lp_collateral_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                lp_collateral_burned,
+            )?;
+        }
+        msg!("LP collateral returned: {}, burned: {}", lp_collateral_returned, lp_collateral_burned);
     }
 
-    // Calculate remaining collateral to return
-    let remaining_collateral = if settlement_usd < collateral_usd_to_close {
+    // Calculate remaining custody collateral to return; LP-collateralised futures hold none
+    let remaining_collateral = if future.lp_collateral_amount == 0 && settlement_usd < collateral_usd_to_close {
         // If settlement was less than collateral, return the difference
         let diff_usd = collateral_usd_to_close - settlement_usd;
         
         // Convert back to collateral tokens
-        let collateral_price_scaled = if future.collateral_custody == sol_custody_key {
-            sol_price.scale_to_exponent(-6)?
+        let (collateral_price, custody_decimals) = if sol_collateral {
+            (&sol_price, sol_custody.decimals)
         } else {
-            usdc_price.scale_to_exponent(-6)?
+            (&usdc_price, usdc_custody.decimals)
         };
         
-        let remaining_amount_6_decimals = math::checked_div(
-            math::checked_mul(diff_usd as u128, 1_000_000u128)?,
-            collateral_price_scaled.price as u128
-        )?;
-        
-        let custody_decimals = if future.collateral_custody == sol_custody_key {
-            sol_custody.decimals
-        } else {
-            usdc_custody.decimals
-        };
-        
-        let remaining_tokens = if custody_decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                remaining_amount_6_decimals,
-                math::checked_pow(10u128, (custody_decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                remaining_amount_6_decimals,
-                math::checked_pow(10u128, (6 - custody_decimals) as usize)?
-            )?)?
-        };
+        let remaining_tokens = math::usd_to_token_amount(diff_usd, collateral_price, custody_decimals)?;
         
         remaining_tokens.min(collateral_amount_to_close)
     } else {
         0
     };
 
-    // Return remaining collateral if any
+    // Return remaining collateral if any, in the collateral asset
     if remaining_collateral > 0 {
-        let collateral_token_account = if future.collateral_custody == sol_custody_key {
-            &ctx.accounts.sol_custody_token_account
+        let (collateral_token_account, collateral_mint) = if sol_collateral {
+            (&ctx.accounts.sol_custody_token_account, sol_custody.mint)
         } else {
-            &ctx.accounts.usdc_custody_token_account
+            (&ctx.accounts.usdc_custody_token_account, usdc_custody.mint)
+        };
+        let collateral_receiving_account = if collateral_mint == payout_mint {
+            ctx.accounts.receiving_account.to_account_info()
+        } else {
+            let account = ctx.accounts.collateral_receiving_account.as_ref()
+                .ok_or(TradingError::ReceivingAccountMintMismatch)?;
+            require_keys_eq!(account.mint, collateral_mint, TradingError::ReceivingAccountMintMismatch);
+            account.to_account_info()
         };
 
         contract.transfer_tokens(
             collateral_token_account.to_account_info(),
-            ctx.accounts.receiving_account.to_account_info(),
+            collateral_receiving_account,
             ctx.accounts.transfer_authority.to_account_info(),
             ctx.accounts.token_program.to_account_info(),
             remaining_collateral,
         )?;
 
-        if future.collateral_custody == sol_custody_key {
+        if sol_collateral {
             sol_custody.token_owned = math::checked_sub(
                 sol_custody.token_owned,
                 remaining_collateral
@@ -356,6 +288,8 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
         future.collateral_usd = 0;
         future.collateral_amount = 0;
         future.locked_amount = 0;
+        future.lp_collateral_amount = 0;
+        future.lp_collateral_usd = 0;
     } else {
         // Update remaining position
         future.size_usd = math::checked_sub(future.size_usd, size_usd_to_close)?;
@@ -365,6 +299,7 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
     }
 
     future.update_time = current_time;
+    ctx.accounts.pool.remove_notional(size_usd_to_close);
 
     emit!(FutureClosed {
         owner,
@@ -376,17 +311,43 @@ pub fn close_future(ctx: Context<CloseFuture>, params: &CloseFutureParams) -> Re
         collateral_usd: future.collateral_usd,
         collateral_amount: future.collateral_amount,
         locked_amount: future.locked_amount,
-        native_exit_amount: native_exit_mount,
+        native_exit_amount: settlement_tokens,
         trade_fees: closing_fee,
         remaining_size_usd: future.size_usd,
         settlement_amount: settlement_usd,
         settlement_tokens,
+        lp_collateral_returned,
+        lp_collateral_burned,
         pnl: pnl_for_closed_portion,
         current_spot_price: current_sol_price_scaled,
         close_time: current_time,
     });
 
+    // A fully closed future is paid out already, so its account goes back to the owner
+    if is_full_close {
+        let future_index = ctx.accounts.future.index;
+        let future_info = ctx.accounts.future.to_account_info();
+        let future_rent = future_info.lamports();
+        **future_info.try_borrow_mut_lamports()? = 0;
+        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? = ctx.accounts.owner
+            .to_account_info()
+            .lamports()
+            .checked_add(future_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        future_info.try_borrow_mut_data()?.fill(0);
+
+        emit!(FutureAccountClosed {
+            owner,
+            future_key,
+            index: future_index,
+            rent_refunded: future_rent,
+        });
+    }
+
     msg!("Future position closed successfully");
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -482,10 +443,58 @@ pub struct CloseFuture<'info> {
     )]
     pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
+    // Receives leftover collateral when it differs from the settlement asset
+    #[account(
+        mut,
+        constraint = collateral_receiving_account.owner == owner.key()
+    )]
+    pub collateral_receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // LP collateral vault and the owner's LP account, required for LP-collateralised futures
+    #[account(
+        mut,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"lp_collateral_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = lp_receiving_account.owner == owner.key()
+    )]
+    pub lp_receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
     pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
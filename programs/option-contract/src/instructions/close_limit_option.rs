@@ -3,7 +3,7 @@ use crate::{
     events::LimitOptionClosed,
     math,
     utils::option_pricing::*,
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    state::{Contract, Custody, LockedProduct, OptionDetail, Pool, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::{
@@ -15,7 +15,7 @@ use anchor_spl::{
 pub struct CloseLimitOptionParams {
     pub option_index: u64,
     pub pool_name: String,
-    pub close_quantity: u64,  // Number of option contracts to close
+    pub close_quantity: u64,  // Option contracts to close, in the option's stored quantity units
 }
 
 pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOptionParams) -> Result<()> {
@@ -24,7 +24,7 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
     let closed_option_detail = &mut ctx.accounts.closed_option_detail;
     let contract = &ctx.accounts.contract;
     let user = &ctx.accounts.user;
-    let pool = &ctx.accounts.pool;
+    let pool = &mut ctx.accounts.pool;
     let custody = &ctx.accounts.custody;
     let transfer_authority = &ctx.accounts.transfer_authority;
 
@@ -47,16 +47,14 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
     // Only if option is valid and not exercised
     if option_detail.valid && option_detail.executed {
         // Get current time and check that option has not expired
-        let current_time: i64 = contract.get_time()?;
+        let current_time: i64 = pool.get_time(contract)?;
         if current_time >= option_detail.expired_date {
             return Err(OptionError::InvalidTimeError.into());
         }
 
         // Calculate proportional amounts for partial close
-        let unlock_amount = math::checked_div(
-            math::checked_mul(option_detail.amount, params.close_quantity)?,
-            option_detail.quantity
-        )?;
+        let unlock_amount = option_detail.get_locked_amount(params.close_quantity)?;
+        let closed_amount = option_detail.get_amount(params.close_quantity)?;
 
         // Validate locked custody has enough tokens
         require_gte!(
@@ -70,37 +68,30 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
         // Calculate remaining years with higher precision to minimize rounding
         let remaining_years = (remaining_seconds as f64) / (365.0 * 24.0 * 60.0 * 60.0);
 
-        // Oracle price of underlying asset (SOL)
-        let underlying_price = OraclePrice::new_from_oracle(
-            custody_oracle_account,
-            current_time,
-            false,
-        )?.get_price();
+        // Oracle price of underlying asset (option custody)
+        let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+        let locked_oracle_secondary = ctx.accounts.locked_oracle_secondary.as_ref().map(|a| a.to_account_info());
+        let underlying_price = custody.get_oracle_price(custody_oracle_account, custody_oracle_secondary.as_ref(), current_time)?.get_price();
 
         // Recalculate current option value using Black-Scholes for full position
         let bs_price_per_contract = black_scholes(
             underlying_price,
-            option_detail.strike_price as f64,
+            math::scaled_price_to_f64(option_detail.strike_price)?,
             remaining_years,
-            option_detail.option_type == 0, // 0 = call, 1 = put
+            option_detail.is_call(),
         );
 
         // Calculate proportional premium for close quantity
-        let bs_price_partial = bs_price_per_contract * params.close_quantity as f64;
+        let bs_price_partial = bs_price_per_contract * option_detail.contracts(params.close_quantity);
 
         // Get locked token oracle price for USD to locked token conversion
-        let locked_token_price = OraclePrice::new_from_oracle(
-            locked_oracle,
-            current_time,
-            false,
-        )?.get_price();
+        let locked_oracle_price = locked_custody.get_oracle_price(locked_oracle, locked_oracle_secondary.as_ref(), current_time)?;
+        let locked_token_price = locked_oracle_price.get_price();
 
-        // Convert USD option value to locked token amount using float math (like original code)
+        // Convert USD option value to locked tokens in integer math
         let token_decimals = locked_custody.decimals;
-        let refund_amount_raw = math::checked_as_u64(
-            math::checked_float_div(bs_price_partial, locked_token_price)?
-                * math::checked_powi(10.0, token_decimals as i32)?
-        )?;
+        let refund_amount_raw =
+            OptionDetail::get_locked_refund_amount(bs_price_partial, &locked_oracle_price, token_decimals)?;
 
         // Debug logging to see actual values
         msg!("Black-Scholes per contract price: {}", bs_price_per_contract);
@@ -112,19 +103,12 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
         msg!("Refund amount raw: {}", refund_amount_raw);
         msg!("Strike price: {}", option_detail.strike_price);
         msg!("Current underlying price: {}", underlying_price);
-        msg!("Option type (0=call, 1=put): {}", option_detail.option_type);
+        msg!("Option type: {:?}", option_detail.option_type);
         msg!("Remaining years: {}", remaining_years);
-        msg!("Original locked amount: {}", option_detail.amount);
+        msg!("Original locked amount: {}", option_detail.get_locked_amount(option_detail.quantity)?);
 
-        // Set minimum refund to prevent 0 amounts (at least 1 unit of the token)
-        let min_refund = 1u64;
-        let refund_amount_raw = if refund_amount_raw == 0 && bs_price_partial > 0.0 {
-            min_refund
-        } else {
-            refund_amount_raw
-        };
-
-        require_gt!(refund_amount_raw, 0, OptionError::InvalidPayAmountError);
+        // Refunds are truncated, never rounded up out of LP funds: an option worth less than
+        // one token unit closes for nothing and the transfer is skipped
 
         // Apply 10% platform fee (90% refund)
         let refund_amount = math::checked_div(math::checked_mul(refund_amount_raw, 9)?, 10)?;
@@ -138,16 +122,26 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
 
         // Update locked custody balances
         locked_custody.token_owned = math::checked_sub(locked_custody.token_owned, refund_amount)?;
-        locked_custody.token_locked = math::checked_sub(locked_custody.token_locked, unlock_amount)?;
+        locked_custody.remove_locked(LockedProduct::Option, unlock_amount)?;
 
         // Transfer refund to user (from locked asset pool)
-        contract.transfer_tokens(
-            locked_custody_token_account.to_account_info(),
-            funding_account.to_account_info(),
-            transfer_authority.to_account_info(),
-            token_program.to_account_info(),
-            refund_amount,
-        )?;
+        if refund_amount > 0 {
+            contract.transfer_tokens(
+                locked_custody_token_account.to_account_info(),
+                funding_account.to_account_info(),
+                transfer_authority.to_account_info(),
+                token_program.to_account_info(),
+                refund_amount,
+            )?;
+        }
+        let notional_usd = option_detail.get_notional_usd(params.close_quantity)?;
+        pool.remove_strike_expiry_notional(
+            option_detail.strike_price,
+            option_detail.expired_date,
+            option_detail.is_call(),
+            notional_usd,
+        );
+        pool.remove_notional(notional_usd);
 
         if option_detail.quantity == params.close_quantity {
             option_detail.valid = false;
@@ -164,7 +158,11 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
                     params.close_quantity
                 )?;
                 closed_option_detail.amount = math::checked_add(
-                    closed_option_detail.amount, 
+                    closed_option_detail.amount,
+                    closed_amount
+                )?;
+                closed_option_detail.locked_amount = math::checked_add(
+                    closed_option_detail.locked_amount,
                     unlock_amount
                 )?;
                 closed_option_detail.bought_back = current_time as u64; // Update to latest close time
@@ -174,14 +172,17 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
                 // Initialize new closed position (first partial close) - following open_option.rs pattern
                 closed_option_detail.valid = false; // Mark as closed position
                 closed_option_detail.quantity = params.close_quantity;
-                closed_option_detail.amount = unlock_amount;
+                closed_option_detail.amount = closed_amount;
+                closed_option_detail.locked_amount = unlock_amount;
                 closed_option_detail.owner = option_detail.owner;
                 closed_option_detail.index = option_detail.index;
                 closed_option_detail.period = option_detail.period;
                 closed_option_detail.expired_date = option_detail.expired_date;
                 closed_option_detail.purchase_date = option_detail.purchase_date;
                 closed_option_detail.option_type = option_detail.option_type;
+                closed_option_detail.exercise_style = option_detail.exercise_style;
                 closed_option_detail.strike_price = option_detail.strike_price;
+                closed_option_detail.quantity_decimals = option_detail.quantity_decimals;
                 closed_option_detail.premium_asset = option_detail.premium_asset;
                 closed_option_detail.locked_asset = option_detail.locked_asset;
                 closed_option_detail.pool = pool.key();
@@ -194,20 +195,76 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
             }
 
             // Update original position (reduce by closed amount)
-            option_detail.quantity = math::checked_sub(option_detail.quantity, params.close_quantity)?;
-            option_detail.amount = math::checked_sub(option_detail.amount, unlock_amount)?;
+            option_detail.reduce_quantity(params.close_quantity)?;
         }
     }
 
-    if !option_detail.executed {
-        let unlock_amount = math::checked_div(
-            math::checked_mul(option_detail.amount, params.close_quantity)?,
-            option_detail.quantity
-        )?;
-        option_detail.quantity = math::checked_sub(option_detail.quantity, params.close_quantity)?;
-        option_detail.amount = math::checked_sub(option_detail.amount, unlock_amount)?;
+    // A pending limit option never carried risk, so it refunds the reserved premium
+    // minus a flat cancellation fee instead of being re-priced like the executed path
+    if option_detail.valid && !option_detail.executed {
+        // Market options are never executed either, but they never reserved a premium to refund
+        require!(option_detail.limit_price > 0, OptionError::InvalidOption);
+        let current_time: i64 = pool.get_time(contract)?;
+        let pay_custody_token_account = ctx
+            .accounts
+            .pay_custody_token_account
+            .as_ref()
+            .ok_or(OptionError::PremiumRefundAccountMissing)?;
+        let premium_receiving_account = ctx
+            .accounts
+            .premium_receiving_account
+            .as_ref()
+            .ok_or(OptionError::PremiumRefundAccountMissing)?;
+
+        // Reserved premium for the closed quantity, less the flat fee
+        let (refund_amount, cancel_fee) = option_detail.get_limit_cancel_refund(params.close_quantity)?;
+
+        msg!("Reserved premium: {}", option_detail.get_amount(params.close_quantity)?);
+        msg!("Cancellation fee: {}", cancel_fee);
+        msg!("Refund amount: {}", refund_amount);
+
+        // Release the backing locked when the limit option was placed; limit options placed
+        // before locked_amount was tracked locked one pay token unit per contract
+        let lock_release = if option_detail.locked_amount > 0 {
+            option_detail.get_locked_amount(params.close_quantity)?
+        } else {
+            math::checked_as_u64(
+                option_detail.contracts(params.close_quantity)
+                    * math::checked_powi(10.0, pay_custody.decimals as i32)?
+            )?
+        };
+        locked_custody.unlock_funds(LockedProduct::Option, lock_release)?;
+
+        // Premium was added to the pay custody at open, so the refund comes out of it
+        pay_custody.token_owned = math::checked_sub(pay_custody.token_owned, refund_amount)?;
+        if refund_amount > 0 {
+            ctx.accounts.contract.transfer_tokens(
+                pay_custody_token_account.to_account_info(),
+                premium_receiving_account.to_account_info(),
+                transfer_authority.to_account_info(),
+                token_program.to_account_info(),
+                refund_amount,
+            )?;
+        }
+        let notional_usd = option_detail.get_notional_usd(params.close_quantity)?;
+        pool.remove_strike_expiry_notional(
+            option_detail.strike_price,
+            option_detail.expired_date,
+            option_detail.is_call(),
+            notional_usd,
+        );
+        pool.remove_notional(notional_usd);
+
+        option_detail.reduce_quantity(params.close_quantity)?;
+        if option_detail.quantity == 0 {
+            option_detail.valid = false;
+            option_detail.bought_back = current_time as u64;
+        }
     }
 
+    let option_key = option_detail.key();
+    pool.sync_option_hedge(option_detail, option_key, ctx.accounts.hedge_position.as_deref())?;
+
     emit!(LimitOptionClosed {
         owner: option_detail.owner,
         index: option_detail.index,
@@ -216,7 +273,7 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
         period: option_detail.period,
         expired_date: option_detail.expired_date,
         purchase_date: option_detail.purchase_date,
-        option_type: option_detail.option_type,
+        option_type: option_detail.option_type as u8,
         strike_price: option_detail.strike_price,
         valid: option_detail.valid,
         locked_asset: option_detail.locked_asset,
@@ -233,6 +290,8 @@ pub fn close_limit_option(ctx: Context<CloseLimitOption>, params: &CloseLimitOpt
         close_quantity: params.close_quantity,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.pay_custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -323,6 +382,24 @@ pub struct CloseLimitOption<'info> {
     )]
     pub locked_custody_token_account: Box<Account<'info, TokenAccount>>,
 
+    // Refund of a pending limit option comes from the premium custody
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 pay_custody_mint.key().as_ref()],
+        bump,
+    )]
+    pub pay_custody_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = premium_receiving_account.mint == pay_custody_mint.key()
+            @ TradingError::ReceivingAccountMintMismatch,
+        has_one = owner
+    )]
+    pub premium_receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
     #[account(
         mut,
         seeds = [b"option", owner.key().as_ref(),
@@ -359,4 +436,20 @@ pub struct CloseLimitOption<'info> {
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
-}
\ No newline at end of file
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = locked_oracle_secondary.key() == locked_custody.oracle_secondary
+    )]
+    pub locked_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
+}
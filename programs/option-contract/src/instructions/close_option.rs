@@ -1,9 +1,8 @@
 use crate::{
-    errors::{OptionError, PoolError, TradingError},
+    errors::{OptionError, TradingError},
     events::OptionClosed,
-    math::{self, scaled_price_to_f64},
-    utils::option_pricing::*,
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    math,
+    state::{Contract, Custody, OptionDetail, Pool, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::{
@@ -15,7 +14,7 @@ use anchor_spl::{
 pub struct CloseOptionParams {
     pub option_index: u64,
     pub pool_name: String,
-    pub close_quantity: u64,  // Number of option contracts to close
+    pub close_quantity: u64,  // Option contracts to close, in the option's stored quantity units
 }
 
 pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Result<()> {
@@ -24,7 +23,7 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
     let closed_option_detail = &mut ctx.accounts.closed_option_detail;
     let contract = &ctx.accounts.contract;
     let user = &ctx.accounts.user;
-    let pool = &ctx.accounts.pool;
+    let pool = &mut ctx.accounts.pool;
     let custody = &ctx.accounts.custody;
     let transfer_authority = &ctx.accounts.transfer_authority;
 
@@ -44,19 +43,19 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
     require_gt!(params.close_quantity, 0, OptionError::InvalidQuantityError);
     require_gte!(option_detail.quantity, params.close_quantity, OptionError::InsufficientQuantityError);
 
+    let mut close_fee_bps: u64 = 0;
+
     // Only if option is valid and not exercised
     if option_detail.valid {
         // Get current time and check that option has not expired
-        let current_time: i64 = contract.get_time()?;
+        let current_time: i64 = pool.get_time(contract)?;
         if current_time >= option_detail.expired_date {
             return Err(OptionError::InvalidTimeError.into());
         }
 
         // Calculate proportional amounts for partial close
-        let unlock_amount = math::checked_div(
-            math::checked_mul(option_detail.amount, params.close_quantity)?,
-            option_detail.quantity
-        )?;
+        let unlock_amount = option_detail.get_locked_amount(params.close_quantity)?;
+        let closed_amount = option_detail.get_amount(params.close_quantity)?;
 
         // Validate locked custody has enough tokens
         require_gte!(
@@ -65,95 +64,58 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
             TradingError::InvalidLockedBalanceError
         );
 
-        // Time decay logic for Black-Scholes
-        let remaining_seconds = option_detail.expired_date.saturating_sub(current_time);
-        let remaining_days = remaining_seconds as f64 / 86400.0;
-        let remaining_years = remaining_days / 365.0;
-
-        // Oracle price of underlying asset (SOL)
-        let underlying_price = OraclePrice::new_from_oracle(
-            custody_oracle_account,
-            current_time,
-            false,
-        )?.get_price();
-        
-        // Get utilization data for the option's underlying asset
-        let (token_locked, token_owned) = (locked_custody.token_locked, locked_custody.token_owned);
-        
-        // Calculate Premium using enhanced Black-Scholes with dynamic borrow rate
-        let bs_price_per_contract = black_scholes_with_borrow_rate(
-            underlying_price,
-            scaled_price_to_f64(option_detail.strike_price)?,
-            remaining_years,
-            option_detail.option_type == 0, // call/put logic
-            token_locked,  // Current utilization of underlying asset
-            token_owned,   // Total supply of underlying asset
-            option_detail.option_type == 0, // Asset type for rate calculation
-        )?;
-
-        // Calculate proportional premium for close quantity
-        let bs_price_partial = bs_price_per_contract * params.close_quantity as f64;
+        // Oracle price of underlying asset (option custody)
+        let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+        let locked_oracle_secondary = ctx.accounts.locked_oracle_secondary.as_ref().map(|a| a.to_account_info());
+        let underlying_price = custody.get_oracle_price(custody_oracle_account, custody_oracle_secondary.as_ref(), current_time)?.get_price();
 
         // Get locked token oracle price for USD to locked token conversion
-        let locked_token_price = OraclePrice::new_from_oracle(
-            locked_oracle,
+        let locked_oracle_price = locked_custody.get_oracle_price(locked_oracle, locked_oracle_secondary.as_ref(), current_time)?;
+
+        // Black-Scholes value with the dynamic borrow rate, less the underlying's
+        // time-to-expiry close fee. Refunds are truncated, never rounded up out of LP funds:
+        // an option worth less than one token unit closes for nothing and the transfer is skipped
+        let refund_amount;
+        (refund_amount, close_fee_bps) = option_detail.get_buyback_refund(
+            params.close_quantity,
             current_time,
-            false,
-        )?.get_price();
-
-        // Convert USD option value to locked token amount using float math (like original code)
-        let token_decimals = locked_custody.decimals;
-        let refund_amount_raw = math::checked_as_u64(
-            math::checked_float_div(bs_price_partial, locked_token_price)?
-                * math::checked_powi(10.0, token_decimals as i32)?
+            underlying_price,
+            custody,
+            locked_custody,
+            &locked_oracle_price,
         )?;
 
-        // Debug logging to see actual values
-        msg!("Black-Scholes per contract price: {}", bs_price_per_contract);
         msg!("Quantity partial price: {}", params.close_quantity);
         msg!("Quantity full price: {}", option_detail.quantity);
-        msg!("Black-Scholes partial price: {}", bs_price_partial);
-        msg!("Locked token price: {}", locked_token_price);
-        msg!("Token decimals: {}", token_decimals);
-        msg!("Refund amount raw: {}", refund_amount_raw);
+        msg!("Locked token price: {}", locked_oracle_price.get_price());
+        msg!("Refund amount: {}", refund_amount);
         msg!("Strike price: {}", option_detail.strike_price);
         msg!("Current underlying price: {}", underlying_price);
-        msg!("Option type (0=call, 1=put): {}", option_detail.option_type);
-        msg!("Remaining years: {}", remaining_years);
-        msg!("Original locked amount: {}", option_detail.amount);
-
-        // Set minimum refund to prevent 0 amounts (at least 1 unit of the token)
-        let min_refund = 1u64;
-        let refund_amount_raw = if refund_amount_raw == 0 && bs_price_partial > 0.0 {
-            min_refund
-        } else {
-            refund_amount_raw
-        };
-
-        require_gt!(refund_amount_raw, 0, OptionError::InvalidPayAmountError);
-
-        // Apply 10% platform fee (90% refund)
-        let refund_amount = math::checked_div(math::checked_mul(refund_amount_raw, 9)?, 10)?;
-
-        // Check locked custody has enough balance for refund
-        require_gte!(
-            math::checked_sub(locked_custody.token_owned, locked_custody.token_locked)?,
-            refund_amount,
-            PoolError::InvalidPoolBalanceError
-        );
+        msg!("Option type: {:?}", option_detail.option_type);
+        msg!("Original locked amount: {}", option_detail.get_locked_amount(option_detail.quantity)?);
+        msg!("Close fee: {} bps", close_fee_bps);
 
-        // Update locked custody balances
-        locked_custody.token_owned = math::checked_sub(locked_custody.token_owned, refund_amount)?;
-        locked_custody.token_locked = math::checked_sub(locked_custody.token_locked, unlock_amount)?;
+        // Release the option's lock and pay the refund out of the freed balance
+        locked_custody.release_option_refund(unlock_amount, refund_amount)?;
 
         // Transfer refund to user (from locked asset pool)
-        contract.transfer_tokens(
-            locked_custody_token_account.to_account_info(),
-            funding_account.to_account_info(),
-            transfer_authority.to_account_info(),
-            token_program.to_account_info(),
-            refund_amount,
-        )?;
+        if refund_amount > 0 {
+            contract.transfer_tokens(
+                locked_custody_token_account.to_account_info(),
+                funding_account.to_account_info(),
+                transfer_authority.to_account_info(),
+                token_program.to_account_info(),
+                refund_amount,
+            )?;
+        }
+        let notional_usd = option_detail.get_notional_usd(params.close_quantity)?;
+        pool.remove_strike_expiry_notional(
+            option_detail.strike_price,
+            option_detail.expired_date,
+            option_detail.is_call(),
+            notional_usd,
+        );
+        pool.remove_notional(notional_usd);
 
         if option_detail.quantity == params.close_quantity {
             option_detail.valid = false;
@@ -170,7 +132,11 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
                     params.close_quantity
                 )?;
                 closed_option_detail.amount = math::checked_add(
-                    closed_option_detail.amount, 
+                    closed_option_detail.amount,
+                    closed_amount
+                )?;
+                closed_option_detail.locked_amount = math::checked_add(
+                    closed_option_detail.locked_amount,
                     unlock_amount
                 )?;
                 closed_option_detail.bought_back = current_time as u64; // Update to latest close time
@@ -180,14 +146,17 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
                 // Initialize new closed position (first partial close) - following open_option.rs pattern
                 closed_option_detail.valid = false; // Mark as closed position
                 closed_option_detail.quantity = params.close_quantity;
-                closed_option_detail.amount = unlock_amount;
+                closed_option_detail.amount = closed_amount;
+                closed_option_detail.locked_amount = unlock_amount;
                 closed_option_detail.owner = option_detail.owner;
                 closed_option_detail.index = option_detail.index;
                 closed_option_detail.period = option_detail.period;
                 closed_option_detail.expired_date = option_detail.expired_date;
                 closed_option_detail.purchase_date = option_detail.purchase_date;
                 closed_option_detail.option_type = option_detail.option_type;
+                closed_option_detail.exercise_style = option_detail.exercise_style;
                 closed_option_detail.strike_price = option_detail.strike_price;
+                closed_option_detail.quantity_decimals = option_detail.quantity_decimals;
                 closed_option_detail.premium_asset = option_detail.premium_asset;
                 closed_option_detail.locked_asset = option_detail.locked_asset;
                 closed_option_detail.pool = pool.key();
@@ -200,11 +169,13 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
             }
             
             // Update original position (reduce by closed amount)
-            option_detail.quantity = math::checked_sub(option_detail.quantity, params.close_quantity)?;
-            option_detail.amount = math::checked_sub(option_detail.amount, unlock_amount)?;
+            option_detail.reduce_quantity(params.close_quantity)?;
         }
     }
 
+    let option_key = option_detail.key();
+    pool.sync_option_hedge(option_detail, option_key, ctx.accounts.hedge_position.as_deref())?;
+
     emit!(OptionClosed {
         owner: option_detail.owner,
         index: option_detail.index,
@@ -213,7 +184,7 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
         period: option_detail.period,
         expired_date: option_detail.expired_date,
         purchase_date: option_detail.purchase_date,
-        option_type: option_detail.option_type,
+        option_type: option_detail.option_type as u8,
         strike_price: option_detail.strike_price,
         valid: option_detail.valid,
         locked_asset: option_detail.locked_asset,
@@ -228,8 +199,11 @@ pub fn close_option(ctx: Context<CloseOption>, params: &CloseOptionParams) -> Re
         take_profit_price: option_detail.take_profit_price,
         stop_loss_price: option_detail.stop_loss_price,
         close_quantity: params.close_quantity,
+        refund_markdown_bps: close_fee_bps,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.pay_custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -356,4 +330,20 @@ pub struct CloseOption<'info> {
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = locked_oracle_secondary.key() == locked_custody.oracle_secondary
+    )]
+    pub locked_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -1,559 +1,610 @@
-use crate::{
-    errors::{PerpetualError, TradingError},
-    events::{PerpPositionClosed, PositionAccountClosed, TpSlOrderbookClosed},
-    math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, OraclePrice, Pool, Position, Side, OrderType, TpSlOrderbook},
-};
-use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct ClosePerpPositionParams {
-    pub position_index: u64,
-    pub pool_name: String,
-    pub contract_type: u8,
-    pub close_percentage: u64,
-    pub receive_sol: bool,          // true = receive SOL, false = receive USDC
-}
-
-pub fn close_perp_position(
-    ctx: Context<ClosePerpPosition>,
-    params: &ClosePerpPositionParams
-) -> Result<()> {
-    msg!("Closing {}% of perpetual position", params.close_percentage);
-    // Note: This instruction is used by both users and keepers for TP/SL execution
-    
-    let contract = &ctx.accounts.contract;
-    let pool = &mut ctx.accounts.pool;
-    let position = &mut ctx.accounts.position;
-    let sol_custody = &mut ctx.accounts.sol_custody;
-    let usdc_custody = &mut ctx.accounts.usdc_custody;
-    
-    // Validation
-    require_keys_eq!(position.owner, ctx.accounts.owner.key(), TradingError::Unauthorized);
-    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
-    require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
-    require!(
-        params.close_percentage > 0 && params.close_percentage <= 100_000_000,
-        TradingError::InvalidAmount
-    );
-
-    let is_full_close = params.close_percentage == 100_000_000;
-    
-    // Get current prices from oracles
-    let current_time = contract.get_time()?;
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
-    
-    let current_sol_price = sol_price.get_price();
-    let usdc_price_value = usdc_price.get_price();
-    
-    msg!("SOL Price: {}", current_sol_price);
-    msg!("USDC Price: {}", usdc_price_value);
-    msg!("Closing at SOL price: ${}", current_sol_price);
-    msg!("User chose to receive: {}", if params.receive_sol { "SOL" } else { "USDC" });
-    msg!("Position side {:?}",  position.side);
-    
-    // Slippage protection
-    let current_price_scaled = f64_to_scaled_price(current_sol_price)?;
-    
-    // Calculate P&L
-    let pnl = position.calculate_pnl(current_price_scaled)?;
-    
-    // Update accrued borrow fees before closing position
-    let interest_payment: u64 = pool.update_position_borrow_fees(
-        position, 
-        current_time, 
-        sol_custody, 
-        usdc_custody
-    )?;
-    
-    // Calculate amounts to close (proportional to percentage) - using integer math
-    let size_usd_to_close = if is_full_close {
-        position.size_usd
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.size_usd as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-    
-    let collateral_amount_to_close = if is_full_close {
-        position.collateral_amount
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.collateral_amount as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-    
-    let collateral_usd_to_close = if is_full_close {
-        position.collateral_usd
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.collateral_usd as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-    
-    // Calculate P&L, funding, and interest for the portion being closed
-    let pnl_for_closed_portion = if is_full_close {
-        pnl
-    } else {
-        // Use integer math for PnL calculation
-        if pnl >= 0 {
-            math::checked_as_i64(math::checked_div(
-                math::checked_mul(pnl as u128, params.close_percentage as u128)?,
-                100_000_000u128
-            )?)?
-        } else {
-            -math::checked_as_i64(math::checked_div(
-                math::checked_mul((-pnl) as u128, params.close_percentage as u128)?,
-                100_000_000u128
-            )?)?
-        }
-    };
-    
-    let interest_for_closed_portion = if is_full_close {
-        interest_payment
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(interest_payment as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    let trade_fees_for_closed_portion = if is_full_close {
-        position.trade_fees
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.trade_fees as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    }; 
-    
-    msg!("Size USD to close: {}", size_usd_to_close);
-    msg!("Collateral amount to close: {}", collateral_amount_to_close);
-    msg!("P&L for closed portion: {}", pnl_for_closed_portion);
-    msg!("Interest for closed portion: {}", interest_for_closed_portion);
-    
-    let mut net_settlement = collateral_usd_to_close as i64 + pnl_for_closed_portion - interest_for_closed_portion as i64 - trade_fees_for_closed_portion as i64;
-    
-    // Ensure settlement is not negative
-    if net_settlement < 0 {
-        net_settlement = 0;
-    }
-    
-    let settlement_usd = net_settlement as u64;
-    
-    // Calculate settlement amount in requested asset using integer math
-    let settlement_tokens = if params.receive_sol {
-        // Scale SOL price to 6 decimals for consistent math
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        
-        // USD amount / SOL price = SOL amount (both with 6 decimals)
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        // Scale from 6 decimals to SOL token decimals
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
-    } else {
-        // Scale USDC price to 6 decimals
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        
-        // USD amount / USDC price = USDC amount
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        // Scale from 6 decimals to USDC token decimals
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
-    };
-
-    let native_exit_tokens = if position.side == Side::Long {
-        // Long positions exit in SOL
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
-    } else {
-        // Short positions exit in USDC
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
-    };
-    
-    msg!("Settlement USD: {}", settlement_usd);
-    msg!("Settlement tokens: {}", settlement_tokens);
-    
-    // Transfer settlement to user
-    if settlement_tokens > 0 {
-        ctx.accounts.contract.transfer_tokens(
-            if params.receive_sol {
-                ctx.accounts.sol_custody_token_account.to_account_info()
-            } else {
-                ctx.accounts.usdc_custody_token_account.to_account_info()
-            },
-            ctx.accounts.receiving_account.to_account_info(),
-            ctx.accounts.transfer_authority.to_account_info(),
-            ctx.accounts.token_program.to_account_info(),
-            settlement_tokens,
-        )?;
-    }
-    
-    // Update custody stats
-    let locked_amount_to_release = if is_full_close {
-        position.locked_amount
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.locked_amount as u128, params.close_percentage as u128)?,
-            100_000_000u128
-        )?)?
-    };
-    
-    if position.side == Side::Long {
-        sol_custody.token_locked = math::checked_sub(
-            sol_custody.token_locked,
-            locked_amount_to_release
-        )?;
-    } else {
-        usdc_custody.token_locked = math::checked_sub(
-            usdc_custody.token_locked,
-            locked_amount_to_release
-        )?;
-    }
-    
-    // Update custody ownership
-    if position.collateral_custody == sol_custody.key() {
-        sol_custody.token_owned = math::checked_sub(
-            sol_custody.token_owned,
-            collateral_amount_to_close
-        )?;
-    } else {
-        usdc_custody.token_owned = math::checked_sub(
-            usdc_custody.token_owned,
-            collateral_amount_to_close
-        )?;
-    }
-    
-    // Update pool open interest
-    if position.side == Side::Long {
-        pool.long_open_interest_usd = math::checked_sub(pool.long_open_interest_usd, size_usd_to_close as u128)?;
-    } else {
-        pool.short_open_interest_usd = math::checked_sub(pool.short_open_interest_usd, size_usd_to_close as u128)?;
-    }
-    
-    // Store values before modifying position for event emission
-    let position_owner = position.owner;
-    let position_key = position.key();
-    let position_pool = position.pool;
-    
-    // Update or close position
-    if is_full_close {
-        msg!("Position fully closed - automatically closing TP/SL orderbook and position accounts");
-        
-        position.is_liquidated = true; // Mark as closed
-        position.size_usd = 0;
-        position.collateral_amount = 0;
-        position.collateral_usd = 0;
-        position.locked_amount = 0;
-        position.trade_fees = 0;
-        
-        // Clear all remaining TP/SL orders in orderbook if it exists
-        if let Some(orderbook_info) = ctx.accounts.tp_sl_orderbook.as_ref() {
-            // Validate the orderbook account if provided
-            let position_index_bytes = params.position_index.to_le_bytes();
-            let contract_type_bytes = params.contract_type.to_le_bytes();
-            let expected_seeds = [
-                b"tp_sl_orderbook",
-                position_owner.as_ref(),
-                position_index_bytes.as_ref(),
-                params.pool_name.as_bytes(),
-                contract_type_bytes.as_ref(),
-            ];
-            let (expected_key, _) = Pubkey::find_program_address(&expected_seeds, ctx.program_id);
-            require_keys_eq!(orderbook_info.key(), expected_key, TradingError::Unauthorized);
-            
-            // Check if account is initialized (has data and correct discriminator)
-            let orderbook_data = orderbook_info.try_borrow_data()?;
-            if orderbook_data.len() >= 8 {
-                // Try to deserialize - if it fails, the account is not properly initialized
-                if let Ok(_orderbook) = TpSlOrderbook::try_deserialize(&mut orderbook_data.as_ref()) {
-                    drop(orderbook_data); // Release the borrow
-                    
-                    // Account is valid, clear orders
-                    let mut orderbook_data = orderbook_info.try_borrow_mut_data()?;
-                    let mut orderbook = TpSlOrderbook::try_deserialize(&mut orderbook_data.as_ref())?;
-                    orderbook.clear_all_orders()?;
-                    
-                    // Serialize back
-                    orderbook.try_serialize(&mut orderbook_data.as_mut())?;
-                }
-            }
-        }
-        
-        msg!("Position fully closed - will automatically close TP/SL orderbook and position accounts");
-        
-    } else {
-        // Update position for partial close
-        position.size_usd = math::checked_sub(position.size_usd, size_usd_to_close)?;
-        position.collateral_amount = math::checked_sub(position.collateral_amount, collateral_amount_to_close)?;
-        position.collateral_usd = math::checked_sub(position.collateral_usd, collateral_usd_to_close)?;
-        position.locked_amount = math::checked_sub(position.locked_amount, locked_amount_to_release)?;
-        position.trade_fees = math::checked_sub(position.trade_fees, trade_fees_for_closed_portion)?;
-    }
-    
-    // Update fee tracking
-    position.borrow_fees_paid = math::checked_add(position.borrow_fees_paid, interest_for_closed_portion.try_into().unwrap())?;
-    position.accrued_borrow_fees = math::checked_sub(position.accrued_borrow_fees, interest_for_closed_portion.try_into().unwrap())?;
-    
-    position.update_time = current_time;
-    
-    emit!(PerpPositionClosed {
-        pub_key: position.key(),
-        index: position.index,
-        owner: position.owner,
-        pool: position.pool,
-        custody: position.custody,
-        collateral_custody: position.collateral_custody,
-        order_type: position.order_type as u8,
-        side: position.side as u8,
-        is_liquidated: position.is_liquidated,
-        price: current_price_scaled,
-        size_usd: position.size_usd,
-        collateral_usd: position.collateral_usd,
-        open_time: position.open_time,
-        update_time: position.update_time,
-        liquidation_price: position.liquidation_price,
-        cumulative_interest_snapshot: position.cumulative_interest_snapshot,
-        trade_fees: position.trade_fees,
-        trade_fees_paid: trade_fees_for_closed_portion,
-        borrow_fees_paid: interest_for_closed_portion.try_into().unwrap(),
-        accrued_borrow_fees: position.accrued_borrow_fees,
-        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
-        locked_amount: position.locked_amount,
-        collateral_amount: position.collateral_amount,
-        native_exit_amount: native_exit_tokens,
-        trigger_price: position.trigger_price,
-        trigger_above_threshold: position.trigger_above_threshold,
-        bump: position.bump,
-        close_percentage: params.close_percentage as u64,
-        settlement_tokens: settlement_tokens,
-        realized_pnl: pnl_for_closed_portion,
-    });
-    
-    // Automatically close accounts if fully closed
-    if is_full_close {
-        // Close TP/SL orderbook first if it exists and is initialized
-        if let Some(orderbook_info) = ctx.accounts.tp_sl_orderbook.as_ref() {
-            // Only close if the account has data (is initialized)
-            let orderbook_data = orderbook_info.try_borrow_data()?;
-            if orderbook_data.len() >= 8 {
-                let orderbook_rent = orderbook_info.lamports();
-                drop(orderbook_data); // Release the borrow
-                
-                **orderbook_info.try_borrow_mut_lamports()? = 0;
-                **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? = ctx.accounts.owner
-                    .to_account_info()
-                    .lamports()
-                    .checked_add(orderbook_rent)
-                    .ok_or(ProgramError::ArithmeticOverflow)?;
-                    
-                // Clear orderbook data
-                {
-                    let mut orderbook_data = orderbook_info.try_borrow_mut_data()?;
-                    orderbook_data.fill(0);
-                }
-                
-                emit!(TpSlOrderbookClosed {
-                    owner: position_owner,
-                    position: position_key,
-                    contract_type: params.contract_type,
-                    rent_refunded: orderbook_rent,
-                });
-            }
-        }
-        
-        // Close position account
-        let position_rent = ctx.accounts.position.to_account_info().lamports();
-        **ctx.accounts.position.to_account_info().try_borrow_mut_lamports()? = 0;
-        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? = ctx.accounts.owner
-            .to_account_info()
-            .lamports()
-            .checked_add(position_rent)
-            .ok_or(ProgramError::ArithmeticOverflow)?;
-            
-        // Clear position account data
-        {
-            let position_info = ctx.accounts.position.to_account_info();
-            let mut position_data = position_info.try_borrow_mut_data()?;
-            position_data.fill(0);
-        }
-        
-        emit!(PositionAccountClosed {
-            owner: position_owner,
-            position_key,
-            position_index: params.position_index,
-            pool: position_pool,
-            rent_refunded: position_rent,
-        });
-        
-        msg!("TP/SL orderbook and position accounts automatically closed - all rent returned to user");
-    }
-    
-    Ok(())
-}
-
-#[derive(Accounts)]
-#[instruction(params: ClosePerpPositionParams)]
-pub struct ClosePerpPosition<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
-    #[account(
-        mut,
-        has_one = owner,
-    )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Transfer authority PDA for contract token operations
-    #[account(
-        seeds = [b"transfer_authority"],
-        bump = contract.transfer_authority_bump
-    )]
-    pub transfer_authority: AccountInfo<'info>,
-
-    #[account(
-        seeds = [b"contract"],
-        bump = contract.bump
-    )]
-    pub contract: Box<Account<'info, Contract>>,
-
-    #[account(
-        mut,
-        seeds = [b"pool", params.pool_name.as_bytes()],
-        bump = pool.bump
-    )]
-    pub pool: Box<Account<'info, Pool>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"position",
-            owner.key().as_ref(),
-            params.position_index.to_le_bytes().as_ref(),
-            pool.key().as_ref()
-        ],
-        bump = position.bump
-    )]
-    pub position: Box<Account<'info, Position>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
-        bump = sol_custody.bump
-    )]
-    pub sol_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
-        bump = usdc_custody.bump
-    )]
-    pub usdc_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            sol_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            usdc_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = sol_oracle_account.key() == sol_custody.oracle
-    )]
-    pub sol_oracle_account: AccountInfo<'info>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = usdc_oracle_account.key() == usdc_custody.oracle
-    )]
-    pub usdc_oracle_account: AccountInfo<'info>,
-
-    #[account(mut)]
-    pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-
-    /// CHECK: Optional TP/SL orderbook account - may not exist if user never set TP/SL
-    #[account(mut)]
-    pub tp_sl_orderbook: Option<AccountInfo<'info>>,
-
-    pub token_program: Program<'info, Token>,
+use crate::{
+    errors::{ContractError, PerpetualError, PoolError, TradingError},
+    events::{LiquidityAdded, PerpPositionClosed, PositionAccountClosed, TpSlOrderbookClosed},
+    math::{self, f64_to_scaled_price},
+    state::{Contract, Custody, LpDepositQuote, Pool, Position, Referral, Side, TpSlOrderbook, User},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClosePerpPositionParams {
+    pub position_index: u64,
+    pub pool_name: String,
+    pub contract_type: u8,
+    pub close_percentage: u64,
+    pub receive_sol: Option<bool>,  // true = receive SOL, false = receive USDC, None = user default
+    pub receive_as_lp: bool,        // deposit the settlement into the receive_sol custody for LP tokens
+}
+
+pub fn close_perp_position<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ClosePerpPosition<'info>>,
+    params: &ClosePerpPositionParams
+) -> Result<()> {
+    msg!("Closing {}% of perpetual position", params.close_percentage);
+    // Note: This instruction is used by both users and keepers for TP/SL execution
+    
+    let receive_sol = params.receive_sol.unwrap_or(
+        ctx.accounts.user.as_ref().is_some_and(|user| user.default_receive_sol),
+    );
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let position = &mut ctx.accounts.position;
+    let sol_custody = &mut ctx.accounts.sol_custody;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    
+    // Validation
+    require_keys_eq!(position.owner, ctx.accounts.owner.key(), TradingError::Unauthorized);
+    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(position.is_executed(), PerpetualError::InvalidOrderType);
+    require!(
+        params.close_percentage > 0 && params.close_percentage <= Position::FULL_CLOSE_PERCENTAGE,
+        TradingError::InvalidAmount
+    );
+
+    let is_full_close = params.close_percentage == Position::FULL_CLOSE_PERCENTAGE;
+    
+    // LP collateral is returned in one piece
+    require!(
+        position.lp_collateral_amount == 0 || is_full_close,
+        PerpetualError::LpCollateralUnsupported
+    );
+    
+    // Get current prices from oracles
+    let current_time = pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+    
+    let current_sol_price = sol_price.get_price();
+    let usdc_price_value = usdc_price.get_price();
+    
+    msg!("SOL Price: {}", current_sol_price);
+    msg!("USDC Price: {}", usdc_price_value);
+    msg!("Closing at SOL price: ${}", current_sol_price);
+    msg!("User chose to receive: {}", if receive_sol { "SOL" } else { "USDC" });
+    msg!("Position side {:?}",  position.side);
+
+    // Receiving account must hold the asset being paid out
+    let payout_mint = if receive_sol { sol_custody.mint } else { usdc_custody.mint };
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        payout_mint,
+        TradingError::ReceivingAccountMintMismatch
+    );
+    
+    // Slippage protection
+    let current_price_scaled = f64_to_scaled_price(current_sol_price)?;
+    
+    let settlement = pool.compute_close_settlement(
+        position,
+        params.close_percentage,
+        current_price_scaled,
+        current_time,
+        sol_custody,
+        usdc_custody,
+    )?;
+    let settlement_usd = settlement.settlement_usd;
+    // Keeper rewards are only ever paid out of borrow fees the pool collected
+    pool.fund_keeper_rewards(contract, settlement.get_collected_borrow_fees())?;
+    let referral_fee_usd = Referral::accrue_perp_close(
+        ctx.accounts.referral.as_deref_mut().map(|r| &mut **r),
+        position,
+        &settlement,
+        pool,
+        contract,
+    )?;
+    
+    msg!("Size USD to close: {}", settlement.size_usd);
+    msg!("Collateral amount to close: {}", settlement.collateral_amount);
+    msg!("P&L for closed portion: {}", settlement.realized_pnl);
+    msg!("Interest for closed portion: {}", settlement.borrow_fees);
+    
+    // LP collateral goes back in kind: a profit is paid in the underlying, a loss is
+    // covered by burning LP tokens worth the shortfall at the current AUM
+    let lp_supply = ctx.accounts.lp_token_mint.as_ref().map(|mint| mint.supply);
+    let (payout_usd, lp_collateral_returned, lp_collateral_burned) =
+        pool.get_lp_collateral_settlement(
+            position.lp_collateral_amount,
+            position.lp_collateral_usd,
+            settlement_usd,
+            lp_supply,
+        )?;
+    
+    // Paying out of a custody already short of its target ratio costs a haircut that stays with LPs.
+    // A settlement taken as LP tokens pays it as well as the add liquidity fee.
+    let (settlement_tokens, settlement_haircut) = if receive_sol {
+        let token_id = pool.get_token_id(&sol_custody.key())?;
+        pool.get_settlement_payout(token_id, payout_usd, sol_custody, &sol_price)?
+    } else {
+        let token_id = pool.get_token_id(&usdc_custody.key())?;
+        pool.get_settlement_payout(token_id, payout_usd, usdc_custody, &usdc_price)?
+    };
+
+    let native_exit_tokens = if position.side == Side::Long {
+        // Long positions exit in SOL
+        math::usd_to_token_amount(payout_usd, &sol_price, sol_custody.decimals)?
+    } else {
+        // Short positions exit in USDC
+        math::usd_to_token_amount(payout_usd, &usdc_price, usdc_custody.decimals)?
+    };
+    
+    msg!("Settlement USD: {}", settlement_usd);
+    msg!("Settlement tokens: {}", settlement_tokens);
+    msg!("Settlement haircut: {}", settlement_haircut);
+    
+    // Release the locked backing first so the payout can draw on it, and fail clearly when
+    // the chosen asset can't cover the payout; the other asset may. A settlement taken as LP
+    // tokens stays in the custody and is deposited below.
+    Pool::release_perp_payout(
+        position.side,
+        settlement.locked_amount,
+        receive_sol,
+        if params.receive_as_lp { 0 } else { settlement_tokens },
+        sol_custody,
+        usdc_custody,
+    )?;
+
+    if !params.receive_as_lp {
+        // Transfer settlement to user
+        if settlement_tokens > 0 {
+            ctx.accounts.contract.transfer_tokens(
+                if receive_sol {
+                    ctx.accounts.sol_custody_token_account.to_account_info()
+                } else {
+                    ctx.accounts.usdc_custody_token_account.to_account_info()
+                },
+                ctx.accounts.receiving_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                settlement_tokens,
+            )?;
+        }
+    }
+    
+    // Return the LP collateral and burn the part that covered a loss
+    if position.lp_collateral_amount > 0 {
+        let (Some(lp_token_mint), Some(lp_collateral_account), Some(lp_receiving_account)) = (
+            ctx.accounts.lp_token_mint.as_ref(),
+            ctx.accounts.lp_collateral_account.as_ref(),
+            ctx.accounts.lp_receiving_account.as_ref(),
+        ) else {
+            return err!(PerpetualError::LpCollateralAccountsMissing);
+        };
+        require_keys_eq!(lp_collateral_account.mint, lp_token_mint.key(), TradingError::InvalidMintError);
+        
+        if lp_collateral_returned > 0 {
+            ctx.accounts.contract.transfer_tokens(
+                lp_collateral_account.to_account_info(),
+                lp_receiving_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                lp_collateral_returned,
+            )?;
+        }
+        if lp_collateral_burned > 0 {
+            ctx.accounts.contract.burn_owned_tokens(
+                lp_token_mint.to_account_info(),
+                lp_collateral_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                lp_collateral_burned,
+            )?;
+        }
+        msg!("LP collateral returned: {}, burned: {}", lp_collateral_returned, lp_collateral_burned);
+    }
+    
+    // Update custody ownership
+    if position.collateral_custody == sol_custody.key() {
+        sol_custody.token_owned = math::checked_sub(
+            sol_custody.token_owned,
+            settlement.collateral_amount
+        )?;
+    } else {
+        usdc_custody.token_owned = math::checked_sub(
+            usdc_custody.token_owned,
+            settlement.collateral_amount
+        )?;
+    }
+    
+    // Deposit the settlement as liquidity, priced the way add_liquidity prices a deposit
+    let mut lp_amount_minted = 0;
+    if params.receive_as_lp && settlement_tokens > 0 {
+        let (Some(lp_token_mint), Some(lp_receiving_account)) = (
+            ctx.accounts.lp_token_mint.as_ref(),
+            ctx.accounts.lp_receiving_account.as_ref(),
+        ) else {
+            return err!(PerpetualError::LpCollateralAccountsMissing);
+        };
+        require_keys_eq!(lp_receiving_account.mint, lp_token_mint.key(), TradingError::InvalidMintError);
+        
+        // Persist the close so a full AUM recompute sees it
+        sol_custody.exit(&crate::ID)?;
+        usdc_custody.exit(&crate::ID)?;
+        let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+        let incremental = pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, current_time)?;
+        
+        let (deposit_custody, deposit_price) = if receive_sol {
+            (sol_custody.as_mut(), &sol_price)
+        } else {
+            (usdc_custody.as_mut(), &usdc_price)
+        };
+        require!(!deposit_custody.trading_paused, PoolError::CustodyTradingPaused);
+        let token_id = pool.get_token_id(&deposit_custody.key())?;
+        
+        let lp_supply = lp_token_mint.supply;
+        let lp_share_price_usd = pool.get_lp_share_price_usd(lp_supply)?;
+        let LpDepositQuote { fee_amount, deposit_amount, token_amount_usd, lp_amount, bonus_lp_amount } =
+            pool.get_lp_deposit_quote(token_id, settlement_tokens, deposit_custody, deposit_price, lp_supply)?;
+        require_gt!(lp_amount, 0, ContractError::InsufficientAmountReturned);
+        
+        if bonus_lp_amount > 0 {
+            pool.rebalance_incentive_budget =
+                math::checked_sub(pool.rebalance_incentive_budget, bonus_lp_amount)?;
+        }
+        lp_amount_minted = math::checked_add(lp_amount, bonus_lp_amount)?;
+        
+        ctx.accounts.contract.mint_tokens(
+            lp_token_mint.to_account_info(),
+            lp_receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            lp_amount_minted,
+        )?;
+        deposit_custody.token_owned = math::checked_add(deposit_custody.token_owned, deposit_amount)?;
+        // The fee stays in the token account outside token_owned; with epochs on it goes to LPs
+        if let Some(epoch) = pool.get_lp_fee_epoch(current_time) {
+            deposit_custody.accrue_lp_fee(fee_amount, epoch, lp_supply)?;
+        }
+        
+        deposit_custody.exit(&crate::ID)?;
+        if incremental {
+            pool.aum_usd = math::checked_add(pool.aum_usd, token_amount_usd as u128)?;
+        } else {
+            pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, failover_oracles, current_time)?;
+        }
+        msg!("Settlement deposited for {} LP tokens", lp_amount_minted);
+        
+        emit!(LiquidityAdded {
+            owner: ctx.accounts.owner.key(),
+            pool: pool.key(),
+            custody: deposit_custody.key(),
+            amount_in: settlement_tokens,
+            deposit_amount,
+            lp_amount,
+            bonus_lp_amount,
+            fee_amount,
+            token_amount_usd,
+            pool_aum_usd: pool.aum_usd,
+            token_price: deposit_price.price,
+            token_price_exponent: deposit_price.exponent,
+            lp_share_price_usd,
+        });
+    }
+    
+    // Update pool open interest
+    pool.update_open_interest(position, settlement.size_usd, false, current_time)?;
+    ctx.accounts.pool.remove_notional(settlement.size_usd);
+    
+    // Store values before modifying position for event emission
+    let borrow_size_usd = settlement.size_usd.saturating_sub(settlement.collateral_usd);
+    let position_owner = position.owner;
+    let position_key = position.key();
+    let position_pool = position.pool;
+    
+    // Update or close position
+    position.apply_close(&settlement, current_time)?;
+    if is_full_close {
+        msg!("Position fully closed - automatically closing TP/SL orderbook and position accounts");
+        
+        // An orderbook in an unexpected state must never trap the position: the owner can
+        // leave it out, and it can be reclaimed with close_tp_sl_orderbook once the position is gone
+        if ctx.accounts.tp_sl_orderbook.is_none() {
+            msg!("TP/SL orderbook not passed, left untouched");
+        }
+        
+        // Clear all remaining TP/SL orders in orderbook if it exists
+        if let Some(orderbook_info) = ctx.accounts.tp_sl_orderbook.as_ref() {
+            // Validate the orderbook account if provided
+            let position_index_bytes = params.position_index.to_le_bytes();
+            let contract_type_bytes = params.contract_type.to_le_bytes();
+            let expected_seeds = [
+                b"tp_sl_orderbook",
+                position_owner.as_ref(),
+                position_index_bytes.as_ref(),
+                params.pool_name.as_bytes(),
+                contract_type_bytes.as_ref(),
+            ];
+            let (expected_key, _) = Pubkey::find_program_address(&expected_seeds, ctx.program_id);
+            require_keys_eq!(orderbook_info.key(), expected_key, TradingError::Unauthorized);
+            
+            // Check if account is initialized (has data and correct discriminator)
+            let orderbook_data = orderbook_info.try_borrow_data()?;
+            if orderbook_data.len() >= 8 {
+                // Try to deserialize - if it fails, the account is not properly initialized
+                if let Ok(_orderbook) = TpSlOrderbook::try_deserialize(&mut orderbook_data.as_ref()) {
+                    drop(orderbook_data); // Release the borrow
+                    
+                    // Account is valid, clear orders
+                    let mut orderbook_data = orderbook_info.try_borrow_mut_data()?;
+                    let mut orderbook = TpSlOrderbook::try_deserialize(&mut orderbook_data.as_ref())?;
+                    orderbook.clear_all_orders()?;
+                    
+                    // Serialize back
+                    orderbook.try_serialize(&mut orderbook_data.as_mut())?;
+                }
+            }
+        }
+        
+        msg!("Position fully closed - will automatically close TP/SL orderbook and position accounts");
+    }
+    
+    emit!(PerpPositionClosed {
+        pub_key: position.key(),
+        index: position.index,
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        order_type: position.order_type as u8,
+        side: position.side as u8,
+        is_liquidated: position.is_liquidated,
+        price: current_price_scaled,
+        size_usd: position.size_usd,
+        collateral_usd: position.collateral_usd,
+        open_time: position.open_time,
+        update_time: position.update_time,
+        liquidation_price: position.liquidation_price,
+        cumulative_interest_snapshot: position.cumulative_interest_snapshot,
+        trade_fees: position.trade_fees,
+        trade_fees_paid: settlement.close_fee_usd,
+        borrow_fees_paid: settlement.borrow_fees,
+        accrued_borrow_fees: position.accrued_borrow_fees,
+        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
+        locked_amount: position.locked_amount,
+        collateral_amount: position.collateral_amount,
+        native_exit_amount: native_exit_tokens,
+        trigger_price: position.trigger_price,
+        trigger_above_threshold: position.trigger_above_threshold,
+        bump: position.bump,
+        close_percentage: params.close_percentage,
+        settlement_tokens,
+        realized_pnl: settlement.realized_pnl,
+        lp_collateral_returned,
+        lp_collateral_burned,
+        borrow_size_usd,
+        settlement_haircut,
+        hedge_fee_discount_usd: position.hedge_fee_discount_usd,
+        lp_amount_minted,
+        referral_fee_usd,
+    });
+    
+    // Automatically close accounts if fully closed
+    if is_full_close {
+        // Close TP/SL orderbook first if it exists and is initialized
+        if let Some(orderbook_info) = ctx.accounts.tp_sl_orderbook.as_ref() {
+            // Only close if the account has data (is initialized)
+            let orderbook_data = orderbook_info.try_borrow_data()?;
+            if orderbook_data.len() >= 8 {
+                let orderbook_rent = orderbook_info.lamports();
+                drop(orderbook_data); // Release the borrow
+                
+                **orderbook_info.try_borrow_mut_lamports()? = 0;
+                **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? = ctx.accounts.owner
+                    .to_account_info()
+                    .lamports()
+                    .checked_add(orderbook_rent)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+                    
+                // Clear orderbook data
+                {
+                    let mut orderbook_data = orderbook_info.try_borrow_mut_data()?;
+                    orderbook_data.fill(0);
+                }
+                
+                emit!(TpSlOrderbookClosed {
+                    owner: position_owner,
+                    position: position_key,
+                    contract_type: params.contract_type,
+                    rent_refunded: orderbook_rent,
+                });
+            }
+        }
+        
+        // Close position account
+        let position_rent = ctx.accounts.position.to_account_info().lamports();
+        **ctx.accounts.position.to_account_info().try_borrow_mut_lamports()? = 0;
+        **ctx.accounts.owner.to_account_info().try_borrow_mut_lamports()? = ctx.accounts.owner
+            .to_account_info()
+            .lamports()
+            .checked_add(position_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+            
+        // Clear position account data
+        {
+            let position_info = ctx.accounts.position.to_account_info();
+            let mut position_data = position_info.try_borrow_mut_data()?;
+            position_data.fill(0);
+        }
+        
+        emit!(PositionAccountClosed {
+            owner: position_owner,
+            position_key,
+            position_index: params.position_index,
+            pool: position_pool,
+            rent_refunded: position_rent,
+        });
+        
+        msg!("TP/SL orderbook and position accounts automatically closed - all rent returned to user");
+    }
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ClosePerpPositionParams)]
+pub struct ClosePerpPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"user_v3", owner.key().as_ref()],
+        bump,
+    )]
+    pub user: Option<Box<Account<'info, User>>>, // settlement preference when receive_sol is unset
+
+    #[account(
+        mut,
+        has_one = owner,
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Optional TP/SL orderbook account - may not exist if user never set TP/SL, and the
+    /// owner can leave it out to close without touching an orderbook in an unexpected state
+    #[account(mut)]
+    pub tp_sl_orderbook: Option<AccountInfo<'info>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // LP collateral vault and the owner's LP account, required for LP-collateralised positions
+    #[account(
+        mut,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"lp_collateral_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = lp_receiving_account.owner == owner.key()
+    )]
+    pub lp_receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+    // remaining accounts (optional, with receive_as_lp once pool.aum_usd is stale):
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    // Referrer's registration, required when position.referrer is set
+    #[account(
+        mut,
+        seeds = [b"referral", position.referrer.unwrap_or_default().as_ref(), pool.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
 }
\ No newline at end of file
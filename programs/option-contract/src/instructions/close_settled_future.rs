@@ -0,0 +1,70 @@
+use crate::{
+    errors::{FutureError, TradingError},
+    events::FutureAccountClosed,
+    state::{Future, FutureStatus, Pool},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CloseSettledFutureParams {
+    pub future_index: u64,
+    pub pool_name: String,
+}
+
+/// Close a settled or liquidated future whose settlement has been fully claimed and return
+/// its rent to the owner, for futures claimed without `close_account`.
+pub fn close_settled_future(
+    ctx: Context<CloseSettledFuture>,
+    params: &CloseSettledFutureParams,
+) -> Result<()> {
+    msg!("Closing settled future account");
+
+    let future = &ctx.accounts.future;
+
+    require!(
+        future.status == FutureStatus::Settled || future.status == FutureStatus::Liquidated,
+        FutureError::FutureNotClaimable
+    );
+    // Anything still claimable has to go through claim_future first
+    require!(
+        future.settlement_amount.unwrap_or(0) == 0,
+        FutureError::SettlementNotClaimed
+    );
+
+    // Rent is returned to the owner by the `close` constraint
+    emit!(FutureAccountClosed {
+        owner: future.owner,
+        future_key: future.key(),
+        index: params.future_index,
+        rent_refunded: future.to_account_info().lamports(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseSettledFutureParams)]
+pub struct CloseSettledFuture<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"future",
+            owner.key().as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = future.bump,
+        constraint = future.owner == owner.key() @ TradingError::Unauthorized,
+        close = owner
+    )]
+    pub future: Box<Account<'info, Future>>,
+}
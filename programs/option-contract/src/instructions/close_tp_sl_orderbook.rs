@@ -0,0 +1,99 @@
+use crate::{
+    errors::TradingError,
+    events::TpSlOrderbookClosed,
+    state::{Future, FutureStatus, OptionDetail, Pool, Position, TpSlOrderbook},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CloseTpSlOrderbookParams {
+    pub contract_type: u8,       // 0 = Perp, 1 = Option, 2 = Future
+    pub position_index: u64,     // Position, Option or Future index
+    pub pool_name: String,
+}
+
+pub fn close_tp_sl_orderbook(
+    ctx: Context<CloseTpSlOrderbook>,
+    params: &CloseTpSlOrderbookParams,
+) -> Result<()> {
+    msg!("Closing orphaned TP/SL orderbook");
+
+    let orderbook = &ctx.accounts.tp_sl_orderbook;
+    let position_info = &ctx.accounts.position;
+
+    require!(
+        orderbook.contract_type == params.contract_type,
+        TradingError::InvalidOrderType
+    );
+
+    // The referenced position must be gone (closed accounts are drained and zero-filled)
+    // or no longer active, otherwise the orderbook is still in use
+    let position_active = if position_info.lamports() == 0
+        || position_info.data_is_empty()
+        || position_info.owner != &crate::ID
+    {
+        false
+    } else {
+        let data = position_info.try_borrow_data()?;
+        match params.contract_type {
+            0 => match Position::try_deserialize(&mut &data[..]) {
+                Ok(position) => !position.is_liquidated && position.size_usd > 0,
+                Err(_) => false,
+            },
+            1 => match OptionDetail::try_deserialize(&mut &data[..]) {
+                Ok(option) => option.valid,
+                Err(_) => false,
+            },
+            2 => match Future::try_deserialize(&mut &data[..]) {
+                Ok(future) => matches!(future.status, FutureStatus::Pending | FutureStatus::Active),
+                Err(_) => false,
+            },
+            _ => return Err(TradingError::InvalidOrderType.into()),
+        }
+    };
+    require!(!position_active, TradingError::PositionNotClosed);
+
+    // Rent is returned to the owner by the `close` constraint
+    emit!(TpSlOrderbookClosed {
+        owner: orderbook.owner,
+        position: orderbook.position,
+        contract_type: orderbook.contract_type,
+        rent_refunded: orderbook.to_account_info().lamports(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseTpSlOrderbookParams)]
+pub struct CloseTpSlOrderbook<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"tp_sl_orderbook",
+            owner.key().as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            params.pool_name.as_bytes(),
+            params.contract_type.to_le_bytes().as_ref(),
+        ],
+        bump = tp_sl_orderbook.bump,
+        constraint = tp_sl_orderbook.owner == owner.key() @ TradingError::Unauthorized,
+        close = owner
+    )]
+    pub tp_sl_orderbook: Box<Account<'info, TpSlOrderbook>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    /// CHECK: Position or option referenced by the orderbook; may already be closed
+    #[account(
+        constraint = position.key() == tp_sl_orderbook.position @ TradingError::InvalidPosition
+    )]
+    pub position: AccountInfo<'info>,
+}
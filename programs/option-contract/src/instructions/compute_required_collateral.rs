@@ -0,0 +1,189 @@
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::RequiredCollateralComputed,
+    math::{self, f64_to_scaled_price},
+    state::{Contract, Custody, Pool, Position, Side},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ComputeRequiredCollateralParams {
+    pub pool_name: String,
+    pub size_usd: u64,        // Desired position size in USD (6 decimals)
+    pub target_leverage: f64, // Leverage left after the trade fee is taken from the margin
+    pub pay_sol: bool,        // true = collateral in SOL, false = collateral in USDC
+    pub side: Side,
+    pub max_loss_usd: Option<u64>, // Max-loss protection the open will buy, as open_perp_position takes it
+}
+
+/// Read-only helper for open_perp_position: the collateral to deposit for a market open of
+/// `size_usd` at `target_leverage`. The open fees (trade plus confidence fee) are added on top
+/// of the margin so they never push the position over the requested leverage, and the max-loss
+/// premium, which the open takes out of the posted collateral, is added as well. Emits the
+/// result; meant to be called through transaction simulation.
+pub fn compute_required_collateral(
+    ctx: Context<ComputeRequiredCollateral>,
+    params: &ComputeRequiredCollateralParams,
+) -> Result<()> {
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let sol_custody = &ctx.accounts.sol_custody;
+    let usdc_custody = &ctx.accounts.usdc_custody;
+
+    require!(params.size_usd > 0, TradingError::InvalidAmount);
+    require!(
+        params.target_leverage >= 1.0 && params.target_leverage <= Position::MAX_LEVERAGE,
+        PerpetualError::InvalidLeverage
+    );
+    let initial_margin_bps =
+        math::checked_as_u64(math::checked_float_div(10_000.0, params.target_leverage)?)?;
+    require!(
+        initial_margin_bps >= Position::MIN_INITIAL_MARGIN_BPS,
+        PerpetualError::InvalidLeverage
+    );
+
+    // Same oracle prices open_perp_position uses
+    let current_time = pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price =
+        sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let (collateral_price, collateral_decimals) = if params.pay_sol {
+        (sol_price, sol_custody.decimals)
+    } else {
+        (
+            usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?,
+            usdc_custody.decimals,
+        )
+    };
+    let collateral_price_value = collateral_price.get_price();
+
+    let margin_usd = math::checked_as_u64(
+        math::checked_float_div(params.size_usd as f64, params.target_leverage)?.ceil(),
+    )?;
+    // Same fee helper and feed confidence open_perp_position records
+    let trade_fee_usd = pool.get_perp_trade_fee(params.size_usd)?;
+    let open_fee_usd = pool.get_perp_open_fee(params.size_usd, sol_price.confidence_bps)?;
+    let confidence_fee_usd = math::checked_sub(open_fee_usd, trade_fee_usd)?;
+
+    // The open deducts the max-loss premium from the posted collateral before the leverage check
+    let max_loss_premium_usd = if let Some(max_loss_usd) = params.max_loss_usd {
+        require!(max_loss_usd > 0, PerpetualError::InvalidMaxLoss);
+        let entry_price = f64_to_scaled_price(sol_price.get_price())?;
+        let max_loss_price =
+            Position::get_max_loss_price(entry_price, params.size_usd, max_loss_usd, params.side)?;
+        let premium_usd = Position::get_max_loss_premium_usd(
+            sol_custody,
+            usdc_custody,
+            sol_price.get_price(),
+            entry_price,
+            params.size_usd,
+            max_loss_price,
+            params.side,
+        )?;
+        // The collateral left after the premium must still cover the floor
+        require_gt!(
+            math::checked_add(margin_usd, open_fee_usd)?,
+            max_loss_usd,
+            PerpetualError::InvalidMaxLoss
+        );
+        premium_usd
+    } else {
+        0
+    };
+    let collateral_usd = math::checked_add(math::checked_add(margin_usd, open_fee_usd)?, max_loss_premium_usd)?;
+
+    // Rounded up so the deposit is never a unit short of the target
+    let collateral_amount = math::checked_as_u64(
+        (math::checked_float_div(collateral_usd as f64 / Contract::USD_SCALE as f64, collateral_price_value)?
+            * math::checked_powi(10.0, collateral_decimals as i32)?)
+        .ceil(),
+    )?;
+    let size_amount =
+        math::usd_to_token_amount(params.size_usd, &collateral_price, collateral_decimals)?;
+
+    msg!("Margin USD: {}", margin_usd);
+    msg!("Open fee USD: {}", open_fee_usd);
+    msg!("Max loss premium USD: {}", max_loss_premium_usd);
+    msg!("Required collateral: {}", collateral_amount);
+
+    emit!(RequiredCollateralComputed {
+        pool: pool.key(),
+        pay_sol: params.pay_sol,
+        size_usd: params.size_usd,
+        size_amount,
+        target_leverage: params.target_leverage,
+        price: f64_to_scaled_price(collateral_price_value)?,
+        margin_usd,
+        trade_fee_usd,
+        confidence_fee_usd,
+        max_loss_premium_usd,
+        collateral_usd,
+        collateral_amount,
+        computed_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ComputeRequiredCollateralParams)]
+pub struct ComputeRequiredCollateral<'info> {
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
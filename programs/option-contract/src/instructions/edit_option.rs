@@ -2,7 +2,7 @@ use crate::{
     errors::{OptionError, PoolError, TradingError},
     math::{self, f64_to_scaled_price, scaled_price_to_f64},
     utils::option_pricing::*,
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    state::{Contract, Custody, LockedProduct, OptionDetail, Pool, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::{
@@ -47,7 +47,7 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     require_gte!(user.option_index, params.option_index);
 
     // Get current time and validate option hasn't expired
-    let current_time = contract.get_time()?;
+    let current_time = ctx.accounts.pool.get_time(contract)?;
     require!(current_time < option_detail.expired_date, OptionError::InvalidTimeError);
 
     // Validate at least one parameter is being changed
@@ -57,17 +57,11 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     );
 
     // Get current oracle prices
-    let underlying_price = OraclePrice::new_from_oracle(
-        custody_oracle_account,
-        current_time,
-        false,
-    )?.get_price();
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let pay_custody_oracle_secondary = ctx.accounts.pay_custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let underlying_price = custody.get_oracle_price(custody_oracle_account, custody_oracle_secondary.as_ref(), current_time)?.get_price();
 
-    let pay_token_price = OraclePrice::new_from_oracle(
-        pay_custody_oracle_account,
-        current_time,
-        false,
-    )?.get_price();
+    let pay_token_price = pay_custody.get_oracle_price(pay_custody_oracle_account, pay_custody_oracle_secondary.as_ref(), current_time)?.get_price();
 
     // Calculate time to expiration for CURRENT terms
     let current_time_to_expiry = math::checked_float_div(
@@ -77,10 +71,10 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
 
     // Get utilization data for dynamic borrow rate
     let (token_locked, token_owned) = (locked_custody.token_locked, locked_custody.token_owned);
-    let is_sol = custody.key() == locked_custody.key();
+    let is_call = custody.key() == locked_custody.key();
 
-    // Get current size (convert from u64 quantity to f64 size)
-    let current_size = option_detail.quantity as f64;
+    // Get current size (convert from fixed-point quantity to f64 contracts)
+    let current_size = option_detail.contracts(option_detail.quantity);
 
     // Calculate CURRENT total option value (old terms)
     let current_strike_f64 = scaled_price_to_f64(option_detail.strike_price)?;
@@ -88,10 +82,11 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
         underlying_price,
         current_strike_f64, // OLD strike converted to f64
         current_time_to_expiry,
-        option_detail.option_type == 0,
+        option_detail.is_call(),
         token_locked,
         token_owned,
-        is_sol,
+        is_call,
+        option_detail.exercise_style,
     )?;
     let current_total_option_value = current_option_value_per_unit * current_size;
 
@@ -108,6 +103,12 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     require!(new_size > 0.0, TradingError::InvalidParameterError);
     require!(new_strike > 0.0, TradingError::InvalidParameterError);
     require!(new_expiry > current_time, OptionError::InvalidTimeError);
+    if params.new_expiry.is_some() {
+        ctx.accounts.pool.validate_option_expiry(new_expiry)?;
+    }
+    if params.new_strike.is_some() || params.new_expiry.is_some() {
+        ctx.accounts.pool.validate_option_grid(new_strike, new_expiry)?;
+    }
 
     // Calculate time to expiration for NEW terms
     let new_time_to_expiry = math::checked_float_div(
@@ -120,10 +121,11 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
         underlying_price,
         new_strike, // NEW strike
         new_time_to_expiry,
-        option_detail.option_type == 0,
+        option_detail.is_call(),
         token_locked,
         token_owned,
-        is_sol,
+        is_call,
+        option_detail.exercise_style,
     )?;
     let new_total_option_value = new_option_value_per_unit * new_size;
 
@@ -131,6 +133,14 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     msg!("New size: {}", new_size);
     msg!("New total option value: {}", new_total_option_value);
 
+    // The lock follows the option's size
+    let new_quantity = math::checked_as_u64(new_size * option_detail.quantity_scale() as f64)?;
+    let previous_locked_amount = option_detail.get_locked_amount(option_detail.quantity)?;
+    let new_locked_amount = math::checked_as_u64(math::checked_div(
+        math::checked_mul(previous_locked_amount as u128, new_quantity as u128)?,
+        option_detail.quantity as u128,
+    )?)?;
+
     // Calculate value difference for premium adjustment
     let value_difference = new_total_option_value - current_total_option_value;
     msg!("Value difference: {}", value_difference);
@@ -138,10 +148,18 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     // Handle premium adjustment based on value difference sign
     if value_difference > 0.0 {
         // User needs to pay MORE (new option is more valuable)
-        
+
+        // Added lock pays the same size impact as a fresh open_option of that size
+        let (additional_value, size_impact_bps) = ctx.accounts.pool.apply_option_size_impact(
+            value_difference,
+            new_locked_amount.saturating_sub(previous_locked_amount),
+            ctx.accounts.pool.get_borrowable_amount(locked_custody)?,
+        )?;
+        msg!("Size impact bps: {}", size_impact_bps);
+
         // Convert to pay token amount (following open_option.rs pattern)
         let additional_premium = math::checked_as_u64(
-            math::checked_float_div(value_difference, pay_token_price)?
+            math::checked_float_div(additional_value, pay_token_price)?
                 * math::checked_powi(10.0, pay_custody.decimals as i32)?
         )?;
 
@@ -191,8 +209,9 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
 
         msg!("Refund amount calculated: {}", refund_amount);
         
-        // Apply platform fee (10% like in close_option.rs)
-        let actual_refund = math::checked_div(math::checked_mul(refund_amount, 9)?, 10)?;
+        // Apply the underlying's sell markdown, same as close_option
+        let actual_refund = custody.apply_option_sell_markdown(refund_amount)?;
+        msg!("Refund markdown: {} bps", custody.option_sell_markdown_bps);
 
         // Slippage protection
         require_gte!(
@@ -203,19 +222,21 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
 
         // Check pool has enough balance for refund
         require_gte!(
-            math::checked_sub(pay_custody.token_owned, pay_custody.token_locked)?,
+            pay_custody.available_for_payout(),
             actual_refund,
             PoolError::InvalidPoolBalanceError
         );
 
-        // Transfer refund from pool to user
-        contract.transfer_tokens(
-            pay_custody_token_account.to_account_info(),
-            refund_account.to_account_info(),
-            transfer_authority.to_account_info(),
-            token_program.to_account_info(),
-            actual_refund,
-        )?;
+        // Transfer refund from pool to user, a refund truncated to zero is skipped
+        if actual_refund > 0 {
+            contract.transfer_tokens(
+                pay_custody_token_account.to_account_info(),
+                refund_account.to_account_info(),
+                transfer_authority.to_account_info(),
+                token_program.to_account_info(),
+                actual_refund,
+            )?;
+        }
 
         // Update pool balances
         pay_custody.token_owned = math::checked_sub(pay_custody.token_owned, actual_refund)?;
@@ -228,12 +249,47 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     // If value_difference == 0.0, no payment needed
 
     // Update option parameters
+    let previous_notional_usd = option_detail.get_notional_usd(option_detail.quantity)?;
+    ctx.accounts.pool.remove_strike_expiry_notional(
+        option_detail.strike_price,
+        option_detail.expired_date,
+        option_detail.is_call(),
+        previous_notional_usd,
+    );
     option_detail.strike_price = f64_to_scaled_price(new_strike)?;
     option_detail.expired_date = new_expiry;
 
-    option_detail.quantity = new_size as u64;
+    // A larger lock must fit in borrowable liquidity
+    require_gte!(
+        ctx.accounts
+            .pool
+            .get_borrowable_amount_after_release(locked_custody, previous_locked_amount)?,
+        new_locked_amount,
+        TradingError::InsufficientPoolLiquidity
+    );
+    locked_custody.remove_locked(LockedProduct::Option, previous_locked_amount)?;
+    locked_custody.add_locked(LockedProduct::Option, new_locked_amount)?;
+    option_detail.quantity = new_quantity;
+    option_detail.locked_amount = new_locked_amount;
+
+    // Only added exposure is held to the global ceiling
+    let new_notional_usd = option_detail.get_notional_usd(option_detail.quantity)?;
+    ctx.accounts.pool.add_strike_expiry_notional(
+        option_detail.strike_price,
+        option_detail.expired_date,
+        option_detail.is_call(),
+        new_notional_usd,
+        current_time,
+    )?;
+    if new_notional_usd > previous_notional_usd {
+        ctx.accounts.pool.add_notional(&ctx.accounts.contract, new_notional_usd - previous_notional_usd)?;
+    } else {
+        ctx.accounts.pool.remove_notional(previous_notional_usd - new_notional_usd);
+    }
     
     option_detail.last_update_time = current_time;
+    let option_key = option_detail.key();
+    ctx.accounts.pool.sync_option_hedge(option_detail, option_key, ctx.accounts.hedge_position.as_deref())?;
 
     // Recalculate period in days (for consistency)
     let new_period_days = math::checked_div(
@@ -247,6 +303,8 @@ pub fn edit_option(ctx: Context<EditOption>, params: &EditOptionParams) -> Resul
     msg!("New expiry: {}", new_expiry);
     msg!("New size: {}", new_size);
 
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.pay_custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -364,4 +422,20 @@ pub struct EditOption<'info> {
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = pay_custody_oracle_secondary.key() == pay_custody.oracle_secondary
+    )]
+    pub pay_custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
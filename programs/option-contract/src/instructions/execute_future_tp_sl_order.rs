@@ -0,0 +1,465 @@
+use crate::{
+    errors::{FutureError, PerpetualError, TradingError},
+    events::{FutureAccountClosed, TpSlOrderExecuted, TpSlOrderbookClosed},
+    math::{self, f64_to_scaled_price},
+    state::{Contract, Custody, LockedProduct, Future, FutureStatus, Pool, Side, TpSlOrderbook},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExecuteFutureTpSlOrderParams {
+    pub future_index: u64,
+    pub pool_name: String,
+    pub trigger_order_type: u8, // 0 = TP, 1 = SL
+    pub order_index: u8,
+}
+
+pub fn execute_future_tp_sl_order(
+    ctx: Context<ExecuteFutureTpSlOrder>,
+    params: &ExecuteFutureTpSlOrderParams,
+) -> Result<()> {
+    msg!("Executing future TP/SL order - dedicated instruction for keeper");
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let future = &mut ctx.accounts.future;
+    let orderbook = &mut ctx.accounts.tp_sl_orderbook;
+    let sol_custody = &mut ctx.accounts.sol_custody;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+
+    // Validation
+    require_keys_eq!(future.owner, orderbook.owner, TradingError::Unauthorized);
+    require!(future.status == FutureStatus::Active, FutureError::FutureNotActive);
+    require!(future.lp_collateral_amount == 0, PerpetualError::LpCollateralUnsupported);
+    require_eq!(orderbook.contract_type, 2, TradingError::InvalidOrderType);
+    require_eq!(orderbook.position, future.key(), TradingError::InvalidPosition);
+
+    // Get current time and prices
+    let current_time = pool.get_time(contract)?;
+    require!(!future.is_expired(current_time), FutureError::FutureExpired);
+
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price =
+        sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price =
+        usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+
+    let current_sol_price = sol_price.get_price();
+    let current_price_scaled = f64_to_scaled_price(current_sol_price)?;
+
+    msg!("Current SOL price from oracle: {}", current_sol_price);
+
+    // Get the TP/SL order to execute
+    let (order_price, size_percent) = if params.trigger_order_type == 0 {
+        // Take Profit
+        require!(
+            params.order_index < orderbook.take_profit_orders.len() as u8,
+            TradingError::InvalidAmount
+        );
+        let order = &orderbook.take_profit_orders[params.order_index as usize];
+        require!(order.is_active, TradingError::InvalidAmount);
+        (order.price, order.size_percent)
+    } else {
+        // Stop Loss
+        require!(
+            params.order_index < orderbook.stop_loss_orders.len() as u8,
+            TradingError::InvalidAmount
+        );
+        let order = &orderbook.stop_loss_orders[params.order_index as usize];
+        require!(order.is_active, TradingError::InvalidAmount);
+        (order.price, order.size_percent)
+    };
+
+    // Futures always settle in the asset chosen at open, regardless of the order's preference
+    let receive_sol = future.get_settlement_custody() == sol_custody.key();
+
+    // Validate execution conditions using oracle spot price
+    let triggered = match (params.trigger_order_type, future.side) {
+        (0, Side::Long) | (1, Side::Short) => current_price_scaled >= order_price,
+        _ => current_price_scaled <= order_price,
+    };
+    require!(triggered, PerpetualError::TpSlNotTriggered);
+
+    // size_percent uses 6 decimal precision: 100,000,000 = 100%
+    let is_full_close = size_percent >= 100_000_000;
+    let portion = |amount: u64| -> Result<u64> {
+        if is_full_close {
+            Ok(amount)
+        } else {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(amount as u128, size_percent as u128)?,
+                100_000_000u128,
+            )?)
+        }
+    };
+
+    let size_usd_to_close = portion(future.size_usd)?;
+    let collateral_usd_to_close = portion(future.collateral_usd)?;
+    let collateral_amount_to_close = portion(future.collateral_amount)?;
+    let locked_amount_to_release = portion(future.locked_amount)?;
+
+    // Calculate P&L for closed portion
+    let pnl = future.calculate_pnl(current_price_scaled, current_time)?;
+    let pnl_for_closed_portion = if pnl >= 0 {
+        portion(pnl as u64)? as i64
+    } else {
+        -(portion((-pnl) as u64)? as i64)
+    };
+
+    // Net settlement (collateral + PnL - fees)
+    let closing_fee = pool.get_close_fee(size_usd_to_close, Future::SETTLEMENT_FEE_BPS)?;
+
+    let net_settlement = (collateral_usd_to_close as i64) + pnl_for_closed_portion - (closing_fee as i64);
+    let settlement_usd = if net_settlement > 0 { net_settlement as u64 } else { 0 };
+
+    msg!("Settlement USD: {}", settlement_usd);
+    msg!("Closing fee: {}", closing_fee);
+
+    // Convert settlement to tokens of the settlement asset
+    let settlement_tokens = if settlement_usd > 0 {
+        let (price, decimals) = if receive_sol {
+            (&sol_price, sol_custody.decimals)
+        } else {
+            (&usdc_price, usdc_custody.decimals)
+        };
+        math::usd_to_token_amount(settlement_usd, price, decimals)?
+    } else {
+        0
+    };
+
+    // Transfer settlement to owner
+    if settlement_tokens > 0 {
+        let settlement_token_account = if receive_sol {
+            &ctx.accounts.sol_custody_token_account
+        } else {
+            &ctx.accounts.usdc_custody_token_account
+        };
+
+        contract.transfer_tokens(
+            settlement_token_account.to_account_info(),
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            settlement_tokens,
+        )?;
+
+        if receive_sol {
+            sol_custody.token_owned = math::checked_sub(sol_custody.token_owned, settlement_tokens)?;
+        } else {
+            usdc_custody.token_owned = math::checked_sub(usdc_custody.token_owned, settlement_tokens)?;
+        }
+    }
+
+    // Release locked liquidity
+    if future.side == Side::Long {
+        sol_custody.remove_locked(LockedProduct::Future, locked_amount_to_release)?;
+    } else {
+        usdc_custody.remove_locked(LockedProduct::Future, locked_amount_to_release)?;
+    }
+
+    // Update pool tracking
+    pool.remove_future_position(
+        size_usd_to_close,
+        future.time_to_expiry(current_time),
+        current_time,
+    )?;
+
+    // Mark the order as executed in the orderbook
+    if params.trigger_order_type == 0 {
+        orderbook.mark_tp_executed(params.order_index as usize, current_time)?;
+    } else {
+        orderbook.mark_sl_executed(params.order_index as usize, current_time)?;
+    }
+
+    // Store values for event before updating future
+    let future_key = future.key();
+    let future_owner = future.owner;
+    let entry_size_usd = future.size_usd;
+    let entry_collateral_usd = future.collateral_usd;
+    let entry_collateral_amount = future.collateral_amount;
+    let entry_locked_amount = future.locked_amount;
+
+    if is_full_close {
+        // Same terminal state as a full close_future
+        future.status = FutureStatus::Settled;
+        future.settlement_time = Some(current_time);
+        future.settlement_price = Some(current_price_scaled);
+        future.pnl_at_settlement = Some(pnl);
+        future.settlement_amount = Some(settlement_usd);
+
+        future.size_usd = 0;
+        future.collateral_usd = 0;
+        future.collateral_amount = 0;
+        future.locked_amount = 0;
+
+        orderbook.clear_all_orders()?;
+    } else {
+        future.size_usd = math::checked_sub(future.size_usd, size_usd_to_close)?;
+        future.collateral_usd = math::checked_sub(future.collateral_usd, collateral_usd_to_close)?;
+        future.collateral_amount = math::checked_sub(future.collateral_amount, collateral_amount_to_close)?;
+        future.locked_amount = math::checked_sub(future.locked_amount, locked_amount_to_release)?;
+    }
+    future.update_time = current_time;
+    ctx.accounts.pool.remove_notional(size_usd_to_close);
+
+    emit!(TpSlOrderExecuted {
+        // Position identification
+        position_index: params.future_index,
+        position_key: future_key,
+        owner: future_owner,
+        pool: future.pool,
+
+        // Position details
+        custody: future.custody,
+        collateral_custody: future.collateral_custody,
+        order_type: 0,
+        side: future.side as u8,
+        is_liquidated: is_full_close,
+        entry_price: future.entry_price,
+        size_usd: entry_size_usd,
+        collateral_usd: entry_collateral_usd,
+        collateral_amount: entry_collateral_amount,
+        native_exit_amount: settlement_tokens,
+        locked_amount: entry_locked_amount,
+
+        // TP/SL specific
+        contract_type: 2,
+        trigger_order_type: params.trigger_order_type,
+        order_index: params.order_index,
+        order_price,
+        executed_price: current_price_scaled,
+        executed_size_percent: size_percent,
+        receive_sol,
+
+        // Fees and PnL
+        trade_fees: future.settlement_fee,
+        trade_fees_paid: closing_fee,
+        borrow_fees_paid: 0,
+        accrued_borrow_fees: 0,
+        realized_pnl: pnl_for_closed_portion,
+        settlement_tokens,
+
+        // Timestamps
+        open_time: future.open_time,
+        update_time: current_time,
+        executed_at: current_time,
+        last_borrow_fees_update_time: 0,
+
+        // Additional info
+        liquidation_price: future.liquidation_price,
+        cumulative_interest_snapshot: 0,
+        is_full_close,
+        referral_fee_usd: 0, // futures are credited at open
+    });
+
+    // Close the orderbook once the future is fully closed
+    if is_full_close {
+        let orderbook_rent = ctx.accounts.tp_sl_orderbook.to_account_info().lamports();
+        **ctx
+            .accounts
+            .tp_sl_orderbook
+            .to_account_info()
+            .try_borrow_mut_lamports()? = 0;
+        **ctx
+            .accounts
+            .owner
+            .to_account_info()
+            .try_borrow_mut_lamports()? = ctx
+            .accounts
+            .owner
+            .to_account_info()
+            .lamports()
+            .checked_add(orderbook_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        {
+            let orderbook_info = ctx.accounts.tp_sl_orderbook.to_account_info();
+            let mut orderbook_data = orderbook_info.try_borrow_mut_data()?;
+            orderbook_data.fill(0);
+        }
+
+        emit!(TpSlOrderbookClosed {
+            owner: future_owner,
+            position: future_key,
+            contract_type: 2,
+            rent_refunded: orderbook_rent,
+        });
+
+        // Close the future account, the settlement was paid out above
+        let future_index = ctx.accounts.future.index;
+        let future_rent = ctx.accounts.future.to_account_info().lamports();
+        **ctx
+            .accounts
+            .future
+            .to_account_info()
+            .try_borrow_mut_lamports()? = 0;
+        **ctx
+            .accounts
+            .owner
+            .to_account_info()
+            .try_borrow_mut_lamports()? = ctx
+            .accounts
+            .owner
+            .to_account_info()
+            .lamports()
+            .checked_add(future_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        {
+            let future_info = ctx.accounts.future.to_account_info();
+            let mut future_data = future_info.try_borrow_mut_data()?;
+            future_data.fill(0);
+        }
+
+        emit!(FutureAccountClosed {
+            owner: future_owner,
+            future_key,
+            index: future_index,
+            rent_refunded: future_rent,
+        });
+    }
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ExecuteFutureTpSlOrderParams)]
+pub struct ExecuteFutureTpSlOrder<'info> {
+    #[account(mut)]
+    pub executor: Signer<'info>, // Keeper can execute
+
+    /// CHECK: Future owner account to receive rent refunds
+    #[account(
+        mut,
+        constraint = owner.key() == future.owner @ TradingError::InvalidOwner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.owner == tp_sl_orderbook.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"future",
+            future.owner.as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = future.bump
+    )]
+    pub future: Box<Account<'info, Future>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"tp_sl_orderbook",
+            tp_sl_orderbook.owner.as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            params.pool_name.as_bytes(),
+            2u8.to_le_bytes().as_ref(),
+        ],
+        bump = tp_sl_orderbook.bump
+    )]
+    pub tp_sl_orderbook: Box<Account<'info, TpSlOrderbook>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
@@ -2,7 +2,7 @@ use crate::{
     errors::{FutureError, TradingError},
     events::LimitFutureExecuted,
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, Future, FutureStatus, OraclePrice, Pool, Side},
+    state::{Contract, Custody, LockedProduct, Future, FutureStatus, Pool, Position, Side},
     utils::risk_management::*,
 };
 use anchor_lang::prelude::*;
@@ -21,7 +21,7 @@ pub fn execute_limit_future(
     params: &ExecuteLimitFutureParams,
 ) -> Result<()> {
     msg!("Executing limit future order");
-    msg!("Execution price: {}", params.execution_price as f64 / 1_000_000.0);
+    msg!("Execution price: {}", params.execution_price as f64 / math::PRICE_SCALE as f64);
 
     // Get keys first to avoid borrowing conflicts
     let sol_custody_key = ctx.accounts.sol_custody.key();
@@ -44,7 +44,7 @@ pub fn execute_limit_future(
         FutureError::FutureNotPending
     );
 
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
 
     // Check if future has already expired
     require!(
@@ -53,7 +53,8 @@ pub fn execute_limit_future(
     );
 
     // Get current price and validate trigger condition
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
     let current_price_scaled = sol_price.scale_to_exponent(-6)?.price;
 
     let trigger_price = future.trigger_price.ok_or(FutureError::NoTriggerPrice)?;
@@ -68,11 +69,7 @@ pub fn execute_limit_future(
     require!(trigger_met, FutureError::TriggerConditionNotMet);
 
     // Validate execution price is within slippage tolerance
-    let price_diff = if params.execution_price > trigger_price {
-        params.execution_price - trigger_price
-    } else {
-        trigger_price - params.execution_price
-    };
+    let price_diff = params.execution_price.abs_diff(trigger_price);
 
     let max_slippage_amount = math::checked_div(
         math::checked_mul(trigger_price as u128, future.max_slippage as u128)?,
@@ -90,11 +87,14 @@ pub fn execute_limit_future(
     future.execution_time = Some(current_time);
     future.update_time = current_time;
 
+    // Limit futures count towards the global ceiling once they are live
+    pool.add_notional(&ctx.accounts.contract, future.size_usd)?;
+
     // Recalculate future price based on actual execution price
     let time_to_expiry = future.expiry_time - current_time;
     let annual_rate = (future.fixed_interest_rate_bps as f64) / 10_000.0;
     let time_fraction = (time_to_expiry as f64) / (365.25 * 24.0 * 3600.0);
-    let future_price_f64 = (params.execution_price as f64 / 1_000_000.0) * (annual_rate * time_fraction).exp();
+    let future_price_f64 = (params.execution_price as f64 / math::PRICE_SCALE as f64) * (annual_rate * time_fraction).exp();
     future.future_price = f64_to_scaled_price(future_price_f64)?;
 
     // Calculate liquidation price
@@ -102,28 +102,28 @@ pub fn execute_limit_future(
     future.liquidation_price = calculate_liquidation_price(
         params.execution_price,
         leverage,
-        future.side
+        future.side,
+        Position::LIQUIDATION_MARGIN_BPS,
     )?;
 
     // Now lock the required liquidity in the pool
-    if future.side == Side::Long {
-        sol_custody.token_locked = math::checked_add(
-            sol_custody.token_locked,
-            future.locked_amount
-        )?;
+    // The liquidity checked when the order was placed may have been taken since
+    let available_liquidity = if future.side == Side::Long {
+        pool.get_borrowable_amount(sol_custody)?
     } else {
-        usdc_custody.token_locked = math::checked_add(
-            usdc_custody.token_locked,
-            future.locked_amount
-        )?;
-    }
+        pool.get_borrowable_amount(usdc_custody)?
+    };
+    require_gte!(
+        available_liquidity,
+        future.locked_amount,
+        TradingError::InsufficientPoolLiquidity
+    );
 
-    // Check if we still have sufficient liquidity
-    let _available_liquidity = if future.side == Side::Long {
-        math::checked_sub(sol_custody.token_owned, sol_custody.token_locked)?
+    if future.side == Side::Long {
+        sol_custody.add_locked(LockedProduct::Future, future.locked_amount)?;
     } else {
-        math::checked_sub(usdc_custody.token_owned, usdc_custody.token_locked)?
-    };
+        usdc_custody.add_locked(LockedProduct::Future, future.locked_amount)?;
+    }
 
     require!(
         sol_custody.token_locked <= sol_custody.token_owned &&
@@ -153,6 +153,8 @@ pub fn execute_limit_future(
     msg!("New future price: {}", future.future_price);
     msg!("Liquidation price: {}", future.liquidation_price);
 
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -208,9 +210,21 @@ pub struct ExecuteLimitFuture<'info> {
     )]
     pub sol_oracle_account: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
     
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
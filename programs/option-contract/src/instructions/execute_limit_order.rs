@@ -1,8 +1,8 @@
 use crate::{
     errors::{PerpetualError, TradingError},
-    events::LimitOrderExecuted,
+    events::{LimitOrderExecuted, StopLimitActivated},
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, OraclePrice, OrderType, Pool, Position, Side},
+    state::{Contract, Custody, LockedProduct, LimitOrderBook, OrderType, Pool, Position, Side},
     utils::risk_management::*,
 };
 use anchor_lang::prelude::*;
@@ -34,14 +34,20 @@ pub fn execute_limit_order(
         position.order_type == OrderType::Limit,
         PerpetualError::NotLimitOrder
     );
+    require!(position.execution_time.is_none(), PerpetualError::LimitOrderAlreadyExecuted);
     require!(params.execution_price > 0.0, TradingError::InvalidPrice);
 
     // Get current time and prices
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
+    if let Some(expiry_time) = position.expiry_time {
+        require!(current_time < expiry_time, PerpetualError::LimitOrderExpired);
+    }
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
     let sol_price =
-        OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
+        sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
     let usdc_price =
-        OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+        usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
 
     let current_sol_price = sol_price.get_price();
     let _usdc_price_value = usdc_price.get_price();
@@ -52,6 +58,35 @@ pub fn execute_limit_order(
     msg!("Position side: {:?}", position.side);
     msg!("Position trigger price: {:?}", position.trigger_price);
 
+    // Stop-limit orders activate on the oracle price first; the fill is then bounded by the limit
+    if position.is_stop_limit_order() && !position.stop_activated {
+        let oracle_price_scaled = f64_to_scaled_price(current_sol_price)?;
+        require!(
+            position.is_stop_triggered(oracle_price_scaled),
+            PerpetualError::LimitOrderNotTriggered
+        );
+        position.stop_activated = true;
+        position.update_time = current_time;
+
+        emit!(StopLimitActivated {
+            pub_key: position.key(),
+            index: position.index,
+            owner: position.owner,
+            pool: position.pool,
+            side: position.side as u8,
+            stop_price: position.stop_price.unwrap_or(0),
+            limit_price: position.trigger_price.unwrap_or(0),
+            activation_price: oracle_price_scaled,
+            activated_at: current_time,
+        });
+
+        // Keep the activation even if the limit can't be filled yet
+        if !position.should_execute_limit_order(execution_price_scaled) {
+            msg!("Stop activated, waiting for price within limit");
+            return Ok(());
+        }
+    }
+
     // Validate that the limit order should be executed at this price
     require!(
         position.should_execute_limit_order(execution_price_scaled),
@@ -94,43 +129,62 @@ pub fn execute_limit_order(
     let new_leverage = math::checked_float_div(position.size_usd as f64, position.collateral_usd as f64)?.max(1.0);
 
     // Calculate liquidation price for the new market position
-    let liquidation_price =
-        calculate_liquidation_price(current_price_scaled, new_leverage, position.side)?;
+    let maintenance_margin_bps = sol_custody.get_maintenance_margin_bps(position.size_usd);
+    let liquidation_price = calculate_liquidation_price(
+        current_price_scaled,
+        new_leverage,
+        position.side,
+        maintenance_margin_bps,
+    )?;
+    let bankruptcy_price =
+        calculate_bankruptcy_price(current_price_scaled, new_leverage, position.side)?;
 
     // Execute the limit order (convert to market position)
     position.execute_limit_order(current_price_scaled, current_time)?;
     
-    // Initialize borrow fee tracking for the newly executed market position
-    let relevant_custody = match position.side {
-        Side::Long => sol_custody.as_ref(),   // Long positions borrow SOL
-        Side::Short => usdc_custody.as_ref(), // Short positions borrow USDC
-    };
-    
-    let current_borrow_rate = pool.get_token_borrow_rate(relevant_custody)?;
-    position.cumulative_interest_snapshot = current_borrow_rate.to_bps().unwrap_or(0u32) as u128;
-    position.last_borrow_fees_update_time = current_time; // Start borrow fee tracking from execution
+    // Start borrow fee tracking from execution at the side's current index
+    pool.update_borrow_index(sol_custody, usdc_custody, current_time)?;
+    position.cumulative_interest_snapshot = pool.get_borrow_index(position.side);
+    position.last_borrow_fees_update_time = current_time;
 
     // Lock tokens when executing limit order (they weren't locked when opened)
     if position.side == Side::Long {
         // Long positions always need SOL backing
-        sol_custody.token_locked = math::checked_add(sol_custody.token_locked, position.locked_amount)?;
+        require_gte!(
+            pool.get_borrowable_amount(sol_custody)?,
+            position.locked_amount,
+            TradingError::InsufficientPoolLiquidity
+        );
+        pool.check_position_concentration(position.size_usd, sol_custody, 0, &sol_price)?;
+        sol_custody.add_locked(LockedProduct::Perp, position.locked_amount)?;
     } else {
         // Short positions always need USDC backing
-        usdc_custody.token_locked =
-            math::checked_add(usdc_custody.token_locked, position.locked_amount)?;
+        require_gte!(
+            pool.get_borrowable_amount(usdc_custody)?,
+            position.locked_amount,
+            TradingError::InsufficientPoolLiquidity
+        );
+        pool.check_position_concentration(position.size_usd, usdc_custody, 0, &usdc_price)?;
+        usdc_custody.add_locked(LockedProduct::Perp, position.locked_amount)?;
     }
 
     // Update position with market position specifics
     position.liquidation_price = liquidation_price;
+    position.bankruptcy_price = bankruptcy_price;
     position.last_borrow_fees_update_time = current_time;
 
     // Update pool open interest tracking
-    if position.side == Side::Long {
-        pool.long_open_interest_usd =
-            math::checked_add(pool.long_open_interest_usd, position.size_usd as u128)?;
-    } else {
-        pool.short_open_interest_usd =
-            math::checked_add(pool.short_open_interest_usd, position.size_usd as u128)?;
+    pool.update_open_interest(position, position.size_usd, true, current_time)?;
+    // Limit orders count towards the global ceiling once they are live
+    pool.add_notional(&ctx.accounts.contract, position.size_usd)?;
+
+    position.require_healthy(current_price_scaled, maintenance_margin_bps, pool.liquidation_buffer_bps)?;
+
+    // Filled orders leave the shared book
+    position.require_limit_order_book(ctx.accounts.limit_order_book.is_some())?;
+    if let Some(book) = ctx.accounts.limit_order_book.as_mut() {
+        book.remove(&position.key());
+        position.in_limit_order_book = false;
     }
 
     emit!(LimitOrderExecuted {
@@ -161,6 +215,8 @@ pub fn execute_limit_order(
         execution_price: execution_price_scaled,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -221,10 +277,36 @@ pub struct ExecuteLimitOrder<'info> {
     )]
     pub usdc_oracle_account: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
+    // Shared limit order book, required when position.in_limit_order_book is set
+    #[account(
+        mut,
+        seeds = [b"limit_order_book", pool.key().as_ref()],
+        bump = limit_order_book.bump
+    )]
+    pub limit_order_book: Option<Box<Account<'info, LimitOrderBook>>>,
+
     pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
@@ -2,7 +2,7 @@ use crate::{
     errors::{PerpetualError, TradingError},
     events::{PositionAccountClosed, TpSlOrderExecuted, TpSlOrderbookClosed},
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, OraclePrice, Pool, Position, Side, TpSlOrderbook},
+    state::{Contract, Custody, LockedProduct, Pool, Position, Referral, Side, TpSlOrderbook},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -32,6 +32,7 @@ pub fn execute_tp_sl_order(
     // Validation
     require_keys_eq!(position.owner, orderbook.owner, TradingError::Unauthorized);
     require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(position.lp_collateral_amount == 0, PerpetualError::LpCollateralUnsupported);
     require_eq!(
         orderbook.contract_type,
         params.contract_type,
@@ -44,11 +45,13 @@ pub fn execute_tp_sl_order(
     );
 
     // Get current time and prices
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
     let sol_price =
-        OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
+        sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
     let usdc_price =
-        OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+        usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
 
     let current_sol_price = sol_price.get_price();
     let _usdc_price_value = usdc_price.get_price();
@@ -56,10 +59,6 @@ pub fn execute_tp_sl_order(
 
     msg!("Current SOL price from oracle: {}", current_sol_price);
 
-    // Update borrow fees before executing TP/SL order
-    let interest_payment =
-        pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
-
     // Get the TP/SL order to execute
     let (order_price, size_percent, receive_sol) = if params.trigger_order_type == 0 {
         // Take Profit
@@ -109,164 +108,61 @@ pub fn execute_tp_sl_order(
     }
 
     // size_percent uses 6 decimal precision: 100,000,000 = 100%
-    let is_full_close = size_percent >= 100_000_000;
-
-    // Calculate P&L using oracle price
-    let pnl = position.calculate_pnl(current_price_scaled)?;
-
-    // Calculate amounts to close (proportional to percentage) - using integer math
-    let size_usd_to_close = if is_full_close {
-        position.size_usd
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.size_usd as u128, size_percent as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    let collateral_amount_to_close = if is_full_close {
-        position.collateral_amount
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.collateral_amount as u128, size_percent as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    let collateral_usd_to_close = if is_full_close {
-        position.collateral_usd
+    let close_percentage = size_percent.min(Position::FULL_CLOSE_PERCENTAGE);
+    let is_full_close = close_percentage == Position::FULL_CLOSE_PERCENTAGE;
+
+    // Settle borrow fees and price the close the same way close_perp_position does
+    let settlement = pool.compute_close_settlement(
+        position,
+        close_percentage,
+        current_price_scaled,
+        current_time,
+        sol_custody,
+        usdc_custody,
+    )?;
+    let settlement_usd = settlement.settlement_usd;
+    // Keeper rewards are only ever paid out of borrow fees the pool collected
+    pool.fund_keeper_rewards(contract, settlement.get_collected_borrow_fees())?;
+    let referral_fee_usd = Referral::accrue_perp_close(
+        ctx.accounts.referral.as_deref_mut().map(|r| &mut **r),
+        position,
+        &settlement,
+        pool,
+        contract,
+    )?;
+
+    // Calculate settlement amount in requested asset, less the haircut that stays with LPs
+    let (settlement_tokens, settlement_haircut) = if receive_sol {
+        let token_id = pool.get_token_id(&sol_custody.key())?;
+        pool.get_settlement_payout(token_id, settlement_usd, sol_custody, &sol_price)?
     } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.collateral_usd as u128, size_percent as u128)?,
-            100_000_000u128
-        )?)?
+        let token_id = pool.get_token_id(&usdc_custody.key())?;
+        pool.get_settlement_payout(token_id, settlement_usd, usdc_custody, &usdc_price)?
     };
+    msg!("Settlement haircut: {}", settlement_haircut);
 
-    // Calculate P&L and interest for the portion being closed
-    let pnl_for_closed_portion = if is_full_close {
-        pnl
-    } else {
-        // Use integer math for PnL calculation
-        if pnl >= 0 {
-            math::checked_as_i64(math::checked_div(
-                math::checked_mul(pnl as u128, size_percent as u128)?,
-                100_000_000u128
-            )?)?
-        } else {
-            -math::checked_as_i64(math::checked_div(
-                math::checked_mul((-pnl) as u128, size_percent as u128)?,
-                100_000_000u128
-            )?)?
-        }
-    };
-
-    let interest_for_closed_portion = if is_full_close {
-        interest_payment
+    let native_exit_tokens = if position.side == Side::Long {
+        // Long positions exit in SOL
+        math::usd_to_token_amount(settlement_usd, &sol_price, sol_custody.decimals)?
     } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(interest_payment as u128, size_percent as u128)?,
-            100_000_000u128
-        )?)?
+        // Short positions exit in USDC
+        math::usd_to_token_amount(settlement_usd, &usdc_price, usdc_custody.decimals)?
     };
 
-    let trade_fees_for_closed_portion = if is_full_close {
-        position.trade_fees
+    // Release the locked backing first so the payout can draw on it
+    if position.side == Side::Long {
+        sol_custody.unlock_funds(LockedProduct::Perp, settlement.locked_amount)?;
     } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.trade_fees as u128, size_percent as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    let mut net_settlement = collateral_usd_to_close as i64 + pnl_for_closed_portion
-        - interest_for_closed_portion as i64
-        - trade_fees_for_closed_portion as i64;
-
-    // Ensure settlement is not negative
-    if net_settlement < 0 {
-        net_settlement = 0;
+        usdc_custody.unlock_funds(LockedProduct::Perp, settlement.locked_amount)?;
     }
 
-    let settlement_usd = net_settlement as u64;
-
-    // Calculate settlement amount in requested asset using integer math
-    let settlement_tokens = if receive_sol {
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
-    } else {
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
-    };
-
-    let native_exit_tokens = if position.side == Side::Long {
-        // Long positions exit in SOL
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
+    // Fail clearly when the chosen asset can't cover the payout
+    let payout_available = if receive_sol {
+        sol_custody.available_for_payout()
     } else {
-        // Short positions exit in USDC
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
+        usdc_custody.available_for_payout()
     };
+    require_gte!(payout_available, settlement_tokens, TradingError::InsufficientPoolLiquidity);
 
     // Transfer settlement to user
     if settlement_tokens > 0 {
@@ -283,41 +179,18 @@ pub fn execute_tp_sl_order(
         )?;
     }
 
-    // Update custody stats
-    let locked_amount_to_release = if is_full_close {
-        position.locked_amount
-    } else {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.locked_amount as u128, size_percent as u128)?,
-            100_000_000u128
-        )?)?
-    };
-
-    if position.side == Side::Long {
-        sol_custody.token_locked =
-            math::checked_sub(sol_custody.token_locked, locked_amount_to_release)?;
-    } else {
-        usdc_custody.token_locked =
-            math::checked_sub(usdc_custody.token_locked, locked_amount_to_release)?;
-    }
-
     // Update custody ownership
     if position.collateral_custody == sol_custody.key() {
         sol_custody.token_owned =
-            math::checked_sub(sol_custody.token_owned, collateral_amount_to_close)?;
+            math::checked_sub(sol_custody.token_owned, settlement.collateral_amount)?;
     } else {
         usdc_custody.token_owned =
-            math::checked_sub(usdc_custody.token_owned, collateral_amount_to_close)?;
+            math::checked_sub(usdc_custody.token_owned, settlement.collateral_amount)?;
     }
 
     // Update pool open interest
-    if position.side == Side::Long {
-        pool.long_open_interest_usd =
-            math::checked_sub(pool.long_open_interest_usd, size_usd_to_close as u128)?;
-    } else {
-        pool.short_open_interest_usd =
-            math::checked_sub(pool.short_open_interest_usd, size_usd_to_close as u128)?;
-    }
+    pool.update_open_interest(position, settlement.size_usd, false, current_time)?;
+    ctx.accounts.pool.remove_notional(settlement.size_usd);
 
     // Store position values before modification for event emission
     let position_owner = position.owner;
@@ -347,41 +220,17 @@ pub fn execute_tp_sl_order(
     }
 
     // Update or close position
+    position.apply_close(&settlement, current_time)?;
     if is_full_close {
         msg!("Position fully closed - triggering automatic account closures");
 
-        position.is_liquidated = true; // Mark as closed
-        position.size_usd = 0;
-        position.collateral_amount = 0;
-        position.collateral_usd = 0;
-        position.locked_amount = 0;
-        position.trade_fees = 0;
-
         // Clear all remaining TP/SL orders in orderbook (executed order already marked above)
         orderbook.clear_all_orders()?;
 
         // Position fully closed - will automatically close both accounts and return rent
         msg!("Position fully closed - will automatically close TP/SL orderbook and position accounts");
-    } else {
-        // Update position for partial close
-        position.size_usd = math::checked_sub(position.size_usd, size_usd_to_close)?;
-        position.collateral_amount =
-            math::checked_sub(position.collateral_amount, collateral_amount_to_close)?;
-        position.collateral_usd =
-            math::checked_sub(position.collateral_usd, collateral_usd_to_close)?;
-        position.locked_amount =
-            math::checked_sub(position.locked_amount, locked_amount_to_release)?;
-        position.trade_fees =
-            math::checked_sub(position.trade_fees, trade_fees_for_closed_portion)?;
     }
 
-    // Update fee tracking
-    position.borrow_fees_paid =
-        math::checked_add(position.borrow_fees_paid, interest_for_closed_portion)?;
-    position.accrued_borrow_fees =
-        math::checked_sub(position.accrued_borrow_fees, interest_for_closed_portion)?;
-    position.update_time = current_time;
-
     emit!(TpSlOrderExecuted {
         // Position identification
         position_index: params.position_index,
@@ -413,10 +262,10 @@ pub fn execute_tp_sl_order(
 
         // Fees and PnL
         trade_fees: position_trade_fees,
-        trade_fees_paid: trade_fees_for_closed_portion,
-        borrow_fees_paid: interest_for_closed_portion,
+        trade_fees_paid: settlement.close_fee_usd,
+        borrow_fees_paid: settlement.borrow_fees,
         accrued_borrow_fees: position_accrued_borrow_fees,
-        realized_pnl: pnl_for_closed_portion,
+        realized_pnl: settlement.realized_pnl,
         settlement_tokens,
 
         // Timestamps
@@ -429,6 +278,7 @@ pub fn execute_tp_sl_order(
         liquidation_price: position_liquidation_price,
         cumulative_interest_snapshot: position_cumulative_interest_snapshot,
         is_full_close,
+        referral_fee_usd,
     });
 
     // Automatically close accounts if position was fully closed
@@ -442,11 +292,11 @@ pub fn execute_tp_sl_order(
             .try_borrow_mut_lamports()? = 0;
         **ctx
             .accounts
-            .executor
+            .owner
             .to_account_info()
             .try_borrow_mut_lamports()? = ctx
             .accounts
-            .executor
+            .owner
             .to_account_info()
             .lamports()
             .checked_add(orderbook_rent)
@@ -475,11 +325,11 @@ pub fn execute_tp_sl_order(
             .try_borrow_mut_lamports()? = 0;
         **ctx
             .accounts
-            .executor
+            .owner
             .to_account_info()
             .try_borrow_mut_lamports()? = ctx
             .accounts
-            .executor
+            .owner
             .to_account_info()
             .lamports()
             .checked_add(position_rent)
@@ -500,9 +350,11 @@ pub fn execute_tp_sl_order(
             rent_refunded: position_rent,
         });
 
-        msg!("TP/SL orderbook and position accounts automatically closed - all rent returned to user");
+        msg!("TP/SL orderbook and position accounts automatically closed - all rent returned to owner");
     }
 
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -512,6 +364,13 @@ pub struct ExecuteTpSlOrder<'info> {
     #[account(mut)]
     pub executor: Signer<'info>, // Keeper can execute
 
+    /// CHECK: Position owner account to receive rent refunds
+    #[account(
+        mut,
+        constraint = owner.key() == position.owner @ TradingError::InvalidOwner
+    )]
+    pub owner: AccountInfo<'info>,
+
     #[account(
         mut,
         constraint = receiving_account.owner == tp_sl_orderbook.owner
@@ -611,10 +470,36 @@ pub struct ExecuteTpSlOrder<'info> {
     )]
     pub usdc_oracle_account: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
     pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    // Referrer's registration, required when position.referrer is set
+    #[account(
+        mut,
+        seeds = [b"referral", position.referrer.unwrap_or_default().as_ref(), pool.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
 }
@@ -2,7 +2,7 @@ use crate::{
     errors::{OptionError, TradingError},
     events::OptionExercised,
     math::{self, scaled_price_to_f64},
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    state::{Contract, Custody, LockedProduct, OptionDetail, Pool, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::{
@@ -53,24 +53,23 @@ pub fn exercise_option(ctx: Context<ExerciseOption>, params: &ExerciseOptionPara
     );
 
     // Current Unix timestamp
-    let current_timestamp = contract.get_time()?;
+    let current_timestamp = ctx.accounts.pool.get_time(contract)?;
 
-    // Check if option is available to exercise, before expired time.
-    require_gt!(
-        option_detail.expired_date,
-        current_timestamp,
-        OptionError::InvalidTimeError
-    );
+    // American options only, before expired time
+    option_detail.check_exercisable(current_timestamp)?;
 
+    let locked_oracle_secondary = ctx.accounts.locked_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
     let token_price =
-        OraclePrice::new_from_oracle(locked_oracle, current_timestamp, false)?;
-    let sol_price =
-        OraclePrice::new_from_oracle(custody_oracle, current_timestamp, false)?;
-    let oracle_price = sol_price.get_price();
+        locked_custody.get_oracle_price(locked_oracle, locked_oracle_secondary.as_ref(), current_timestamp)?;
+    let underlying_price =
+        custody.get_oracle_price(custody_oracle, custody_oracle_secondary.as_ref(), current_timestamp)?;
+    let oracle_price = underlying_price.get_price();
 
+    let locked_amount = option_detail.get_locked_amount(option_detail.quantity)?;
     require_gte!(
         locked_custody.token_locked,
-        option_detail.amount,
+        locked_amount,
         TradingError::InvalidLockedBalanceError
     );
 
@@ -90,7 +89,7 @@ pub fn exercise_option(ctx: Context<ExerciseOption>, params: &ExerciseOptionPara
             price_diff,
             0, // oracle price exponent (assuming normalized)
             option_detail.quantity,
-            0, // quantity exponent 
+            -(option_detail.quantity_decimals as i32), // quantity exponent
             -(custody.decimals as i32), // target token decimals
         )?;        
 
@@ -130,7 +129,7 @@ pub fn exercise_option(ctx: Context<ExerciseOption>, params: &ExerciseOptionPara
             price_diff,
             0, // oracle price exponent (assuming normalized)
             option_detail.quantity,
-            0, // quantity exponent
+            -(option_detail.quantity_decimals as i32), // quantity exponent
             -(custody.decimals as i32), // target token decimals
         )?;
         require_gt!(token_price.price, 0, OptionError::InvalidPriceRequirementError);
@@ -158,10 +157,20 @@ pub fn exercise_option(ctx: Context<ExerciseOption>, params: &ExerciseOptionPara
     // Mark option as exercised and invalid (these changes will now be saved!)
     option_detail.exercised = current_timestamp as u64;
     option_detail.valid = false;
+    let notional_usd = option_detail.get_notional_usd(option_detail.quantity)?;
+    ctx.accounts.pool.remove_strike_expiry_notional(
+        option_detail.strike_price,
+        option_detail.expired_date,
+        option_detail.is_call(),
+        notional_usd,
+    );
+    ctx.accounts.pool.remove_notional(notional_usd);
+
+    let option_key = option_detail.key();
+    ctx.accounts.pool.sync_option_hedge(option_detail, option_key, ctx.accounts.hedge_position.as_deref())?;
 
     // Update locked custody balance
-    locked_custody.token_locked =
-        math::checked_sub(locked_custody.token_locked, option_detail.amount)?;
+    locked_custody.remove_locked(LockedProduct::Option, locked_amount)?;
 
     emit!(OptionExercised {
         owner: option_detail.owner,
@@ -171,7 +180,7 @@ pub fn exercise_option(ctx: Context<ExerciseOption>, params: &ExerciseOptionPara
         period: option_detail.period,
         expired_date: option_detail.expired_date,
         purchase_date: option_detail.purchase_date,
-        option_type: option_detail.option_type,
+        option_type: option_detail.option_type as u8,
         strike_price: option_detail.strike_price,
         valid: option_detail.valid,
         locked_asset: option_detail.locked_asset,
@@ -189,6 +198,8 @@ pub fn exercise_option(ctx: Context<ExerciseOption>, params: &ExerciseOptionPara
         profit: option_detail.profit,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -293,4 +304,20 @@ pub struct ExerciseOption<'info> {
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = locked_oracle_secondary.key() == locked_custody.oracle_secondary
+    )]
+    pub locked_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -0,0 +1,284 @@
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::{LimitOrderExpired, PositionAccountClosed, TpSlOrderbookClosed},
+    math,
+    state::{Contract, Custody, KeeperRewards, LimitOrderBook, OrderType, Pool, Position},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ExpireLimitOrderParams {
+    pub position_index: u64,
+    pub pool_name: String,
+}
+
+/// Expire a pending limit order past its `expiry_time`. The collateral goes back to the
+/// owner less Position::LIMIT_EXPIRY_KEEPER_FEE_BPS, which stays in the pool and is credited
+/// to the keeper's rewards. The position (and TP/SL orderbook) rent goes back to the owner.
+pub fn expire_limit_order(
+    ctx: Context<ExpireLimitOrder>,
+    params: &ExpireLimitOrderParams,
+) -> Result<()> {
+    let contract = &ctx.accounts.contract;
+    let position = &ctx.accounts.position;
+
+    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(
+        position.order_type == OrderType::Limit,
+        PerpetualError::NotLimitOrder
+    );
+    require!(position.execution_time.is_none(), PerpetualError::LimitOrderAlreadyExecuted);
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    let expiry_time = position.expiry_time.ok_or(PerpetualError::LimitOrderNotExpired)?;
+    require!(current_time >= expiry_time, PerpetualError::LimitOrderNotExpired);
+
+    let pay_sol = position.collateral_custody == ctx.accounts.sol_custody.key();
+    let collateral_mint = if pay_sol {
+        ctx.accounts.sol_custody.mint
+    } else {
+        ctx.accounts.usdc_custody.mint
+    };
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        collateral_mint,
+        TradingError::ReceivingAccountMintMismatch
+    );
+
+    // Keeper fee withheld from the refund; the tokens stay owned by the pool, which pays the
+    // keeper its USD value through claim_keeper_rewards
+    let keeper_fee_amount = math::checked_as_u64(math::checked_div(
+        math::checked_mul(position.collateral_amount as u128, Position::LIMIT_EXPIRY_KEEPER_FEE_BPS as u128)?,
+        Contract::BPS_POWER,
+    )?)?;
+    let keeper_fee_usd = math::checked_as_u64(math::checked_div(
+        math::checked_mul(position.collateral_usd as u128, Position::LIMIT_EXPIRY_KEEPER_FEE_BPS as u128)?,
+        Contract::BPS_POWER,
+    )?)?;
+    let refund_amount = math::checked_sub(position.collateral_amount, keeper_fee_amount)?;
+
+    msg!("Expiring limit order of {}", position.owner);
+    msg!("Refund amount: {}", refund_amount);
+    msg!("Keeper fee USD: {}", keeper_fee_usd);
+
+    if refund_amount > 0 {
+        contract.transfer_tokens(
+            if pay_sol {
+                ctx.accounts.sol_custody_token_account.to_account_info()
+            } else {
+                ctx.accounts.usdc_custody_token_account.to_account_info()
+            },
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            refund_amount,
+        )?;
+    }
+    let collateral_custody = if pay_sol {
+        &mut ctx.accounts.sol_custody
+    } else {
+        &mut ctx.accounts.usdc_custody
+    };
+    collateral_custody.token_owned = math::checked_sub(collateral_custody.token_owned, refund_amount)?;
+
+    let keeper_rewards = &mut ctx.accounts.keeper_rewards;
+    if keeper_rewards.keeper == Pubkey::default() {
+        keeper_rewards.keeper = ctx.accounts.keeper.key();
+        keeper_rewards.pool = ctx.accounts.pool.key();
+        keeper_rewards.bump = ctx.bumps.keeper_rewards;
+    }
+    keeper_rewards.accrued_usd = math::checked_add(keeper_rewards.accrued_usd, keeper_fee_usd)?;
+    // The withheld collateral is what funds this reward
+    let pool = &mut ctx.accounts.pool;
+    pool.keeper_reward_budget_usd = math::checked_add(pool.keeper_reward_budget_usd, keeper_fee_usd)?;
+
+    let position_key = position.key();
+    position.require_limit_order_book(ctx.accounts.limit_order_book.is_some())?;
+    if let Some(book) = ctx.accounts.limit_order_book.as_mut() {
+        book.remove(&position_key);
+    }
+
+    emit!(LimitOrderExpired {
+        pub_key: position_key,
+        index: position.index,
+        owner: position.owner,
+        pool: position.pool,
+        keeper: ctx.accounts.keeper.key(),
+        side: position.side as u8,
+        size_usd: position.size_usd,
+        collateral_custody: position.collateral_custody,
+        collateral_amount: position.collateral_amount,
+        refund_amount,
+        keeper_fee_usd,
+        expiry_time,
+        expired_at: current_time,
+    });
+
+    // Close the TP/SL orderbook the order references, rent to the owner
+    if let Some(orderbook_key) = position.tp_sl_orderbook {
+        let orderbook_info = ctx
+            .accounts
+            .tp_sl_orderbook
+            .as_ref()
+            .ok_or(TradingError::Unauthorized)?;
+        require_keys_eq!(orderbook_info.key(), orderbook_key, TradingError::Unauthorized);
+        let orderbook_rent = orderbook_info.lamports();
+        **orderbook_info.try_borrow_mut_lamports()? = 0;
+        **ctx.accounts.owner.try_borrow_mut_lamports()? = ctx.accounts.owner
+            .lamports()
+            .checked_add(orderbook_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        orderbook_info.try_borrow_mut_data()?.fill(0);
+
+        emit!(TpSlOrderbookClosed {
+            owner: position.owner,
+            position: position_key,
+            contract_type: 0, // perp
+            rent_refunded: orderbook_rent,
+        });
+    }
+
+    // Close the position account, rent to the owner
+    let position_info = ctx.accounts.position.to_account_info();
+    let position_rent = position_info.lamports();
+    **position_info.try_borrow_mut_lamports()? = 0;
+    **ctx.accounts.owner.try_borrow_mut_lamports()? = ctx.accounts.owner
+        .lamports()
+        .checked_add(position_rent)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    position_info.try_borrow_mut_data()?.fill(0);
+
+    emit!(PositionAccountClosed {
+        owner: ctx.accounts.owner.key(),
+        position_key,
+        position_index: params.position_index,
+        pool: ctx.accounts.pool.key(),
+        rent_refunded: position_rent,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: ExpireLimitOrderParams)]
+pub struct ExpireLimitOrder<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    /// CHECK: Position owner, receives the refund and the rent
+    #[account(
+        mut,
+        constraint = owner.key() == position.owner @ TradingError::Unauthorized
+    )]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.owner == position.owner @ TradingError::Unauthorized
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            position.owner.as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = KeeperRewards::LEN,
+        seeds = [b"keeper_rewards", keeper.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub keeper_rewards: Box<Account<'info, KeeperRewards>>,
+
+    /// CHECK: TP/SL orderbook of the order, required when position.tp_sl_orderbook is set
+    #[account(mut)]
+    pub tp_sl_orderbook: Option<AccountInfo<'info>>,
+
+    // Shared limit order book, required when position.in_limit_order_book is set
+    #[account(
+        mut,
+        seeds = [b"limit_order_book", pool.key().as_ref()],
+        bump = limit_order_book.bump
+    )]
+    pub limit_order_book: Option<Box<Account<'info, LimitOrderBook>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
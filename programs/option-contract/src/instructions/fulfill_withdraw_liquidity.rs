@@ -0,0 +1,269 @@
+use crate::{
+    errors::PoolError,
+    events::WithdrawalFulfilled,
+    math,
+    state::{Contract, Custody, Pool, WithdrawalRequest},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FulfillWithdrawLiquidityParams {
+    pub owner: Pubkey, // Owner of the withdrawal request
+    pub pool_name: String,
+}
+
+/// Redeem as much of the request at the head of the custody's withdrawal queue as its free
+/// liquidity allows, leaving the reserves of other requests untouched, so requests are
+/// filled in the order they were made. The request account is closed once it is fully
+/// filled, as are cancelled placeholders reaching the head. Requests older than
+/// RATIO_CHECK_TIMEOUT_SEC skip the token ratio check.
+pub fn fulfill_withdraw_liquidity<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FulfillWithdrawLiquidity<'info>>,
+    params: &FulfillWithdrawLiquidityParams,
+) -> Result<()> {
+    msg!("Fulfilling queued liquidity withdrawal");
+
+    let contract = ctx.accounts.contract.as_mut();
+    let custody = ctx.accounts.custody.as_mut();
+    let pool = ctx.accounts.pool.as_mut();
+    let request = ctx.accounts.withdrawal_request.as_mut();
+    let token_id = pool.get_token_id(&custody.key())?;
+
+    // A request cancelled behind the head only leaves the queue here
+    if request.is_cancelled() {
+        custody.withdrawal_queue_head = math::checked_add(custody.withdrawal_queue_head, 1)?;
+        WithdrawalRequest::close_account(
+            &ctx.accounts.withdrawal_request.to_account_info(),
+            &ctx.accounts.owner,
+        )?;
+        msg!("Cancelled withdrawal request closed");
+        return Ok(());
+    }
+
+    let curtime = pool.get_time(contract)?;
+
+    // Refresh pool.aum_usd to adapt to token price change
+    let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+    let incremental = pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
+
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let token_price = custody.get_oracle_price(&ctx.accounts.custody_oracle_account, custody_oracle_secondary.as_ref(), curtime)?;
+
+    let lp_supply = ctx.accounts.lp_token_mint.supply;
+    let lp_share_price_usd = pool.get_lp_share_price_usd(lp_supply)?;
+    let request_amount =
+        token_price.get_token_amount(pool.get_lp_token_value_usd(request.lp_amount, lp_supply)?, custody.decimals)?;
+
+    // Free liquidity excluding what settlements and the other queued requests hold back
+    let other_reserves = custody.reserved_for_withdrawals.saturating_sub(request.reserved_amount);
+    let free_amount = custody
+        .token_owned
+        .saturating_sub(custody.token_locked)
+        .saturating_sub(custody.reserved_for_settlement)
+        .saturating_sub(other_reserves);
+
+    let lp_amount_filled = if free_amount >= request_amount {
+        request.lp_amount
+    } else {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(request.lp_amount as u128, free_amount as u128)?,
+            request_amount as u128,
+        )?)?
+    };
+    require!(lp_amount_filled > 0, PoolError::WithdrawalNotFillable);
+
+    let withdrawal_amount = if lp_amount_filled == request.lp_amount {
+        request_amount
+    } else {
+        token_price.get_token_amount(pool.get_lp_token_value_usd(lp_amount_filled, lp_supply)?, custody.decimals)?
+    };
+
+    let fee_amount =
+        pool.get_remove_liquidity_fee(token_id, withdrawal_amount, custody, &token_price)?;
+    let transfer_amount = math::checked_sub(withdrawal_amount, fee_amount)?;
+
+    msg!("LP tokens filled: {} of {}", lp_amount_filled, request.lp_amount);
+    msg!("Collected fee: {}", fee_amount);
+    msg!("Amount out: {}", transfer_amount);
+
+    if !request.is_ratio_check_waived(curtime)? {
+        require!(
+            pool.check_token_ratio(token_id, 0, withdrawal_amount, custody, &token_price)?,
+            PoolError::TokenRatioOutOfRange
+        );
+    }
+
+    contract.transfer_tokens(
+        ctx.accounts.custody_token_account.to_account_info(),
+        ctx.accounts.receiving_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        transfer_amount,
+    )?;
+
+    contract.burn_owned_tokens(
+        ctx.accounts.lp_token_mint.to_account_info(),
+        ctx.accounts.lp_escrow_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        lp_amount_filled,
+    )?;
+
+    // update custody and queue stats
+    let reserved_released = request.get_released_reservation(lp_amount_filled)?;
+    custody.token_owned = math::checked_sub(custody.token_owned, withdrawal_amount)?;
+    // The fee stays in the token account outside token_owned; with epochs on it goes to LPs
+    if let Some(epoch) = pool.get_lp_fee_epoch(curtime) {
+        custody.accrue_lp_fee(fee_amount, epoch, ctx.accounts.lp_token_mint.supply)?;
+    }
+    custody.reserved_for_withdrawals =
+        custody.reserved_for_withdrawals.saturating_sub(reserved_released);
+    pool.pending_withdrawal_lp = math::checked_sub(pool.pending_withdrawal_lp, lp_amount_filled)?;
+
+    request.lp_amount = math::checked_sub(request.lp_amount, lp_amount_filled)?;
+    request.reserved_amount = math::checked_sub(request.reserved_amount, reserved_released)?;
+    request.last_fill_time = curtime;
+    if request.lp_amount == 0 {
+        custody.withdrawal_queue_head = math::checked_add(custody.withdrawal_queue_head, 1)?;
+    }
+
+    // update pool stats
+    custody.exit(&crate::ID)?;
+    if incremental {
+        let withdrawal_usd = token_price.get_asset_amount_usd(withdrawal_amount, custody.decimals)?;
+        pool.aum_usd = pool.aum_usd.saturating_sub(withdrawal_usd as u128);
+    } else {
+        pool.aum_usd = pool.get_assets_under_management_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
+    }
+
+    emit!(WithdrawalFulfilled {
+        owner: params.owner,
+        pool: pool.key(),
+        custody: custody.key(),
+        request: request.key(),
+        keeper: ctx.accounts.keeper.key(),
+        lp_amount_filled,
+        lp_amount_remaining: request.lp_amount,
+        transfer_amount,
+        fee_amount,
+        withdrawal_amount,
+        pool_aum_usd: pool.aum_usd,
+        fill_time: curtime,
+        token_price: token_price.price,
+        token_price_exponent: token_price.exponent,
+        lp_share_price_usd,
+    });
+
+    // Fully filled requests are closed, rent goes back to the owner
+    if request.lp_amount == 0 {
+        WithdrawalRequest::close_account(
+            &ctx.accounts.withdrawal_request.to_account_info(),
+            &ctx.accounts.owner,
+        )?;
+        msg!("Withdrawal request fully filled and closed");
+    }
+
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: FulfillWithdrawLiquidityParams)]
+pub struct FulfillWithdrawLiquidity<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    /// CHECK: Request owner, receives the rent once the request is filled
+    #[account(
+        mut,
+        constraint = owner.key() == params.owner
+    )]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = receiving_account.mint == custody.mint,
+        constraint = receiving_account.owner == params.owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = lp_escrow_account.mint == lp_token_mint.key(),
+        constraint = lp_escrow_account.owner == transfer_authority.key()
+    )]
+    pub lp_escrow_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the withdrawn token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account", pool.key().as_ref(), custody.mint.as_ref()],
+        bump = custody.token_account_bump
+    )]
+    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"withdrawal_request",
+            custody.key().as_ref(),
+            custody.withdrawal_queue_head.to_le_bytes().as_ref()
+        ],
+        bump = withdrawal_request.bump,
+        constraint = withdrawal_request.owner == params.owner
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    pub token_program: Program<'info, Token>,
+    // remaining accounts (optional, required once pool.aum_usd is stale):
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
@@ -0,0 +1,126 @@
+use crate::{
+    errors::{FutureError, TradingError},
+    events::FutureMark,
+    math::{self, f64_to_scaled_price},
+    state::{Contract, Custody, Future, FutureStatus, Pool, Side},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GetFutureMarkParams {
+    pub future_index: u64,
+    pub pool_name: String,
+}
+
+/// Read-only valuation of an active future with the same math close_future settles with:
+/// spot compounded at the fixed rate for the remaining time, unrealized pnl, leverage on
+/// current equity and distance to the liquidation price. Meant to be called through
+/// transaction simulation.
+pub fn get_future_mark(ctx: Context<GetFutureMark>, params: &GetFutureMarkParams) -> Result<()> {
+    let future = &ctx.accounts.future;
+    require!(
+        future.status == FutureStatus::Active,
+        FutureError::FutureNotActive
+    );
+
+    let current_time = ctx.accounts.pool.get_time(&ctx.accounts.contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = ctx.accounts.sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let spot_price = sol_price.get_price();
+    let spot_price_scaled = f64_to_scaled_price(spot_price)?;
+
+    let remaining_years = future.time_to_expiry(current_time) as f64 / (365.25 * 24.0 * 3600.0);
+    let mark_price = f64_to_scaled_price(Future::calculate_theoretical_price(
+        spot_price,
+        future.fixed_interest_rate_bps,
+        remaining_years,
+    )?)?;
+
+    let unrealized_pnl = future.calculate_pnl(spot_price_scaled, current_time)?;
+    let equity_usd = (future.collateral_usd as i64 + unrealized_pnl).max(0) as u64;
+    let current_leverage = if equity_usd == 0 {
+        0.0
+    } else {
+        math::checked_float_div(future.size_usd as f64, equity_usd as f64)?
+    };
+
+    // Share of spot the price can still move against the future before liquidation
+    let liquidation_price = future.calculate_liquidation_price(current_time)?;
+    let price_buffer = match future.side {
+        Side::Long => spot_price_scaled.saturating_sub(liquidation_price),
+        Side::Short => liquidation_price.saturating_sub(spot_price_scaled),
+    };
+    let liquidation_distance_bps = math::checked_as_u64(math::checked_div(
+        math::checked_mul(price_buffer as u128, 10_000u128)?,
+        spot_price_scaled.max(1) as u128,
+    )?)?;
+
+    emit!(FutureMark {
+        future_key: future.key(),
+        future_index: params.future_index,
+        owner: future.owner,
+        spot_price: spot_price_scaled,
+        mark_price,
+        future_price: future.future_price,
+        unrealized_pnl,
+        equity_usd,
+        current_leverage,
+        liquidation_price,
+        liquidation_distance_bps,
+        time_to_expiry: future.time_to_expiry(current_time),
+        marked_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: GetFutureMarkParams)]
+pub struct GetFutureMark<'info> {
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [
+            b"future",
+            future.owner.as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = future.bump
+    )]
+    pub future: Box<Account<'info, Future>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
@@ -0,0 +1,43 @@
+use crate::state::{LimitOrderBook, Pool};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InitLimitOrderBookParams {
+    pub pool_name: String,
+}
+
+pub fn init_limit_order_book(
+    ctx: Context<InitLimitOrderBook>,
+    _params: &InitLimitOrderBookParams,
+) -> Result<()> {
+    msg!("Initializing shared limit order book");
+
+    let book = &mut ctx.accounts.limit_order_book;
+    book.initialize(ctx.accounts.pool.key(), ctx.bumps.limit_order_book)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: InitLimitOrderBookParams)]
+pub struct InitLimitOrderBook<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = LimitOrderBook::LEN,
+        seeds = [b"limit_order_book", pool.key().as_ref()],
+        bump
+    )]
+    pub limit_order_book: Box<Account<'info, LimitOrderBook>>,
+
+    pub system_program: Program<'info, System>,
+}
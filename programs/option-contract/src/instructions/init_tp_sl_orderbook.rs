@@ -1,14 +1,14 @@
 use crate::{
-    errors::TradingError,
+    errors::{FutureError, TradingError},
     events::TpSlOrderbookInitialized,
-    state::{Pool, Position, OptionDetail, TpSlOrderbook},
+    state::{Future, FutureStatus, Pool, Position, OptionDetail, TpSlOrderbook},
 };
 use anchor_lang::prelude::*;
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct InitTpSlOrderbookParams {
-    pub order_type: u8,       // 0 = Perp, 1 = Option
-    pub position_index: u64,     // Position or Option index
+    pub order_type: u8,       // 0 = Perp, 1 = Option, 2 = Future
+    pub position_index: u64,     // Position, Option or Future index
     pub pool_name: String,
 }
 
@@ -20,6 +20,7 @@ pub fn init_tp_sl_orderbook(
     
     let orderbook = &mut ctx.accounts.tp_sl_orderbook;
     let owner = ctx.accounts.owner.key();
+    let max_orders = ctx.accounts.pool.get_max_tp_sl_orders();
     
     // Initialize based on position type
     match params.order_type {
@@ -37,6 +38,7 @@ pub fn init_tp_sl_orderbook(
                 owner,
                 position.key(),
                 params.order_type,
+                max_orders,
                 ctx.bumps.tp_sl_orderbook,
             )?;
             
@@ -57,12 +59,30 @@ pub fn init_tp_sl_orderbook(
                 owner,
                 option.key(),
                 params.order_type,
+                max_orders,
                 ctx.bumps.tp_sl_orderbook,
             )?;
             
             // Link option to orderbook
             option.tp_sl_orderbook = Some(orderbook.key());
         },
+        2 => {
+            // Future position (linked through the orderbook's position key only)
+            let future = ctx.accounts.future.as_ref().unwrap();
+            
+            // Validation
+            require_keys_eq!(future.owner, owner, TradingError::Unauthorized);
+            require!(future.status == FutureStatus::Active, FutureError::FutureNotActive);
+            
+            // Initialize orderbook
+            orderbook.initialize(
+                owner,
+                future.key(),
+                params.order_type,
+                max_orders,
+                ctx.bumps.tp_sl_orderbook,
+            )?;
+        },
         _ => return Err(TradingError::InvalidOrderType.into()),
     }
     
@@ -70,6 +90,7 @@ pub fn init_tp_sl_orderbook(
         owner,
         position: orderbook.position,
         contract_type: orderbook.contract_type,
+        max_orders: orderbook.max_orders,
         bump: orderbook.bump,
     });
     
@@ -110,5 +131,8 @@ pub struct InitTpSlOrderbook<'info> {
     // Option account (for options - only present when order_type = 1)  
     pub option_detail: Option<Box<Account<'info, OptionDetail>>>,
     
+    // Future account (for futures - only present when order_type = 2)
+    pub future: Option<Box<Account<'info, Future>>>,
+    
     pub system_program: Program<'info, System>,
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use crate::{
-    errors::{TradingError, PerpetualError, OptionError},
+    errors::{TradingError, PerpetualError, OptionError, FutureError},
     events::{TpSlOrderAdded, TpSlOrderRemoved, TpSlOrderUpdated},
-    state::{Pool, Position, OptionDetail, TpSlOrderbook, Side, Contract, Custody},
+    state::{Pool, Position, OptionDetail, TpSlOrderbook, Side, Contract, Custody, Future, FutureStatus},
     math::scaled_price_to_f64,
 };
 use anchor_lang::prelude::*;
@@ -17,6 +17,16 @@ pub enum OrderAction {
     ClearAll,
 }
 
+impl OrderAction {
+    /// Whether the action leaves an order that a keeper could execute
+    pub fn places_order(&self) -> bool {
+        matches!(
+            self,
+            Self::AddTakeProfit { .. } | Self::AddStopLoss { .. } | Self::UpdateTakeProfit { .. } | Self::UpdateStopLoss { .. }
+        )
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct ManageTpSlOrdersParams {
     pub contract_type: u8,
@@ -46,7 +56,7 @@ pub fn manage_tp_sl_orders(
         let sol_custody = ctx.accounts.sol_custody.as_mut().unwrap();
         let usdc_custody = ctx.accounts.usdc_custody.as_mut().unwrap();
         
-        let current_time = contract.get_time()?;
+        let current_time = pool.get_time(contract)?;
         pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
         
         // Update position timestamp to reflect TP/SL management activity
@@ -61,6 +71,11 @@ pub fn manage_tp_sl_orders(
             require_keys_eq!(position.owner, owner, TradingError::Unauthorized);
             require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
             require_keys_eq!(orderbook.position, position.key(), TradingError::InvalidPosition);
+            // TP/SL execution can't return LP collateral, so such positions only clear orders
+            require!(
+                position.lp_collateral_amount == 0 || !params.action.places_order(),
+                PerpetualError::LpCollateralUnsupported
+            );
             
             // Validate prices based on position side
             match &params.action {
@@ -101,7 +116,7 @@ pub fn manage_tp_sl_orders(
                 OrderAction::AddTakeProfit { price, .. } |
                 OrderAction::UpdateTakeProfit { new_price: Some(price), .. } => {
                     let price_f64 = scaled_price_to_f64(*price)?;
-                    if option.option_type == 0 { // Call
+                    if option.is_call() { // Call
                         require!(price_f64 > strike_price_f64, TradingError::InvalidTakeProfitPrice);
                     } else { // Put
                         require!(price_f64 < strike_price_f64, TradingError::InvalidTakeProfitPrice);
@@ -110,7 +125,7 @@ pub fn manage_tp_sl_orders(
                 OrderAction::AddStopLoss { price, .. } |
                 OrderAction::UpdateStopLoss { new_price: Some(price), .. } => {
                     let price_f64 = scaled_price_to_f64(*price)?;
-                    if option.option_type == 0 { // Call
+                    if option.is_call() { // Call
                         require!(price_f64 < strike_price_f64, TradingError::InvalidStopLossPrice);
                     } else { // Put
                         require!(price_f64 > strike_price_f64, TradingError::InvalidStopLossPrice);
@@ -119,6 +134,42 @@ pub fn manage_tp_sl_orders(
                 _ => {}
             }
         },
+        2 => {
+            // Future position validation
+            let future = ctx.accounts.future.as_ref().unwrap();
+            require_keys_eq!(future.owner, owner, TradingError::Unauthorized);
+            require!(future.status == FutureStatus::Active, FutureError::FutureNotActive);
+            require_keys_eq!(orderbook.position, future.key(), TradingError::InvalidPosition);
+            require!(
+                future.lp_collateral_amount == 0 || !params.action.places_order(),
+                PerpetualError::LpCollateralUnsupported
+            );
+            
+            // Validate prices based on future side
+            match &params.action {
+                OrderAction::AddTakeProfit { price, .. } | 
+                OrderAction::UpdateTakeProfit { new_price: Some(price), .. } => {
+                    match future.side {
+                        Side::Long => require!(*price > future.entry_price, TradingError::InvalidTakeProfitPrice),
+                        Side::Short => require!(*price < future.entry_price, TradingError::InvalidTakeProfitPrice),
+                    }
+                },
+                OrderAction::AddStopLoss { price, .. } |
+                OrderAction::UpdateStopLoss { new_price: Some(price), .. } => {
+                    match future.side {
+                        Side::Long => {
+                            require!(*price < future.entry_price, TradingError::InvalidStopLossPrice);
+                            require!(*price > future.liquidation_price, TradingError::InvalidStopLossPrice);
+                        },
+                        Side::Short => {
+                            require!(*price > future.entry_price, TradingError::InvalidStopLossPrice);
+                            require!(*price < future.liquidation_price, TradingError::InvalidStopLossPrice);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        },
         _ => return Err(TradingError::InvalidOrderType.into()),
     }
     
@@ -129,6 +180,9 @@ pub fn manage_tp_sl_orders(
             let (accrued_borrow_fees, last_borrow_fees_update_time, position_side) = if params.contract_type == 0 {
                 let position = ctx.accounts.position.as_ref().unwrap();
                 (position.accrued_borrow_fees, position.last_borrow_fees_update_time, position.side as u8)
+            } else if params.contract_type == 2 {
+                let future = ctx.accounts.future.as_ref().unwrap();
+                (0, 0, future.side as u8) // Futures use a fixed rate instead of borrow fees
             } else {
                 (0, 0, 0) // For options, position_side is not applicable
             };
@@ -151,6 +205,9 @@ pub fn manage_tp_sl_orders(
             let (accrued_borrow_fees, last_borrow_fees_update_time, position_side) = if params.contract_type == 0 {
                 let position = ctx.accounts.position.as_ref().unwrap();
                 (position.accrued_borrow_fees, position.last_borrow_fees_update_time, position.side as u8)
+            } else if params.contract_type == 2 {
+                let future = ctx.accounts.future.as_ref().unwrap();
+                (0, 0, future.side as u8) // Futures use a fixed rate instead of borrow fees
             } else {
                 (0, 0, 0) // For options, position_side is not applicable
             };
@@ -293,6 +350,9 @@ pub struct ManageTpSlOrders<'info> {
     // Option account (for options - only present when contract_type = 1)
     pub option_detail: Option<Box<Account<'info, OptionDetail>>>,
     
+    // Future account (for futures - only present when contract_type = 2)
+    pub future: Option<Box<Account<'info, Future>>>,
+    
     // Custody accounts (for perps - only present when contract_type = 0)
     pub sol_custody: Option<Box<Account<'info, Custody>>>,
     pub usdc_custody: Option<Box<Account<'info, Custody>>>,
@@ -0,0 +1,121 @@
+use crate::{
+    errors::{FutureError, TradingError},
+    events::FutureExpiryMarked,
+    math::f64_to_scaled_price,
+    state::{Contract, Custody, Future, Pool},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MarkFutureExpiredParams {
+    pub future_index: u64,         // Index of future to mark
+    pub pool_name: String,         // Pool name for seeds
+    pub owner: Pubkey,             // Owner of the future (for seeds)
+}
+
+/// Freeze the spot price at expiry so a later settle_expired_future pays out at the
+/// expiry price rather than whatever the price has drifted to by then. The price is the one
+/// published at expiry_time, so the caller can't pick it by choosing when to mark.
+pub fn mark_future_expired(ctx: Context<MarkFutureExpired>, params: &MarkFutureExpiredParams) -> Result<()> {
+    msg!("Marking future as expired");
+
+    let contract = &ctx.accounts.contract;
+    let future = &mut ctx.accounts.future;
+    let sol_custody = &ctx.accounts.sol_custody;
+
+    require_keys_eq!(
+        future.owner,
+        params.owner,
+        TradingError::Unauthorized
+    );
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    require!(
+        future.is_expired(current_time),
+        FutureError::FutureNotYetExpired
+    );
+
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price_update = ctx.accounts.sol_price_update.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price_at(
+        &ctx.accounts.sol_oracle_account,
+        sol_oracle_secondary.as_ref(),
+        sol_price_update.as_ref(),
+        future.expiry_time,
+    )?;
+    let expiry_spot_price = f64_to_scaled_price(sol_price.get_price())?;
+
+    future.mark_expired(expiry_spot_price, current_time)?;
+
+    msg!("Expiry spot price: {}", sol_price.get_price());
+    msg!("Marked {} seconds after expiry", current_time - future.expiry_time);
+
+    emit!(FutureExpiryMarked {
+        owner: future.owner,
+        future_key: future.key(),
+        index: future.index,
+        settlement_price: expiry_spot_price,
+        expiry_time: future.expiry_time,
+        marked_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: MarkFutureExpiredParams)]
+pub struct MarkFutureExpired<'info> {
+    /// CHECK: This can be any account - keeper, owner, or other authorized party
+    pub keeper: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"future",
+            params.owner.as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = future.bump
+    )]
+    pub future: Box<Account<'info, Future>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Price update posted for the expiry, checked against the SOL oracle's feed
+    pub sol_price_update: Option<UncheckedAccount<'info>>,
+}
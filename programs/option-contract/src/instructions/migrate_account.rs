@@ -0,0 +1,543 @@
+use crate::{
+    errors::ContractError,
+    events::AccountMigrated,
+    state::{Contract, Custody, Future, OptionDetail, Pool, Position, User},
+};
+use anchor_lang::{prelude::*, Discriminator};
+
+/// Grow an account created before its type gained fields to the current layout. Fields are
+/// only ever appended, so the zero-filled tail deserializes as None / 0 / false for them.
+/// Anyone can call it: the payer covers the extra rent and the account can only grow to the
+/// current size of its own type.
+pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+    let account = &ctx.accounts.account;
+    require_keys_eq!(*account.owner, crate::ID, ContractError::AccountNotMigratable);
+
+    let old_len = account.data_len();
+    let new_len = get_migrated_len(&account.try_borrow_data()?)?;
+    if old_len >= new_len {
+        msg!("Account is already at the current layout");
+        return Ok(());
+    }
+
+    Contract::realloc(
+        ctx.accounts.payer.to_account_info(),
+        account.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        new_len,
+        true,
+    )?;
+
+    msg!("Migrated account from {} to {} bytes", old_len, new_len);
+    emit!(AccountMigrated {
+        account: account.key(),
+        old_len: old_len as u64,
+        new_len: new_len as u64,
+        migrated_at: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Current size of the account whose data is `data`, chosen by its discriminator
+pub fn get_migrated_len(data: &[u8]) -> Result<usize> {
+    require!(data.len() >= 8, ContractError::AccountNotMigratable);
+    let discriminator = &data[..8];
+
+    if discriminator == Position::DISCRIMINATOR {
+        Ok(Position::LEN)
+    } else if discriminator == User::DISCRIMINATOR {
+        Ok(User::LEN)
+    } else if discriminator == OptionDetail::DISCRIMINATOR {
+        Ok(OptionDetail::LEN)
+    } else if discriminator == Custody::DISCRIMINATOR {
+        Ok(Custody::LEN)
+    } else if discriminator == Future::DISCRIMINATOR {
+        Ok(Future::LEN)
+    } else if discriminator == Pool::DISCRIMINATOR {
+        let (custody_count, ratio_count) = get_pool_vec_lens(data)?;
+        Ok(Pool::get_space(custody_count, ratio_count))
+    } else if discriminator == Contract::DISCRIMINATOR {
+        let pool_count = read_vec_len(data, 8)?;
+        Ok(Contract::get_space(pool_count))
+    } else {
+        err!(ContractError::AccountNotMigratable)
+    }
+}
+
+/// Custody and ratio counts of a serialized pool. They are read from the vectors at the front
+/// of the layout, which every release shares, so a pool too short to deserialize can be sized.
+fn get_pool_vec_lens(data: &[u8]) -> Result<(usize, usize)> {
+    let read_len = |offset: usize| read_vec_len(data, offset);
+    let vec_end = |offset: usize, len: usize, item_size: usize| -> Result<usize> {
+        len.checked_mul(item_size)
+            .and_then(|size| size.checked_add(offset + 4))
+            .ok_or_else(|| ContractError::AccountNotMigratable.into())
+    };
+
+    let name_len = read_len(8)?;
+    let custodies_offset = vec_end(8, name_len, 1)?;
+    let custody_count = read_len(custodies_offset)?;
+    let ratios_offset = vec_end(custodies_offset, custody_count, std::mem::size_of::<Pubkey>())?;
+    let ratio_count = read_len(ratios_offset)?;
+    Ok((custody_count, ratio_count))
+}
+
+/// Length prefix of the borsh vector or string serialized at `offset`
+fn read_vec_len(data: &[u8], offset: usize) -> Result<usize> {
+    let bytes: [u8; 4] = offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ContractError::AccountNotMigratable)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: owner and discriminator are checked in the handler; the account may be too short
+    /// to deserialize as its current type, which is what this instruction fixes
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{ExerciseStyle, Fees, FutureStatus, OrderType, Side, TokenRatios},
+        utils::BorrowRateCurve,
+    };
+
+    // Position as first released, before any field was appended
+    #[derive(AnchorSerialize)]
+    struct LegacyPosition {
+        index: u64,
+        owner: Pubkey,
+        pool: Pubkey,
+        custody: Pubkey,
+        collateral_custody: Pubkey,
+        order_type: OrderType,
+        side: Side,
+        is_liquidated: bool,
+        entry_price: u64,
+        size_usd: u64,
+        collateral_usd: u64,
+        open_time: i64,
+        update_time: i64,
+        execution_time: Option<i64>,
+        liquidation_price: u64,
+        cumulative_interest_snapshot: u128,
+        last_borrow_fees_update_time: i64,
+        accrued_borrow_fees: u64,
+        borrow_fees_paid: u64,
+        trade_fees: u64,
+        locked_amount: u64,
+        collateral_amount: u64,
+        tp_sl_orderbook: Option<Pubkey>,
+        trigger_price: Option<u64>,
+        trigger_above_threshold: bool,
+        bump: u8,
+    }
+
+    fn legacy_account_data<T: AnchorSerialize>(discriminator: &[u8], legacy: &T) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn migrated_position_keeps_legacy_fields() {
+        let owner = Pubkey::new_unique();
+        let legacy = LegacyPosition {
+            index: 7,
+            owner,
+            pool: Pubkey::new_unique(),
+            custody: Pubkey::new_unique(),
+            collateral_custody: Pubkey::new_unique(),
+            order_type: OrderType::Market,
+            side: Side::Short,
+            is_liquidated: false,
+            entry_price: 150_000_000,
+            size_usd: 1_000_000_000,
+            collateral_usd: 100_000_000,
+            open_time: 1_700_000_000,
+            update_time: 1_700_000_100,
+            execution_time: Some(1_700_000_000),
+            liquidation_price: 163_000_000,
+            cumulative_interest_snapshot: 42,
+            last_borrow_fees_update_time: 1_700_000_100,
+            accrued_borrow_fees: 5,
+            borrow_fees_paid: 3,
+            trade_fees: 1_000_000,
+            locked_amount: 900,
+            collateral_amount: 100_000_000,
+            tp_sl_orderbook: None,
+            trigger_price: None,
+            trigger_above_threshold: false,
+            bump: 254,
+        };
+        let mut data = legacy_account_data(Position::DISCRIMINATOR, &legacy);
+        assert!(data.len() < get_migrated_len(&data).unwrap());
+
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let position = Position::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(position.index, 7);
+        assert_eq!(position.owner, owner);
+        assert_eq!(position.side, Side::Short);
+        assert_eq!(position.liquidation_price, 163_000_000);
+        assert_eq!(position.trade_fees, 1_000_000);
+        assert_eq!(position.bump, 254);
+        // Appended fields read as their defaults
+        assert_eq!(position.bankruptcy_price, 0);
+        assert_eq!(position.stop_price, None);
+        assert_eq!(position.referrer, None);
+        assert_eq!(position.expiry_time, None);
+        assert_eq!(position.max_loss_price, 0);
+    }
+
+    #[derive(AnchorSerialize)]
+    struct LegacyUser {
+        option_index: u64,
+        bump: u8,
+        perp_position_index: u64,
+        future_index: u64,
+    }
+
+    #[test]
+    fn migrated_user_defaults_to_usdc_settlement() {
+        let legacy = LegacyUser { option_index: 3, bump: 255, perp_position_index: 9, future_index: 1 };
+        let mut data = legacy_account_data(User::DISCRIMINATOR, &legacy);
+        // Allocated exactly, with no room for default_receive_sol
+        assert!(User::try_deserialize(&mut data.as_slice()).is_err());
+
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let user = User::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(user.option_index, 3);
+        assert_eq!(user.perp_position_index, 9);
+        assert_eq!(user.future_index, 1);
+        assert!(!user.default_receive_sol);
+    }
+
+    // OptionDetail as first released, allocated at exactly OptionDetail::RELEASED_LEN
+    #[derive(AnchorSerialize)]
+    struct LegacyOptionDetail {
+        index: u64,
+        owner: Pubkey,
+        amount: u64,
+        quantity: u64,
+        strike_price: u64,
+        period: u64,
+        expired_date: i64,
+        purchase_date: u64,
+        option_type: u8,
+        premium: u64,
+        premium_asset: Pubkey,
+        profit: u64,
+        locked_asset: Pubkey,
+        pool: Pubkey,
+        custody: Pubkey,
+        exercised: u64,
+        bought_back: u64,
+        claimed: u64,
+        valid: bool,
+        bump: u8,
+        limit_price: u64,
+        executed: bool,
+        entry_price: u64,
+        last_update_time: i64,
+        take_profit_price: Option<u64>,
+        stop_loss_price: Option<u64>,
+        tp_sl_orderbook: Option<Pubkey>,
+    }
+
+    #[test]
+    fn migrated_option_keeps_legacy_fields() {
+        let owner = Pubkey::new_unique();
+        let legacy = LegacyOptionDetail {
+            index: 2,
+            owner,
+            amount: 1_500_000,
+            quantity: 3,
+            strike_price: 180_000_000,
+            period: 7,
+            expired_date: 1_700_604_800,
+            purchase_date: 1_700_000_000,
+            option_type: 1,
+            premium: 1_500_000,
+            premium_asset: Pubkey::new_unique(),
+            profit: 0,
+            locked_asset: Pubkey::new_unique(),
+            pool: Pubkey::new_unique(),
+            custody: Pubkey::new_unique(),
+            exercised: 0,
+            bought_back: 0,
+            claimed: 0,
+            valid: true,
+            bump: 253,
+            limit_price: 0,
+            executed: true,
+            entry_price: 175_000_000,
+            last_update_time: 1_700_000_000,
+            take_profit_price: Some(150_000_000),
+            stop_loss_price: Some(200_000_000),
+            tp_sl_orderbook: Some(Pubkey::new_unique()),
+        };
+        let mut data = legacy_account_data(OptionDetail::DISCRIMINATOR, &legacy);
+        assert_eq!(data.len(), OptionDetail::RELEASED_LEN);
+        assert!(OptionDetail::try_deserialize(&mut data.as_slice()).is_err());
+
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let option = OptionDetail::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(option.owner, owner);
+        assert_eq!(option.quantity, 3);
+        assert!(!option.is_call());
+        assert_eq!(option.stop_loss_price, Some(200_000_000));
+        assert!(option.valid);
+        // Appended fields read as their defaults: a whole-contract American option
+        assert_eq!(option.settlement_price, None);
+        assert_eq!(option.quantity_decimals, 0);
+        assert_eq!(option.referrer, None);
+        assert_eq!(option.exercise_style, ExerciseStyle::American);
+    }
+
+    #[test]
+    fn option_len_fits_every_field() {
+        // Every Option set, so the serialized size is the largest it can be
+        let mut data = vec![0u8; OptionDetail::LEN];
+        data[..8].copy_from_slice(OptionDetail::DISCRIMINATOR);
+        let mut option = OptionDetail::try_deserialize(&mut data.as_slice()).unwrap();
+        option.take_profit_price = Some(1);
+        option.stop_loss_price = Some(1);
+        option.tp_sl_orderbook = Some(Pubkey::new_unique());
+        option.settlement_price = Some(1);
+        option.referrer = Some(Pubkey::new_unique());
+        option.hedge_position = Some(Pubkey::new_unique());
+
+        let mut serialized = Vec::new();
+        option.try_serialize(&mut serialized).unwrap();
+        assert_eq!(serialized.len(), OptionDetail::LEN);
+    }
+
+    // Custody as first released
+    #[derive(AnchorSerialize, Default)]
+    struct LegacyCustody {
+        mint: Pubkey,
+        token_account: Pubkey,
+        decimals: u8,
+        oracle: Pubkey,
+        token_owned: u64,
+        token_locked: u64,
+        fees: Fees,
+        bump: u8,
+        token_account_bump: u8,
+    }
+
+    #[test]
+    fn migrated_custody_keeps_legacy_fields() {
+        // Released custodies were allocated from the in-memory size, not the serialized one
+        assert_eq!(8 + std::mem::size_of::<LegacyCustody>(), Custody::RELEASED_LEN);
+
+        let oracle = Pubkey::new_unique();
+        let legacy = LegacyCustody {
+            mint: Pubkey::new_unique(),
+            token_account: Pubkey::new_unique(),
+            decimals: 9,
+            oracle,
+            token_owned: 5_000_000_000,
+            token_locked: 1_000_000_000,
+            fees: Fees { ratio_mult: 1, add_liquidity: 3, remove_liquidity: 4 },
+            bump: 252,
+            token_account_bump: 251,
+        };
+        let mut data = legacy_account_data(Custody::DISCRIMINATOR, &legacy);
+        data.resize(Custody::RELEASED_LEN, 0);
+        assert!(Custody::try_deserialize(&mut data.as_slice()).is_err());
+
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let custody = Custody::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(custody.decimals, 9);
+        assert_eq!(custody.oracle, oracle);
+        assert_eq!(custody.token_owned, 5_000_000_000);
+        assert_eq!(custody.token_locked, 1_000_000_000);
+        assert_eq!(custody.fees.remove_liquidity, 4);
+        assert_eq!(custody.bump, 252);
+        assert_eq!(custody.token_account_bump, 251);
+        // Appended fields read as their defaults: no failover, Pyth primary, nothing reserved
+        assert_eq!(custody.oracle_secondary, Pubkey::default());
+        assert_eq!(custody.oracle_type_primary, Custody::ORACLE_TYPE_PYTH);
+        assert_eq!(custody.reserved_for_settlement, 0);
+        assert_eq!(custody.option_close_fee_tier_count, 0);
+        assert_eq!(custody.margin_tier_count, 0);
+        assert_eq!(custody.get_locked_by_products().unwrap(), 0);
+    }
+
+    // Pool as first released
+    #[derive(AnchorSerialize, Default)]
+    struct LegacyPool {
+        name: String,
+        custodies: Vec<Pubkey>,
+        ratios: Vec<TokenRatios>,
+        aum_usd: u128,
+        bump: u8,
+        lp_token_bump: u8,
+        borrow_rate_curve: BorrowRateCurve,
+        cumulative_interest_rate_long: u128,
+        cumulative_interest_rate_short: u128,
+        last_rate_update: i64,
+        long_open_interest_usd: u128,
+        short_open_interest_usd: u128,
+        total_borrowed_usd: u128,
+        last_utilization_update: i64,
+        total_future_notional_usd: u128,
+        total_future_time_value: u128,
+        total_option_notional_usd: u128,
+        total_option_time_value: u128,
+        last_fixed_rate_update: i64,
+    }
+
+    #[test]
+    fn migrated_pool_keeps_legacy_fields() {
+        let custodies = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let ratio = TokenRatios { target: 5_000, min: 4_000, max: 6_000 };
+        let legacy = LegacyPool {
+            name: "main".to_string(),
+            custodies: custodies.clone(),
+            ratios: vec![ratio; 2],
+            aum_usd: 1_000_000_000,
+            bump: 250,
+            long_open_interest_usd: 77,
+            last_fixed_rate_update: 1_700_000_000,
+            ..Default::default()
+        };
+        let mut data = legacy_account_data(Pool::DISCRIMINATOR, &legacy);
+        assert!(Pool::try_deserialize(&mut data.as_slice()).is_err());
+
+        assert_eq!(get_migrated_len(&data).unwrap(), Pool::get_space(2, 2));
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let pool = Pool::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(pool.name, "main");
+        assert_eq!(pool.custodies, custodies);
+        assert_eq!(pool.ratios[1], ratio);
+        assert_eq!(pool.aum_usd, 1_000_000_000);
+        assert_eq!(pool.long_open_interest_usd, 77);
+        assert_eq!(pool.last_fixed_rate_update, 1_700_000_000);
+        // Appended fields read as their defaults
+        assert_eq!(pool.sol_mint, Pubkey::default());
+        assert_eq!(pool.close_fee_bps, 0);
+        assert_eq!(pool.notional_usd, 0);
+        assert_eq!(pool.last_seen_time, 0);
+        assert!(!pool.option_grid_only);
+    }
+
+    #[test]
+    fn truncated_pool_vectors_are_rejected() {
+        let legacy = LegacyPool { name: "main".to_string(), ..Default::default() };
+        let data = legacy_account_data(Pool::DISCRIMINATOR, &legacy);
+        // Cut inside the custodies length prefix
+        assert!(get_migrated_len(&data[..8 + 4 + 4 + 2]).is_err());
+    }
+
+    #[derive(AnchorSerialize)]
+    struct LegacyContract {
+        pools: Vec<Pubkey>,
+        bump: u8,
+        transfer_authority_bump: u8,
+    }
+
+    #[test]
+    fn migrated_contract_keeps_its_pools() {
+        let pools = vec![Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let legacy = LegacyContract { pools: pools.clone(), bump: 255, transfer_authority_bump: 254 };
+        let mut data = legacy_account_data(Contract::DISCRIMINATOR, &legacy);
+        assert!(Contract::try_deserialize(&mut data.as_slice()).is_err());
+
+        assert_eq!(get_migrated_len(&data).unwrap(), Contract::get_space(3));
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let contract = Contract::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(contract.pools, pools);
+        assert_eq!(contract.transfer_authority_bump, 254);
+        // Appended fields read as their defaults: no ceiling, not paused
+        assert_eq!(contract.max_global_notional_usd, 0);
+        assert!(!contract.paused);
+    }
+
+    // Future as first released
+    #[derive(AnchorSerialize, Default)]
+    struct LegacyFuture {
+        index: u64,
+        owner: Pubkey,
+        pool: Pubkey,
+        custody: Pubkey,
+        collateral_custody: Pubkey,
+        side: Side,
+        status: FutureStatus,
+        entry_price: u64,
+        future_price: u64,
+        size_usd: u64,
+        collateral_usd: u64,
+        collateral_amount: u64,
+        open_time: i64,
+        expiry_time: i64,
+        update_time: i64,
+        settlement_time: Option<i64>,
+        fixed_interest_rate_bps: u32,
+        time_to_expiry_at_open: i64,
+        liquidation_price: u64,
+        maintenance_margin_bps: u64,
+        settlement_price: Option<u64>,
+        pnl_at_settlement: Option<i64>,
+        settlement_amount: Option<u64>,
+        opening_fee: u64,
+        settlement_fee: u64,
+        locked_amount: u64,
+        trigger_price: Option<u64>,
+        trigger_above_threshold: bool,
+        max_slippage: u64,
+        execution_time: Option<i64>,
+        bump: u8,
+    }
+
+    #[test]
+    fn migrated_future_settles_in_its_collateral() {
+        let collateral_custody = Pubkey::new_unique();
+        let legacy = LegacyFuture {
+            index: 4,
+            collateral_custody,
+            side: Side::Long,
+            status: FutureStatus::Active,
+            size_usd: 2_000_000_000,
+            settlement_price: Some(190_000_000),
+            bump: 249,
+            ..Default::default()
+        };
+        let mut data = legacy_account_data(Future::DISCRIMINATOR, &legacy);
+        data.resize(get_migrated_len(&data).unwrap(), 0);
+        let future = Future::try_deserialize(&mut data.as_slice()).unwrap();
+
+        assert_eq!(future.index, 4);
+        assert_eq!(future.status, FutureStatus::Active);
+        assert_eq!(future.settlement_price, Some(190_000_000));
+        assert_eq!(future.bump, 249);
+        assert_eq!(future.referrer, None);
+        assert_eq!(future.lp_collateral_amount, 0);
+        assert_eq!(future.get_settlement_custody(), collateral_custody);
+    }
+
+    #[test]
+    fn unknown_discriminator_is_rejected() {
+        assert!(get_migrated_len(&[0u8; 8]).is_err());
+        assert!(get_migrated_len(&[0u8; 4]).is_err());
+    }
+}
@@ -4,6 +4,7 @@ pub use auto_exercise::*;
 pub use initialize::*;
 pub use open_option::*;
 pub use edit_option::*;
+pub use roll_option::*;
 pub use set_option_tp_sl::*;
 pub use open_limit_option::*;
 pub use close_limit_option::*;
@@ -13,28 +14,62 @@ pub use remove_custody::*;
 pub use set_signers::*;
 pub use add_liquidity::*;
 pub use remove_liquidity::*;
+pub use request_withdraw_liquidity::*;
+pub use fulfill_withdraw_liquidity::*;
+pub use cancel_withdraw_liquidity::*;
 pub use create_lp_mint::*;
 pub use add_pool::*;
 pub use claim_option::*;
 pub use realloc_pool::*;
 pub use open_perp_position::*;
 pub use close_perp_position::*;
+pub use close_all_positions::*;
 pub use add_collateral::*;
 pub use remove_collateral::*;
 pub use update_position_size::*;
 pub use update_borrow_fees::*;
+pub use set_position_hedge::*;
+pub use set_user_prefs::*;
+pub use claim_keeper_rewards::*;
+pub use claim_referral_fees::*;
+pub use claim_lp_fees::*;
 pub use liquidate::*;
 pub use cancel_limit_order::*;
+pub use expire_limit_order::*;
 pub use execute_limit_order::*;
 pub use init_tp_sl_orderbook::*;
 pub use manage_tp_sl_orders::*;
+pub use close_tp_sl_orderbook::*;
+pub use init_limit_order_book::*;
+pub use execute_future_tp_sl_order::*;
+pub use sweep_closed_option::*;
+pub use simulate_close_perp::*;
+pub use compute_required_collateral::*;
+pub use get_future_mark::*;
+pub use add_liquidity_balanced::*;
+pub use set_custody_config::*;
+pub use transfer_position_ownership::*;
+pub use transfer_future_ownership::*;
+pub use transfer_option_ownership::*;
 pub use execute_tp_sl_order::*;
 pub use open_future::*;
 pub use open_limit_future::*;
 pub use execute_limit_future::*;
 pub use close_future::*;
 pub use settle_expired_future::*;
+pub use mark_future_expired::*;
 pub use claim_future::*;
+pub use close_settled_future::*;
+pub use set_pool_config::*;
+pub use set_pool_ratios::*;
+pub use set_contract_config::*;
+pub use reconcile_custody_locked::*;
+pub use reconcile_open_interest::*;
+pub use set_referral::*;
+pub use stake_lp::*;
+pub use unstake_lp::*;
+pub use migrate_account::*;
+pub use sync_global_notional::*;
 
 pub mod close_option;
 pub mod exercise_option;
@@ -42,6 +77,7 @@ pub mod auto_exercise;
 pub mod initialize;
 pub mod open_option;
 pub mod edit_option;
+pub mod roll_option;
 pub mod set_option_tp_sl;
 pub mod open_limit_option;
 pub mod close_limit_option;
@@ -52,24 +88,58 @@ pub mod remove_custody;
 pub mod set_signers;
 pub mod add_liquidity;
 pub mod remove_liquidity;
+pub mod request_withdraw_liquidity;
+pub mod fulfill_withdraw_liquidity;
+pub mod cancel_withdraw_liquidity;
 pub mod create_lp_mint;
 pub mod claim_option;
 pub mod realloc_pool;
 pub mod open_perp_position;
 pub mod close_perp_position;
+pub mod close_all_positions;
 pub mod add_collateral;
 pub mod remove_collateral;
 pub mod update_position_size;
 pub mod update_borrow_fees;
+pub mod set_position_hedge;
+pub mod set_user_prefs;
+pub mod claim_keeper_rewards;
+pub mod claim_referral_fees;
+pub mod claim_lp_fees;
 pub mod liquidate;
 pub mod cancel_limit_order;
+pub mod expire_limit_order;
 pub mod execute_limit_order;
 pub mod init_tp_sl_orderbook;
 pub mod manage_tp_sl_orders;
+pub mod close_tp_sl_orderbook;
+pub mod init_limit_order_book;
+pub mod execute_future_tp_sl_order;
+pub mod sweep_closed_option;
+pub mod simulate_close_perp;
+pub mod compute_required_collateral;
+pub mod get_future_mark;
+pub mod add_liquidity_balanced;
+pub mod set_custody_config;
+pub mod transfer_position_ownership;
+pub mod transfer_future_ownership;
+pub mod transfer_option_ownership;
 pub mod execute_tp_sl_order;
 pub mod open_future;
 pub mod open_limit_future;
 pub mod execute_limit_future;
 pub mod close_future;
 pub mod settle_expired_future;
+pub mod mark_future_expired;
 pub mod claim_future;
+pub mod close_settled_future;
+pub mod set_pool_config;
+pub mod set_pool_ratios;
+pub mod set_contract_config;
+pub mod reconcile_custody_locked;
+pub mod reconcile_open_interest;
+pub mod set_referral;
+pub mod stake_lp;
+pub mod unstake_lp;
+pub mod migrate_account;
+pub mod sync_global_notional;
@@ -1,8 +1,8 @@
 use crate::{
-    errors::{FutureError, TradingError},
+    errors::{FutureError, PerpetualError, PoolError, TradingError},
     events::FutureOpened,
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, Future, FutureStatus, OraclePrice, Pool, Side, User},
+    state::{Contract, Custody, LockedProduct, Future, FutureStatus, Pool, Referral, Side, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -13,12 +13,18 @@ pub struct OpenFutureParams {
     pub size_usd: u64,                // Position size in USD (6 decimals)
     pub collateral_amount: u64,       // Collateral tokens to deposit
     pub pay_sol: bool,                // Pay collateral in SOL or USDC
+    pub pay_lp: bool,                 // true = collateral_amount is pool LP tokens
+    pub receive_sol: Option<bool>,    // Settle in SOL or USDC on close/expiry, None = user default
     pub expiry_timestamp: i64,        // Future expiry time (unix timestamp)
     pub max_slippage_bps: u64,        // Maximum slippage tolerance in basis points
     pub pool_name: String,            // Pool name for seeds
+    pub referrer: Option<Pubkey>,     // Credited a share of the opening fee
 }
 
-pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Result<()> {
+pub fn open_future<'info>(
+    ctx: Context<'_, '_, 'info, 'info, OpenFuture<'info>>,
+    params: &OpenFutureParams,
+) -> Result<()> {
     msg!("Opening future position");
     msg!("Side: {:?}", params.side);
     msg!("Size USD: {}", params.size_usd);
@@ -36,33 +42,28 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
     let usdc_custody = &mut ctx.accounts.usdc_custody;
 
     // Get current time and validate expiry
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
     
     require!(
         params.expiry_timestamp > current_time,
         FutureError::InvalidExpiryTime
     );
     
-    // Validate expiry is not too far in the future (max 1 year)
-    let max_expiry = current_time + (365 * 24 * 3600);
-    require!(
-        params.expiry_timestamp <= max_expiry,
-        FutureError::ExpiryTooFar
-    );
-    
-    // Validate expiry is not too close (min 1 hour)
-    let min_expiry = current_time + 3600;
-    require!(
-        params.expiry_timestamp >= min_expiry,
-        FutureError::ExpiryTooClose
-    );
+    // A wound-down custody takes no new exposure, whether traded, locked or posted as collateral
+    require!(!sol_custody.trading_paused, PoolError::CustodyTradingPaused);
+    if params.side == Side::Short || !params.pay_sol {
+        require!(!usdc_custody.trading_paused, PoolError::CustodyTradingPaused);
+    }
+
+    // Validate expiry against the pool's allowed futures duration range
+    pool.validate_future_expiry(params.expiry_timestamp, current_time)?;
 
     let time_to_expiry = params.expiry_timestamp - current_time;
 
     // Validate position size
     require!(params.size_usd > 0, FutureError::InvalidFutureSize);
     require!(
-        params.size_usd >= 1_000_000, // Minimum $1
+        params.size_usd >= Contract::USD_SCALE as u64, // Minimum $1
         FutureError::FutureSizeTooSmall
     );
     require!(
@@ -71,8 +72,10 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
     );
 
     // Get oracle prices
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
     
     let current_sol_price = sol_price.get_price();
     let current_sol_price_scaled = f64_to_scaled_price(current_sol_price)?;
@@ -91,21 +94,39 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
         10_000u128,
     )? as u64;
 
+    // LP collateral is valued at the stored AUM, so it must have been reconciled within a few
+    // slots or be recomputed from the custodies passed as remaining accounts
+    let lp_supply = if params.pay_lp {
+        let lp_token_mint = ctx.accounts.lp_token_mint.as_ref()
+            .ok_or(PerpetualError::LpCollateralAccountsMissing)?;
+        require!(ctx.accounts.lp_collateral_account.is_some(), PerpetualError::LpCollateralAccountsMissing);
+        let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+        pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, current_time)?;
+        lp_token_mint.supply
+    } else {
+        0
+    };
+    let lp_collateral_usd = if params.pay_lp {
+        pool.get_lp_token_value_usd(params.collateral_amount, lp_supply)?
+    } else {
+        0
+    };
+
     // Calculate collateral value in USD
-    let collateral_usd = if params.pay_sol {
+    let collateral_usd = if params.pay_lp {
+        lp_collateral_usd
+    } else if params.pay_sol {
         // Convert SOL to USD
-        let sol_amount_usd = math::checked_div(
+        math::checked_div(
             math::checked_mul(params.collateral_amount as u128, current_sol_price_scaled as u128)?,
             math::checked_pow(10u128, sol_custody.decimals as usize)? // Convert from token decimals to base
-        )? as u64;
-        sol_amount_usd
+        )? as u64
     } else {
         // Convert USDC to USD (USDC is pegged to $1)
-        let usdc_amount_usd = math::checked_div(
+        math::checked_div(
             math::checked_mul(params.collateral_amount as u128, current_usdc_price_scaled as u128)?, // $1 = 1_000_000 (6 decimals)
             math::checked_pow(10u128, usdc_custody.decimals as usize)?
-        )? as u64;
-        usdc_amount_usd
+        )? as u64
     } - opening_fee;
 
     msg!("Collateral USD value: {}", collateral_usd);
@@ -149,71 +170,45 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
     // Calculate locked amount (for pool liquidity)
     let locked_amount = if params.side == Side::Long {
         // Long positions lock underlying asset (SOL)
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(params.size_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(params.size_usd, &sol_price, sol_custody.decimals)?
     } else {
         // Short positions lock stable coin (USDC)
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(params.size_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(params.size_usd, &usdc_price, usdc_custody.decimals)?
     };
 
     // Check pool has sufficient liquidity
     let available_liquidity = if params.side == Side::Long {
-        math::checked_sub(sol_custody.token_owned, sol_custody.token_locked)?
+        pool.get_borrowable_amount(sol_custody)?
     } else {
-        math::checked_sub(usdc_custody.token_owned, usdc_custody.token_locked)?
+        pool.get_borrowable_amount(usdc_custody)?
     };
     
     require!(
         available_liquidity >= locked_amount,
         TradingError::InsufficientPoolLiquidity
     );
-    let collateral_token_account = if params.pay_sol {
-        &ctx.accounts.sol_custody_token_account
+    let collateral_token_account = if params.pay_lp {
+        ctx.accounts.lp_collateral_account.as_ref()
+            .ok_or(PerpetualError::LpCollateralAccountsMissing)?
+            .to_account_info()
+    } else if params.pay_sol {
+        ctx.accounts.sol_custody_token_account.to_account_info()
     } else {
-        &ctx.accounts.usdc_custody_token_account
+        ctx.accounts.usdc_custody_token_account.to_account_info()
     };
 
     contract.transfer_tokens(
         ctx.accounts.funding_account.to_account_info(),
-        collateral_token_account.to_account_info(),
+        collateral_token_account,
         ctx.accounts.owner.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.collateral_amount,
     )?;
 
-    // Update custody balances
-    if params.pay_sol {
+    // Update custody balances; LP collateral is held outside the custodies
+    if params.pay_lp {
+        msg!("LP tokens posted as collateral: {}", params.collateral_amount);
+    } else if params.pay_sol {
         sol_custody.token_owned = math::checked_add(
             sol_custody.token_owned,
             params.collateral_amount
@@ -227,15 +222,9 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
 
     // Lock liquidity in relevant custody
     if params.side == Side::Long {
-        sol_custody.token_locked = math::checked_add(
-            sol_custody.token_locked,
-            locked_amount
-        )?;
+        sol_custody.add_locked(LockedProduct::Future, locked_amount)?;
     } else {
-        usdc_custody.token_locked = math::checked_add(
-            usdc_custody.token_locked,
-            locked_amount
-        )?;
+        usdc_custody.add_locked(LockedProduct::Future, locked_amount)?;
     }
 
     // Initialize future position  
@@ -251,6 +240,11 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
     } else {
         usdc_custody_key
     };
+    future.settlement_custody = if params.receive_sol.unwrap_or(ctx.accounts.user.default_receive_sol) {
+        sol_custody_key
+    } else {
+        usdc_custody_key
+    };
     
     future.side = params.side;
     future.status = FutureStatus::Active;
@@ -259,7 +253,9 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
     future.future_price = future_price_scaled;
     future.size_usd = params.size_usd;
     future.collateral_usd = collateral_usd;
-    future.collateral_amount = params.collateral_amount;
+    future.collateral_amount = if params.pay_lp { 0 } else { params.collateral_amount };
+    future.lp_collateral_amount = if params.pay_lp { params.collateral_amount } else { 0 };
+    future.lp_collateral_usd = lp_collateral_usd;
     
     future.open_time = current_time;
     future.expiry_time = params.expiry_timestamp;
@@ -283,6 +279,17 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
     future.locked_amount = locked_amount;
     future.bump = ctx.bumps.future;
 
+    // Referral share of the opening fee
+    future.referrer = params.referrer;
+    let mut referral_fee_usd = 0;
+    if let Some(referrer) = params.referrer {
+        Referral::validate_open(ctx.accounts.referral.as_deref().map(|r| &**r), referrer, future.owner)?;
+        referral_fee_usd = ctx.accounts.contract.get_referral_fee(opening_fee)?;
+        ctx.accounts.referral.as_mut().unwrap().accrue(referral_fee_usd)?;
+    }
+
+    pool.add_notional(&ctx.accounts.contract, future.size_usd)?;
+
     emit!(FutureOpened {
         owner: future.owner,
         future_key: future.key(),
@@ -290,10 +297,12 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
         pool: pool.key(),
         custody: sol_custody_key,
         collateral_custody: future.collateral_custody,
+        settlement_custody: future.settlement_custody,
         side: future.side as u8,
         size_usd: params.size_usd,
         collateral_usd,
-        collateral_amount: params.collateral_amount,
+        collateral_amount: future.collateral_amount,
+        lp_collateral_amount: future.lp_collateral_amount,
         entry_price: current_sol_price_scaled,
         future_price: future_price_scaled,
         fixed_interest_rate_bps: fixed_rate_bps,
@@ -301,12 +310,16 @@ pub fn open_future(ctx: Context<OpenFuture>, params: &OpenFutureParams) -> Resul
         liquidation_price: future.liquidation_price,
         locked_amount,
         open_time: current_time,
+        referrer: future.referrer,
+        referral_fee_usd,
     });
 
     msg!("Future position opened successfully");
     msg!("Future price: {}", future_price_scaled);
     msg!("Liquidation price: {}", future.liquidation_price);
 
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -413,11 +426,58 @@ pub struct OpenFuture<'info> {
     )]
     pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // LP collateral vault, required when pay_lp
+    #[account(
+        init_if_needed,
+        payer = owner,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"lp_collateral_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Referrer's registration, required when params.referrer is set
+    #[account(
+        mut,
+        seeds = [b"referral", params.referrer.unwrap_or_default().as_ref(), pool.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+    // remaining accounts (optional, with pay_lp once pool.aum_usd is stale):
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
 }
\ No newline at end of file
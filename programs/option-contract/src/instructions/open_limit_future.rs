@@ -1,8 +1,8 @@
 use crate::{
-    errors::{FutureError, TradingError},
+    errors::{ContractError, FutureError, PoolError, TradingError},
     events::LimitFutureOpened,
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, Future, FutureStatus, OraclePrice, Pool, Side, User},
+    state::{Contract, Custody, Future, FutureStatus, Pool, Side, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -18,6 +18,7 @@ pub struct OpenLimitFutureParams {
     pub max_slippage: u64,                 // Max acceptable slippage in basis points
     pub pool_name: String,                 // Pool name for seeds
     pub pay_sol: bool,                     // true = pay collateral in SOL, false = USDC
+    pub receive_sol: Option<bool>,         // true = settle in SOL, false = USDC, None = user default
 }
 
 pub fn open_limit_future(
@@ -25,7 +26,7 @@ pub fn open_limit_future(
     params: &OpenLimitFutureParams,
 ) -> Result<()> {
     msg!("Opening limit future position");
-    msg!("Trigger price: {}", params.trigger_price as f64 / 1_000_000.0);
+    msg!("Trigger price: {}", params.trigger_price as f64 / math::PRICE_SCALE as f64);
     msg!("Size USD: {}", params.size_usd);
 
     // Get keys first to avoid borrowing conflicts
@@ -39,23 +40,28 @@ pub fn open_limit_future(
     let usdc_custody = &mut ctx.accounts.usdc_custody;
 
     // Get current time and validate expiry
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
+    require!(!contract.paused, ContractError::ContractPaused);
+
+    // A wound-down custody takes no new exposure, whether traded, locked or posted as collateral
+    require!(!sol_custody.trading_paused, PoolError::CustodyTradingPaused);
+    if params.side == Side::Short || !params.pay_sol {
+        require!(!usdc_custody.trading_paused, PoolError::CustodyTradingPaused);
+    }
     
     require!(
         params.expiry_timestamp > current_time,
         FutureError::InvalidExpiryTime
     );
     
-    // Validate expiry is not too far in the future (max 1 year)
-    let max_expiry = current_time + (365 * 24 * 60 * 60);
-    require!(
-        params.expiry_timestamp <= max_expiry,
-        FutureError::ExpiryTooFar
-    );
+    // Validate expiry against the pool's allowed futures duration range
+    pool.validate_future_expiry(params.expiry_timestamp, current_time)?;
 
     // Get current prices for validation
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
     
     let current_sol_price_scaled = sol_price.scale_to_exponent(-6)?.price;
 
@@ -94,55 +100,23 @@ pub fn open_limit_future(
     // Calculate future price using F = S * exp(r * t)
     let annual_rate = (fixed_rate_bps as f64) / 10_000.0;
     let time_fraction = (time_to_expiry as f64) / (365.25 * 24.0 * 3600.0);
-    let future_price_f64 = (params.trigger_price as f64 / 1_000_000.0) * (annual_rate * time_fraction).exp();
+    let future_price_f64 = (params.trigger_price as f64 / math::PRICE_SCALE as f64) * (annual_rate * time_fraction).exp();
     let future_price_scaled = f64_to_scaled_price(future_price_f64)?;
 
     // Calculate required liquidity to lock
     let locked_amount = if params.side == Side::Long {
         // Long positions lock SOL equivalent to position size
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(params.size_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(params.size_usd, &sol_price, sol_custody.decimals)?
     } else {
         // Short positions lock USDC equivalent to position size
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(params.size_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
+        math::usd_to_token_amount(params.size_usd, &usdc_price, usdc_custody.decimals)?
     };
 
     // Check pool has sufficient liquidity (but don't lock it yet - only when executed)
     let available_liquidity = if params.side == Side::Long {
-        math::checked_sub(sol_custody.token_owned, sol_custody.token_locked)?
+        pool.get_borrowable_amount(sol_custody)?
     } else {
-        math::checked_sub(usdc_custody.token_owned, usdc_custody.token_locked)?
+        pool.get_borrowable_amount(usdc_custody)?
     };
     
     require!(
@@ -194,6 +168,11 @@ pub fn open_limit_future(
     } else {
         usdc_custody_key
     };
+    future.settlement_custody = if params.receive_sol.unwrap_or(ctx.accounts.user.default_receive_sol) {
+        sol_custody_key
+    } else {
+        usdc_custody_key
+    };
     
     future.side = params.side;
     future.status = FutureStatus::Pending; // Limit order waiting execution
@@ -235,6 +214,7 @@ pub fn open_limit_future(
         pool: pool.key(),
         custody: sol_custody_key,
         collateral_custody: future.collateral_custody,
+        settlement_custody: future.settlement_custody,
         side: future.side as u8,
         size_usd: params.size_usd,
         collateral_usd,
@@ -252,6 +232,8 @@ pub fn open_limit_future(
     msg!("Trigger price: {}", params.trigger_price);
     msg!("Future price: {}", future_price_scaled);
     
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -355,11 +337,29 @@ pub struct OpenLimitFuture<'info> {
     )]
     pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -3,19 +3,21 @@ use crate::{
     events::LimitOptionOpened,
     math::{self, f64_to_scaled_price},
     utils::option_pricing::*,
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    state::{Contract, Custody, ExerciseStyle, LockedProduct, OptionDetail, OptionType, Pool, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct OpenLimitOptionParams {
-    amount: u64, // WSOL/USDC account for options, call option - SOL amount, Put option - USDC amount
+    amount: u64, // Premium amount in pay_custody tokens, call option - underlying locked, Put option - stable locked
     strike: f64, // Strike price
     period: u64, // Number of days from option creation to expiration
     expired_time: u64, // when the option is expired : Unix epoch time
     pool_name : String,
-    limit_price: f64
+    limit_price: f64,
+    max_premium: u64, // Max accepted premium per contract in pay_custody tokens
+    exercise_style: ExerciseStyle, // European options only settle at expiry
 }
 
 pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOptionParams) -> Result<()> {
@@ -24,7 +26,7 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
     let option_detail = &mut ctx.accounts.option_detail;
     let contract = &ctx.accounts.contract;
     let user = &mut ctx.accounts.user;
-    let pool = &ctx.accounts.pool;
+    let pool = &mut ctx.accounts.pool;
     let custody = &mut ctx.accounts.custody;
     let custody_oracle_account = &ctx.accounts.custody_oracle_account;
     let locked_custody = &mut ctx.accounts.locked_custody;
@@ -37,7 +39,21 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
 
     let option_index = user.option_index + 1;
     // compute position price
-    let curtime = contract.get_time()?;
+    let curtime = pool.get_time(contract)?;
+
+    // Any custody registered in the pool can act as the underlying
+    pool.get_token_id(&custody.key())?;
+    pool.get_token_id(&pay_custody.key())?;
+    pool.get_token_id(&locked_custody.key())?;
+    pool.validate_option_custodies(&custody.key(), &locked_custody.key(), &pay_custody.key())?;
+    // A wound-down custody takes no new options in any role
+    require!(
+        !custody.trading_paused && !locked_custody.trading_paused && !pay_custody.trading_paused,
+        PoolError::CustodyTradingPaused
+    );
+    let is_call = custody.key() == locked_custody.key();
+    pool.validate_option_expiry(params.expired_time as i64)?;
+    pool.validate_option_grid(params.strike, params.expired_time as i64)?;
 
     // Check if the user's token balance is enough to pay premium
     require_gte!(
@@ -55,7 +71,9 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
         params.amount,
     )?;
     
-    let token_price = OraclePrice::new_from_oracle(custody_oracle_account, curtime, false)?;
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let pay_custody_oracle_secondary = ctx.accounts.pay_custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let token_price = custody.get_oracle_price(custody_oracle_account, custody_oracle_secondary.as_ref(), curtime)?;
 
     let oracle_price = token_price.get_price();
     let period_year = math::checked_as_f64(math::checked_float_div(params.period as f64, 365.0)?)?;
@@ -64,21 +82,42 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
     msg!("params.strike: {}", params.strike);
     msg!("period_year: {}", period_year);
     // Calculate Premium in usd using black scholes formula.
-    let premium = black_scholes(
+    let fair_premium = black_scholes(
         oracle_price,
         params.strike,
         period_year,
-        custody.key() == locked_custody.key(),
+        is_call,
     );
-    msg!("premium: {}", premium);
+    // Fills pay the underlying's buy markup like a market open, before the max_premium check
+    let marked_up_premium = custody.apply_option_buy_markup(fair_premium)?;
+    msg!("fair premium: {}", fair_premium);
 
-    let pay_token_price = OraclePrice::new_from_oracle(pay_custody_oracle_account, curtime, false)?;
+    let pay_token_price = pay_custody.get_oracle_price(pay_custody_oracle_account, pay_custody_oracle_secondary.as_ref(), curtime)?;
+    let decimals_multiplier = math::checked_powi(10.0, pay_custody.decimals as i32)?;
+    let to_pay_amount = |usd: f64| -> Result<u64> {
+        math::checked_as_u64(
+            math::checked_float_div(usd, pay_token_price.get_price())? * decimals_multiplier,
+        )
+    };
+
+    // Same size impact as open_option, sized at the pre-impact premium
+    let available_liquidity = pool.get_borrowable_amount(locked_custody)?;
+    let pre_impact_pay_amount = to_pay_amount(marked_up_premium)?;
+    require_gt!(
+        pre_impact_pay_amount,
+        0,
+        OptionError::InvalidPayAmountError
+    );
+    let pre_impact_lock_amount = math::checked_as_u64(
+        params.amount as f64 / pre_impact_pay_amount as f64 * decimals_multiplier,
+    )?;
+    let (premium, size_impact_bps) =
+        pool.apply_option_size_impact(marked_up_premium, pre_impact_lock_amount, available_liquidity)?;
+    msg!("size impact bps: {}", size_impact_bps);
+    msg!("premium: {}", premium);
 
     // Calculate Premium in pay_toke amount
-    let pay_amount = math::checked_as_u64(
-        math::checked_float_div(premium, pay_token_price.get_price())?
-            * math::checked_powi(10.0, pay_custody.decimals as i32)?,
-    )?;
+    let pay_amount = to_pay_amount(premium)?;
 
     require_gt!(
         pay_amount,
@@ -86,19 +125,33 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
         OptionError::InvalidPayAmountError
     );
 
+    // Reject the fill (reverting the premium transfer) if pricing moved past the user's bound
+    require_gte!(
+        params.max_premium,
+        pay_amount,
+        OptionError::PremiumExceedsMax
+    );
+
     // Add premium to liquidity pool
     pay_custody.token_owned = math::checked_add(pay_custody.token_owned, params.amount)?;
     option_detail.premium = pay_amount;
     option_detail.premium_asset = pay_custody.key();
 
-    let quantity = math::checked_div(params.amount, pay_amount)?;
+    // Quantity is fixed-point, so a premium that isn't a whole multiple buys a fractional contract
+    option_detail.quantity_decimals = OptionDetail::QUANTITY_DECIMALS;
+    let quantity = math::checked_as_u64(math::checked_div(
+        math::checked_mul(params.amount as u128, option_detail.quantity_scale() as u128)?,
+        pay_amount as u128,
+    )?)?;
     msg!("quantity: {}", quantity);
 
-    let decimals_multiplier = math::checked_powi(10.0, pay_custody.decimals as i32)?;
-    locked_custody.token_locked = math::checked_add(
-        locked_custody.token_locked,
-        math::checked_as_u64(quantity as f64 * decimals_multiplier)?
-    )?;
+    let lock_amount = math::checked_as_u64(option_detail.contracts(quantity) * decimals_multiplier)?;
+    require_gte!(
+        available_liquidity,
+        lock_amount,
+        TradingError::InsufficientPoolLiquidity
+    );
+    locked_custody.add_locked(LockedProduct::Option, lock_amount)?;
 
     require_gte!(
         locked_custody.token_owned,
@@ -108,13 +161,15 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
 
     // store option data
     option_detail.amount = params.amount;
+    option_detail.locked_amount = lock_amount;
     option_detail.quantity = quantity;
     option_detail.owner = owner.key();
     option_detail.index = option_index;
     option_detail.period = params.period;
     option_detail.expired_date = params.expired_time as i64;
     option_detail.purchase_date = curtime as u64;
-    option_detail.option_type = if custody.key() == locked_custody.key() { 0 } else { 1 };
+    option_detail.option_type = OptionType::from_is_call(is_call);
+    option_detail.exercise_style = params.exercise_style;
     option_detail.strike_price = f64_to_scaled_price(params.strike)?;
     option_detail.valid = true;
     option_detail.locked_asset = locked_custody.key();
@@ -128,6 +183,16 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
     option_detail.stop_loss_price = None;
     user.option_index = option_index;
 
+    let notional_usd = option_detail.get_notional_usd(quantity)?;
+    pool.add_strike_expiry_notional(
+        option_detail.strike_price,
+        option_detail.expired_date,
+        option_detail.is_call(),
+        notional_usd,
+        curtime,
+    )?;
+    ctx.accounts.pool.add_notional(&ctx.accounts.contract, notional_usd)?;
+
     emit!(LimitOptionOpened {
         owner: option_detail.owner,
         index: option_detail.index,
@@ -136,7 +201,7 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
         period: option_detail.period,
         expired_date: option_detail.expired_date,
         purchase_date: option_detail.purchase_date,
-        option_type: option_detail.option_type,
+        option_type: option_detail.option_type as u8,
         strike_price: option_detail.strike_price,
         valid: option_detail.valid,
         locked_asset: option_detail.locked_asset,
@@ -144,6 +209,7 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
         custody: option_detail.custody,
         premium: option_detail.premium,
         premium_asset: option_detail.premium_asset,
+        max_premium: params.max_premium,
         limit_price: option_detail.limit_price,
         executed: option_detail.executed,
         entry_price: option_detail.entry_price,
@@ -152,6 +218,8 @@ pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: &OpenLimitOption
         stop_loss_price: option_detail.stop_loss_price,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.pay_custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -260,4 +328,16 @@ pub struct OpenLimitOption<'info> {
     pub locked_custody_mint: Box<Account<'info, Mint>>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = pay_custody_oracle_secondary.key() == pay_custody.oracle_secondary
+    )]
+    pub pay_custody_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
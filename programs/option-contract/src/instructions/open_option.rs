@@ -1,9 +1,9 @@
 use crate::{
     errors::{OptionError, TradingError, PoolError},
-    events::OptionOpened,
+    events::{OptionOpened, OptionTpSlSet},
     math::{self, f64_to_scaled_price},
-    utils::option_pricing::*,
-    state::{Contract, Custody, OptionDetail, OraclePrice, Pool, User},
+    utils::{option_pricing::*, pool::calculate_borrow_rate},
+    state::{Contract, Custody, ExerciseStyle, LockedProduct, OptionDetail, OptionType, Pool, Referral, TpSlOrderbook, User},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::
@@ -11,11 +11,16 @@ use anchor_spl::
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct OpenOptionParams {
-    amount: u64, // WSOL/USDC account for options, call option - SOL amount, Put option - USDC amount
+    amount: u64, // Premium amount in pay_custody tokens, call option - underlying locked, Put option - stable locked
     strike: f64, // Strike price
     period: u64, // Number of days from option creation to expiration
     expired_time: u64, // when the option is expired : Unix epoch time
     pool_name : String,
+    take_profit_price: Option<f64>, // Optional TP set atomically with the open
+    stop_loss_price: Option<f64>,   // Optional SL set atomically with the open
+    referrer: Option<Pubkey>,       // Credited a share of the premium markup
+    max_premium_tokens: Option<u64>, // Slippage protection on the premium per contract, in pay tokens (None = unbounded)
+    exercise_style: ExerciseStyle,  // European options only settle at expiry
 }
 
 pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Result<()> {
@@ -24,7 +29,7 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
     let option_detail = &mut ctx.accounts.option_detail;
     let contract = &ctx.accounts.contract;
     let user = &mut ctx.accounts.user;
-    let pool = &ctx.accounts.pool;
+    let pool = &mut ctx.accounts.pool;
     let custody = &mut ctx.accounts.custody;
     let custody_oracle_account = &ctx.accounts.custody_oracle_account;
     let locked_custody = &mut ctx.accounts.locked_custody;
@@ -37,7 +42,20 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
 
     let option_index = user.option_index + 1;
     // compute position price
-    let curtime = contract.get_time()?;
+    let curtime = pool.get_time(contract)?;
+
+    // Underlying, premium and locked assets are all taken from the accounts passed in,
+    // so any custody registered in the pool can act as the underlying
+    pool.get_token_id(&custody.key())?;
+    pool.get_token_id(&pay_custody.key())?;
+    pool.get_token_id(&locked_custody.key())?;
+    pool.validate_option_custodies(&custody.key(), &locked_custody.key(), &pay_custody.key())?;
+    // A wound-down custody takes no new options in any role
+    require!(
+        !custody.trading_paused && !locked_custody.trading_paused && !pay_custody.trading_paused,
+        PoolError::CustodyTradingPaused
+    );
+    let is_call = custody.key() == locked_custody.key();
 
     // Validate option parameters
     require_gt!(
@@ -62,6 +80,8 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
         curtime as u64,
         OptionError::OptionExpired
     );
+    pool.validate_option_expiry(params.expired_time as i64)?;
+    pool.validate_option_grid(params.strike, params.expired_time as i64)?;
 
     // Check if the user's token balance is enough to pay premium
     require_gte!(
@@ -83,7 +103,9 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
         params.amount,
     )?;
     
-    let token_price = OraclePrice::new_from_oracle(custody_oracle_account, curtime, false)?;
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let pay_custody_oracle_secondary = ctx.accounts.pay_custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let token_price = custody.get_oracle_price(custody_oracle_account, custody_oracle_secondary.as_ref(), curtime)?;
     let oracle_price = token_price.get_price();
     let period_year = math::checked_as_f64(math::checked_float_div(params.period as f64, 365.0)?)?;
 
@@ -98,38 +120,70 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
     
     
     // Calculate Premium using enhanced Black-Scholes with dynamic borrow rate
-    let premium = black_scholes_with_borrow_rate(
+    let fair_premium = black_scholes_with_borrow_rate(
         oracle_price,
         params.strike,
         period_year,
-        custody.key() == locked_custody.key(), // call/put logic
+        is_call, // call/put logic
         token_locked,  // Current utilization of underlying asset
         token_owned,   // Total supply of underlying asset
-        custody.key() == locked_custody.key(), // Asset type for rate calculation
+        is_call, // Asset type for rate calculation
+        params.exercise_style,
     )?;
     
-    msg!("premium: {}", premium);
+    // Charge the underlying's buy markup on top of fair value
+    let marked_up_premium = custody.apply_option_buy_markup(fair_premium)?;
+
+    let pay_token_price = pay_custody.get_oracle_price(pay_custody_oracle_account, pay_custody_oracle_secondary.as_ref(), curtime)?;
+    let pay_decimals_multiplier = math::checked_powi(10.0, pay_custody.decimals as i32)?;
+    let to_pay_amount = |usd: f64| -> Result<u64> {
+        math::checked_as_u64(
+            math::checked_float_div(usd, pay_token_price.get_price())? * pay_decimals_multiplier,
+        )
+    };
+
+    // Size impact: the share of free locked-custody liquidity the buy would lock at the
+    // marked-up premium sets a progressive markup, so large buys don't lock the pool at the
+    // marginal price. Sizing at the pre-impact premium slightly overstates the share.
+    let available_liquidity = pool.get_borrowable_amount(locked_custody)?;
+    let pre_impact_pay_amount = to_pay_amount(marked_up_premium)?;
+    require_gt!(
+        pre_impact_pay_amount,
+        0,
+        OptionError::InvalidPayAmountError
+    );
+    let pre_impact_lock_amount = math::checked_as_u64(
+        params.amount as f64 / pre_impact_pay_amount as f64 * pay_decimals_multiplier,
+    )?;
+    let (premium, size_impact_bps) =
+        pool.apply_option_size_impact(marked_up_premium, pre_impact_lock_amount, available_liquidity)?;
 
-    let pay_token_price = OraclePrice::new_from_oracle(pay_custody_oracle_account, curtime, false)?;
+    msg!("fair premium: {}", fair_premium);
+    msg!("size impact bps: {}", size_impact_bps);
+    msg!("premium: {}", premium);
 
     // Calculate Premium in pay_token amount
-    let pay_amount = math::checked_as_u64(
-        math::checked_float_div(premium, pay_token_price.get_price())?
-            * math::checked_powi(10.0, pay_custody.decimals as i32)?,
-    )?;
+    let pay_amount = to_pay_amount(premium)?;
 
     require_gt!(
         pay_amount,
         0,
         OptionError::InvalidPayAmountError
     );
+    // A price or utilisation move since quoting shrinks the quantity bought, bound it
+    OptionDetail::check_premium_slippage(pay_amount, params.max_premium_tokens)?;
 
     // Add premium to liquidity pool
     pay_custody.token_owned = math::checked_add(pay_custody.token_owned, params.amount)?;
     option_detail.premium = pay_amount;
     option_detail.premium_asset = pay_custody.key();
 
-    let quantity = math::checked_div(params.amount, pay_amount)?;
+    // Quantity is fixed-point, so a premium that isn't a whole multiple buys a fractional contract
+    option_detail.quantity_decimals = OptionDetail::QUANTITY_DECIMALS;
+    let quantity = math::checked_as_u64(math::checked_div(
+        math::checked_mul(params.amount as u128, option_detail.quantity_scale() as u128)?,
+        pay_amount as u128,
+    )?)?;
     
     // Validate minimum quantity to prevent zero-quantity options
     require_gt!(
@@ -140,11 +194,13 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
     
     msg!("quantity: {}", quantity);
 
-    let decimals_multiplier = math::checked_powi(10.0, pay_custody.decimals as i32)?;
-    locked_custody.token_locked = math::checked_add(
-        locked_custody.token_locked,
-        math::checked_as_u64(quantity as f64 * decimals_multiplier)?
-    )?;
+    let lock_amount = math::checked_as_u64(option_detail.contracts(quantity) * pay_decimals_multiplier)?;
+    require_gte!(
+        available_liquidity,
+        lock_amount,
+        TradingError::InsufficientPoolLiquidity
+    );
+    locked_custody.add_locked(LockedProduct::Option, lock_amount)?;
 
     require_gte!(
         locked_custody.token_owned,
@@ -152,6 +208,40 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
         PoolError::InvalidPoolBalanceError
     );
 
+    // Hold the (strike, expiry) series to the pool's concentration cap
+    let strike_price = f64_to_scaled_price(params.strike)?;
+    let notional_usd = math::checked_as_u64(math::checked_div(
+        math::checked_mul(quantity as u128, strike_price as u128)?,
+        option_detail.quantity_scale() as u128,
+    )?)?;
+    let strike_expiry_notional_usd = pool.add_strike_expiry_notional(
+        strike_price,
+        params.expired_time as i64,
+        is_call,
+        notional_usd,
+        curtime,
+    )?;
+    msg!("strike/expiry notional: {}", strike_expiry_notional_usd);
+
+    let size_impact_usd = math::checked_as_u64(
+        (premium - marked_up_premium).max(0.0)
+            * option_detail.contracts(quantity)
+            * Contract::USD_SCALE as f64,
+    )?;
+
+    // Referral share of the buy markup charged over the fair premium, the size impact stays with LPs
+    let mut referral_fee_usd = 0;
+    if let Some(referrer) = params.referrer {
+        Referral::validate_open(ctx.accounts.referral.as_deref().map(|r| &**r), referrer, owner.key())?;
+        let markup_usd = math::checked_as_u64(
+            (marked_up_premium - fair_premium).max(0.0)
+                * option_detail.contracts(quantity)
+                * math::checked_powi(10.0, Contract::USD_DECIMALS as i32)?,
+        )?;
+        referral_fee_usd = contract.get_referral_fee(markup_usd)?;
+        ctx.accounts.referral.as_mut().unwrap().accrue(referral_fee_usd)?;
+    }
+
     emit!(OptionOpened {
         owner: option_detail.owner,
         index: option_detail.index,
@@ -160,7 +250,7 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
         period: option_detail.period,
         expired_date: option_detail.expired_date,
         purchase_date: option_detail.purchase_date,
-        option_type: option_detail.option_type,
+        option_type: option_detail.option_type as u8,
         strike_price: option_detail.strike_price,
         valid: option_detail.valid,
         locked_asset: option_detail.locked_asset,
@@ -175,17 +265,34 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
         take_profit_price: option_detail.take_profit_price,
         stop_loss_price: option_detail.stop_loss_price,
         bump: option_detail.bump,
+        premium_markup_bps: custody.option_buy_markup_bps,
+        pricing_spot_price: f64_to_scaled_price(oracle_price)?,
+        pricing_time_to_expiry: period_year,
+        pricing_volatility: get_option_volatility(is_call),
+        pricing_borrow_rate: calculate_borrow_rate(token_locked, token_owned, is_call)? / 100.0,
+        pricing_token_locked: token_locked,
+        pricing_token_owned: token_owned,
+        fair_premium_usd: math::checked_as_u64(fair_premium * Contract::USD_SCALE as f64)?,
+        size_impact_bps,
+        size_impact_usd,
+        strike_expiry_notional_usd,
+        max_strike_expiry_notional_usd: pool.max_strike_expiry_notional_usd,
+        referrer: params.referrer,
+        referral_fee_usd,
     });
 
     // store option data
+    // Every release path (close, exercise, auto-exercise) unlocks from `locked_amount`
     option_detail.amount = params.amount;
+    option_detail.locked_amount = lock_amount;
     option_detail.quantity = quantity;
     option_detail.owner = owner.key();
     option_detail.index = option_index;
     option_detail.period = params.period;
     option_detail.expired_date = params.expired_time as i64;
     option_detail.purchase_date = curtime as u64;
-    option_detail.option_type = if custody.key() == locked_custody.key() { 0 } else { 1 };
+    option_detail.option_type = OptionType::from_is_call(is_call);
+    option_detail.exercise_style = params.exercise_style;
     option_detail.strike_price = f64_to_scaled_price(params.strike)?;
     option_detail.valid = true;
     option_detail.locked_asset = locked_custody.key();
@@ -198,9 +305,97 @@ pub fn open_option(ctx: Context<OpenOption>, params: &OpenOptionParams) -> Resul
     option_detail.take_profit_price = None;
     option_detail.stop_loss_price = None;
     option_detail.tp_sl_orderbook = None; // No orderbook initially
+    option_detail.settlement_price = None; // Recorded by the keeper at expiry
+    option_detail.referrer = params.referrer;
     option_detail.bump = ctx.bumps.option_detail;  
     user.option_index = option_index;
 
+    pool.add_notional(&ctx.accounts.contract, option_detail.get_notional_usd(quantity)?)?;
+
+    // The orderbook is only created to seed exits, never left uninitialized
+    let has_tp_sl = params.take_profit_price.is_some() || params.stop_loss_price.is_some();
+    require!(
+        has_tp_sl || ctx.accounts.tp_sl_orderbook.is_none(),
+        TradingError::InvalidParameterError
+    );
+
+    // Optional exits, validated the same way as set_option_tp_sl
+    if has_tp_sl {
+        let strike_price_f64 = params.strike;
+
+        if let Some(tp_price) = params.take_profit_price {
+            require!(tp_price > 0.0, TradingError::InvalidPrice);
+            if is_call {
+                require!(tp_price > strike_price_f64, TradingError::InvalidTakeProfitPrice);
+            } else {
+                require!(tp_price < strike_price_f64, TradingError::InvalidTakeProfitPrice);
+            }
+            option_detail.take_profit_price = Some(f64_to_scaled_price(tp_price)?);
+        }
+
+        if let Some(sl_price) = params.stop_loss_price {
+            require!(sl_price > 0.0, TradingError::InvalidPrice);
+            if is_call {
+                require!(sl_price < strike_price_f64, TradingError::InvalidStopLossPrice);
+            } else {
+                require!(sl_price > strike_price_f64, TradingError::InvalidStopLossPrice);
+            }
+            option_detail.stop_loss_price = Some(f64_to_scaled_price(sl_price)?);
+        }
+
+        if let (Some(tp), Some(sl)) = (option_detail.take_profit_price, option_detail.stop_loss_price) {
+            if is_call {
+                require!(tp > sl, TradingError::InvalidPriceRange);
+            } else {
+                require!(tp < sl, TradingError::InvalidPriceRange);
+            }
+        }
+
+        // Seed a full-size order in the orderbook when the caller passes one
+        if let Some(orderbook) = ctx.accounts.tp_sl_orderbook.as_mut() {
+            orderbook.initialize(
+                owner.key(),
+                option_detail.key(),
+                1,
+                pool.get_max_tp_sl_orders(),
+                ctx.bumps.tp_sl_orderbook.unwrap(),
+            )?;
+            if let Some(tp) = option_detail.take_profit_price {
+                orderbook.add_take_profit_order(tp, 100_000_000, false)?;
+            }
+            if let Some(sl) = option_detail.stop_loss_price {
+                orderbook.add_stop_loss_order(sl, 100_000_000, false)?;
+            }
+            option_detail.tp_sl_orderbook = Some(orderbook.key());
+        }
+
+        emit!(OptionTpSlSet {
+            owner: option_detail.owner,
+            index: option_detail.index,
+            amount: option_detail.amount,
+            quantity: option_detail.quantity,
+            period: option_detail.period,
+            expired_date: option_detail.expired_date,
+            purchase_date: option_detail.purchase_date,
+            option_type: option_detail.option_type as u8,
+            strike_price: option_detail.strike_price,
+            valid: option_detail.valid,
+            locked_asset: option_detail.locked_asset,
+            pool: option_detail.pool,
+            custody: option_detail.custody,
+            premium: option_detail.premium,
+            premium_asset: option_detail.premium_asset,
+            limit_price: option_detail.limit_price,
+            executed: option_detail.executed,
+            entry_price: option_detail.entry_price,
+            last_update_time: option_detail.last_update_time,
+            take_profit_price: option_detail.take_profit_price,
+            stop_loss_price: option_detail.stop_loss_price,
+        });
+    }
+
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.pay_custody, &ctx.accounts.locked_custody])?;
+
     Ok(())
 }
 
@@ -307,6 +502,42 @@ pub struct OpenOption<'info> {
     pub pay_custody_mint: Box<Account<'info, Mint>>,
     #[account(mut)]
     pub locked_custody_mint: Box<Account<'info, Mint>>,
+    // Optional orderbook created with the option, only allowed when TP/SL params are set
+    #[account(
+        init,
+        payer = owner,
+        space = TpSlOrderbook::LEN,
+        seeds = [
+            b"tp_sl_orderbook",
+            owner.key().as_ref(),
+            (user.option_index+1).to_le_bytes().as_ref(),
+            params.pool_name.as_bytes(),
+            1u8.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub tp_sl_orderbook: Option<Box<Account<'info, TpSlOrderbook>>>,
+
+    // Referrer's registration, required when params.referrer is set
+    #[account(
+        mut,
+        seeds = [b"referral", params.referrer.unwrap_or_default().as_ref(), pool.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = pay_custody_oracle_secondary.key() == pay_custody.oracle_secondary
+    )]
+    pub pay_custody_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -1,471 +1,640 @@
-use crate::{
-    errors::{PerpetualError, PoolError, TradingError},
-    events::PerpPositionOpened,
-    math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, OraclePrice, OrderType, Pool, Position, Side, User},
-    utils::risk_management::*,
-};
-use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct OpenPerpPositionParams {
-    pub size_amount: u64,              // Position amount in tokens
-    pub collateral_amount: u64,        // Collateral amount in tokens
-    pub side: Side,                    // Long or Short
-    pub order_type: OrderType,         // Market or Limit
-    pub trigger_price: Option<u64>,    // For limit orders
-    pub trigger_above_threshold: bool, // Direction for limit orders
-    pub max_slippage: u64,             // Max acceptable slippage in basis points
-    pub pool_name: String,             // Pool name
-    pub pay_sol: bool,                 // true = pay with SOL, false = pay with USDC
-}
-
-pub fn open_perp_position(
-    ctx: Context<OpenPerpPosition>,
-    params: &OpenPerpPositionParams,
-) -> Result<()> {
-    msg!("Opening perpetual position");
-
-    let owner = &ctx.accounts.owner;
-    let contract = &ctx.accounts.contract;
-    let pool = &mut ctx.accounts.pool;
-    let sol_custody = &mut ctx.accounts.sol_custody;
-    let usdc_custody = &mut ctx.accounts.usdc_custody;
-    let position = &mut ctx.accounts.position;
-    let user: &mut Box<Account<'_, User>> = &mut ctx.accounts.user;
-
-    // Basic validation
-    require!(params.size_amount > 0, TradingError::InvalidAmount);
-    require!(params.collateral_amount > 0, TradingError::InvalidAmount);
-    require!(params.max_slippage <= 1000, TradingError::InvalidSlippage); // Max 10%
-    require!(!params.pool_name.is_empty(), PoolError::InvalidPoolName);
-
-    // Get current prices
-    let current_time = contract.get_time()?;
-    let sol_price =
-        OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price =
-        OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
-
-    let sol_price_value = sol_price.get_price();
-    let usdc_price_value = usdc_price.get_price();
-
-    msg!("SOL Price: {}", sol_price_value);
-    msg!("USDC Price: {}", usdc_price_value);
-
-    // Determine collateral asset and custody
-    let (collateral_custody, collateral_decimals, collateral_price) = if params.pay_sol {
-        (sol_custody.key(), sol_custody.decimals, sol_price_value)
-    } else {
-        (usdc_custody.key(), usdc_custody.decimals, usdc_price_value)
-    };
-
-    // Calculate collateral value in USD
-    let collateral_usd = math::checked_as_u64(
-        math::checked_float_mul(
-            params.collateral_amount as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
-            collateral_price,
-        )? * 1_000_000.0,
-    )?;
-
-    let size_usd = math::checked_as_u64(
-        math::checked_float_mul(
-            params.size_amount as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
-            collateral_price,
-        )? * 1_000_000.0,
-    )?;
-
-    // Calculate leverage
-    let leverage =
-        math::checked_float_div(params.size_amount as f64, params.collateral_amount as f64)?;
-
-    msg!("Position Size USD: {}", size_usd);
-    msg!("Collateral USD: {}", collateral_usd);
-    msg!("Leverage: {}x", leverage);
-
-    // Validate leverage (100x max)
-    require!(
-        leverage <= Position::MAX_LEVERAGE && leverage >= 1.0,
-        PerpetualError::InvalidLeverage
-    );
-
-    // Check user has sufficient balance
-    require_gte!(
-        ctx.accounts.funding_account.amount,
-        params.collateral_amount,
-        TradingError::InsufficientBalance
-    );
-
-    // Calculate margin requirements
-    let initial_margin_bps = math::checked_as_u64(math::checked_float_div(10_000.0, leverage)?)?; // 10000 / leverage
-
-    // Ensure minimum margin requirements
-    require!(
-        initial_margin_bps >= Position::MIN_INITIAL_MARGIN_BPS,
-        PerpetualError::InvalidLeverage
-    );
-
-    // Calculate liquidation price
-    let entry_price = if params.order_type == OrderType::Limit {
-        params
-            .trigger_price
-            .unwrap_or(f64_to_scaled_price(sol_price_value)?)
-    } else {
-        f64_to_scaled_price(sol_price_value)?
-    };
-
-    let liquidation_price = calculate_liquidation_price(entry_price, leverage, params.side)?;
-
-    msg!("Entry Price: {}", entry_price);
-    msg!("Liquidation Price: {}", liquidation_price);
-
-    // Check pool liquidity using integer math
-    let required_liquidity = if params.side == Side::Long {
-        // Convert USD to SOL tokens using integer math
-        let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-        let sol_amount_6_decimals = math::checked_div(
-            math::checked_mul(size_usd as u128, 1_000_000u128)?,
-            sol_price_scaled.price as u128
-        )?;
-        
-        if sol_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                sol_amount_6_decimals,
-                math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-            )?)?
-        }
-    } else {
-        // Convert USD to USDC tokens using integer math
-        let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-        let usdc_amount_6_decimals = math::checked_div(
-            math::checked_mul(size_usd as u128, 1_000_000u128)?,
-            usdc_price_scaled.price as u128
-        )?;
-        
-        if usdc_custody.decimals > 6 {
-            math::checked_as_u64(math::checked_mul(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-            )?)?
-        } else {
-            math::checked_as_u64(math::checked_div(
-                usdc_amount_6_decimals,
-                math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-            )?)?
-        }
-    };
-
-    let normalized_collateral_amount = if params.side == Side::Long {
-        // For long positions, convert collateral to SOL token units
-        if params.pay_sol {
-            // Already in SOL, use as-is
-            params.collateral_amount
-        } else {
-            // Convert USDC collateral to equivalent SOL tokens using integer math
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(collateral_usd as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
-        }
-    } else {
-        // For short positions, convert collateral to USDC token units
-        if params.pay_sol {
-            // Convert SOL collateral to equivalent USDC tokens using integer math
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(collateral_usd as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
-        } else {
-            // Already in USDC, use as-is
-            params.collateral_amount
-        }
-    };
-
-    if params.side == Side::Long {
-        require_gte!(
-            sol_custody.token_owned,
-            required_liquidity,
-            TradingError::InsufficientPoolLiquidity
-        );
-    } else {
-        require_gte!(
-            usdc_custody.token_owned,
-            required_liquidity,
-            TradingError::InsufficientPoolLiquidity
-        );
-    }
-
-    // Transfer collateral from user to pool
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            SplTransfer {
-                from: ctx.accounts.funding_account.to_account_info(),
-                to: if params.pay_sol {
-                    ctx.accounts.sol_custody_token_account.to_account_info()
-                } else {
-                    ctx.accounts.usdc_custody_token_account.to_account_info()
-                },
-                authority: owner.to_account_info(),
-            },
-        ),
-        params.collateral_amount,
-    )?;
-
-    // Update custody stats - lock tokens only for MARKET orders (limit orders lock when executed)
-    if params.order_type == OrderType::Market {
-        if params.side == Side::Long {
-            // Long positions always need SOL backing
-            sol_custody.token_locked =
-                math::checked_add(sol_custody.token_locked, required_liquidity)?;
-        } else {
-            // Short positions always need USDC backing
-            usdc_custody.token_locked =
-                math::checked_add(usdc_custody.token_locked, required_liquidity)?;
-        }
-    }
-
-    if params.pay_sol {
-        sol_custody.token_owned =
-            math::checked_add(sol_custody.token_owned, params.collateral_amount)?;
-    } else {
-        usdc_custody.token_owned =
-            math::checked_add(usdc_custody.token_owned, params.collateral_amount)?;
-    }
-
-    // Initialize position
-    position.index = user.perp_position_index.checked_add(1).unwrap_or(1);
-    position.owner = owner.key();
-    position.pool = pool.key();
-    position.custody = sol_custody.key(); // Position always tracks SOL
-    position.collateral_custody = collateral_custody;
-    position.order_type = params.order_type;
-    position.side = params.side;
-    position.is_liquidated = false;
-    position.entry_price = entry_price;
-    position.size_usd = size_usd;
-    position.collateral_usd = collateral_usd;
-    position.open_time = current_time;
-    position.update_time = current_time;
-    position.execution_time = if params.order_type == OrderType::Market {
-        Some(current_time) // Market orders execute immediately
-    } else {
-        None // Limit orders start with no execution time
-    };
-    position.last_borrow_fees_update_time = current_time;
-    position.liquidation_price = liquidation_price;
-
-    // Set snapshots from current pool state (side-specific)
-    position.cumulative_interest_snapshot = match params.side {
-        Side::Long => pool.cumulative_interest_rate_long,
-        Side::Short => pool.cumulative_interest_rate_short,
-    };
-
-    position.trade_fees = math::checked_div(
-        math::checked_mul(size_usd, Position::EXITING_FEE_BPS)?,
-        10000,
-    )?;
-    position.borrow_fees_paid = 0;
-
-    position.accrued_borrow_fees = 0;
-
-    // Asset amounts
-    position.locked_amount = required_liquidity;
-    position.collateral_amount = normalized_collateral_amount;
-
-    // TP/SL
-    position.tp_sl_orderbook = None; // No orderbook initially
-
-    // Limit order specific
-    position.trigger_price = params.trigger_price;
-    position.trigger_above_threshold = params.trigger_above_threshold;
-
-    position.bump = ctx.bumps.position;
-
-    // Update pool open interest
-    if params.side == Side::Long {
-        pool.long_open_interest_usd =
-            math::checked_add(pool.long_open_interest_usd, size_usd as u128)?;
-    } else {
-        pool.short_open_interest_usd =
-            math::checked_add(pool.short_open_interest_usd, size_usd as u128)?;
-    }
-
-    // Update user stats
-    user.perp_position_index = user.perp_position_index.checked_add(1).unwrap_or(1);
-
-    emit!(PerpPositionOpened {
-        index: position.index,
-        owner: position.owner,
-        pool: position.pool,
-        pub_key: position.key(),
-        custody: position.custody,
-        collateral_custody: position.collateral_custody,
-        order_type: position.order_type as u8,
-        side: position.side as u8,
-        is_liquidated: position.is_liquidated,
-        price: position.entry_price,
-        size_usd: position.size_usd,
-        collateral_usd: position.collateral_usd,
-        open_time: position.open_time,
-        execution_time: position.execution_time,
-        update_time: position.update_time,
-        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
-        liquidation_price: position.liquidation_price,
-        cumulative_interest_snapshot: position.cumulative_interest_snapshot,
-        trade_fees: position.trade_fees,
-        accrued_borrow_fees: position.accrued_borrow_fees,
-        locked_amount: position.locked_amount,
-        collateral_amount: position.collateral_amount,
-        trigger_price: position.trigger_price,
-        trigger_above_threshold: position.trigger_above_threshold,
-        max_slippage: params.max_slippage,
-        bump: position.bump,
-    });
-
-    Ok(())
-}
-
-#[derive(Accounts)]
-#[instruction(params: OpenPerpPositionParams)]
-pub struct OpenPerpPosition<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
-    #[account(
-        mut,
-        has_one = owner
-    )]
-    pub funding_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Program derived address (PDA) used as authority for token operations.
-    #[account(
-        seeds = [b"transfer_authority"],
-        bump = contract.transfer_authority_bump
-    )]
-    pub transfer_authority: AccountInfo<'info>,
-
-    #[account(
-        seeds = [b"contract"],
-        bump = contract.bump
-    )]
-    pub contract: Box<Account<'info, Contract>>,
-
-    #[account(
-        mut,
-        seeds = [b"pool", params.pool_name.as_bytes()],
-        bump = pool.bump
-    )]
-    pub pool: Box<Account<'info, Pool>>,
-
-    #[account(
-        init_if_needed,
-        payer = owner,
-        space = User::LEN,
-        seeds = [b"user_v3", owner.key().as_ref()],
-        bump,
-    )]
-    pub user: Box<Account<'info, User>>,
-
-    #[account(
-        init,
-        payer = owner,
-        space = Position::LEN,
-        seeds = [
-            b"position",
-            owner.key().as_ref(),
-            (user.perp_position_index + 1).to_le_bytes().as_ref(),
-            pool.key().as_ref()
-        ],
-        bump
-    )]
-    pub position: Box<Account<'info, Position>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
-        bump = sol_custody.bump
-    )]
-    pub sol_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
-        bump = usdc_custody.bump
-    )]
-    pub usdc_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            sol_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            usdc_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = sol_oracle_account.key() == sol_custody.oracle
-    )]
-    pub sol_oracle_account: AccountInfo<'info>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = usdc_oracle_account.key() == usdc_custody.oracle
-    )]
-    pub usdc_oracle_account: AccountInfo<'info>,
-
-    #[account(mut)]
-    pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+use crate::{
+    errors::{ContractError, PerpetualError, PoolError, TradingError},
+    events::PerpPositionOpened,
+    math::{self, f64_to_scaled_price},
+    state::{Contract, Custody, LockedProduct, LimitOrderBook, OrderType, Pool, Position, Referral, RestingOrder, Side, User},
+    utils::risk_management::*,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OpenPerpPositionParams {
+    pub size_amount: u64,              // Position amount in tokens
+    pub collateral_amount: u64,        // Collateral amount in tokens
+    pub side: Side,                    // Long or Short
+    pub order_type: OrderType,         // Market or Limit
+    pub trigger_price: Option<u64>,    // For limit orders
+    pub trigger_above_threshold: bool, // Direction for limit orders
+    pub stop_price: Option<u64>,       // Stop-limit activation price; trigger_price is then the worst fill
+    pub max_slippage: u64,             // Max acceptable slippage in basis points
+    pub pool_name: String,             // Pool name
+    pub pay_sol: bool,                 // true = pay with SOL, false = pay with USDC
+    pub pay_lp: bool,                  // true = collateral_amount is pool LP tokens (market orders only)
+    pub referrer: Option<Pubkey>,      // Credited a share of the trade fee
+    pub expiry_time: Option<i64>,      // Limit orders only: keepers may expire it after this, None = GTC
+    pub max_loss_usd: Option<u64>,     // Market orders only: buy a floor on losses for a premium
+}
+
+pub fn open_perp_position<'info>(
+    ctx: Context<'_, '_, 'info, 'info, OpenPerpPosition>,
+    params: &OpenPerpPositionParams,
+) -> Result<()> {
+    msg!("Opening perpetual position");
+
+    let owner = &ctx.accounts.owner;
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let sol_custody = &mut ctx.accounts.sol_custody;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    let position = &mut ctx.accounts.position;
+    let user: &mut Box<Account<'_, User>> = &mut ctx.accounts.user;
+
+    // Basic validation
+    require!(params.size_amount > 0, TradingError::InvalidAmount);
+    require!(params.collateral_amount > 0, TradingError::InvalidAmount);
+    require!(params.max_slippage <= 1000, TradingError::InvalidSlippage); // Max 10%
+    require!(!params.pool_name.is_empty(), PoolError::InvalidPoolName);
+
+    // A wound-down custody takes no new exposure, whether traded, locked or posted as collateral
+    require!(!sol_custody.trading_paused, PoolError::CustodyTradingPaused);
+    if params.side == Side::Short || !params.pay_sol {
+        require!(!usdc_custody.trading_paused, PoolError::CustodyTradingPaused);
+    }
+
+    // Stop-limit: the limit price must sit on the worse side of the stop
+    if let Some(stop_price) = params.stop_price {
+        require!(
+            params.order_type == OrderType::Limit && stop_price > 0,
+            PerpetualError::InvalidStopLimitOrder
+        );
+        let limit_price = params.trigger_price.ok_or(PerpetualError::InvalidStopLimitOrder)?;
+        let valid_limit = match params.side {
+            Side::Long => limit_price >= stop_price,
+            Side::Short => limit_price <= stop_price,
+        };
+        require!(valid_limit, PerpetualError::InvalidStopLimitOrder);
+    }
+
+    // Get current prices
+    let current_time = pool.get_time(contract)?;
+    if let Some(expiry_time) = params.expiry_time {
+        require!(
+            params.order_type == OrderType::Limit && expiry_time > current_time,
+            PerpetualError::InvalidLimitExpiry
+        );
+    }
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price =
+        sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price =
+        usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+
+    let sol_price_value = sol_price.get_price();
+    let usdc_price_value = usdc_price.get_price();
+
+    msg!("SOL Price: {}", sol_price_value);
+    msg!("USDC Price: {}", usdc_price_value);
+
+    if params.order_type == OrderType::Limit {
+        Position::validate_limit_trigger(
+            params.side,
+            params.trigger_price,
+            params.stop_price,
+            params.trigger_above_threshold,
+            f64_to_scaled_price(sol_price_value)?,
+        )?;
+    }
+
+    // LP collateral is valued at the stored AUM, so it must have been reconciled within a few
+    // slots or be recomputed from the custodies passed as remaining accounts
+    let lp_supply = if params.pay_lp {
+        require!(params.order_type == OrderType::Market, PerpetualError::LpCollateralUnsupported);
+        let lp_token_mint = ctx.accounts.lp_token_mint.as_ref()
+            .ok_or(PerpetualError::LpCollateralAccountsMissing)?;
+        let lp_collateral_account = ctx.accounts.lp_collateral_account.as_ref()
+            .ok_or(PerpetualError::LpCollateralAccountsMissing)?;
+        require_keys_eq!(lp_collateral_account.mint, lp_token_mint.key(), TradingError::InvalidMintError);
+        let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+        pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, current_time)?;
+        lp_token_mint.supply
+    } else {
+        0
+    };
+
+    // Determine collateral asset and custody
+    let (collateral_custody, collateral_decimals, collateral_price) = if params.pay_sol {
+        (sol_custody.key(), sol_custody.decimals, sol_price_value)
+    } else {
+        (usdc_custody.key(), usdc_custody.decimals, usdc_price_value)
+    };
+
+    // Calculate collateral value in USD
+    let collateral_usd = if params.pay_lp {
+        pool.get_lp_token_value_usd(params.collateral_amount, lp_supply)?
+    } else {
+        math::checked_as_u64(
+            math::checked_float_mul(
+                params.collateral_amount as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
+                collateral_price,
+            )? * Contract::USD_SCALE as f64,
+        )?
+    };
+
+    let size_usd = math::checked_as_u64(
+        math::checked_float_mul(
+            params.size_amount as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
+            collateral_price,
+        )? * Contract::USD_SCALE as f64,
+    )?;
+
+    let entry_price = if params.order_type == OrderType::Limit {
+        params
+            .trigger_price
+            .unwrap_or(f64_to_scaled_price(sol_price_value)?)
+    } else {
+        f64_to_scaled_price(sol_price_value)?
+    };
+
+    // Max-loss protection is the put (long) or call (short) struck at the floor price that the
+    // pool writes. The premium is paid out of the posted collateral, which must still cover the
+    // floor afterwards.
+    let (max_loss_price, max_loss_premium_usd) = if let Some(max_loss_usd) = params.max_loss_usd {
+        require!(
+            params.order_type == OrderType::Market && !params.pay_lp && max_loss_usd > 0 && max_loss_usd < collateral_usd,
+            PerpetualError::InvalidMaxLoss
+        );
+        let max_loss_price = Position::get_max_loss_price(entry_price, size_usd, max_loss_usd, params.side)?;
+        let premium_usd = Position::get_max_loss_premium_usd(
+            sol_custody,
+            usdc_custody,
+            sol_price_value,
+            entry_price,
+            size_usd,
+            max_loss_price,
+            params.side,
+        )?;
+        require_gt!(collateral_usd - max_loss_usd, premium_usd, PerpetualError::InvalidMaxLoss);
+        msg!("Max loss price: {}, premium USD: {}", max_loss_price, premium_usd);
+        (max_loss_price, premium_usd)
+    } else {
+        (0, 0)
+    };
+    let max_loss_premium_amount = if max_loss_premium_usd > 0 {
+        let premium_price = if params.pay_sol { &sol_price } else { &usdc_price };
+        math::usd_to_token_amount(max_loss_premium_usd, premium_price, collateral_decimals)?
+    } else {
+        0
+    };
+    // Collateral left backing the position once the premium is paid
+    let collateral_usd = collateral_usd - max_loss_premium_usd;
+    let net_collateral_amount = math::checked_sub(params.collateral_amount, max_loss_premium_amount)?;
+
+    // Calculate leverage and initial margin in integer bps
+    let (leverage_size, leverage_collateral) = if params.pay_lp {
+        (size_usd, collateral_usd)
+    } else {
+        (params.size_amount, net_collateral_amount)
+    };
+    let (leverage_bps, initial_margin_bps) =
+        Position::get_leverage_and_margin_bps(leverage_size, leverage_collateral)?;
+
+    msg!("Position Size USD: {}", size_usd);
+    msg!("Collateral USD: {}", collateral_usd);
+    msg!("Leverage: {} bps", leverage_bps);
+
+    pool.check_min_position_size(size_usd)?;
+
+    // Validate leverage (250x max)
+    Position::validate_leverage_bps(leverage_bps)?;
+
+    // Check user has sufficient balance
+    require_gte!(
+        ctx.accounts.funding_account.amount,
+        params.collateral_amount,
+        TradingError::InsufficientBalance
+    );
+
+    // Ensure minimum margin requirements
+    require!(
+        initial_margin_bps >= Position::MIN_INITIAL_MARGIN_BPS,
+        PerpetualError::InvalidLeverage
+    );
+
+    // Exact ratio of the validated integers for the price math
+    let leverage = math::checked_float_div(leverage_size as f64, leverage_collateral as f64)?;
+    let maintenance_margin_bps = sol_custody.get_maintenance_margin_bps(size_usd);
+    let liquidation_price =
+        calculate_liquidation_price(entry_price, leverage, params.side, maintenance_margin_bps)?;
+    let bankruptcy_price = calculate_bankruptcy_price(entry_price, leverage, params.side)?;
+
+    msg!("Entry Price: {}", entry_price);
+    msg!("Liquidation Price: {}", liquidation_price);
+    msg!("Bankruptcy Price: {}", bankruptcy_price);
+
+    if max_loss_price > 0 {
+        let inside_liquidation = match params.side {
+            Side::Long => max_loss_price > liquidation_price,
+            Side::Short => max_loss_price < liquidation_price,
+        };
+        require!(inside_liquidation, PerpetualError::InvalidMaxLoss);
+    }
+
+    // Check pool liquidity using integer math
+    let required_liquidity = if params.side == Side::Long {
+        // Convert USD to SOL tokens using integer math
+        math::usd_to_token_amount(size_usd, &sol_price, sol_custody.decimals)?
+    } else {
+        // Convert USD to USDC tokens using integer math
+        math::usd_to_token_amount(size_usd, &usdc_price, usdc_custody.decimals)?
+    };
+
+    let normalized_collateral_amount = if params.pay_lp {
+        // LP collateral is tracked separately in lp_collateral_amount
+        0
+    } else if params.side == Side::Long {
+        // For long positions, convert collateral to SOL token units
+        if params.pay_sol {
+            // Already in SOL, use as-is
+            net_collateral_amount
+        } else {
+            // Convert USDC collateral to equivalent SOL tokens using integer math
+            math::usd_to_token_amount(collateral_usd, &sol_price, sol_custody.decimals)?
+        }
+    } else {
+        // For short positions, convert collateral to USDC token units
+        if params.pay_sol {
+            // Convert SOL collateral to equivalent USDC tokens using integer math
+            math::usd_to_token_amount(collateral_usd, &usdc_price, usdc_custody.decimals)?
+        } else {
+            // Already in USDC, use as-is
+            net_collateral_amount
+        }
+    };
+
+    if params.side == Side::Long {
+        require_gte!(
+            pool.get_borrowable_amount(sol_custody)?,
+            required_liquidity,
+            TradingError::InsufficientPoolLiquidity
+        );
+        pool.check_position_concentration(size_usd, sol_custody, 0, &sol_price)?;
+    } else {
+        require_gte!(
+            pool.get_borrowable_amount(usdc_custody)?,
+            required_liquidity,
+            TradingError::InsufficientPoolLiquidity
+        );
+        pool.check_position_concentration(size_usd, usdc_custody, 0, &usdc_price)?;
+    }
+
+    // Transfer collateral from user to pool
+    let collateral_destination = if params.pay_lp {
+        ctx.accounts.lp_collateral_account.as_ref()
+            .ok_or(PerpetualError::LpCollateralAccountsMissing)?
+            .to_account_info()
+    } else if params.pay_sol {
+        ctx.accounts.sol_custody_token_account.to_account_info()
+    } else {
+        ctx.accounts.usdc_custody_token_account.to_account_info()
+    };
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            SplTransfer {
+                from: ctx.accounts.funding_account.to_account_info(),
+                to: collateral_destination,
+                authority: owner.to_account_info(),
+            },
+        ),
+        params.collateral_amount,
+    )?;
+
+    // Update custody stats - lock tokens only for MARKET orders (limit orders lock when executed)
+    if params.order_type == OrderType::Market {
+        if params.side == Side::Long {
+            // Long positions always need SOL backing
+            sol_custody.add_locked(LockedProduct::Perp, required_liquidity)?;
+        } else {
+            // Short positions always need USDC backing
+            usdc_custody.add_locked(LockedProduct::Perp, required_liquidity)?;
+        }
+    }
+
+    // LP collateral is held outside the custodies
+    if params.pay_lp {
+        msg!("LP tokens posted as collateral: {}", params.collateral_amount);
+    } else if params.pay_sol {
+        sol_custody.token_owned =
+            math::checked_add(sol_custody.token_owned, params.collateral_amount)?;
+    } else {
+        usdc_custody.token_owned =
+            math::checked_add(usdc_custody.token_owned, params.collateral_amount)?;
+    }
+
+    // Initialize position
+    position.index = user.perp_position_index.checked_add(1).unwrap_or(1);
+    position.owner = owner.key();
+    position.pool = pool.key();
+    position.custody = sol_custody.key(); // Position always tracks SOL
+    position.collateral_custody = collateral_custody;
+    position.order_type = params.order_type;
+    position.side = params.side;
+    position.is_liquidated = false;
+    position.entry_price = entry_price;
+    position.size_usd = size_usd;
+    position.collateral_usd = collateral_usd;
+    position.open_time = current_time;
+    position.update_time = current_time;
+    position.execution_time = if params.order_type == OrderType::Market {
+        Some(current_time) // Market orders execute immediately
+    } else {
+        None // Limit orders start with no execution time
+    };
+    position.last_borrow_fees_update_time = current_time;
+    position.liquidation_price = liquidation_price;
+    position.bankruptcy_price = bankruptcy_price;
+
+    // Borrow fees accrue from the side's index as of now
+    pool.update_borrow_index(sol_custody, usdc_custody, current_time)?;
+    position.cumulative_interest_snapshot = pool.get_borrow_index(params.side);
+
+    // Wider oracle confidence costs a risk premium on top of the trade fee, kept by LPs
+    let confidence_fee_usd = pool.get_confidence_fee(size_usd, sol_price.confidence_bps)?;
+    if confidence_fee_usd > 0 {
+        msg!("Confidence fee USD: {} at {} bps confidence", confidence_fee_usd, sol_price.confidence_bps);
+    }
+    // The max-loss premium was already taken from the collateral and stays with LPs, who carry the floor
+    position.trade_fees = pool.get_perp_open_fee(size_usd, sol_price.confidence_bps)?;
+    position.borrow_fees_paid = 0;
+
+    // The referral share of the trade fee is credited at close, once the fee is collected
+    position.referrer = params.referrer;
+    if let Some(referrer) = params.referrer {
+        Referral::validate_open(ctx.accounts.referral.as_deref().map(|r| &**r), referrer, owner.key())?;
+    }
+
+    position.accrued_borrow_fees = 0;
+
+    // Asset amounts
+    position.locked_amount = required_liquidity;
+    position.collateral_amount = normalized_collateral_amount;
+    position.lp_collateral_amount = if params.pay_lp { params.collateral_amount } else { 0 };
+    position.lp_collateral_usd = if params.pay_lp { collateral_usd } else { 0 };
+
+    // TP/SL
+    position.tp_sl_orderbook = None; // No orderbook initially
+
+    // Limit order specific
+    position.trigger_price = params.trigger_price;
+    position.trigger_above_threshold = params.trigger_above_threshold;
+    position.stop_price = params.stop_price;
+    position.stop_activated = false;
+    position.expiry_time = params.expiry_time;
+
+    // Max-loss protection
+    position.max_loss_usd = params.max_loss_usd.unwrap_or(0);
+    position.max_loss_price = max_loss_price;
+    position.max_loss_expiry = if max_loss_price > 0 {
+        math::checked_add(current_time, Position::MAX_LOSS_COVER_PERIOD_SEC)?
+    } else {
+        0
+    };
+
+    position.bump = ctx.bumps.position;
+
+    // Update pool open interest
+    pool.update_open_interest(position, size_usd, true, current_time)?;
+
+    // Market positions must not open already liquidatable (limit orders are checked at execution)
+    if params.order_type == OrderType::Market {
+        position.require_healthy(entry_price, maintenance_margin_bps, pool.liquidation_buffer_bps)?;
+    }
+
+    // Limit orders are kept off the global ceiling until they execute, but not while paused
+    if params.order_type == OrderType::Market {
+        pool.add_notional(&ctx.accounts.contract, size_usd)?;
+    } else {
+        require!(!ctx.accounts.contract.paused, ContractError::ContractPaused);
+    }
+
+    // Rest limit orders in the shared book when the pool has one
+    if params.order_type == OrderType::Limit {
+        if let Some(book) = ctx.accounts.limit_order_book.as_mut() {
+            book.insert(
+                RestingOrder {
+                    position: position.key(),
+                    trigger_price: params.stop_price.unwrap_or(entry_price),
+                    size_usd,
+                    open_time: current_time,
+                },
+                params.trigger_above_threshold,
+            )?;
+            position.in_limit_order_book = true;
+        }
+    }
+
+    // Update user stats
+    user.perp_position_index = user.perp_position_index.checked_add(1).unwrap_or(1);
+
+    emit!(PerpPositionOpened {
+        index: position.index,
+        owner: position.owner,
+        pool: position.pool,
+        pub_key: position.key(),
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        order_type: position.order_type as u8,
+        side: position.side as u8,
+        is_liquidated: position.is_liquidated,
+        price: position.entry_price,
+        size_usd: position.size_usd,
+        collateral_usd: position.collateral_usd,
+        open_time: position.open_time,
+        execution_time: position.execution_time,
+        update_time: position.update_time,
+        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
+        liquidation_price: position.liquidation_price,
+        cumulative_interest_snapshot: position.cumulative_interest_snapshot,
+        trade_fees: position.trade_fees,
+        accrued_borrow_fees: position.accrued_borrow_fees,
+        locked_amount: position.locked_amount,
+        collateral_amount: position.collateral_amount,
+        trigger_price: position.trigger_price,
+        trigger_above_threshold: position.trigger_above_threshold,
+        stop_price: position.stop_price,
+        max_slippage: params.max_slippage,
+        trade_fee_bps: pool.get_perp_trade_fee_bps(),
+        lp_collateral_amount: position.lp_collateral_amount,
+        borrow_size_usd: position.get_borrow_size_usd(),
+        bump: position.bump,
+        referrer: position.referrer,
+        referral_fee_usd: 0,
+        confidence_fee_usd,
+        oracle_confidence_bps: sol_price.confidence_bps,
+        max_loss_usd: position.max_loss_usd,
+        max_loss_price: position.max_loss_price,
+        max_loss_premium_usd,
+        max_loss_expiry: position.max_loss_expiry,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: OpenPerpPositionParams)]
+pub struct OpenPerpPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Program derived address (PDA) used as authority for token operations.
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = User::LEN,
+        seeds = [b"user_v3", owner.key().as_ref()],
+        bump,
+    )]
+    pub user: Box<Account<'info, User>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            (user.perp_position_index + 1).to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"limit_order_book", pool.key().as_ref()],
+        bump = limit_order_book.bump
+    )]
+    pub limit_order_book: Option<Box<Account<'info, LimitOrderBook>>>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // LP collateral vault, required when pay_lp
+    #[account(
+        init_if_needed,
+        payer = owner,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"lp_collateral_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Referrer's registration, required when params.referrer is set
+    #[account(
+        seeds = [b"referral", params.referrer.unwrap_or_default().as_ref(), pool.key().as_ref()],
+        bump = referral.bump
+    )]
+    pub referral: Option<Box<Account<'info, Referral>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+    // remaining accounts (optional, with pay_lp once pool.aum_usd is stale):
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+}
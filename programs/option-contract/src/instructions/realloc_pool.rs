@@ -53,8 +53,7 @@ pub struct RealocPool<'info> {
 
     #[account(
         mut,
-        realloc = Pool::LEN + (pool.custodies.len() + 1) * std::mem::size_of::<Pubkey>() +
-        (pool.ratios.len() + 1) * std::mem::size_of::<TokenRatios>(),
+        realloc = Pool::get_space(pool.custodies.len() + 1, pool.ratios.len() + 1),
         realloc::payer = signer,
         realloc::zero = false,
         seeds = [b"pool", params.pool_name.as_bytes()],
@@ -0,0 +1,120 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::{
+    errors::PoolError,
+    events::CustodyLockedReconciled,
+    math,
+    state::{multisig::{AdminInstruction, Multisig}, Contract, Custody, Pool},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReconcileCustodyLockedParams {
+    pub pool_name: String,
+    pub token_locked: u64, // Authoritative sum of locked_amount over all live positions, computed off-chain
+    pub token_locked_perp: u64, // the same sum split by product, adding up to token_locked
+    pub token_locked_option: u64,
+    pub token_locked_future: u64,
+}
+
+/// Safety valve for the locked-liquidity accounting: several instructions adjust
+/// `custody.token_locked` independently, so a failed close or a past bug can leave it out of
+/// line with the positions that actually hold liquidity. Signers overwrite it with the true sum.
+pub fn reconcile_custody_locked<'info>(
+    ctx: Context<'_, '_, '_, 'info, ReconcileCustodyLocked<'info>>,
+    params: &ReconcileCustodyLockedParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::ReconcileCustodyLocked, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let custody = &mut ctx.accounts.custody;
+
+    require_gte!(
+        custody.token_owned,
+        params.token_locked,
+        PoolError::InvalidPoolBalanceError
+    );
+    require_eq!(
+        math::checked_add(
+            math::checked_add(params.token_locked_perp, params.token_locked_option)?,
+            params.token_locked_future,
+        )?,
+        params.token_locked,
+        PoolError::InvalidPoolBalanceError
+    );
+
+    let previous_token_locked = custody.token_locked;
+    custody.token_locked = params.token_locked;
+    custody.token_locked_perp = params.token_locked_perp;
+    custody.token_locked_option = params.token_locked_option;
+    custody.token_locked_future = params.token_locked_future;
+    msg!(
+        "Custody locked reconciled: {} -> {}",
+        previous_token_locked,
+        custody.token_locked
+    );
+
+    emit!(CustodyLockedReconciled {
+        pool: ctx.accounts.pool.key(),
+        custody: custody.key(),
+        previous_token_locked,
+        token_locked: custody.token_locked,
+        token_locked_perp: custody.token_locked_perp,
+        token_locked_option: custody.token_locked_option,
+        token_locked_future: custody.token_locked_future,
+        token_owned: custody.token_owned,
+        timestamp: ctx.accounts.pool.get_time(&ctx.accounts.contract)?,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: ReconcileCustodyLockedParams)]
+pub struct ReconcileCustodyLocked<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), custody_mint.key().as_ref()],
+        bump = custody.bump,
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    pub custody_mint: Box<Account<'info, Mint>>,
+}
@@ -0,0 +1,115 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::PerpetualError,
+    events::OpenInterestReconciled,
+    state::{multisig::{AdminInstruction, Multisig}, Contract, Pool, Position},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReconcileOpenInterestParams {
+    pub pool_name: String,
+    // size_usd of live positions not passed in this transaction, summed off-chain; zero when
+    // every live position of the pool is in remaining accounts
+    pub carried_long_open_interest_usd: u128,
+    pub carried_short_open_interest_usd: u128,
+}
+
+/// Safety valve for the open interest accounting: opens, closes, resizes and liquidations
+/// each adjust the pool counters, so a missed decrement leaves them inflated and skews borrow
+/// rates and OI caps. Signers overwrite them with the summed size of the passed live
+/// positions plus the carried totals. Pending limit orders and liquidated positions hold no
+/// open interest and are not counted.
+pub fn reconcile_open_interest<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ReconcileOpenInterest<'info>>,
+    params: &ReconcileOpenInterestParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::ReconcileOpenInterest, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let pool_key = ctx.accounts.pool.key();
+    let mut counted_keys: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+    let mut positions: Vec<Account<Position>> = Vec::with_capacity(ctx.remaining_accounts.len());
+
+    for position_info in ctx.remaining_accounts.iter() {
+        require!(
+            position_info.owner == ctx.program_id && !counted_keys.contains(position_info.key),
+            PerpetualError::InvalidPositionState
+        );
+        let position = Account::<Position>::try_from(position_info)?;
+        require_keys_eq!(position.pool, pool_key, PerpetualError::InvalidPositionState);
+        counted_keys.push(position_info.key());
+        positions.push(position);
+    }
+
+    let pool = &mut ctx.accounts.pool;
+    let (previous_long_open_interest_usd, previous_short_open_interest_usd) = pool.reconcile_open_interest(
+        positions.iter().map(|position| &**position),
+        params.carried_long_open_interest_usd,
+        params.carried_short_open_interest_usd,
+    )?;
+    let long_open_interest_usd = pool.long_open_interest_usd;
+    let short_open_interest_usd = pool.short_open_interest_usd;
+    msg!(
+        "Open interest reconciled: long {} -> {}, short {} -> {}",
+        previous_long_open_interest_usd,
+        long_open_interest_usd,
+        previous_short_open_interest_usd,
+        short_open_interest_usd
+    );
+
+    emit!(OpenInterestReconciled {
+        pool: pool_key,
+        previous_long_open_interest_usd,
+        previous_short_open_interest_usd,
+        long_open_interest_usd,
+        short_open_interest_usd,
+        positions_passed: counted_keys.len() as u32,
+        timestamp: ctx.accounts.pool.get_time(&ctx.accounts.contract)?,
+    });
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: ReconcileOpenInterestParams)]
+pub struct ReconcileOpenInterest<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    // remaining accounts:
+    //   position accounts of this pool to sum (read-only, each at most once)
+}
@@ -1,371 +1,379 @@
-use crate::{
-    errors::{PerpetualError, TradingError},
-    events::CollateralRemoved,
-    math::{self, f64_to_scaled_price},
-    utils::risk_management::*,
-    state::{Contract, Custody, OraclePrice, Pool, Position, Side, OrderType},
-};
-use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct RemoveCollateralParams {
-    pub position_index: u64,
-    pub pool_name: String,
-    pub collateral_amount: u64,  // Amount to remove from collateral
-    pub receive_sol: bool,       // true = receive SOL, false = receive USDC
-}
-
-pub fn remove_collateral(
-    ctx: Context<RemoveCollateral>,
-    params: &RemoveCollateralParams
-) -> Result<()> {
-    msg!("Removing collateral from perpetual position");
-    
-    let contract = &ctx.accounts.contract;
-    let pool = &mut ctx.accounts.pool;
-    let position = &mut ctx.accounts.position;
-    let sol_custody = &mut ctx.accounts.sol_custody;
-    let usdc_custody = &mut ctx.accounts.usdc_custody;
-    
-    // Validation
-    require_keys_eq!(position.owner, ctx.accounts.owner.key(), TradingError::Unauthorized);
-    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
-    require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
-    require!(params.collateral_amount > 0, TradingError::InvalidAmount);
-    require!(
-        params.collateral_amount < position.collateral_amount,
-        TradingError::InvalidAmount
-    );
-    
-    // Get current prices
-    let current_time = contract.get_time()?;
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
-    
-    let sol_price_value = sol_price.get_price();
-    let usdc_price_value = usdc_price.get_price();
-    let current_price_scaled = f64_to_scaled_price(sol_price_value)?;
-    
-    msg!("SOL Price: {}", sol_price_value);
-    msg!("USDC Price: {}", usdc_price_value);
-    msg!("Removing {} tokens from collateral", params.collateral_amount);
-    
-    // Calculate USD value to remove based on what the user wants to withdraw
-    // params.collateral_amount is in the asset user wants to receive (receive_sol)
-    let collateral_usd_to_remove = if params.receive_sol {
-        // User wants to receive SOL, so params.collateral_amount is in SOL
-        math::checked_as_u64(math::checked_float_mul(
-            params.collateral_amount as f64 / math::checked_powi(10.0, sol_custody.decimals as i32)?,
-            sol_price_value
-        )? * 1_000_000.0)?
-    } else {
-        // User wants to receive USDC, so params.collateral_amount is in USDC
-        math::checked_as_u64(math::checked_float_mul(
-            params.collateral_amount as f64 / math::checked_powi(10.0, usdc_custody.decimals as i32)?,
-            usdc_price_value
-        )? * 1_000_000.0)?
-    };
-    
-    msg!("Collateral USD to remove: {}", collateral_usd_to_remove);
-    msg!("Current collateral USD: {}", position.collateral_usd);
-    
-    // Calculate new collateral USD
-    let new_collateral_usd = math::checked_sub(position.collateral_usd, collateral_usd_to_remove)?;
-    
-    // Calculate new leverage and ensure it doesn't exceed limits
-    let new_leverage = math::checked_float_div(position.size_usd as f64, position.collateral_usd as f64)?.max(1.0);
-    require!(new_leverage <= Position::MAX_LEVERAGE, PerpetualError::InvalidLeverage);
-    
-    // Calculate new margin requirements
-    let new_initial_margin_bps = math::checked_as_u64(math::checked_float_div(10_000.0, new_leverage)?)?; // 10000 / leverage
-    
-    // Ensure new margin requirements meet minimum standards
-    require!(
-        new_initial_margin_bps >= Position::MIN_INITIAL_MARGIN_BPS,
-        PerpetualError::InvalidLeverage
-    );
-    
-    // Check if position would be liquidatable after removing collateral
-    let new_liquidation_price = calculate_liquidation_price(
-        position.entry_price,
-        new_leverage,
-        position.side
-    )?;
-    
-    // Ensure position won't be immediately liquidatable
-    let would_be_liquidatable = match position.side {
-        Side::Long => current_price_scaled <= new_liquidation_price,
-        Side::Short => current_price_scaled >= new_liquidation_price,
-    };
-    
-    require!(!would_be_liquidatable, PerpetualError::WouldCauseLiquidation);
-    
-    // Check margin ratio wouldn't be too low
-    let pnl = position.calculate_pnl(current_price_scaled)?;
-    let new_equity = if pnl >= 0 {
-        new_collateral_usd + pnl as u64
-    } else {
-        let loss = (-pnl) as u64;
-        if loss >= new_collateral_usd {
-            0
-        } else {
-            new_collateral_usd - loss
-        }
-    };
-    
-    let new_margin_ratio_bps = math::checked_as_u64(math::checked_div(
-        math::checked_mul(new_equity as u128, 10_000u128)?,
-        position.size_usd as u128,
-    )?)?;
-    
-    require!(
-        new_margin_ratio_bps > Position::LIQUIDATION_MARGIN_BPS + 20, // 1% buffer
-        PerpetualError::InsufficientMargin
-    );
-    
-    // The withdrawal amount is exactly what the user requested
-    let withdrawal_tokens = params.collateral_amount;
-    
-    msg!("Withdrawal tokens: {}", withdrawal_tokens);
-    
-    // Check if custody has enough tokens for withdrawal
-    if params.receive_sol {
-        require_gte!(
-            sol_custody.token_owned,
-            withdrawal_tokens,
-            TradingError::InsufficientBalance
-        );
-    } else {
-        require_gte!(
-            usdc_custody.token_owned,
-            withdrawal_tokens,
-            TradingError::InsufficientBalance
-        );
-    }
-    
-    // Transfer withdrawal to user
-    if withdrawal_tokens > 0 {
-        ctx.accounts.contract.transfer_tokens(
-            if params.receive_sol {
-                ctx.accounts.sol_custody_token_account.to_account_info()
-            } else {
-                ctx.accounts.usdc_custody_token_account.to_account_info()
-            },
-            ctx.accounts.receiving_account.to_account_info(),
-            ctx.accounts.transfer_authority.to_account_info(),
-            ctx.accounts.token_program.to_account_info(),
-            withdrawal_tokens,
-        )?;
-    }
-    
-    // Update custody stats based on where tokens are withdrawn from
-    // This accounts for cross-asset withdrawals (e.g., withdrawing SOL from USDC collateral)
-    if params.receive_sol {
-        sol_custody.token_owned = math::checked_sub(
-            sol_custody.token_owned,
-            withdrawal_tokens
-        )?;
-    } else {
-        usdc_custody.token_owned = math::checked_sub(
-            usdc_custody.token_owned,
-            withdrawal_tokens
-        )?;
-    }
-    
-    // Update position
-    // Calculate how much to subtract from the stored collateral amount
-    let collateral_amount_to_subtract = if position.collateral_custody == sol_custody.key() {
-        // Position stores SOL
-        if params.receive_sol {
-            // Withdrawing SOL from SOL position - direct subtract
-            params.collateral_amount
-        } else {
-            // Withdrawing USDC from SOL position - convert using integer math
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(collateral_usd_to_remove as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
-        }
-    } else {
-        // Position stores USDC
-        if params.receive_sol {
-            // Withdrawing SOL from USDC position - convert using integer math
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(collateral_usd_to_remove as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
-        } else {
-            // Withdrawing USDC from USDC position - direct subtract
-            params.collateral_amount
-        }
-    };
-    
-    position.collateral_amount = math::checked_sub(
-        position.collateral_amount,
-        collateral_amount_to_subtract
-    )?;
-    position.collateral_usd = new_collateral_usd;
-    // Update accrued borrow fees before modifying position
-    pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
-    
-    position.liquidation_price = new_liquidation_price;
-    position.update_time = current_time;
-    
-    msg!("Successfully removed collateral");
-    msg!("New collateral amount: {}", position.collateral_amount);
-    msg!("New collateral USD: {}", position.collateral_usd);
-    msg!("New leverage: {}x", new_leverage);
-    msg!("New liquidation price: {}", position.liquidation_price);
-    msg!("Withdrawal amount: {} tokens", withdrawal_tokens);
-    
-    emit!(CollateralRemoved {
-        pub_key: position.key(),
-        owner: ctx.accounts.owner.key(),
-        position_index: params.position_index,
-        pool: pool.key(),
-        custody: position.custody,
-        collateral_custody: position.collateral_custody,
-        order_type: position.order_type as u8,
-        side: position.side as u8,
-        collateral_amount_removed: params.collateral_amount,
-        collateral_usd_removed: collateral_usd_to_remove,
-        new_collateral_amount: position.collateral_amount,
-        new_collateral_usd: position.collateral_usd,
-        new_leverage,
-        new_liquidation_price: position.liquidation_price,
-        withdrawal_tokens,
-        withdrawal_asset: if params.receive_sol { sol_custody.mint } else { usdc_custody.mint },
-        update_time: current_time,
-        accrued_borrow_fees: position.accrued_borrow_fees,
-        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
-    });
-    
-    Ok(())
-}
-
-#[derive(Accounts)]
-#[instruction(params: RemoveCollateralParams)]
-pub struct RemoveCollateral<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-
-    #[account(
-        mut,
-        has_one = owner
-    )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Transfer authority PDA for contract token operations
-    #[account(
-        seeds = [b"transfer_authority"],
-        bump = contract.transfer_authority_bump
-    )]
-    pub transfer_authority: AccountInfo<'info>,
-
-    #[account(
-        seeds = [b"contract"],
-        bump = contract.bump
-    )]
-    pub contract: Box<Account<'info, Contract>>,
-
-    #[account(
-        mut,
-        seeds = [b"pool", params.pool_name.as_bytes()],
-        bump = pool.bump
-    )]
-    pub pool: Box<Account<'info, Pool>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"position",
-            owner.key().as_ref(),
-            params.position_index.to_le_bytes().as_ref(),
-            pool.key().as_ref()
-        ],
-        bump = position.bump
-    )]
-    pub position: Box<Account<'info, Position>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
-        bump = sol_custody.bump
-    )]
-    pub sol_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
-        bump = usdc_custody.bump
-    )]
-    pub usdc_custody: Box<Account<'info, Custody>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            sol_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        mut,
-        seeds = [
-            b"custody_token_account",
-            pool.key().as_ref(),
-            usdc_custody.mint.key().as_ref()
-        ],
-        bump
-    )]
-    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = sol_oracle_account.key() == sol_custody.oracle
-    )]
-    pub sol_oracle_account: AccountInfo<'info>,
-
-    /// CHECK: Oracle account validation is handled by constraint
-    #[account(
-        constraint = usdc_oracle_account.key() == usdc_custody.oracle
-    )]
-    pub usdc_oracle_account: AccountInfo<'info>,
-
-    #[account(mut)]
-    pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
-    pub usdc_mint: Box<Account<'info, Mint>>,
-
-    pub token_program: Program<'info, Token>,
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::CollateralRemoved,
+    math::{self, f64_to_scaled_price},
+    utils::risk_management::*,
+    state::{Contract, Custody, Pool, Position, Side, OrderType},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RemoveCollateralParams {
+    pub position_index: u64,
+    pub pool_name: String,
+    pub collateral_amount: u64,  // Amount to remove from collateral
+    pub receive_sol: bool,       // true = receive SOL, false = receive USDC
+    pub remove_usd: Option<u64>, // USD value to remove instead of a token amount (collateral_amount must be 0)
+}
+
+pub fn remove_collateral(
+    ctx: Context<RemoveCollateral>,
+    params: &RemoveCollateralParams
+) -> Result<()> {
+    msg!("Removing collateral from perpetual position");
+    
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let position = &mut ctx.accounts.position;
+    let sol_custody = &mut ctx.accounts.sol_custody;
+    let usdc_custody = &mut ctx.accounts.usdc_custody;
+    
+    // Validation
+    require_keys_eq!(position.owner, ctx.accounts.owner.key(), TradingError::Unauthorized);
+    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
+    
+    // Get current prices
+    let current_time = pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+    
+    let sol_price_value = sol_price.get_price();
+    let usdc_price_value = usdc_price.get_price();
+    let current_price_scaled = f64_to_scaled_price(sol_price_value)?;
+    
+    // A USD request is converted to tokens of the payout asset at the oracle price
+    let (payout_price, payout_decimals) = if params.receive_sol {
+        (&sol_price, sol_custody.decimals)
+    } else {
+        (&usdc_price, usdc_custody.decimals)
+    };
+    let (collateral_amount, collateral_usd_to_remove) = Position::get_collateral_removal(
+        params.collateral_amount,
+        params.remove_usd,
+        payout_price,
+        payout_decimals,
+    )?;
+    require!(
+        collateral_amount < position.collateral_amount,
+        TradingError::InvalidAmount
+    );
+    
+    msg!("SOL Price: {}", sol_price_value);
+    msg!("USDC Price: {}", usdc_price_value);
+    msg!("Removing {} tokens from collateral", collateral_amount);
+
+    // Receiving account must hold the asset being paid out
+    let payout_mint = if params.receive_sol { sol_custody.mint } else { usdc_custody.mint };
+    require_keys_eq!(
+        ctx.accounts.receiving_account.mint,
+        payout_mint,
+        TradingError::ReceivingAccountMintMismatch
+    );
+    
+    
+    // Settle accrued borrow fees before the collateral changes
+    pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
+    
+    msg!("Collateral USD to remove: {}", collateral_usd_to_remove);
+    msg!("Current collateral USD: {}", position.collateral_usd);
+    
+    // Calculate new collateral USD
+    let new_collateral_usd = math::checked_sub(position.collateral_usd, collateral_usd_to_remove)?;
+    // LP collateral can only leave through a full close
+    require_gte!(new_collateral_usd, position.lp_collateral_usd, PerpetualError::LpCollateralUnsupported);
+    
+    // Calculate new leverage and ensure it doesn't exceed limits
+    let new_leverage = math::checked_float_div(position.size_usd as f64, new_collateral_usd as f64)?.max(1.0);
+    require!(new_leverage <= Position::MAX_LEVERAGE, PerpetualError::InvalidLeverage);
+    
+    // Calculate new margin requirements
+    let new_initial_margin_bps = math::checked_as_u64(math::checked_float_div(10_000.0, new_leverage)?)?; // 10000 / leverage
+    
+    // Ensure new margin requirements meet minimum standards
+    require!(
+        new_initial_margin_bps >= Position::MIN_INITIAL_MARGIN_BPS,
+        PerpetualError::InvalidLeverage
+    );
+    
+    // Check if position would be liquidatable after removing collateral
+    let maintenance_margin_bps = sol_custody.get_maintenance_margin_bps(position.size_usd);
+    let new_liquidation_price = calculate_liquidation_price(
+        position.entry_price,
+        new_leverage,
+        position.side,
+        maintenance_margin_bps,
+    )?;
+    
+    // Ensure position won't be immediately liquidatable
+    let would_be_liquidatable = match position.side {
+        Side::Long => current_price_scaled <= new_liquidation_price,
+        Side::Short => current_price_scaled >= new_liquidation_price,
+    };
+    
+    require!(!would_be_liquidatable, PerpetualError::WouldCauseLiquidation);
+    
+    // Check margin ratio wouldn't be too low
+    let pnl = position.calculate_pnl(current_price_scaled)?;
+    let new_equity = if pnl >= 0 {
+        new_collateral_usd + pnl as u64
+    } else {
+        new_collateral_usd.saturating_sub((-pnl) as u64)
+    };
+    
+    let new_margin_ratio_bps = math::checked_as_u64(math::checked_div(
+        math::checked_mul(new_equity as u128, 10_000u128)?,
+        position.size_usd as u128,
+    )?)?;
+    
+    require!(
+        new_margin_ratio_bps > maintenance_margin_bps + 20, // 1% buffer
+        PerpetualError::InsufficientMargin
+    );
+    
+    // The user receives the requested amount less the adjustment fee, which stays in the custody for LPs
+    let adjust_fee_amount = math::checked_div(
+        math::checked_mul(collateral_amount, pool.collateral_adjust_fee_bps)?,
+        10_000,
+    )?;
+    let withdrawal_tokens = math::checked_sub(collateral_amount, adjust_fee_amount)?;
+    
+    msg!("Withdrawal tokens: {}", withdrawal_tokens);
+    
+    // Check if custody has enough free tokens for withdrawal
+    if params.receive_sol {
+        require_gte!(
+            sol_custody.available_for_payout(),
+            withdrawal_tokens,
+            TradingError::InsufficientPoolLiquidity
+        );
+    } else {
+        require_gte!(
+            usdc_custody.available_for_payout(),
+            withdrawal_tokens,
+            TradingError::InsufficientPoolLiquidity
+        );
+    }
+    
+    // Transfer withdrawal to user
+    if withdrawal_tokens > 0 {
+        ctx.accounts.contract.transfer_tokens(
+            if params.receive_sol {
+                ctx.accounts.sol_custody_token_account.to_account_info()
+            } else {
+                ctx.accounts.usdc_custody_token_account.to_account_info()
+            },
+            ctx.accounts.receiving_account.to_account_info(),
+            ctx.accounts.transfer_authority.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            withdrawal_tokens,
+        )?;
+    }
+    
+    // Update custody stats based on where tokens are withdrawn from
+    // This accounts for cross-asset withdrawals (e.g., withdrawing SOL from USDC collateral)
+    if params.receive_sol {
+        sol_custody.token_owned = math::checked_sub(
+            sol_custody.token_owned,
+            withdrawal_tokens
+        )?;
+    } else {
+        usdc_custody.token_owned = math::checked_sub(
+            usdc_custody.token_owned,
+            withdrawal_tokens
+        )?;
+    }
+    
+    // Update position
+    // Calculate how much to subtract from the stored collateral amount
+    let collateral_amount_to_subtract = if position.collateral_custody == sol_custody.key() {
+        // Position stores SOL
+        if params.receive_sol {
+            // Withdrawing SOL from SOL position - direct subtract
+            collateral_amount
+        } else {
+            // Withdrawing USDC from SOL position - convert using integer math
+            math::usd_to_token_amount(collateral_usd_to_remove, &sol_price, sol_custody.decimals)?
+        }
+    } else {
+        // Position stores USDC
+        if params.receive_sol {
+            // Withdrawing SOL from USDC position - convert using integer math
+            math::usd_to_token_amount(collateral_usd_to_remove, &usdc_price, usdc_custody.decimals)?
+        } else {
+            // Withdrawing USDC from USDC position - direct subtract
+            collateral_amount
+        }
+    };
+    
+    position.collateral_amount = math::checked_sub(
+        position.collateral_amount,
+        collateral_amount_to_subtract
+    )?;
+    position.collateral_usd = new_collateral_usd;
+    
+    position.liquidation_price = new_liquidation_price;
+    position.bankruptcy_price = calculate_bankruptcy_price(
+        position.entry_price,
+        new_leverage,
+        position.side
+    )?;
+    position.update_time = current_time;
+    
+    position.require_healthy(current_price_scaled, maintenance_margin_bps, pool.liquidation_buffer_bps)?;
+    
+    msg!("Successfully removed collateral");
+    msg!("New collateral amount: {}", position.collateral_amount);
+    msg!("New collateral USD: {}", position.collateral_usd);
+    msg!("New leverage: {}x", new_leverage);
+    msg!("New liquidation price: {}", position.liquidation_price);
+    msg!("Withdrawal amount: {} tokens", withdrawal_tokens);
+    
+    emit!(CollateralRemoved {
+        pub_key: position.key(),
+        owner: ctx.accounts.owner.key(),
+        position_index: params.position_index,
+        pool: pool.key(),
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        order_type: position.order_type as u8,
+        side: position.side as u8,
+        collateral_amount_removed: collateral_amount,
+        collateral_usd_removed: collateral_usd_to_remove,
+        new_collateral_amount: position.collateral_amount,
+        new_collateral_usd: position.collateral_usd,
+        new_leverage,
+        new_liquidation_price: position.liquidation_price,
+        withdrawal_tokens,
+        adjust_fee_amount,
+        withdrawal_asset: if params.receive_sol { sol_custody.mint } else { usdc_custody.mint },
+        update_time: current_time,
+        accrued_borrow_fees: position.accrued_borrow_fees,
+        last_borrow_fees_update_time: position.last_borrow_fees_update_time,
+    });
+    
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: RemoveCollateralParams)]
+pub struct RemoveCollateral<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub receiving_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            sol_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub sol_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"custody_token_account",
+            pool.key().as_ref(),
+            usdc_custody.mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ use {
     crate::{
         errors::{ContractError, PerpetualError, PoolError}, events::LiquidityRemoved, math, state::{
             custody::Custody,
-            oracle::OraclePrice, Contract, Pool,
+            Contract, Pool,
         }
     },
     anchor_lang::prelude::*,
@@ -86,9 +86,16 @@ pub struct RemoveLiquidity<'info> {
     pub custody_mint: Box<Account<'info, Mint>>,
 
     token_program: Program<'info, Token>,
-    // remaining accounts:
+    // remaining accounts (optional, required once pool.aum_usd is stale):
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -116,20 +123,17 @@ pub fn remove_liquidity<'info>(
 
     // compute assets under management
     msg!("Compute assets under management");
-    let curtime = contract.get_time()?;
+    let curtime = pool.get_time(contract)?;
 
     // Refresh pool.aum_usm to adapt to token price change
-    pool.aum_usd =
-        pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+    let incremental = pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
 
-    let token_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody_oracle_account.to_account_info(),
-        curtime,
-        false,
-    )?;
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let token_price = custody.get_oracle_price(&ctx.accounts.custody_oracle_account, custody_oracle_secondary.as_ref(), curtime)?;
 
-    let pool_amount_usd =
-        pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    let pool_amount_usd = pool.aum_usd;
+    let lp_share_price_usd = pool.get_lp_share_price_usd(ctx.accounts.lp_token_mint.supply)?;
 
     // compute amount of tokens to return
     let remove_amount_usd = math::checked_as_u64(math::checked_div(
@@ -161,8 +165,10 @@ pub fn remove_liquidity<'info>(
         PoolError::TokenRatioOutOfRange
     );
 
+    // Liquidity held back for queued withdrawals is served through fulfill_withdraw_liquidity
     require!(
-        math::checked_sub(custody.token_owned, custody.token_locked)? >= withdrawal_amount,
+        math::checked_sub(custody.token_owned, custody.token_locked)?
+            .saturating_sub(custody.reserved_for_withdrawals) >= withdrawal_amount,
         PerpetualError::CustodyAmountLimit
     );
 
@@ -189,12 +195,22 @@ pub fn remove_liquidity<'info>(
     // update custody stats
     
     custody.token_owned = math::checked_sub(custody.token_owned, withdrawal_amount)?;
+    // The fee stays in the token account outside token_owned; with epochs on it goes to LPs
+    if let Some(epoch) = pool.get_lp_fee_epoch(curtime) {
+        custody.accrue_lp_fee(fee_amount, epoch, ctx.accounts.lp_token_mint.supply)?;
+    }
 
     // update pool stats
     msg!("Update pool stats");
     custody.exit(&crate::ID)?;
-    pool.aum_usd =
-        pool.get_assets_under_management_usd(ctx.remaining_accounts, curtime)?;
+    if incremental {
+        // only this custody changed, so adjust by the withdrawal alone
+        let withdrawal_usd = token_price.get_asset_amount_usd(withdrawal_amount, custody.decimals)?;
+        pool.aum_usd = pool.aum_usd.saturating_sub(withdrawal_usd as u128);
+    } else {
+        pool.aum_usd =
+            pool.get_assets_under_management_usd(ctx.remaining_accounts, failover_oracles, curtime)?;
+    }
 
     emit!(LiquidityRemoved {
         owner: ctx.accounts.owner.key(),
@@ -205,7 +221,12 @@ pub fn remove_liquidity<'info>(
         fee_amount,
         withdrawal_amount,
         pool_aum_usd: pool.aum_usd,
+        token_price: token_price.price,
+        token_price_exponent: token_price.exponent,
+        lp_share_price_usd,
     });
 
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
     Ok(())
 }
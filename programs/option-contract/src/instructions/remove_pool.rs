@@ -57,7 +57,7 @@ pub struct RemovePool<'info> {
 
     #[account(
         mut, 
-        realloc= Contract::LEN +(contract.pools.len() - 1) * std::mem::size_of::<Pubkey>(),
+        realloc = Contract::get_space(contract.pools.len() - 1),
         realloc::payer = signer,
         realloc::zero = false,
         seeds = [b"contract"],
@@ -0,0 +1,173 @@
+use crate::{
+    errors::{PoolError, TradingError},
+    events::WithdrawalRequested,
+    math,
+    state::{Contract, Custody, Pool, WithdrawalRequest},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RequestWithdrawLiquidityParams {
+    pub lp_amount: u64,
+    pub pool_name: String,
+}
+
+/// Queue a withdrawal for when remove_liquidity cannot be served because the custody is
+/// mostly locked. The LP tokens are escrowed for at least MIN_CANCEL_DELAY_SEC and their
+/// current value in custody tokens is reserved, so new opens cannot lock liquidity as it
+/// frees up. Reservations of all queued requests are capped at MAX_RESERVED_BPS of the
+/// custody, and requests join the back of its queue.
+pub fn request_withdraw_liquidity(
+    ctx: Context<RequestWithdrawLiquidity>,
+    params: &RequestWithdrawLiquidityParams,
+) -> Result<()> {
+    msg!("Requesting queued liquidity withdrawal");
+
+    require!(params.lp_amount > 0, TradingError::InvalidAmount);
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let custody = &mut ctx.accounts.custody;
+    let request = &mut ctx.accounts.withdrawal_request;
+
+    let current_time = pool.get_time(contract)?;
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let token_price =
+        custody.get_oracle_price(&ctx.accounts.custody_oracle_account, custody_oracle_secondary.as_ref(), current_time)?;
+
+    // Reserve what the LP tokens are worth now; the payout is priced again at fill time
+    let lp_value_usd =
+        pool.get_lp_token_value_usd(params.lp_amount, ctx.accounts.lp_token_mint.supply)?;
+    let reserved_amount = token_price.get_token_amount(lp_value_usd, custody.decimals)?;
+    require_gte!(
+        WithdrawalRequest::get_reserve_headroom(custody)?,
+        reserved_amount,
+        PoolError::WithdrawalReserveExceeded
+    );
+
+    msg!("LP tokens escrowed: {}", params.lp_amount);
+    msg!("Custody tokens reserved: {}", reserved_amount);
+
+    contract.transfer_tokens_from_user(
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.lp_escrow_account.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.lp_amount,
+    )?;
+
+    custody.reserved_for_withdrawals =
+        math::checked_add(custody.reserved_for_withdrawals, reserved_amount)?;
+    pool.pending_withdrawal_lp = math::checked_add(pool.pending_withdrawal_lp, params.lp_amount)?;
+    let queue_index = custody.withdrawal_queue_tail;
+    custody.withdrawal_queue_tail = math::checked_add(queue_index, 1)?;
+
+    request.owner = ctx.accounts.owner.key();
+    request.pool = pool.key();
+    request.custody = custody.key();
+    request.lp_amount = params.lp_amount;
+    request.reserved_amount = reserved_amount;
+    request.request_time = current_time;
+    request.last_fill_time = 0;
+    request.queue_index = queue_index;
+    request.bump = ctx.bumps.withdrawal_request;
+
+    emit!(WithdrawalRequested {
+        owner: request.owner,
+        pool: request.pool,
+        custody: request.custody,
+        request: request.key(),
+        lp_amount: request.lp_amount,
+        reserved_amount,
+        queue_index,
+        request_time: current_time,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: RequestWithdrawLiquidityParams)]
+pub struct RequestWithdrawLiquidity<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key(),
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    // LP tokens waiting in the queue, held by the transfer authority
+    #[account(
+        mut,
+        constraint = lp_escrow_account.mint == lp_token_mint.key(),
+        constraint = lp_escrow_account.owner == transfer_authority.key()
+    )]
+    pub lp_escrow_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), custody.mint.as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: oracle account for the withdrawn token
+    #[account(
+        constraint = custody_oracle_account.key() == custody.oracle
+    )]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = WithdrawalRequest::LEN,
+        seeds = [
+            b"withdrawal_request",
+            custody.key().as_ref(),
+            custody.withdrawal_queue_tail.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub withdrawal_request: Box<Account<'info, WithdrawalRequest>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+}
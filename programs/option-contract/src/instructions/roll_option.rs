@@ -0,0 +1,366 @@
+use crate::{
+    errors::{OptionError, PoolError, TradingError},
+    events::OptionRolled,
+    math::{self, f64_to_scaled_price, scaled_price_to_f64},
+    utils::option_pricing::*,
+    state::{Contract, Custody, OptionDetail, Pool, User},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RollOptionParams {
+    pub option_index: u64,
+    pub pool_name: String,
+    pub new_strike: f64,             // Strike of the rolled option
+    pub new_expiry: i64,             // Must be later than the current expiry
+    pub max_additional_premium: u64, // Slippage protection when the roll costs more
+    pub min_refund_amount: u64,      // Slippage protection when the roll pays out
+}
+
+/// Roll an option to a later expiry (and optionally a new strike) in place. The current
+/// terms are sold back and the new terms bought at fair value, with the close fee and buy
+/// markup of the two legs discounted by OptionDetail::ROLL_FEE_DISCOUNT_BPS. Quantity, and
+/// so the locked liquidity, is unchanged, but holding it to the new expiry must still fit in
+/// borrowable liquidity as a fresh lock would.
+pub fn roll_option(ctx: Context<RollOption>, params: &RollOptionParams) -> Result<()> {
+    let owner = &ctx.accounts.owner;
+    let token_program = &ctx.accounts.token_program;
+    let option_detail = &mut ctx.accounts.option_detail;
+    let contract = &ctx.accounts.contract;
+    let user = &ctx.accounts.user;
+    let custody = &ctx.accounts.custody;
+    let transfer_authority = &ctx.accounts.transfer_authority;
+
+    let locked_custody = &ctx.accounts.locked_custody;
+    let pay_custody = &mut ctx.accounts.pay_custody;
+    let pay_custody_token_account = &ctx.accounts.pay_custody_token_account;
+    let funding_account = &ctx.accounts.funding_account;
+    let refund_account = &ctx.accounts.refund_account;
+
+    require!(option_detail.valid, OptionError::OptionExpired);
+    require_keys_eq!(option_detail.owner, owner.key());
+    require_keys_eq!(option_detail.locked_asset, locked_custody.key());
+    require_keys_eq!(option_detail.premium_asset, pay_custody.key());
+    require_gte!(user.option_index, params.option_index);
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    require!(current_time < option_detail.expired_date, OptionError::InvalidTimeError);
+
+    // New terms: a later expiry within the one-year open limit, and a valid strike
+    require_gt!(params.new_strike, 0.0, OptionError::InvalidStrikePrice);
+    require_gt!(params.new_expiry, option_detail.expired_date, OptionError::InvalidExpiryDate);
+    require!(
+        params.new_expiry - current_time <= 365 * 86_400,
+        OptionError::InvalidExpiryDate
+    );
+    ctx.accounts.pool.validate_option_expiry(params.new_expiry)?;
+    ctx.accounts.pool.validate_option_grid(params.new_strike, params.new_expiry)?;
+
+    let custody_oracle_secondary = ctx.accounts.custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let pay_custody_oracle_secondary = ctx.accounts.pay_custody_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let underlying_price = custody.get_oracle_price(&ctx.accounts.custody_oracle_account, custody_oracle_secondary.as_ref(), current_time)?.get_price();
+    let pay_token_price = pay_custody.get_oracle_price(&ctx.accounts.pay_custody_oracle_account, pay_custody_oracle_secondary.as_ref(), current_time)?.get_price();
+
+    let (token_locked, token_owned) = (locked_custody.token_locked, locked_custody.token_owned);
+    let is_call = custody.key() == locked_custody.key();
+    let size = option_detail.contracts(option_detail.quantity);
+
+    let locked_amount = option_detail.get_locked_amount(option_detail.quantity)?;
+    let available_liquidity = ctx
+        .accounts
+        .pool
+        .get_borrowable_amount_after_release(locked_custody, locked_amount)?;
+    require_gte!(
+        available_liquidity,
+        locked_amount,
+        TradingError::InsufficientPoolLiquidity
+    );
+
+    // Fair value of the current terms
+    let remaining_seconds = option_detail.expired_date - current_time;
+    let old_strike = scaled_price_to_f64(option_detail.strike_price)?;
+    let old_value = black_scholes_with_borrow_rate(
+        underlying_price,
+        old_strike,
+        math::checked_float_div(remaining_seconds as f64, 365.25 * 24.0 * 3600.0)?,
+        option_detail.is_call(),
+        token_locked,
+        token_owned,
+        is_call,
+        option_detail.exercise_style,
+    )? * size;
+
+    // Fair value of the new terms
+    let new_value = black_scholes_with_borrow_rate(
+        underlying_price,
+        params.new_strike,
+        math::checked_float_div((params.new_expiry - current_time) as f64, 365.25 * 24.0 * 3600.0)?,
+        option_detail.is_call(),
+        token_locked,
+        token_owned,
+        is_call,
+        option_detail.exercise_style,
+    )? * size;
+    // The new leg re-locks the position, so it pays the size impact of a fresh open
+    let (new_value, size_impact_bps) =
+        ctx.accounts.pool.apply_option_size_impact(new_value, locked_amount, available_liquidity)?;
+
+    // One discounted fee for both legs instead of a full close fee plus a full buy markup
+    let close_fee_bps = custody.get_option_close_fee_bps(remaining_seconds);
+    let fee_discount = 1.0 - OptionDetail::ROLL_FEE_DISCOUNT_BPS as f64 / 10_000.0;
+    let roll_fee = (old_value * close_fee_bps as f64 + new_value * custody.option_buy_markup_bps as f64)
+        / 10_000.0
+        * fee_discount;
+    let net_cost = new_value - old_value + roll_fee;
+
+    msg!("Old value: {}", old_value);
+    msg!("New value: {}", new_value);
+    msg!("Size impact bps: {}", size_impact_bps);
+    msg!("Roll fee: {}", roll_fee);
+
+    let pay_decimals = pay_custody.decimals;
+    let to_pay_tokens = |usd: f64| -> Result<u64> {
+        math::checked_as_u64(
+            math::checked_float_div(usd, pay_token_price)?
+                * math::checked_powi(10.0, pay_decimals as i32)?,
+        )
+    };
+
+    let mut additional_premium = 0u64;
+    let mut refund_amount = 0u64;
+    if net_cost > 0.0 {
+        additional_premium = to_pay_tokens(net_cost)?;
+        require_gte!(
+            params.max_additional_premium,
+            additional_premium,
+            TradingError::SlippageExceededError
+        );
+        require_gte!(
+            funding_account.amount,
+            additional_premium,
+            TradingError::InvalidSignerBalanceError
+        );
+
+        token::transfer(
+            CpiContext::new(
+                token_program.to_account_info(),
+                SplTransfer {
+                    from: funding_account.to_account_info(),
+                    to: pay_custody_token_account.to_account_info(),
+                    authority: owner.to_account_info(),
+                },
+            ),
+            additional_premium,
+        )?;
+
+        pay_custody.token_owned = math::checked_add(pay_custody.token_owned, additional_premium)?;
+        option_detail.premium = math::checked_add(option_detail.premium, additional_premium)?;
+    } else if net_cost < 0.0 {
+        refund_amount = to_pay_tokens(-net_cost)?;
+        require_gte!(
+            refund_amount,
+            params.min_refund_amount,
+            TradingError::SlippageExceededError
+        );
+        require_gte!(
+            pay_custody.available_for_payout(),
+            refund_amount,
+            PoolError::InvalidPoolBalanceError
+        );
+
+        if refund_amount > 0 {
+            contract.transfer_tokens(
+                pay_custody_token_account.to_account_info(),
+                refund_account.to_account_info(),
+                transfer_authority.to_account_info(),
+                token_program.to_account_info(),
+                refund_amount,
+            )?;
+        }
+
+        pay_custody.token_owned = math::checked_sub(pay_custody.token_owned, refund_amount)?;
+        option_detail.premium = option_detail.premium.saturating_sub(refund_amount);
+    }
+
+    // Move the exposure from the old series to the new one
+    let old_strike_price = option_detail.strike_price;
+    let old_expired_date = option_detail.expired_date;
+    let old_notional_usd = option_detail.get_notional_usd(option_detail.quantity)?;
+    ctx.accounts.pool.remove_strike_expiry_notional(
+        old_strike_price,
+        old_expired_date,
+        option_detail.is_call(),
+        old_notional_usd,
+    );
+
+    option_detail.strike_price = f64_to_scaled_price(params.new_strike)?;
+    option_detail.expired_date = params.new_expiry;
+    option_detail.period = math::checked_div(
+        params.new_expiry - option_detail.purchase_date as i64,
+        86400, // seconds per day
+    )? as u64;
+    option_detail.last_update_time = current_time;
+
+    let new_notional_usd = option_detail.get_notional_usd(option_detail.quantity)?;
+    ctx.accounts.pool.add_strike_expiry_notional(
+        option_detail.strike_price,
+        option_detail.expired_date,
+        option_detail.is_call(),
+        new_notional_usd,
+        current_time,
+    )?;
+    if new_notional_usd > old_notional_usd {
+        ctx.accounts.pool.add_notional(&ctx.accounts.contract, new_notional_usd - old_notional_usd)?;
+    } else {
+        ctx.accounts.pool.remove_notional(old_notional_usd - new_notional_usd);
+    }
+
+    let option_key = option_detail.key();
+    ctx.accounts.pool.sync_option_hedge(option_detail, option_key, ctx.accounts.hedge_position.as_deref())?;
+
+    emit!(OptionRolled {
+        owner: option_detail.owner,
+        index: option_detail.index,
+        pool: option_detail.pool,
+        custody: option_detail.custody,
+        quantity: option_detail.quantity,
+        old_strike_price,
+        new_strike_price: option_detail.strike_price,
+        old_expired_date,
+        new_expired_date: option_detail.expired_date,
+        old_value_usd: math::checked_as_u64(old_value * Contract::USD_SCALE as f64)?,
+        new_value_usd: math::checked_as_u64(new_value * Contract::USD_SCALE as f64)?,
+        roll_fee_usd: math::checked_as_u64(roll_fee * Contract::USD_SCALE as f64)?,
+        additional_premium,
+        refund_amount,
+        premium_asset: option_detail.premium_asset,
+        rolled_at: current_time,
+    });
+
+    Custody::check_invariants(&[&ctx.accounts.custody, &ctx.accounts.pay_custody, &ctx.accounts.locked_custody])?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: RollOptionParams)]
+pub struct RollOption<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = funding_account.mint == pay_custody.mint,
+        has_one = owner
+    )]
+    pub funding_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = refund_account.mint == pay_custody.mint,
+        has_one = owner
+    )]
+    pub refund_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: empty PDA, authority for token accounts
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool",
+                 params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"user_v3", owner.key().as_ref()],
+        bump,
+    )]
+    pub user: Box<Account<'info, User>>,
+
+    pub custody_mint: Box<Account<'info, Mint>>,
+    pub pay_custody_mint: Box<Account<'info, Mint>>,
+    pub locked_custody_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody_mint.key().as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>, // underlying price asset
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 pay_custody_mint.key().as_ref()],
+        bump = pay_custody.bump
+    )]
+    pub pay_custody: Box<Account<'info, Custody>>, // premium payment asset
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 locked_custody_mint.key().as_ref()],
+        bump = locked_custody.bump
+    )]
+    pub locked_custody: Box<Account<'info, Custody>>, // locked asset
+
+    #[account(
+        mut,
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 pay_custody.mint.key().as_ref()],
+        bump,
+        constraint = pay_custody_token_account.mint == pay_custody_mint.key()
+    )]
+    pub pay_custody_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"option", owner.key().as_ref(),
+            params.option_index.to_le_bytes().as_ref(),
+            pool.key().as_ref(), custody.key().as_ref()],
+        bump = option_detail.bump
+    )]
+    pub option_detail: Box<Account<'info, OptionDetail>>,
+
+    /// CHECK: oracle for underlying asset
+    #[account(constraint = custody_oracle_account.key() == custody.oracle)]
+    pub custody_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: oracle for payment asset
+    #[account(constraint = pay_custody_oracle_account.key() == pay_custody.oracle)]
+    pub pay_custody_oracle_account: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = custody_oracle_secondary.key() == custody.oracle_secondary
+    )]
+    pub custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = pay_custody_oracle_secondary.key() == pay_custody.oracle_secondary
+    )]
+    pub pay_custody_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
+}
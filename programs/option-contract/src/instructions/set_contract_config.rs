@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::ContractError,
+    state::{multisig::{AdminInstruction, Multisig}, Contract},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetContractConfigParams {
+    pub max_global_notional_usd: Option<u64>, // None = keep current, 0 = no ceiling
+    pub paused: Option<bool>,
+    pub keeper_reward_bps: Option<u64>,
+    pub referral_fee_share_bps: Option<u64>,
+    pub liquidator_reward_bps: Option<u64>,
+}
+
+pub fn set_contract_config<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetContractConfig<'info>>,
+    params: &SetContractConfigParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetContractConfig, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let contract = &mut ctx.accounts.contract;
+
+    if let Some(max_global_notional_usd) = params.max_global_notional_usd {
+        contract.max_global_notional_usd = max_global_notional_usd;
+        msg!("Max global notional set to {}", max_global_notional_usd);
+
+        // a raised or removed ceiling lifts a breaker pause straight away
+        if contract.paused_by_global_limit
+            && (max_global_notional_usd == 0
+                || contract.global_notional_usd < max_global_notional_usd)
+        {
+            contract.paused = false;
+            contract.paused_by_global_limit = false;
+        }
+    }
+
+    if let Some(paused) = params.paused {
+        // an explicit operator decision overrides the breaker
+        contract.paused = paused;
+        contract.paused_by_global_limit = false;
+        msg!("Contract paused: {}", paused);
+    }
+
+    if let Some(keeper_reward_bps) = params.keeper_reward_bps {
+        require!(
+            keeper_reward_bps <= Contract::MAX_KEEPER_REWARD_BPS,
+            ContractError::InvalidContractConfig
+        );
+        contract.keeper_reward_bps = keeper_reward_bps;
+        msg!("Keeper reward set to {} bps", keeper_reward_bps);
+    }
+
+    if let Some(referral_fee_share_bps) = params.referral_fee_share_bps {
+        require!(
+            referral_fee_share_bps <= Contract::MAX_REFERRAL_FEE_SHARE_BPS,
+            ContractError::InvalidContractConfig
+        );
+        contract.referral_fee_share_bps = referral_fee_share_bps;
+        msg!("Referral fee share set to {} bps", referral_fee_share_bps);
+    }
+
+    if let Some(liquidator_reward_bps) = params.liquidator_reward_bps {
+        require!(
+            liquidator_reward_bps <= Contract::MAX_LIQUIDATOR_REWARD_BPS,
+            ContractError::InvalidContractConfig
+        );
+        contract.liquidator_reward_bps = liquidator_reward_bps;
+        msg!("Liquidator reward set to {} bps", liquidator_reward_bps);
+    }
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetContractConfigParams)]
+pub struct SetContractConfig<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+}
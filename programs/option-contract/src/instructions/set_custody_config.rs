@@ -0,0 +1,182 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::{
+    errors::PoolError,
+    events::CustodyTradingPaused,
+    state::{multisig::{AdminInstruction, Multisig}, CloseFeeTier, Contract, Custody, MarginTier, Pool},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetCustodyConfigParams {
+    pub pool_name: String,
+    pub option_buy_markup_bps: Option<u64>,    // None = keep current
+    pub option_sell_markdown_bps: Option<u64>,
+    pub option_close_fee_tiers: Option<Vec<CloseFeeTier>>, // empty = flat sell markdown
+    pub margin_tiers: Option<Vec<MarginTier>>, // empty = flat liquidation margin
+    pub oracle: Option<Pubkey>, // replaces a migrated or deprecated primary feed
+    pub oracle_secondary: Option<Pubkey>, // Pubkey::default() = no failover
+    pub oracle_type_primary: Option<u8>,
+    pub oracle_type_secondary: Option<u8>,
+    pub reserved_for_settlement: Option<u64>, // token amount held back from opens
+    pub trading_paused: Option<bool>, // blocks new opens and deposits, exits stay open
+}
+
+pub fn set_custody_config<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetCustodyConfig<'info>>,
+    params: &SetCustodyConfigParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetCustodyConfig, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let custody = &mut ctx.accounts.custody;
+
+    if let Some(option_buy_markup_bps) = params.option_buy_markup_bps {
+        require!(
+            option_buy_markup_bps <= Custody::MAX_OPTION_SPREAD_BPS,
+            PoolError::InvalidCustodyConfig
+        );
+        custody.option_buy_markup_bps = option_buy_markup_bps;
+        msg!("Option buy markup set to {} bps", option_buy_markup_bps);
+    }
+
+    if let Some(option_sell_markdown_bps) = params.option_sell_markdown_bps {
+        require!(
+            option_sell_markdown_bps <= Custody::MAX_OPTION_SPREAD_BPS,
+            PoolError::InvalidCustodyConfig
+        );
+        custody.option_sell_markdown_bps = option_sell_markdown_bps;
+        msg!("Option sell markdown set to {} bps", option_sell_markdown_bps);
+    }
+
+    if let Some(tiers) = &params.option_close_fee_tiers {
+        require!(
+            tiers.len() <= Custody::MAX_CLOSE_FEE_TIERS,
+            PoolError::InvalidCustodyConfig
+        );
+        // first tier covers the run-up to expiry, thresholds strictly ascending
+        if let Some(first) = tiers.first() {
+            require!(first.min_remaining_sec == 0, PoolError::InvalidCustodyConfig);
+        }
+        for (idx, tier) in tiers.iter().enumerate() {
+            require!(
+                tier.fee_bps <= Custody::MAX_OPTION_SPREAD_BPS,
+                PoolError::InvalidCustodyConfig
+            );
+            if idx > 0 {
+                require!(
+                    tier.min_remaining_sec > tiers[idx - 1].min_remaining_sec,
+                    PoolError::InvalidCustodyConfig
+                );
+            }
+        }
+
+        custody.option_close_fee_tiers = [CloseFeeTier::default(); Custody::MAX_CLOSE_FEE_TIERS];
+        custody.option_close_fee_tiers[..tiers.len()].copy_from_slice(tiers);
+        custody.option_close_fee_tier_count = tiers.len() as u8;
+        msg!("Option close fee tiers set: {}", tiers.len());
+    }
+
+    if let Some(tiers) = &params.margin_tiers {
+        custody.set_margin_tiers(tiers)?;
+        msg!("Margin tiers set: {}", tiers.len());
+    }
+
+    // Positions, options and futures keep no oracle snapshot: every instruction checks the
+    // oracle account against the custody's current feed, so open positions close on the new one
+    if let Some(oracle) = params.oracle {
+        // a new feed must say what it is rather than inherit the old feed's type
+        require!(params.oracle_type_primary.is_some(), PoolError::InvalidCustodyConfig);
+        msg!("Primary oracle changed from {} to {}", custody.oracle, oracle);
+        custody.oracle = oracle;
+    }
+
+    if let Some(oracle_secondary) = params.oracle_secondary {
+        custody.oracle_secondary = oracle_secondary;
+        msg!("Secondary oracle set to {}", oracle_secondary);
+    }
+
+    // validate the final pair, so primary and secondary can be swapped in one call
+    require_keys_neq!(custody.oracle, Pubkey::default(), PoolError::InvalidCustodyConfig);
+    require_keys_neq!(custody.oracle, custody.oracle_secondary, PoolError::InvalidCustodyConfig);
+
+    // only Pyth feeds are read today
+    if let Some(oracle_type) = params.oracle_type_primary {
+        require!(oracle_type == Custody::ORACLE_TYPE_PYTH, PoolError::InvalidCustodyConfig);
+        custody.oracle_type_primary = oracle_type;
+    }
+
+    if let Some(oracle_type) = params.oracle_type_secondary {
+        require!(oracle_type == Custody::ORACLE_TYPE_PYTH, PoolError::InvalidCustodyConfig);
+        custody.oracle_type_secondary = oracle_type;
+    }
+
+    if let Some(reserved_for_settlement) = params.reserved_for_settlement {
+        custody.reserved_for_settlement = reserved_for_settlement;
+        msg!("Reserved for settlement set to {}", reserved_for_settlement);
+    }
+
+    if let Some(trading_paused) = params.trading_paused {
+        custody.trading_paused = trading_paused;
+        msg!("Custody trading paused: {}", trading_paused);
+        emit!(CustodyTradingPaused {
+            pool: ctx.accounts.pool.key(),
+            custody: custody.key(),
+            mint: custody.mint,
+            paused: trading_paused,
+            updated_at: ctx.accounts.pool.get_time(&ctx.accounts.contract)?,
+        });
+    }
+
+    Custody::check_invariants(&[&ctx.accounts.custody])?;
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetCustodyConfigParams)]
+pub struct SetCustodyConfig<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody", pool.key().as_ref(), custody_mint.key().as_ref()],
+        bump = custody.bump,
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+
+    pub custody_mint: Box<Account<'info, Mint>>,
+}
@@ -1,7 +1,7 @@
 use crate::{
     errors::{OptionError, TradingError},
     events::OptionTpSlSet,
-    math::f64_to_scaled_price,
+    math::{self, f64_to_scaled_price},
     state::{Contract, OptionDetail, Pool, User},
 };
 use anchor_lang::prelude::*;
@@ -29,7 +29,7 @@ pub fn set_option_tp_sl(
     require!(!option_detail.executed, OptionError::OptionExecuted);
     
     // Get current time
-    let current_time = contract.get_time()?;
+    let current_time = ctx.accounts.pool.get_time(contract)?;
     require!(current_time < option_detail.expired_date, OptionError::OptionExpired);
     
     // Convert and validate take profit price
@@ -37,8 +37,8 @@ pub fn set_option_tp_sl(
         require!(tp_price > 0.0, TradingError::InvalidPrice);
         
         // Validate TP price makes sense based on option type
-        let strike_price_f64 = option_detail.strike_price as f64 / 1_000_000.0;
-        if option_detail.option_type == 0 { // Call option
+        let strike_price_f64 = option_detail.strike_price as f64 / math::PRICE_SCALE as f64;
+        if option_detail.is_call() { // Call option
             require!(tp_price > strike_price_f64, TradingError::InvalidTakeProfitPrice);
         } else { // Put option
             require!(tp_price < strike_price_f64, TradingError::InvalidTakeProfitPrice);
@@ -54,8 +54,8 @@ pub fn set_option_tp_sl(
         require!(sl_price > 0.0, TradingError::InvalidPrice);
         
         // Validate SL price makes sense based on option type
-        let strike_price_f64 = option_detail.strike_price as f64 / 1_000_000.0;
-        if option_detail.option_type == 0 { // Call option
+        let strike_price_f64 = option_detail.strike_price as f64 / math::PRICE_SCALE as f64;
+        if option_detail.is_call() { // Call option
             require!(sl_price < strike_price_f64, TradingError::InvalidStopLossPrice);
         } else { // Put option
             require!(sl_price > strike_price_f64, TradingError::InvalidStopLossPrice);
@@ -68,7 +68,7 @@ pub fn set_option_tp_sl(
     
     // Validate TP and SL don't conflict with each other
     if let (Some(tp), Some(sl)) = (option_detail.take_profit_price, option_detail.stop_loss_price) {
-        if option_detail.option_type == 0 { // Call option
+        if option_detail.is_call() { // Call option
             require!(tp > sl, TradingError::InvalidPriceRange);
         } else { // Put option
             require!(tp < sl, TradingError::InvalidPriceRange);
@@ -86,7 +86,7 @@ pub fn set_option_tp_sl(
         period: option_detail.period,
         expired_date: option_detail.expired_date,
         purchase_date: option_detail.purchase_date,
-        option_type: option_detail.option_type,
+        option_type: option_detail.option_type as u8,
         strike_price: option_detail.strike_price,
         valid: option_detail.valid,
         locked_asset: option_detail.locked_asset,
@@ -0,0 +1,364 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::PoolError,
+    state::{multisig::{AdminInstruction, Multisig}, Contract, OptionCustodyCombo, Pool, TpSlOrderbook},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetPoolConfigParams {
+    pub pool_name: String,
+    pub liquidation_buffer_bps: Option<u64>, // None = keep current
+    pub collateral_adjust_fee_bps: Option<u64>,
+    pub min_update_interval_sec: Option<i64>,
+    pub liquidation_cooldown_sec: Option<i64>, // 0 = no cooldown
+    pub perp_trade_fee_bps: Option<u64>,       // 0 = Position::EXITING_FEE_BPS
+    pub sol_mint: Option<Pubkey>,  // For pools created before designated mints existed
+    pub usdc_mint: Option<Pubkey>,
+    pub min_future_duration_sec: Option<i64>,
+    pub max_future_duration_sec: Option<i64>,
+    pub rebalance_incentive_bps: Option<u64>,
+    pub rebalance_incentive_budget: Option<u64>, // replaces the remaining budget
+    pub max_position_fraction_bps: Option<u64>, // 0 = no cap
+    pub option_custody_combos: Option<Vec<OptionCustodyCombo>>, // replaces the allowlist, empty = unrestricted
+    pub max_funding_rate_bps: Option<u64>, // annual bps cap on the side borrow rate, 0 = uncapped
+    pub min_funding_rate_bps: Option<u64>, // annual bps floor on the rate a position is charged
+    pub max_strike_expiry_notional_usd: Option<u64>, // per option series, 0 = no cap
+    pub reserve_ratio_bps: Option<u64>, // share of token_owned that can never be locked
+    pub settlement_haircut_bps: Option<u64>, // base fee on settlements below target ratio, 0 = off
+    pub lp_fee_epoch_duration_sec: Option<i64>, // enables epoch LP fee distribution, can't change once set
+    pub option_expiry_interval_sec: Option<i64>, // expiry grid for new options, 0 = any expiry
+    pub option_expiry_offset_sec: Option<i64>,   // grid offset from 00:00 UTC Jan 1 1970, below the interval
+    pub max_option_size_impact_bps: Option<u64>, // option premium markup at the top size tier, 0 = off
+    pub max_tp_sl_orders: Option<u8>, // per side in new TP/SL orderbooks, 0 = TpSlOrderbook::MAX_ORDERS
+    pub min_position_usd: Option<u64>, // smallest perp size, 0 = off
+    pub hedge_borrow_fee_discount_bps: Option<u64>, // borrow rate discount for fully hedged perps, 0 = off
+    pub confidence_fee_multiplier_bps: Option<u64>, // open fee per unit of oracle confidence, 0 = off
+    pub close_fee_bps: Option<u64>, // fee on closed perp and future notional, 0 = each product's default
+    pub option_grid_only: Option<bool>, // only listed strikes and expiries can be opened
+    pub allowed_option_strikes: Option<Vec<u64>>, // scaled strikes, ascending, replaces the list
+    pub allowed_option_expiries: Option<Vec<i64>>, // unix expiries, ascending, replaces the list
+}
+
+pub fn set_pool_config<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+    params: &SetPoolConfigParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetPoolConfig, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let pool = &mut ctx.accounts.pool;
+
+    if let Some(liquidation_buffer_bps) = params.liquidation_buffer_bps {
+        require!(
+            liquidation_buffer_bps <= Pool::MAX_LIQUIDATION_BUFFER_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.liquidation_buffer_bps = liquidation_buffer_bps;
+        msg!("Liquidation buffer set to {} bps", liquidation_buffer_bps);
+    }
+
+    if let Some(collateral_adjust_fee_bps) = params.collateral_adjust_fee_bps {
+        require!(
+            collateral_adjust_fee_bps <= Pool::MAX_COLLATERAL_ADJUST_FEE_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.collateral_adjust_fee_bps = collateral_adjust_fee_bps;
+        msg!("Collateral adjust fee set to {} bps", collateral_adjust_fee_bps);
+    }
+
+    if let Some(min_update_interval_sec) = params.min_update_interval_sec {
+        require!(
+            (0..=Pool::MAX_MIN_UPDATE_INTERVAL_SEC).contains(&min_update_interval_sec),
+            PoolError::InvalidPoolConfig
+        );
+        pool.min_update_interval_sec = min_update_interval_sec;
+        msg!("Minimum borrow fee update interval set to {} sec", min_update_interval_sec);
+    }
+
+    if let Some(liquidation_cooldown_sec) = params.liquidation_cooldown_sec {
+        require!(
+            (0..=Pool::MAX_LIQUIDATION_COOLDOWN_SEC).contains(&liquidation_cooldown_sec),
+            PoolError::InvalidPoolConfig
+        );
+        pool.liquidation_cooldown_sec = liquidation_cooldown_sec;
+        msg!("Liquidation cooldown set to {} sec", liquidation_cooldown_sec);
+    }
+
+    if let Some(perp_trade_fee_bps) = params.perp_trade_fee_bps {
+        require!(
+            perp_trade_fee_bps <= Pool::MAX_PERP_TRADE_FEE_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.perp_trade_fee_bps = perp_trade_fee_bps;
+        msg!("Perp trade fee set to {} bps", perp_trade_fee_bps);
+    }
+
+    if params.sol_mint.is_some() || params.usdc_mint.is_some() {
+        // Designating a mint for a legacy pool is fine, swapping one under live positions is not
+        let replaces_mint = |current: Pubkey, new: Option<Pubkey>| {
+            current != Pubkey::default() && new.is_some_and(|mint| mint != current)
+        };
+        if replaces_mint(pool.sol_mint, params.sol_mint) || replaces_mint(pool.usdc_mint, params.usdc_mint) {
+            require!(!pool.has_open_positions(), PoolError::PoolNotEmpty);
+        }
+        pool.sol_mint = params.sol_mint.unwrap_or(pool.sol_mint);
+        pool.usdc_mint = params.usdc_mint.unwrap_or(pool.usdc_mint);
+        require_keys_neq!(pool.sol_mint, pool.usdc_mint, PoolError::InvalidPoolConfig);
+        msg!("Designated mints set to {} / {}", pool.sol_mint, pool.usdc_mint);
+    }
+
+    if params.min_future_duration_sec.is_some() || params.max_future_duration_sec.is_some() {
+        let min_future_duration_sec = params
+            .min_future_duration_sec
+            .unwrap_or(pool.min_future_duration_sec);
+        let max_future_duration_sec = params
+            .max_future_duration_sec
+            .unwrap_or(pool.max_future_duration_sec);
+        require!(
+            min_future_duration_sec > 0 && max_future_duration_sec > min_future_duration_sec,
+            PoolError::InvalidPoolConfig
+        );
+        pool.min_future_duration_sec = min_future_duration_sec;
+        pool.max_future_duration_sec = max_future_duration_sec;
+        msg!(
+            "Future duration range set to {} - {} sec",
+            min_future_duration_sec,
+            max_future_duration_sec
+        );
+    }
+
+    if let Some(rebalance_incentive_bps) = params.rebalance_incentive_bps {
+        require!(
+            rebalance_incentive_bps <= Pool::MAX_REBALANCE_INCENTIVE_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.rebalance_incentive_bps = rebalance_incentive_bps;
+        msg!("Rebalance incentive set to {} bps", rebalance_incentive_bps);
+    }
+
+    if let Some(rebalance_incentive_budget) = params.rebalance_incentive_budget {
+        pool.rebalance_incentive_budget = rebalance_incentive_budget;
+        msg!("Rebalance incentive budget set to {}", rebalance_incentive_budget);
+    }
+
+    if let Some(max_position_fraction_bps) = params.max_position_fraction_bps {
+        require!(
+            max_position_fraction_bps <= Pool::MAX_POSITION_FRACTION_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.max_position_fraction_bps = max_position_fraction_bps;
+        msg!("Max position fraction set to {} bps", max_position_fraction_bps);
+    }
+
+    if let Some(combos) = &params.option_custody_combos {
+        pool.option_custody_combos = [OptionCustodyCombo::default(); Pool::MAX_OPTION_CUSTODY_COMBOS];
+        pool.option_custody_combo_count = 0;
+        pool.add_option_custody_combos(combos)?;
+        msg!("Option custody combinations set: {}", pool.option_custody_combo_count);
+    }
+
+    if let Some(max_funding_rate_bps) = params.max_funding_rate_bps {
+        pool.max_funding_rate_bps = max_funding_rate_bps;
+        msg!("Max funding rate set to {} bps per year", max_funding_rate_bps);
+    }
+
+    if let Some(min_funding_rate_bps) = params.min_funding_rate_bps {
+        pool.min_funding_rate_bps = min_funding_rate_bps;
+        msg!("Min funding rate set to {} bps per year", min_funding_rate_bps);
+    }
+
+    if params.max_funding_rate_bps.is_some() || params.min_funding_rate_bps.is_some() {
+        require!(
+            pool.max_funding_rate_bps == 0 || pool.min_funding_rate_bps <= pool.max_funding_rate_bps,
+            PoolError::InvalidPoolConfig
+        );
+    }
+
+    if let Some(max_strike_expiry_notional_usd) = params.max_strike_expiry_notional_usd {
+        pool.max_strike_expiry_notional_usd = max_strike_expiry_notional_usd;
+        msg!("Max option notional per strike/expiry set to {}", max_strike_expiry_notional_usd);
+    }
+
+    if let Some(reserve_ratio_bps) = params.reserve_ratio_bps {
+        require!(
+            reserve_ratio_bps <= Pool::MAX_RESERVE_RATIO_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.reserve_ratio_bps = reserve_ratio_bps;
+        msg!("Reserve ratio set to {} bps", reserve_ratio_bps);
+    }
+
+    if let Some(settlement_haircut_bps) = params.settlement_haircut_bps {
+        require!(
+            settlement_haircut_bps <= Pool::MAX_SETTLEMENT_HAIRCUT_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.settlement_haircut_bps = settlement_haircut_bps;
+        msg!("Settlement haircut set to {} bps", settlement_haircut_bps);
+    }
+
+    // Epoch indexes are derived from the duration, so it can only be set once
+    if let Some(lp_fee_epoch_duration_sec) = params.lp_fee_epoch_duration_sec {
+        require!(
+            pool.lp_fee_epoch_duration_sec == 0
+                && (Pool::MIN_LP_FEE_EPOCH_DURATION_SEC..=Pool::MAX_LP_FEE_EPOCH_DURATION_SEC)
+                    .contains(&lp_fee_epoch_duration_sec),
+            PoolError::InvalidPoolConfig
+        );
+        pool.lp_fee_epoch_duration_sec = lp_fee_epoch_duration_sec;
+        msg!("LP fee epoch duration set to {} sec", lp_fee_epoch_duration_sec);
+    }
+
+    if let Some(option_expiry_interval_sec) = params.option_expiry_interval_sec {
+        require!(
+            option_expiry_interval_sec == 0
+                || (Pool::MIN_OPTION_EXPIRY_INTERVAL_SEC..=Pool::MAX_OPTION_EXPIRY_INTERVAL_SEC)
+                    .contains(&option_expiry_interval_sec),
+            PoolError::InvalidPoolConfig
+        );
+        pool.option_expiry_interval_sec = option_expiry_interval_sec;
+        msg!("Option expiry interval set to {} sec", option_expiry_interval_sec);
+    }
+
+    if let Some(option_expiry_offset_sec) = params.option_expiry_offset_sec {
+        pool.option_expiry_offset_sec = option_expiry_offset_sec;
+        msg!("Option expiry offset set to {} sec", option_expiry_offset_sec);
+    }
+
+    if let Some(max_option_size_impact_bps) = params.max_option_size_impact_bps {
+        require!(
+            max_option_size_impact_bps <= Pool::MAX_OPTION_SIZE_IMPACT_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.max_option_size_impact_bps = max_option_size_impact_bps;
+        msg!("Max option size impact set to {} bps", max_option_size_impact_bps);
+    }
+
+    if let Some(max_tp_sl_orders) = params.max_tp_sl_orders {
+        require!(
+            max_tp_sl_orders as usize <= TpSlOrderbook::MAX_ORDERS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.max_tp_sl_orders = max_tp_sl_orders;
+        msg!("Max TP/SL orders per side set to {}", max_tp_sl_orders);
+    }
+
+    if let Some(min_position_usd) = params.min_position_usd {
+        require!(
+            min_position_usd <= Pool::MAX_MIN_POSITION_USD,
+            PoolError::InvalidPoolConfig
+        );
+        pool.min_position_usd = min_position_usd;
+        msg!("Min position size set to {} USD", min_position_usd);
+    }
+
+    if let Some(hedge_borrow_fee_discount_bps) = params.hedge_borrow_fee_discount_bps {
+        require!(
+            hedge_borrow_fee_discount_bps <= Pool::MAX_HEDGE_BORROW_FEE_DISCOUNT_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.hedge_borrow_fee_discount_bps = hedge_borrow_fee_discount_bps;
+        msg!("Hedge borrow fee discount set to {} bps", hedge_borrow_fee_discount_bps);
+    }
+
+    if let Some(confidence_fee_multiplier_bps) = params.confidence_fee_multiplier_bps {
+        require!(
+            confidence_fee_multiplier_bps <= Pool::MAX_CONFIDENCE_FEE_MULTIPLIER_BPS,
+            PoolError::InvalidPoolConfig
+        );
+        pool.confidence_fee_multiplier_bps = confidence_fee_multiplier_bps;
+        msg!("Confidence fee multiplier set to {} bps", confidence_fee_multiplier_bps);
+    }
+
+    if let Some(close_fee_bps) = params.close_fee_bps {
+        require!(close_fee_bps <= Pool::MAX_CLOSE_FEE_BPS, PoolError::InvalidPoolConfig);
+        pool.close_fee_bps = close_fee_bps;
+        msg!("Close fee set to {} bps", close_fee_bps);
+    }
+
+    if let Some(strikes) = &params.allowed_option_strikes {
+        require!(
+            strikes.len() <= Pool::MAX_ALLOWED_OPTION_GRID_ENTRIES
+                && !strikes.contains(&0)
+                && strikes.windows(2).all(|w| w[0] < w[1]),
+            PoolError::InvalidPoolConfig
+        );
+        pool.allowed_option_strikes = [0; Pool::MAX_ALLOWED_OPTION_GRID_ENTRIES];
+        pool.allowed_option_strikes[..strikes.len()].copy_from_slice(strikes);
+        msg!("Allowed option strikes set: {}", strikes.len());
+    }
+
+    if let Some(expiries) = &params.allowed_option_expiries {
+        require!(
+            expiries.len() <= Pool::MAX_ALLOWED_OPTION_GRID_ENTRIES
+                && expiries.iter().all(|e| *e > 0)
+                && expiries.windows(2).all(|w| w[0] < w[1]),
+            PoolError::InvalidPoolConfig
+        );
+        pool.allowed_option_expiries = [0; Pool::MAX_ALLOWED_OPTION_GRID_ENTRIES];
+        pool.allowed_option_expiries[..expiries.len()].copy_from_slice(expiries);
+        msg!("Allowed option expiries set: {}", expiries.len());
+    }
+
+    if let Some(option_grid_only) = params.option_grid_only {
+        pool.option_grid_only = option_grid_only;
+        msg!("Option grid-only mode: {}", option_grid_only);
+    }
+
+    // Grid-only mode needs at least one listed strike and expiry
+    require!(
+        !pool.option_grid_only
+            || (pool.allowed_option_strikes[0] > 0 && pool.allowed_option_expiries[0] > 0),
+        PoolError::InvalidPoolConfig
+    );
+
+    require!(
+        pool.option_expiry_offset_sec >= 0
+            && (pool.option_expiry_interval_sec == 0
+                || pool.option_expiry_offset_sec < pool.option_expiry_interval_sec),
+        PoolError::InvalidPoolConfig
+    );
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetPoolConfigParams)]
+pub struct SetPoolConfig<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+}
@@ -0,0 +1,94 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::PoolError,
+    events::PoolRatiosUpdated,
+    state::{multisig::{AdminInstruction, Multisig}, Contract, Pool, TokenRatios},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetPoolRatiosParams {
+    pub pool_name: String,
+    pub ratios: Vec<TokenRatios>, // one per pool custody, in pool.custodies order, in percent
+}
+
+/// Retarget the pool composition after launch. Every custody needs min <= target <= max
+/// within 0..=100 percent and the targets must add up to 100.
+pub fn set_pool_ratios<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetPoolRatios<'info>>,
+    params: &SetPoolRatiosParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetPoolRatios, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let pool = &mut ctx.accounts.pool;
+
+    require!(
+        params.ratios.len() == pool.custodies.len(),
+        PoolError::InvalidPoolConfig
+    );
+    let mut target_sum = 0u64;
+    for ratio in params.ratios.iter() {
+        require!(
+            ratio.min <= ratio.target && ratio.target <= ratio.max && ratio.max <= 100,
+            PoolError::InvalidPoolConfig
+        );
+        target_sum += ratio.target;
+    }
+    require!(
+        params.ratios.is_empty() || target_sum == 100,
+        PoolError::InvalidPoolConfig
+    );
+
+    let old_ratios = std::mem::replace(&mut pool.ratios, params.ratios.clone());
+    msg!("Pool ratios updated for {} custodies", pool.ratios.len());
+
+    emit!(PoolRatiosUpdated {
+        pool: pool.key(),
+        old_ratios,
+        new_ratios: pool.ratios.clone(),
+        updated_at: ctx.accounts.pool.get_time(&ctx.accounts.contract)?,
+    });
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetPoolRatiosParams)]
+pub struct SetPoolRatios<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+}
@@ -0,0 +1,177 @@
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::PositionHedgeUpdated,
+    state::{Contract, Custody, OptionDetail, OrderType, Pool, Position, Side},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetPositionHedgeParams {
+    pub position_index: u64,
+    pub pool_name: String,
+    pub hedge: bool, // true = tag the position as a hedge of `option_detail`, false = remove the tag
+}
+
+/// Tag a perp as the hedge of an option held by the same owner, one perp per option. While
+/// the option is live the perp offsets the pool's short option exposure (short perp against
+/// a call, long against a put), so its borrow rate is discounted by
+/// pool.hedge_borrow_fee_discount_bps scaled by the share of the perp's size that both the
+/// option notional and the pool's net exposure cover. Option paths keep the hedge in step
+/// with the option; anyone may remove a hedge whose option is gone or no longer valid.
+pub fn set_position_hedge(ctx: Context<SetPositionHedge>, params: &SetPositionHedgeParams) -> Result<()> {
+    msg!("Updating perp position hedge");
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let position = &mut ctx.accounts.position;
+    let option_info = &ctx.accounts.option_detail;
+    let position_key = position.key();
+    let authority = ctx.accounts.authority.key();
+
+    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
+
+    let current_time = pool.get_time(contract)?;
+
+    // Settle borrow fees at the previous discount before it changes
+    pool.update_position_borrow_fees(
+        position,
+        current_time,
+        &ctx.accounts.sol_custody,
+        &ctx.accounts.usdc_custody,
+    )?;
+
+    // Closed option accounts are drained and zero-filled
+    let mut option = if option_info.lamports() == 0
+        || option_info.data_is_empty()
+        || option_info.owner != &crate::ID
+    {
+        None
+    } else {
+        let data = option_info.try_borrow_data()?;
+        OptionDetail::try_deserialize(&mut &data[..]).ok()
+    };
+    let option_live = option
+        .as_ref()
+        .is_some_and(|option| option.valid && current_time < option.expired_date);
+
+    if params.hedge {
+        require_keys_eq!(authority, position.owner, TradingError::Unauthorized);
+        let option = option
+            .as_mut()
+            .filter(|_| option_live)
+            .ok_or(PerpetualError::InvalidHedge)?;
+        require_keys_eq!(option.owner, position.owner, PerpetualError::InvalidHedge);
+        require_keys_eq!(option.pool, pool.key(), PerpetualError::InvalidHedge);
+        require_keys_eq!(option.custody, position.custody, PerpetualError::InvalidHedge);
+        // A pending limit option has no exposure yet
+        require!(
+            option.limit_price == 0 || option.executed,
+            PerpetualError::InvalidHedge
+        );
+
+        // The pool is short the option, so only the opposite-delta perp offsets it
+        let offsetting_side = if option.is_call() { Side::Short } else { Side::Long };
+        require!(position.side == offsetting_side, PerpetualError::InvalidHedge);
+
+        // One perp per option and one option per perp
+        require!(
+            option.hedge_position.is_none_or(|key| key == position_key),
+            PerpetualError::InvalidHedge
+        );
+        require!(
+            position.hedge_option.is_none_or(|key| key == option_info.key()),
+            PerpetualError::InvalidHedge
+        );
+
+        position.hedge_option = Some(option_info.key());
+        position.sync_option_hedge(option_info.key(), option)?;
+        option.hedge_position = Some(position_key);
+    } else {
+        require!(
+            position.hedge_option == Some(option_info.key()),
+            PerpetualError::InvalidHedge
+        );
+        require!(authority == position.owner || !option_live, TradingError::Unauthorized);
+
+        position.clear_option_hedge();
+        if let Some(option) = option.as_mut() {
+            if option.hedge_position == Some(position_key) {
+                option.hedge_position = None;
+            }
+        }
+    }
+    position.update_time = current_time;
+
+    // Write the link back onto a live option account
+    if let Some(option) = option.as_ref() {
+        let mut data = option_info.try_borrow_mut_data()?;
+        option.try_serialize(&mut &mut data[..])?;
+    }
+
+    let hedge_discount_bps = pool.get_hedge_discount_bps(position, current_time)?;
+    msg!("Hedge discount: {} bps", hedge_discount_bps);
+
+    emit!(PositionHedgeUpdated {
+        pub_key: position.key(),
+        owner: position.owner,
+        position_index: params.position_index,
+        pool: pool.key(),
+        option: option_info.key(),
+        hedged: params.hedge,
+        hedged_usd: position.hedged_usd,
+        hedge_discount_bps,
+        hedge_expiry: position.hedge_expiry,
+        hedge_fee_discount_usd: position.hedge_fee_discount_usd,
+        update_time: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetPositionHedgeParams)]
+pub struct SetPositionHedge<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        mut,
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            position.owner.as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    /// CHECK: option hedged by the position, deserialized in the handler; may already be closed
+    #[account(mut)]
+    pub option_detail: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), pool.sol_mint.as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), pool.usdc_mint.as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+}
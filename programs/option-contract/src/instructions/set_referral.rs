@@ -0,0 +1,93 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    events::ReferralRegistered,
+    state::{multisig::{AdminInstruction, Multisig}, Contract, Pool, Referral},
+};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetReferralParams {
+    pub pool_name: String,
+    pub referrer: Pubkey,
+    pub active: bool, // false = new opens can no longer name the referrer, owed fees stay claimable
+}
+
+/// Register a referrer for a pool, or change whether new opens may name it. Referral fees
+/// are only credited to registered referrers, so a trader can't collect a share of their own
+/// fees by naming a second wallet.
+pub fn set_referral<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetReferral<'info>>,
+    params: &SetReferralParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.signer,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetReferral, params)?,
+    )?;
+
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    let pool_key = ctx.accounts.pool.key();
+    let referral = &mut ctx.accounts.referral;
+    if referral.referrer == Pubkey::default() {
+        referral.referrer = params.referrer;
+        referral.pool = pool_key;
+        referral.bump = ctx.bumps.referral;
+    }
+    referral.active = params.active;
+    msg!("Referrer {} active: {}", params.referrer, params.active);
+
+    emit!(ReferralRegistered {
+        referrer: params.referrer,
+        pool: pool_key,
+        active: params.active,
+        updated_at: ctx.accounts.pool.get_time(&ctx.accounts.contract)?,
+    });
+
+    Ok(0)
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetReferralParams)]
+pub struct SetReferral<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump,
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump,
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = Referral::LEN,
+        seeds = [b"referral", params.referrer.as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub referral: Box<Account<'info, Referral>>,
+
+    pub system_program: Program<'info, System>,
+}
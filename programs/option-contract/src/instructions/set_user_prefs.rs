@@ -0,0 +1,56 @@
+use crate::{
+    events::UserPrefsUpdated,
+    state::{Contract, User},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetUserPrefsParams {
+    pub default_receive_sol: bool, // settlement asset used when close params leave receive_sol unset
+}
+
+/// Store the owner's settlement preference on their User account. Closes, limit order
+/// cancels and future opens fall back to it when `receive_sol` is None; an explicit value
+/// always wins. User accounts created before the preference existed are one byte short and
+/// have to go through migrate_account first.
+pub fn set_user_prefs(ctx: Context<SetUserPrefs>, params: &SetUserPrefsParams) -> Result<()> {
+    let user = &mut ctx.accounts.user;
+    user.default_receive_sol = params.default_receive_sol;
+
+    msg!(
+        "Default settlement set to {}",
+        if params.default_receive_sol { "SOL" } else { "USDC" }
+    );
+
+    emit!(UserPrefsUpdated {
+        owner: ctx.accounts.owner.key(),
+        default_receive_sol: params.default_receive_sol,
+        updated_at: ctx.accounts.contract.get_time()?,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: SetUserPrefsParams)]
+pub struct SetUserPrefs<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = User::LEN,
+        seeds = [b"user_v3", owner.key().as_ref()],
+        bump
+    )]
+    pub user: Box<Account<'info, User>>,
+
+    pub system_program: Program<'info, System>,
+}
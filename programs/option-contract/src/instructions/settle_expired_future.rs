@@ -1,8 +1,8 @@
 use crate::{
-    errors::{FutureError, TradingError},
+    errors::{FutureError, PerpetualError, TradingError},
     events::FutureSettled,
     math::{self, f64_to_scaled_price},
-    state::{Contract, Custody, Future, FutureStatus, OraclePrice, Pool, Side},
+    state::{Contract, Custody, LockedProduct, Future, FutureStatus, Pool, Side},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
@@ -30,7 +30,7 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
         TradingError::Unauthorized
     );
 
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
 
     // Check if future has expired
     require!(
@@ -38,24 +38,36 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
         FutureError::FutureNotYetExpired
     );
     
-    // Future must be active to settle
+    // Future must be active, or already marked expired by the keeper
     require!(
-        future.status == FutureStatus::Active,
+        future.status == FutureStatus::Active || future.status == FutureStatus::Expired,
         FutureError::FutureNotActive
     );
 
-    // Get final settlement price from oracle
-    let sol_price = OraclePrice::new_from_oracle(&ctx.accounts.sol_oracle_account, current_time, false)?;
-    let usdc_price = OraclePrice::new_from_oracle(&ctx.accounts.usdc_oracle_account, current_time, false)?;
-    
-    let settlement_spot_price = sol_price.get_price();
-    let settlement_spot_price_scaled = f64_to_scaled_price(settlement_spot_price)?;
-
-    msg!("Settlement spot price: {}", settlement_spot_price);
-    msg!("Original future price: {}", (future.future_price as f64) / 1_000_000.0);
+    // Oracle prices convert the payout into tokens
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+
+    // Settle at the price frozen by mark_future_expired when available,
+    // otherwise mark now at the price published at expiry
+    if future.status == FutureStatus::Active {
+        let sol_price_update = ctx.accounts.sol_price_update.as_ref().map(|a| a.to_account_info());
+        let expiry_price = sol_custody.get_oracle_price_at(
+            &ctx.accounts.sol_oracle_account,
+            sol_oracle_secondary.as_ref(),
+            sol_price_update.as_ref(),
+            future.expiry_time,
+        )?;
+        future.mark_expired(f64_to_scaled_price(expiry_price.get_price())?, current_time)?;
+    }
+    let settlement_spot_price_scaled = future
+        .settlement_price
+        .ok_or(FutureError::FutureNotExpired)?;
 
-    // Mark future as expired first
-    future.mark_expired(current_time)?;
+    msg!("Settlement spot price: {}", (settlement_spot_price_scaled as f64) / math::PRICE_SCALE as f64);
+    msg!("Original future price: {}", (future.future_price as f64) / math::PRICE_SCALE as f64);
 
     // Calculate settlement
     let settlement_amount = future.settle_future(settlement_spot_price_scaled, current_time)?;
@@ -64,47 +76,25 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
     msg!("Settlement amount USD: {}", settlement_amount);
     msg!("Final P&L: {}", pnl);
 
+    // LP collateral goes back in kind, only the profit over it is paid in the settlement asset
+    let lp_supply = ctx.accounts.lp_token_mint.as_ref().map(|mint| mint.supply);
+    let (payout_usd, lp_collateral_returned, lp_collateral_burned) = pool.get_lp_collateral_settlement(
+        future.lp_collateral_amount,
+        future.lp_collateral_usd,
+        settlement_amount,
+        lp_supply,
+    )?;
+    future.settlement_amount = Some(payout_usd);
+
     // Convert settlement to tokens for transfer
-    let settlement_tokens = if settlement_amount > 0 {
-        // Always settle in the same asset as collateral was provided
-        if future.collateral_custody == sol_custody.key() {
+    let settlement_tokens = if payout_usd > 0 {
+        // Settle in the asset chosen at open
+        if future.get_settlement_custody() == sol_custody.key() {
             // Settle in SOL
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_amount as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
+            math::usd_to_token_amount(payout_usd, &sol_price, sol_custody.decimals)?
         } else {
             // Settle in USDC
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_amount as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
+            math::usd_to_token_amount(payout_usd, &usdc_price, usdc_custody.decimals)?
         }
     } else {
         0
@@ -112,7 +102,7 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
 
     // Transfer settlement to owner if any
     if settlement_tokens > 0 {
-        let settlement_token_account = if future.collateral_custody == sol_custody.key() {
+        let settlement_token_account = if future.get_settlement_custody() == sol_custody.key() {
             &ctx.accounts.sol_custody_token_account
         } else {
             &ctx.accounts.usdc_custody_token_account
@@ -127,7 +117,7 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
         )?;
 
         // Update custody balance
-        if future.collateral_custody == sol_custody.key() {
+        if future.get_settlement_custody() == sol_custody.key() {
             sol_custody.token_owned = math::checked_sub(
                 sol_custody.token_owned,
                 settlement_tokens
@@ -140,17 +130,45 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
         }
     }
 
+    // Return the LP collateral to the owner and burn the part that covered a loss
+    if future.lp_collateral_amount > 0 {
+        let (Some(lp_token_mint), Some(lp_collateral_account), Some(lp_receiving_account)) = (
+            ctx.accounts.lp_token_mint.as_ref(),
+            ctx.accounts.lp_collateral_account.as_ref(),
+            ctx.accounts.lp_receiving_account.as_ref(),
+        ) else {
+            return err!(PerpetualError::LpCollateralAccountsMissing);
+        };
+
+        if lp_collateral_returned > 0 {
+            contract.transfer_tokens(
+                lp_collateral_account.to_account_info(),
+                lp_receiving_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                lp_collateral_returned,
+            )?;
+        }
+        if lp_collateral_burned > 0 {
+            contract.burn_owned_tokens(
+                lp_token_mint.to_account_info(),
+                lp_collateral_account.to_account_info(),
+                ctx.accounts.transfer_authority.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                lp_collateral_burned,
+            )?;
+        }
+        msg!("LP collateral returned: {}, burned: {}", lp_collateral_returned, lp_collateral_burned);
+
+        future.lp_collateral_amount = 0;
+        future.lp_collateral_usd = 0;
+    }
+
     // Release locked liquidity
     if future.side == Side::Long {
-        sol_custody.token_locked = math::checked_sub(
-            sol_custody.token_locked,
-            future.locked_amount
-        )?;
+        sol_custody.remove_locked(LockedProduct::Future, future.locked_amount)?;
     } else {
-        usdc_custody.token_locked = math::checked_sub(
-            usdc_custody.token_locked,
-            future.locked_amount
-        )?;
+        usdc_custody.remove_locked(LockedProduct::Future, future.locked_amount)?;
     }
 
     // Update pool tracking
@@ -160,6 +178,7 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
         time_remaining,
         current_time,
     )?;
+    ctx.accounts.pool.remove_notional(future.size_usd);
 
     // Store values for events
     let future_key = future.key();
@@ -177,11 +196,16 @@ pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: &SettleE
         pnl,
         settlement_amount,
         settlement_tokens,
+        lp_collateral_returned,
+        lp_collateral_burned,
         expiry_time: future.expiry_time,
         settlement_time: current_time,
     });
 
     msg!("Future position settled successfully");
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -276,10 +300,54 @@ pub struct SettleExpiredFuture<'info> {
     )]
     pub usdc_custody_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
+    #[account(
+        mut,
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Option<Box<Account<'info, Mint>>>,
+
+    // LP collateral vault and the owner's LP account, required for LP-collateralised futures
+    #[account(
+        mut,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority,
+        seeds = [b"lp_collateral_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = lp_receiving_account.owner == params.owner
+    )]
+    pub lp_receiving_account: Option<Box<Account<'info, TokenAccount>>>,
+
     pub token_program: Program<'info, Token>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Price update posted for the expiry, checked against the SOL oracle's feed
+    pub sol_price_update: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
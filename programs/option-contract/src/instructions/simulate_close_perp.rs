@@ -0,0 +1,237 @@
+use crate::{
+    errors::{ContractError, PerpetualError, PoolError, TradingError},
+    events::CloseSimulation,
+    math::{self, f64_to_scaled_price},
+    state::{Contract, Custody, LockedProduct, Pool, Position, Side, User},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SimulateClosePerpParams {
+    pub position_index: u64,
+    pub pool_name: String,
+    pub close_percentage: u64,
+    pub receive_sol: Option<bool>,  // true = receive SOL, false = receive USDC, None = user default
+    pub receive_as_lp: bool,        // preview depositing the settlement for LP tokens
+}
+
+/// Read-only preview of close_perp_position. Runs the same settlement math on copies of the
+/// pool, custodies and position and emits the result; meant to be called through transaction
+/// simulation. Fails wherever the close itself would fail, e.g. on insufficient liquidity.
+pub fn simulate_close_perp<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SimulateClosePerp<'info>>,
+    params: &SimulateClosePerpParams,
+) -> Result<()> {
+    let contract = &ctx.accounts.contract;
+    let mut pool = (**ctx.accounts.pool).clone();
+    let mut sol_custody = (**ctx.accounts.sol_custody).clone();
+    let mut usdc_custody = (**ctx.accounts.usdc_custody).clone();
+    let mut position = (**ctx.accounts.position).clone();
+
+    let receive_sol = params.receive_sol.unwrap_or(
+        ctx.accounts.user.as_ref().is_some_and(|user| user.default_receive_sol),
+    );
+
+    // Validation
+    require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
+    require!(position.is_executed(), PerpetualError::InvalidOrderType);
+    require!(
+        position.lp_collateral_amount == 0 || params.close_percentage == Position::FULL_CLOSE_PERCENTAGE,
+        PerpetualError::LpCollateralUnsupported
+    );
+
+    // Get current prices from oracles
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
+
+    let current_price_scaled = f64_to_scaled_price(sol_price.get_price())?;
+
+    let settlement = pool.compute_close_settlement(
+        &mut position,
+        params.close_percentage,
+        current_price_scaled,
+        current_time,
+        &sol_custody,
+        &usdc_custody,
+    )?;
+
+    let lp_supply = ctx.accounts.lp_token_mint.as_ref().map(|mint| mint.supply);
+    let (payout_usd, lp_collateral_returned, lp_collateral_burned) =
+        pool.get_lp_collateral_settlement(
+            position.lp_collateral_amount,
+            position.lp_collateral_usd,
+            settlement.settlement_usd,
+            lp_supply,
+        )?;
+
+    let (settlement_tokens, settlement_haircut) = if receive_sol {
+        let token_id = pool.get_token_id(&ctx.accounts.sol_custody.key())?;
+        pool.get_settlement_payout(token_id, payout_usd, &sol_custody, &sol_price)?
+    } else {
+        let token_id = pool.get_token_id(&ctx.accounts.usdc_custody.key())?;
+        pool.get_settlement_payout(token_id, payout_usd, &usdc_custody, &usdc_price)?
+    };
+
+    if position.side == Side::Long {
+        sol_custody.unlock_funds(LockedProduct::Perp, settlement.locked_amount)?;
+    } else {
+        usdc_custody.unlock_funds(LockedProduct::Perp, settlement.locked_amount)?;
+    }
+    if !params.receive_as_lp {
+        let payout_available = if receive_sol {
+            sol_custody.available_for_payout()
+        } else {
+            usdc_custody.available_for_payout()
+        };
+        require_gte!(payout_available, settlement_tokens, TradingError::InsufficientPoolLiquidity);
+    }
+
+    let (collateral_price, collateral_custody) = if position.collateral_custody == ctx.accounts.sol_custody.key() {
+        (&sol_price, &mut sol_custody)
+    } else {
+        (&usdc_price, &mut usdc_custody)
+    };
+    collateral_custody.token_owned =
+        math::checked_sub(collateral_custody.token_owned, settlement.collateral_amount)?;
+    let collateral_removed_usd =
+        collateral_price.get_asset_amount_usd(settlement.collateral_amount, collateral_custody.decimals)?;
+
+    // Preview the deposit against the AUM close_perp_position would see
+    let mut lp_amount_minted = 0;
+    if params.receive_as_lp && settlement_tokens > 0 {
+        let lp_supply = lp_supply.ok_or(PerpetualError::LpCollateralAccountsMissing)?;
+        let failover_oracles = pool.get_failover_oracles(ctx.remaining_accounts, 2);
+        let incremental = pool.refresh_aum_usd(ctx.remaining_accounts, failover_oracles, current_time)?;
+        if !incremental {
+            // The passed custodies still hold the collateral the close removes before recomputing
+            pool.aum_usd = pool.aum_usd.saturating_sub(collateral_removed_usd as u128);
+        }
+
+        let (deposit_key, deposit_custody, deposit_price) = if receive_sol {
+            (ctx.accounts.sol_custody.key(), &sol_custody, &sol_price)
+        } else {
+            (ctx.accounts.usdc_custody.key(), &usdc_custody, &usdc_price)
+        };
+        require!(!deposit_custody.trading_paused, PoolError::CustodyTradingPaused);
+        let token_id = pool.get_token_id(&deposit_key)?;
+        let quote = pool.get_lp_deposit_quote(token_id, settlement_tokens, deposit_custody, deposit_price, lp_supply)?;
+        require_gt!(quote.lp_amount, 0, ContractError::InsufficientAmountReturned);
+        lp_amount_minted = math::checked_add(quote.lp_amount, quote.bonus_lp_amount)?;
+    }
+
+    emit!(CloseSimulation {
+        position_key: ctx.accounts.position.key(),
+        position_index: params.position_index,
+        owner: position.owner,
+        close_percentage: params.close_percentage,
+        receive_sol,
+        price: current_price_scaled,
+        settlement_usd: settlement.settlement_usd,
+        settlement_tokens,
+        realized_pnl: settlement.realized_pnl,
+        borrow_fees: settlement.borrow_fees,
+        trade_fees: settlement.close_fee_usd,
+        simulated_at: current_time,
+        receive_as_lp: params.receive_as_lp,
+        borrow_size_usd: settlement.size_usd.saturating_sub(settlement.collateral_usd),
+        settlement_haircut,
+        hedge_fee_discount_usd: position.hedge_fee_discount_usd,
+        lp_collateral_returned,
+        lp_collateral_burned,
+        lp_amount_minted,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: SimulateClosePerpParams)]
+pub struct SimulateClosePerp<'info> {
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [
+            b"position",
+            position.owner.as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), sol_mint.key().as_ref()],
+        bump = sol_custody.bump
+    )]
+    pub sol_custody: Box<Account<'info, Custody>>,
+
+    #[account(
+        seeds = [b"custody", pool.key().as_ref(), usdc_mint.key().as_ref()],
+        bump = usdc_custody.bump
+    )]
+    pub usdc_custody: Box<Account<'info, Custody>>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_account.key() == sol_custody.oracle
+    )]
+    pub sol_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: Oracle account validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_account.key() == usdc_custody.oracle
+    )]
+    pub usdc_oracle_account: AccountInfo<'info>,
+
+    #[account(
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
+    pub sol_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
+    pub usdc_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        seeds = [b"user_v3", position.owner.as_ref()],
+        bump,
+    )]
+    pub user: Option<Box<Account<'info, User>>>, // settlement preference when receive_sol is unset
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Option<Box<Account<'info, Mint>>>, // required with receive_as_lp or LP collateral
+    // remaining accounts (optional, with receive_as_lp once pool.aum_usd is stale):
+    //   pool.tokens.len() custody accounts (read-only, unsigned)
+    //   pool.tokens.len() custody oracles (read-only, unsigned)
+    //   pool.tokens.len() failover oracles (read-only, unsigned, optional as a block)
+}
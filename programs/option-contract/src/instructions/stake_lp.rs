@@ -0,0 +1,112 @@
+use crate::{
+    errors::TradingError,
+    events::LpStakeUpdated,
+    math,
+    state::{Contract, LpStake, Pool},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StakeLpParams {
+    pub pool_name: String,
+    pub amount: u64,
+}
+
+/// Escrow LP tokens in the pool's stake vault so they earn epoch LP fees. The new balance
+/// only starts earning on a custody once claim_lp_fees checkpoints it there.
+pub fn stake_lp(ctx: Context<StakeLp>, params: &StakeLpParams) -> Result<()> {
+    msg!("Staking {} LP tokens in pool {}", params.amount, params.pool_name);
+    require!(params.amount > 0, TradingError::InvalidAmount);
+
+    let contract = &ctx.accounts.contract;
+    let lp_stake = &mut ctx.accounts.lp_stake;
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+
+    if lp_stake.owner == Pubkey::default() {
+        lp_stake.owner = ctx.accounts.owner.key();
+        lp_stake.pool = ctx.accounts.pool.key();
+        lp_stake.bump = ctx.bumps.lp_stake;
+    }
+
+    contract.transfer_tokens_from_user(
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.lp_stake_vault.to_account_info(),
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount,
+    )?;
+    lp_stake.amount = math::checked_add(lp_stake.amount, params.amount)?;
+
+    emit!(LpStakeUpdated {
+        owner: lp_stake.owner,
+        pool: lp_stake.pool,
+        staked: true,
+        amount: params.amount,
+        staked_amount: lp_stake.amount,
+        updated_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: StakeLpParams)]
+pub struct StakeLp<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key() @ TradingError::InvalidMintError,
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        token::mint = lp_token_mint,
+        token::authority = transfer_authority, // PDA
+        seeds = [b"lp_stake_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_stake_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = LpStake::LEN,
+        seeds = [b"lp_stake", owner.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub lp_stake: Box<Account<'info, LpStake>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
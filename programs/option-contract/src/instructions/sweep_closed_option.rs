@@ -0,0 +1,103 @@
+use crate::{
+    errors::{OptionError, TradingError},
+    events::PositionAccountClosed,
+    state::{Contract, Custody, OptionDetail, Pool},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SweepClosedOptionParams {
+    pub option_index: u64,
+    pub pool_name: String,
+}
+
+/// Close the `closed` tracking account that close_option accumulates partial closes into.
+/// The account only serves as on-chain history for the frontend; OptionClosed events carry the
+/// same data, so once the parent option is finished the owner can trade that history for rent.
+pub fn sweep_closed_option(
+    ctx: Context<SweepClosedOption>,
+    params: &SweepClosedOptionParams,
+) -> Result<()> {
+    msg!("Sweeping closed option tracking account");
+
+    let contract = &ctx.accounts.contract;
+    let closed_option_detail = &ctx.accounts.closed_option_detail;
+    let option_info = &ctx.accounts.option_detail;
+
+    require_keys_eq!(
+        closed_option_detail.owner,
+        ctx.accounts.owner.key(),
+        TradingError::Unauthorized
+    );
+
+    // Parent option must be closed, exercised or expired
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    if option_info.lamports() > 0 && !option_info.data_is_empty() && option_info.owner == &crate::ID {
+        let data = option_info.try_borrow_data()?;
+        if let Ok(option) = OptionDetail::try_deserialize(&mut &data[..]) {
+            require!(
+                !option.valid || current_time > option.expired_date,
+                OptionError::OptionNotValid
+            );
+        }
+    }
+
+    // Rent is returned to the owner by the `close` constraint
+    emit!(PositionAccountClosed {
+        owner: closed_option_detail.owner,
+        position_key: closed_option_detail.key(),
+        position_index: params.option_index,
+        pool: ctx.accounts.pool.key(),
+        rent_refunded: closed_option_detail.to_account_info().lamports(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: SweepClosedOptionParams)]
+pub struct SweepClosedOption<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool",
+                 params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.key().as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>, // underlying price asset
+
+    /// CHECK: parent option, may already be closed
+    #[account(
+        seeds = [b"option", owner.key().as_ref(),
+            params.option_index.to_le_bytes().as_ref(),
+            pool.key().as_ref(), custody.key().as_ref()],
+        bump
+    )]
+    pub option_detail: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"option", owner.key().as_ref(),
+            params.option_index.to_le_bytes().as_ref(),
+            pool.key().as_ref(), custody.key().as_ref(),
+            b"closed"],
+        bump,
+        close = owner
+    )]
+    pub closed_option_detail: Box<Account<'info, OptionDetail>>,
+}
@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::ContractError,
+    math,
+    state::{Contract, Pool},
+};
+
+/// Permissionless: sum every pool's notional into Contract::global_notional_usd and run the
+/// circuit breaker on the total. Opens and closes only move their pool's counter, so the
+/// contract stays read-only on trading paths and is written here instead.
+pub fn sync_global_notional<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SyncGlobalNotional<'info>>,
+) -> Result<()> {
+    let contract = &mut ctx.accounts.contract;
+    require_eq!(
+        ctx.remaining_accounts.len(),
+        contract.pools.len(),
+        ContractError::InvalidPoolList
+    );
+
+    let mut global_notional_usd: u64 = 0;
+    for (pool_info, pool_key) in ctx.remaining_accounts.iter().zip(contract.pools.iter()) {
+        require_keys_eq!(pool_info.key(), *pool_key, ContractError::InvalidPoolList);
+        let mut pool = Account::<Pool>::try_from(pool_info)?;
+        global_notional_usd = math::checked_add(global_notional_usd, pool.notional_usd)?;
+        pool.synced_notional_usd = pool.notional_usd;
+        pool.exit(&crate::ID)?;
+    }
+
+    msg!("Global notional synced: {} -> {}", contract.global_notional_usd, global_notional_usd);
+    contract.set_global_notional(global_notional_usd)
+}
+
+#[derive(Accounts)]
+pub struct SyncGlobalNotional<'info> {
+    #[account(
+        mut,
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    // remaining accounts:
+    //   every pool in contract.pools, in the same order (writable, unsigned)
+}
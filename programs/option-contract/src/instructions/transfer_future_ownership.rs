@@ -0,0 +1,146 @@
+use crate::{
+    errors::{FutureError, TradingError},
+    events::OwnershipTransferred,
+    math,
+    state::{Contract, Future, FutureStatus, Pool, User},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TransferFutureOwnershipParams {
+    pub future_index: u64,
+    pub pool_name: String,
+    pub new_owner: Pubkey,
+}
+
+/// Move an active future to a new owner. The future PDA is seeded by owner, so the data is
+/// copied into a fresh PDA under the new owner's next index and the old account is closed.
+pub fn transfer_future_ownership(
+    ctx: Context<TransferFutureOwnership>,
+    params: &TransferFutureOwnershipParams,
+) -> Result<()> {
+    msg!("Transferring future ownership");
+
+    let contract = &ctx.accounts.contract;
+    let old_future = &ctx.accounts.future;
+    let new_user = &mut ctx.accounts.new_user;
+
+    require_keys_eq!(
+        old_future.owner,
+        ctx.accounts.owner.key(),
+        TradingError::InvalidOwner
+    );
+    require_keys_neq!(
+        params.new_owner,
+        ctx.accounts.owner.key(),
+        TradingError::InvalidOwner
+    );
+    require!(
+        old_future.status == FutureStatus::Active,
+        FutureError::FutureNotActive
+    );
+
+    // TP/SL orderbooks are seeded by the old owner and would be orphaned
+    require!(
+        ctx.accounts.tp_sl_orderbook.data_is_empty(),
+        TradingError::OwnershipTransferNotAllowed
+    );
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    let new_index = new_user.future_index;
+
+    let mut data = (***old_future).clone();
+    data.owner = params.new_owner;
+    data.index = new_index;
+    data.update_time = current_time;
+    data.bump = ctx.bumps.new_future;
+    ctx.accounts.new_future.set_inner(data);
+
+    new_user.future_index = math::checked_add(new_index, 1)?;
+    new_user.bump = ctx.bumps.new_user;
+
+    // Old future rent is returned to the owner by the `close` constraint
+    emit!(OwnershipTransferred {
+        contract_type: 2,
+        old_owner: ctx.accounts.owner.key(),
+        new_owner: params.new_owner,
+        old_account: old_future.key(),
+        new_account: ctx.accounts.new_future.key(),
+        old_index: params.future_index,
+        new_index,
+        pool: ctx.accounts.pool.key(),
+        transferred_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: TransferFutureOwnershipParams)]
+pub struct TransferFutureOwnership<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"future",
+            owner.key().as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = future.bump,
+        close = owner
+    )]
+    pub future: Box<Account<'info, Future>>,
+
+    /// CHECK: TP/SL orderbook PDA of the old future, must not exist
+    #[account(
+        seeds = [
+            b"tp_sl_orderbook",
+            owner.key().as_ref(),
+            params.future_index.to_le_bytes().as_ref(),
+            params.pool_name.as_bytes(),
+            2u8.to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub tp_sl_orderbook: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = User::LEN,
+        seeds = [b"user_v3", params.new_owner.as_ref()],
+        bump,
+    )]
+    pub new_user: Box<Account<'info, User>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Future::LEN,
+        seeds = [
+            b"future",
+            params.new_owner.as_ref(),
+            new_user.future_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump
+    )]
+    pub new_future: Box<Account<'info, Future>>,
+
+    pub system_program: Program<'info, System>,
+}
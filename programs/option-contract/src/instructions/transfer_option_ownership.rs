@@ -0,0 +1,148 @@
+use crate::{
+    errors::{OptionError, TradingError},
+    events::OwnershipTransferred,
+    math,
+    state::{Contract, Custody, OptionDetail, Pool, User},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TransferOptionOwnershipParams {
+    pub option_index: u64,
+    pub pool_name: String,
+    pub new_owner: Pubkey,
+}
+
+/// Move a live option to a new owner. The option PDA is seeded by owner, so the data is
+/// copied into a fresh PDA under the new owner's next index and the old account is closed.
+pub fn transfer_option_ownership(
+    ctx: Context<TransferOptionOwnership>,
+    params: &TransferOptionOwnershipParams,
+) -> Result<()> {
+    msg!("Transferring option ownership");
+
+    let contract = &ctx.accounts.contract;
+    let old_option = &ctx.accounts.option_detail;
+    let new_user = &mut ctx.accounts.new_user;
+
+    require_keys_eq!(
+        old_option.owner,
+        ctx.accounts.owner.key(),
+        TradingError::InvalidOwner
+    );
+    require_keys_neq!(
+        params.new_owner,
+        ctx.accounts.owner.key(),
+        TradingError::InvalidOwner
+    );
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    require!(old_option.valid, OptionError::OptionNotValid);
+    require_gt!(
+        old_option.expired_date,
+        current_time,
+        OptionError::OptionExpired
+    );
+
+    // TP/SL orderbooks are seeded by the old owner and would be orphaned
+    require!(
+        old_option.tp_sl_orderbook.is_none(),
+        TradingError::OwnershipTransferNotAllowed
+    );
+
+    let new_index = math::checked_add(new_user.option_index, 1)?;
+
+    // A perp hedge stays with the old owner and lapses with the closed account
+    let mut data = (***old_option).clone();
+    data.valid = false;
+    ctx.accounts
+        .pool
+        .sync_option_hedge(&mut data, old_option.key(), ctx.accounts.hedge_position.as_deref())?;
+    data.valid = true;
+    data.owner = params.new_owner;
+    data.index = new_index;
+    data.last_update_time = current_time;
+    data.bump = ctx.bumps.new_option_detail;
+    ctx.accounts.new_option_detail.set_inner(data);
+
+    new_user.option_index = new_index;
+    new_user.bump = ctx.bumps.new_user;
+
+    // Old option rent is returned to the owner by the `close` constraint
+    emit!(OwnershipTransferred {
+        contract_type: 1,
+        old_owner: ctx.accounts.owner.key(),
+        new_owner: params.new_owner,
+        old_account: old_option.key(),
+        new_account: ctx.accounts.new_option_detail.key(),
+        old_index: params.option_index,
+        new_index,
+        pool: ctx.accounts.pool.key(),
+        transferred_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: TransferOptionOwnershipParams)]
+pub struct TransferOptionOwnership<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.key().as_ref()],
+        bump = custody.bump
+    )]
+    pub custody: Box<Account<'info, Custody>>, // underlying price asset
+
+    #[account(
+        mut,
+        seeds = [b"option", owner.key().as_ref(),
+            params.option_index.to_le_bytes().as_ref(),
+            pool.key().as_ref(), custody.key().as_ref()],
+        bump,
+        close = owner
+    )]
+    pub option_detail: Box<Account<'info, OptionDetail>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = User::LEN,
+        seeds = [b"user_v3", params.new_owner.as_ref()],
+        bump,
+    )]
+    pub new_user: Box<Account<'info, User>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = OptionDetail::LEN,
+        seeds = [b"option", params.new_owner.as_ref(),
+            (new_user.option_index + 1).to_le_bytes().as_ref(),
+            pool.key().as_ref(), custody.key().as_ref()],
+        bump
+    )]
+    pub new_option_detail: Box<Account<'info, OptionDetail>>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: Perp tagged as the option's hedge, required when option_detail.hedge_position is set
+    #[account(mut)]
+    pub hedge_position: Option<UncheckedAccount<'info>>,
+}
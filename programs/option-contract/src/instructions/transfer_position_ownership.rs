@@ -0,0 +1,137 @@
+use crate::{
+    errors::{PerpetualError, TradingError},
+    events::OwnershipTransferred,
+    math,
+    state::{Contract, OrderType, Pool, Position, User},
+};
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TransferPositionOwnershipParams {
+    pub position_index: u64,
+    pub pool_name: String,
+    pub new_owner: Pubkey,
+}
+
+/// Move a perp position to a new owner. The position PDA is seeded by owner, so the data is
+/// copied into a fresh PDA under the new owner's next index and the old account is closed.
+pub fn transfer_position_ownership(
+    ctx: Context<TransferPositionOwnership>,
+    params: &TransferPositionOwnershipParams,
+) -> Result<()> {
+    msg!("Transferring perp position ownership");
+
+    let contract = &ctx.accounts.contract;
+    let old_position = &ctx.accounts.position;
+    let new_user = &mut ctx.accounts.new_user;
+
+    require_keys_eq!(
+        old_position.owner,
+        ctx.accounts.owner.key(),
+        TradingError::InvalidOwner
+    );
+    require_keys_neq!(
+        params.new_owner,
+        ctx.accounts.owner.key(),
+        TradingError::InvalidOwner
+    );
+    require!(!old_position.is_liquidated, PerpetualError::PositionLiquidated);
+
+    // Resting limit orders are keyed by position PDA in the order book
+    require!(
+        old_position.order_type == OrderType::Market || old_position.execution_time.is_some(),
+        TradingError::OwnershipTransferNotAllowed
+    );
+    // TP/SL orderbooks are seeded by the old owner and would be orphaned
+    require!(
+        old_position.tp_sl_orderbook.is_none(),
+        TradingError::OwnershipTransferNotAllowed
+    );
+
+    let current_time = ctx.accounts.pool.get_time(contract)?;
+    let new_index = math::checked_add(new_user.perp_position_index, 1)?;
+
+    let mut data = (***old_position).clone();
+    data.owner = params.new_owner;
+    data.index = new_index;
+    data.update_time = current_time;
+    data.bump = ctx.bumps.new_position;
+    // The hedged option stays with the old owner
+    data.clear_option_hedge();
+    ctx.accounts.new_position.set_inner(data);
+
+    new_user.perp_position_index = new_index;
+    new_user.bump = ctx.bumps.new_user;
+
+    // Old position rent is returned to the owner by the `close` constraint
+    emit!(OwnershipTransferred {
+        contract_type: 0,
+        old_owner: ctx.accounts.owner.key(),
+        new_owner: params.new_owner,
+        old_account: old_position.key(),
+        new_account: ctx.accounts.new_position.key(),
+        old_index: params.position_index,
+        new_index,
+        pool: ctx.accounts.pool.key(),
+        transferred_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: TransferPositionOwnershipParams)]
+pub struct TransferPositionOwnership<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"position",
+            owner.key().as_ref(),
+            params.position_index.to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = User::LEN,
+        seeds = [b"user_v3", params.new_owner.as_ref()],
+        bump,
+    )]
+    pub new_user: Box<Account<'info, User>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [
+            b"position",
+            params.new_owner.as_ref(),
+            (new_user.perp_position_index + 1).to_le_bytes().as_ref(),
+            pool.key().as_ref()
+        ],
+        bump
+    )]
+    pub new_position: Box<Account<'info, Position>>,
+
+    pub system_program: Program<'info, System>,
+}
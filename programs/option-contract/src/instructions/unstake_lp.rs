@@ -0,0 +1,139 @@
+use crate::{
+    errors::{PoolError, TradingError},
+    events::LpStakeUpdated,
+    math,
+    state::{Contract, Custody, LpFeeAccount, LpStake, Pool},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UnstakeLpParams {
+    pub pool_name: String,
+    pub amount: u64,
+}
+
+/// Return staked LP tokens to the owner. The owner's LP fee account of every custody is
+/// checkpointed at the balance held so far and continues at the reduced one, so the tokens
+/// can't be counted again under another stake for the same stretch.
+pub fn unstake_lp<'info>(
+    ctx: Context<'_, '_, 'info, 'info, UnstakeLp<'info>>,
+    params: &UnstakeLpParams,
+) -> Result<()> {
+    msg!("Unstaking {} LP tokens in pool {}", params.amount, params.pool_name);
+    require!(params.amount > 0, TradingError::InvalidAmount);
+
+    let contract = &ctx.accounts.contract;
+    let pool = &mut ctx.accounts.pool;
+    let lp_stake = &mut ctx.accounts.lp_stake;
+    let current_time = pool.get_time(contract)?;
+
+    let held_amount = lp_stake.amount;
+    lp_stake.amount = math::checked_sub(lp_stake.amount, params.amount)?;
+
+    if let Some(epoch) = pool.get_lp_fee_epoch(current_time) {
+        let custody_count = pool.custodies.len();
+        require_eq!(ctx.remaining_accounts.len(), custody_count * 2, PoolError::InvalidPoolConfig);
+        for (idx, custody_key) in pool.custodies.iter().enumerate() {
+            let custody_info = &ctx.remaining_accounts[idx];
+            let lp_fee_account_info = &ctx.remaining_accounts[custody_count + idx];
+            require_keys_eq!(custody_info.key(), *custody_key);
+            let (expected_lp_fee_account, _) = Pubkey::find_program_address(
+                &[b"lp_fees", lp_stake.owner.as_ref(), custody_key.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(lp_fee_account_info.key(), expected_lp_fee_account);
+            // Never checkpointed, so nothing has been earned there yet
+            if lp_fee_account_info.owner != &crate::ID {
+                continue;
+            }
+
+            let mut custody = Account::<Custody>::try_from(custody_info)?;
+            let mut lp_fee_account = Account::<LpFeeAccount>::try_from(lp_fee_account_info)?;
+            custody.advance_lp_fee_epoch(epoch, ctx.accounts.lp_token_mint.supply)?;
+            lp_fee_account.checkpoint(&custody, pool.lp_fee_epoch_duration_sec, current_time, held_amount)?;
+            lp_fee_account.lp_balance = lp_stake.amount;
+            custody.exit(&crate::ID)?;
+            lp_fee_account.exit(&crate::ID)?;
+        }
+    }
+
+    contract.transfer_tokens(
+        ctx.accounts.lp_stake_vault.to_account_info(),
+        ctx.accounts.lp_token_account.to_account_info(),
+        ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+        params.amount,
+    )?;
+
+    emit!(LpStakeUpdated {
+        owner: lp_stake.owner,
+        pool: lp_stake.pool,
+        staked: false,
+        amount: params.amount,
+        staked_amount: lp_stake.amount,
+        updated_at: current_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(params: UnstakeLpParams)]
+pub struct UnstakeLp<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = lp_token_account.mint == lp_token_mint.key() @ TradingError::InvalidMintError,
+        has_one = owner
+    )]
+    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Transfer authority PDA for contract token operations
+    #[account(
+        seeds = [b"transfer_authority"],
+        bump = contract.transfer_authority_bump
+    )]
+    pub transfer_authority: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"contract"],
+        bump = contract.bump
+    )]
+    pub contract: Box<Account<'info, Contract>>,
+
+    #[account(
+        seeds = [b"pool", params.pool_name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [b"lp_token_mint", pool.name.as_bytes()],
+        bump = pool.lp_token_bump
+    )]
+    pub lp_token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"lp_stake_vault", pool.key().as_ref()],
+        bump
+    )]
+    pub lp_stake_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"lp_stake", owner.key().as_ref(), pool.key().as_ref()],
+        bump = lp_stake.bump
+    )]
+    pub lp_stake: Box<Account<'info, LpStake>>,
+
+    pub token_program: Program<'info, Token>,
+    // remaining accounts, with epoch LP fees enabled on the pool:
+    //   pool.custodies.len() custody accounts (writable, unsigned)
+    //   pool.custodies.len() owner's LP fee accounts of those custodies (writable, unsigned,
+    //   may be uninitialized)
+}
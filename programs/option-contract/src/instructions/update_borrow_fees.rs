@@ -1,7 +1,8 @@
 use crate::{
-    errors::PerpetualError,
+    errors::{PerpetualError, TradingError},
     events::BorrowFeesUpdated,
-    state::{Contract, Custody, Pool, Position, OrderType, Side},
+    math,
+    state::{Contract, Custody, KeeperRewards, Pool, Position, OrderType, Side},
 };
 use anchor_lang::prelude::*;
 
@@ -27,12 +28,23 @@ pub fn update_borrow_fees(
     require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
     require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
     
-    let current_time = contract.get_time()?;
+    let current_time = pool.get_time(contract)?;
     
     // Store previous values for logging
     let previous_interest_snapshot = position.cumulative_interest_snapshot;
     let previous_borrow_fee_update_time = position.last_borrow_fees_update_time;
     
+    // Keepers can't update more often than the pool allows; skip without failing the tx
+    let elapsed = current_time - previous_borrow_fee_update_time;
+    if elapsed < pool.min_update_interval_sec {
+        msg!(
+            "Skipping update: {} seconds since last update, minimum interval is {}",
+            elapsed,
+            pool.min_update_interval_sec
+        );
+        return Ok(());
+    }
+    
     // Calculate time-based borrow fee accrual using the helper method
     let borrow_fee_payment = pool.update_position_borrow_fees(
         position, 
@@ -41,18 +53,47 @@ pub fn update_borrow_fees(
         usdc_custody
     )?;
     
+    // The keeper earns a slice of the fees this update caught up, claimable once closes have
+    // collected them into the pool's keeper reward budget. Owners earn nothing on their own
+    // positions, and a keeper that passes no rewards account opts out.
+    let keeper_reward_usd = match ctx.accounts.keeper_rewards.as_mut() {
+        Some(keeper_rewards) if ctx.accounts.keeper.key() != position.owner => {
+            let reward_usd = contract.get_keeper_reward(borrow_fee_payment)?;
+            if keeper_rewards.keeper == Pubkey::default() {
+                keeper_rewards.keeper = ctx.accounts.keeper.key();
+                keeper_rewards.pool = pool.key();
+                keeper_rewards.bump = ctx.bumps.keeper_rewards.ok_or(ProgramError::InvalidSeeds)?;
+            }
+            keeper_rewards.accrued_usd = math::checked_add(keeper_rewards.accrued_usd, reward_usd)?;
+            reward_usd
+        }
+        _ => 0,
+    };
+    msg!("Keeper reward accrued: {}", keeper_reward_usd);
+    
     // Get relevant custody for logging
     let relevant_custody = match position.side {
         Side::Long => sol_custody.as_ref(),  // Long positions borrow SOL
         Side::Short => usdc_custody.as_ref(), // Short positions borrow USDC
     };
-    let current_borrow_rate = pool.get_token_borrow_rate(relevant_custody)?;
-    let current_borrow_rate_bps = current_borrow_rate.to_bps().unwrap_or(0u32);
+    let current_borrow_rate_bps = pool.get_perp_borrow_rate_bps(relevant_custody)?;
+
+    // Average annual rate actually charged over the settled period, after the cap, any hedge
+    // discount and the floor
+    let applied_rate_bps = if elapsed > 0 && position.size_usd > 0 {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(borrow_fee_payment as u128, math::checked_mul(Pool::SECONDS_PER_YEAR, 10_000u128)?)?,
+            math::checked_mul(position.size_usd as u128, elapsed as u128)?,
+        )?)?
+    } else {
+        0
+    };
     
     msg!("Position size USD: {}", position.size_usd);
     msg!("Position side: {:?}", position.side);
     msg!("Using custody utilization: {:.2}%", crate::utils::pool::calculate_utilization(relevant_custody.token_locked, relevant_custody.token_owned));
     msg!("Current borrow rate: {:.2}% APR", current_borrow_rate_bps as f64 / 100.0);
+    msg!("Applied borrow rate: {:.2}% APR", applied_rate_bps as f64 / 100.0);
     msg!("Time elapsed: {} seconds", current_time - previous_borrow_fee_update_time);
     msg!("Borrow fee payment: {}", borrow_fee_payment);
     
@@ -69,12 +110,14 @@ pub fn update_borrow_fees(
         order_type: position.order_type as u8,
         side: position.side as u8,
         position_size_usd: position.size_usd,
-        borrow_fee_payment: borrow_fee_payment.try_into().unwrap(),
+        borrow_fee_payment,
         new_accrued_borrow_fees: position.accrued_borrow_fees,
         last_borrow_fees_update_time: position.last_borrow_fees_update_time,
         previous_interest_snapshot,
-        new_interest_snapshot: current_borrow_rate_bps as u128,
+        new_interest_snapshot: position.cumulative_interest_snapshot,
         update_time: current_time,
+        keeper_reward_usd,
+        applied_rate_bps,
     });
     
     Ok(())
@@ -84,6 +127,7 @@ pub fn update_borrow_fees(
 #[instruction(params: UpdateBorrowFeesParams)]
 pub struct UpdateBorrowFees<'info> {
     /// CHECK: This can be any account, typically a keeper bot
+    #[account(mut)]
     pub keeper: Signer<'info>,
 
     #[account(
@@ -125,8 +169,27 @@ pub struct UpdateBorrowFees<'info> {
     )]
     pub usdc_custody: Box<Account<'info, Custody>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, anchor_spl::token::Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, anchor_spl::token::Mint>>,
+
+    // Optional, at the end so clients that pass neither still work: without them the
+    // update earns no keeper reward
+    #[account(
+        init_if_needed,
+        payer = keeper,
+        space = KeeperRewards::LEN,
+        seeds = [b"keeper_rewards", keeper.key().as_ref(), pool.key().as_ref()],
+        bump
+    )]
+    pub keeper_rewards: Option<Box<Account<'info, KeeperRewards>>>,
+
+    pub system_program: Option<Program<'info, System>>,
 }
\ No newline at end of file
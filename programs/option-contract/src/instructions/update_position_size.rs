@@ -1,9 +1,9 @@
 use crate::{
-    errors::{PerpetualError, TradingError},
+    errors::{PerpetualError, PoolError, TradingError},
     events::PositionSizeUpdated,
     math::{self, f64_to_scaled_price},
     utils::risk_management::*,
-    state::{Contract, Custody, OraclePrice, Pool, Position, Side, OrderType},
+    state::{Contract, Custody, LockedProduct, Pool, Position, Side, OrderType},
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer as SplTransfer};
@@ -37,19 +37,15 @@ pub fn update_position_size(
     require!(!position.is_liquidated, PerpetualError::PositionLiquidated);
     require!(position.order_type == OrderType::Market, PerpetualError::InvalidOrderType);
     require!(params.size_delta_usd > 0, TradingError::InvalidAmount);
+    // The floor and its premium were priced for the opened size
+    require!(position.max_loss_price == 0, PerpetualError::MaxLossResizeUnsupported);
     
     // Get current prices
-    let current_time = contract.get_time()?;
-    let sol_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.sol_oracle_account, 
-        current_time, 
-        false
-    )?;
-    let usdc_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.usdc_oracle_account, 
-        current_time, 
-        false
-    )?;
+    let current_time = pool.get_time(contract)?;
+    let sol_oracle_secondary = ctx.accounts.sol_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let usdc_oracle_secondary = ctx.accounts.usdc_oracle_secondary.as_ref().map(|a| a.to_account_info());
+    let sol_price = sol_custody.get_oracle_price(&ctx.accounts.sol_oracle_account, sol_oracle_secondary.as_ref(), current_time)?;
+    let usdc_price = usdc_custody.get_oracle_price(&ctx.accounts.usdc_oracle_account, usdc_oracle_secondary.as_ref(), current_time)?;
     
     let sol_price_value = sol_price.get_price();
     let usdc_price_value = usdc_price.get_price();
@@ -59,6 +55,10 @@ pub fn update_position_size(
     msg!("USDC Price: {}", usdc_price_value);
     msg!("{} position size by {} USD", if params.is_increase { "Increasing" } else { "Decreasing" }, params.size_delta_usd);
     
+    
+    // Settle borrow fees at the pre-change size and utilization before touching OI or locks
+    pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
+    
     // Store previous values for event
     let previous_size_usd = position.size_usd;
     let previous_collateral_usd = position.collateral_usd;
@@ -67,6 +67,10 @@ pub fn update_position_size(
     if params.is_increase {
         // Increase position size
         require!(params.collateral_delta > 0, TradingError::InvalidAmount);
+        require!(!sol_custody.trading_paused, PoolError::CustodyTradingPaused);
+        if position.side == Side::Short || !params.pay_sol {
+            require!(!usdc_custody.trading_paused, PoolError::CustodyTradingPaused);
+        }
         
         // Check user has sufficient balance
         require_gte!(
@@ -90,7 +94,7 @@ pub fn update_position_size(
         let collateral_usd_delta = math::checked_as_u64(math::checked_float_mul(
             params.collateral_delta as f64 / math::checked_powi(10.0, collateral_decimals as i32)?,
             collateral_price
-        )? * 1_000_000.0)?;
+        )? * Contract::USD_SCALE as f64)?;
         
         // Calculate new position values
         let new_size_usd = math::checked_add(position.size_usd, params.size_delta_usd)?;
@@ -98,11 +102,11 @@ pub fn update_position_size(
         
         // Calculate required liquidity for the size delta
         let required_liquidity_delta = if position.side == Side::Long {
-            let usd_amount = params.size_delta_usd as f64 / 1_000_000.0;
+            let usd_amount = params.size_delta_usd as f64 / Contract::USD_SCALE as f64;
             let sol_tokens_needed = usd_amount / sol_price_value;
             math::checked_as_u64(sol_tokens_needed * math::checked_powi(10.0, sol_custody.decimals as i32)?)?
         } else {
-            let usd_amount = params.size_delta_usd as f64 / 1_000_000.0;
+            let usd_amount = params.size_delta_usd as f64 / Contract::USD_SCALE as f64;
             let usdc_tokens_needed = usd_amount / usdc_price_value;
             math::checked_as_u64(usdc_tokens_needed * math::checked_powi(10.0, usdc_custody.decimals as i32)?)?
         };
@@ -110,16 +114,18 @@ pub fn update_position_size(
         // Check pool liquidity
         if position.side == Side::Long {
             require_gte!(
-                sol_custody.token_owned.saturating_sub(sol_custody.token_locked),
+                pool.get_borrowable_amount(sol_custody)?,
                 required_liquidity_delta,
                 TradingError::InsufficientPoolLiquidity
             );
+            pool.check_position_concentration(new_size_usd, sol_custody, position.locked_amount, &sol_price)?;
         } else {
             require_gte!(
-                usdc_custody.token_owned.saturating_sub(usdc_custody.token_locked),
+                pool.get_borrowable_amount(usdc_custody)?,
                 required_liquidity_delta,
                 TradingError::InsufficientPoolLiquidity
             );
+            pool.check_position_concentration(new_size_usd, usdc_custody, position.locked_amount, &usdc_price)?;
         }
         
         // Transfer collateral from user to pool
@@ -141,15 +147,9 @@ pub fn update_position_size(
         
         // Update custody stats
         if position.side == Side::Long {
-            sol_custody.token_locked = math::checked_add(
-                sol_custody.token_locked,
-                required_liquidity_delta
-            )?;
+            sol_custody.add_locked(LockedProduct::Perp, required_liquidity_delta)?;
         } else {
-            usdc_custody.token_locked = math::checked_add(
-                usdc_custody.token_locked,
-                required_liquidity_delta
-            )?;
+            usdc_custody.add_locked(LockedProduct::Perp, required_liquidity_delta)?;
         }
         
         if params.pay_sol {
@@ -177,21 +177,21 @@ pub fn update_position_size(
         )?;
         
         // Update pool open interest
-        if position.side == Side::Long {
-            pool.long_open_interest_usd = math::checked_add(
-                pool.long_open_interest_usd, 
-                params.size_delta_usd as u128
-            )?;
-        } else {
-            pool.short_open_interest_usd = math::checked_add(
-                pool.short_open_interest_usd, 
-                params.size_delta_usd as u128
-            )?;
-        }
+        pool.update_open_interest(position, params.size_delta_usd, true, current_time)?;
+        pool.add_notional(&ctx.accounts.contract, params.size_delta_usd)?;
         
     } else {
         // Decrease position size
         require!(params.size_delta_usd < position.size_usd, TradingError::InvalidAmount);
+        require!(position.lp_collateral_amount == 0, PerpetualError::LpCollateralUnsupported);
+
+        // Receiving account must hold the asset being paid out
+        let payout_mint = if params.receive_sol { sol_custody.mint } else { usdc_custody.mint };
+        require_keys_eq!(
+            ctx.accounts.receiving_account.mint,
+            payout_mint,
+            TradingError::ReceivingAccountMintMismatch
+        );
         
         // Calculate proportional collateral reduction
         let size_reduction_ratio = math::checked_div(params.size_delta_usd as u128, position.size_usd as u128)?;
@@ -213,7 +213,10 @@ pub fn update_position_size(
         let new_collateral_usd = math::checked_sub(position.collateral_usd, collateral_usd_to_return)?;
         
         // Ensure minimum position size
-        require!(new_size_usd >= 1_000_000, TradingError::PositionTooSmall); // Min $1
+        require!(
+            new_size_usd >= (Contract::USD_SCALE as u64).max(pool.min_position_usd),
+            TradingError::PositionTooSmall
+        ); // Min $1 or the pool minimum
         
         // Calculate PnL for the portion being closed
         let pnl = position.calculate_pnl(current_price_scaled)?;
@@ -232,42 +235,25 @@ pub fn update_position_size(
         
         // Calculate withdrawal tokens using integer math
         let withdrawal_token_amount = if params.receive_sol {
-            let sol_price_scaled = sol_price.scale_to_exponent(-6)?;
-            let sol_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-                sol_price_scaled.price as u128
-            )?;
-            
-            if sol_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (sol_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    sol_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - sol_custody.decimals) as usize)?
-                )?)?
-            }
+            math::usd_to_token_amount(settlement_usd, &sol_price, sol_custody.decimals)?
         } else {
-            let usdc_price_scaled = usdc_price.scale_to_exponent(-6)?;
-            let usdc_amount_6_decimals = math::checked_div(
-                math::checked_mul(settlement_usd as u128, 1_000_000u128)?,
-                usdc_price_scaled.price as u128
-            )?;
-            
-            if usdc_custody.decimals > 6 {
-                math::checked_as_u64(math::checked_mul(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (usdc_custody.decimals - 6) as usize)?
-                )?)?
-            } else {
-                math::checked_as_u64(math::checked_div(
-                    usdc_amount_6_decimals,
-                    math::checked_pow(10u128, (6 - usdc_custody.decimals) as usize)?
-                )?)?
-            }
+            math::usd_to_token_amount(settlement_usd, &usdc_price, usdc_custody.decimals)?
+        };
+        
+        // Release the locked backing first so the payout can draw on it
+        if position.side == Side::Long {
+            sol_custody.remove_locked(LockedProduct::Perp, locked_amount_to_release)?;
+        } else {
+            usdc_custody.remove_locked(LockedProduct::Perp, locked_amount_to_release)?;
+        }
+        
+        // Fail clearly when the chosen asset can't cover the payout; the other asset may
+        let payout_available = if params.receive_sol {
+            sol_custody.available_for_payout()
+        } else {
+            usdc_custody.available_for_payout()
         };
+        require_gte!(payout_available, withdrawal_token_amount, TradingError::InsufficientPoolLiquidity);
         
         // Transfer settlement to user
         if withdrawal_token_amount > 0 {
@@ -285,18 +271,6 @@ pub fn update_position_size(
         }
         
         // Update custody stats
-        if position.side == Side::Long {
-            sol_custody.token_locked = math::checked_sub(
-                sol_custody.token_locked,
-                locked_amount_to_release
-            )?;
-        } else {
-            usdc_custody.token_locked = math::checked_sub(
-                usdc_custody.token_locked,
-                locked_amount_to_release
-            )?;
-        }
-        
         if position.collateral_custody == sol_custody.key() {
             sol_custody.token_owned = math::checked_sub(
                 sol_custody.token_owned,
@@ -322,34 +296,33 @@ pub fn update_position_size(
         )?;
         
         // Update pool open interest
-        if position.side == Side::Long {
-            pool.long_open_interest_usd = math::checked_sub(
-                pool.long_open_interest_usd, 
-                params.size_delta_usd as u128
-            )?;
-        } else {
-            pool.short_open_interest_usd = math::checked_sub(
-                pool.short_open_interest_usd, 
-                params.size_delta_usd as u128
-            )?;
-        }
+        pool.update_open_interest(position, params.size_delta_usd, false, current_time)?;
+        pool.remove_notional(params.size_delta_usd);
     }
     
     let new_leverage = math::checked_float_div(position.size_usd as f64, position.collateral_usd as f64)?;
     
-    // Recalculate liquidation price
+    // Recalculate liquidation price at the margin tier for the new size
+    let maintenance_margin_bps = sol_custody.get_maintenance_margin_bps(position.size_usd);
     let new_liquidation_price = calculate_liquidation_price(
         position.entry_price,
         new_leverage,
-        position.side
+        position.side,
+        maintenance_margin_bps,
     )?;
     
-    // Update accrued borrow fees before modifying position
-    pool.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
-    
     position.liquidation_price = new_liquidation_price;
+    position.bankruptcy_price = calculate_bankruptcy_price(
+        position.entry_price,
+        new_leverage,
+        position.side
+    )?;
     position.update_time = current_time;
     
+    if params.is_increase {
+        position.require_healthy(current_price_scaled, maintenance_margin_bps, pool.liquidation_buffer_bps)?;
+    }
+    
     msg!("Position size updated successfully");
     msg!("New size USD: {}", position.size_usd);
     msg!("New collateral USD: {}", position.collateral_usd);
@@ -382,7 +355,9 @@ pub fn update_position_size(
         new_locked_amount: position.locked_amount,
         update_time: current_time,
     });
-    
+
+    Custody::check_invariants(&[&ctx.accounts.sol_custody, &ctx.accounts.usdc_custody])?;
+
     Ok(())
 }
 
@@ -484,11 +459,29 @@ pub struct UpdatePositionSize<'info> {
     )]
     pub usdc_oracle_account: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = sol_mint.key() == pool.sol_mint @ TradingError::InvalidMintError
+    )]
     pub sol_mint: Box<Account<'info, Mint>>,
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = usdc_mint.key() == pool.usdc_mint @ TradingError::InvalidMintError
+    )]
     pub usdc_mint: Box<Account<'info, Mint>>,
 
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = sol_oracle_secondary.key() == sol_custody.oracle_secondary
+    )]
+    pub sol_oracle_secondary: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Failover oracle, validation is handled by constraint
+    #[account(
+        constraint = usdc_oracle_secondary.key() == usdc_custody.oracle_secondary
+    )]
+    pub usdc_oracle_secondary: Option<UncheckedAccount<'info>>,
 }
\ No newline at end of file
@@ -50,6 +50,67 @@ pub mod option_contract {
         instructions::remove_pool::remove_pool(ctx, &params)
     }
 
+    // Update pool risk parameters with multi sig
+    pub fn set_pool_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetPoolConfig<'info>>,
+        params: SetPoolConfigParams,
+    ) -> Result<u8> {
+        instructions::set_pool_config::set_pool_config(ctx, &params)
+    }
+
+    // Retarget pool token ratios with multi sig
+    pub fn set_pool_ratios<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetPoolRatios<'info>>,
+        params: SetPoolRatiosParams,
+    ) -> Result<u8> {
+        instructions::set_pool_ratios::set_pool_ratios(ctx, &params)
+    }
+
+    // Update protocol-wide notional ceiling and pause flag with multi sig
+    pub fn set_contract_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetContractConfig<'info>>,
+        params: SetContractConfigParams,
+    ) -> Result<u8> {
+        instructions::set_contract_config::set_contract_config(ctx, &params)
+    }
+
+    // Update custody option spread with multi sig
+    pub fn set_custody_config<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetCustodyConfig<'info>>,
+        params: SetCustodyConfigParams,
+    ) -> Result<u8> {
+        instructions::set_custody_config::set_custody_config(ctx, &params)
+    }
+
+    // Correct custody locked amount to the sum of live positions with multi sig
+    pub fn reconcile_custody_locked<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReconcileCustodyLocked<'info>>,
+        params: ReconcileCustodyLockedParams,
+    ) -> Result<u8> {
+        instructions::reconcile_custody_locked::reconcile_custody_locked(ctx, &params)
+    }
+
+    // Correct pool open interest to the summed size of live positions with multi sig
+    pub fn reconcile_open_interest<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ReconcileOpenInterest<'info>>,
+        params: ReconcileOpenInterestParams,
+    ) -> Result<u8> {
+        instructions::reconcile_open_interest::reconcile_open_interest(ctx, &params)
+    }
+
+    // Register a referrer for a pool or (de)activate it with multi sig
+    pub fn set_referral<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetReferral<'info>>,
+        params: SetReferralParams,
+    ) -> Result<u8> {
+        instructions::set_referral::set_referral(ctx, &params)
+    }
+
+    // Grow an account created before its layout gained fields (anyone can pay)
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        instructions::migrate_account::migrate_account(ctx)
+    }
+
     // Make Storate in Pool for new custody
     pub fn realloc_pool(ctx: Context<RealocPool>, params: ReallocPoolParams) -> Result<()> {
         instructions::realloc_pool::realloc_pool(ctx, &params)
@@ -77,6 +138,13 @@ pub mod option_contract {
     ) -> Result<()> {
         instructions::add_liquidity::add_liquidity(ctx, &params)
     }
+    // Add liquidity in all pool assets at the target ratios, without ratio fees
+    pub fn add_liquidity_balanced<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddLiquidityBalanced<'info>>,
+        params: AddLiquidityBalancedParams,
+    ) -> Result<()> {
+        instructions::add_liquidity_balanced::add_liquidity_balanced(ctx, &params)
+    }
     // Remove liquidity
     pub fn remove_liquidity<'info>(
         ctx: Context<'_, '_, 'info, 'info, RemoveLiquidity<'info>>,
@@ -84,6 +152,27 @@ pub mod option_contract {
     ) -> Result<()> {
         instructions::remove_liquidity::remove_liquidity(ctx, &params)
     }
+    // Queue an LP withdrawal that is paid out as custody liquidity frees up
+    pub fn request_withdraw_liquidity(
+        ctx: Context<RequestWithdrawLiquidity>,
+        params: RequestWithdrawLiquidityParams,
+    ) -> Result<()> {
+        instructions::request_withdraw_liquidity::request_withdraw_liquidity(ctx, &params)
+    }
+    // Fill a queued LP withdrawal from free custody liquidity (keeper)
+    pub fn fulfill_withdraw_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FulfillWithdrawLiquidity<'info>>,
+        params: FulfillWithdrawLiquidityParams,
+    ) -> Result<()> {
+        instructions::fulfill_withdraw_liquidity::fulfill_withdraw_liquidity(ctx, &params)
+    }
+    // Cancel a queued LP withdrawal and return the remaining LP tokens
+    pub fn cancel_withdraw_liquidity(
+        ctx: Context<CancelWithdrawLiquidity>,
+        params: CancelWithdrawLiquidityParams,
+    ) -> Result<()> {
+        instructions::cancel_withdraw_liquidity::cancel_withdraw_liquidity(ctx, &params)
+    }
 
     pub fn open_limit_option(ctx: Context<OpenLimitOption>, params: OpenLimitOptionParams) -> Result<()> {
         instructions::open_limit_option::open_limit_option(ctx, &params)
@@ -104,6 +193,11 @@ pub mod option_contract {
         instructions::edit_option::edit_option(ctx, &params)
     }
 
+    // Roll option to a later expiry / new strike
+    pub fn roll_option(ctx: Context<RollOption>, params: RollOptionParams) -> Result<()> {
+        instructions::roll_option::roll_option(ctx, &params)
+    }
+
     // Set TP/SL for existing option
     pub fn set_option_tp_sl(ctx: Context<SetOptionTpSl>, params: SetOptionTpSlParams) -> Result<()> {
         instructions::set_option_tp_sl::set_option_tp_sl(ctx, &params)
@@ -114,6 +208,16 @@ pub mod option_contract {
         instructions::close_option::close_option(ctx, &params)
     }
 
+    // Reclaim rent from the closed-option tracking account once the option is finished
+    pub fn sweep_closed_option(ctx: Context<SweepClosedOption>, params: SweepClosedOptionParams) -> Result<()> {
+        instructions::sweep_closed_option::sweep_closed_option(ctx, &params)
+    }
+
+    // Move an option to a new owner wallet
+    pub fn transfer_option_ownership(ctx: Context<TransferOptionOwnership>, params: TransferOptionOwnershipParams) -> Result<()> {
+        instructions::transfer_option_ownership::transfer_option_ownership(ctx, &params)
+    }
+
     // Exercise option before expired time by user
     pub fn exercise_option(
         ctx: Context<ExerciseOption>,
@@ -136,15 +240,44 @@ pub mod option_contract {
     }
 
     //Open perpetual position
-    pub fn open_perp_position(ctx: Context<OpenPerpPosition>, params: OpenPerpPositionParams) -> Result<()> {
+    pub fn open_perp_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenPerpPosition<'info>>,
+        params: OpenPerpPositionParams,
+    ) -> Result<()> {
         instructions::open_perp_position::open_perp_position(ctx, &params)
     }
 
     //Close perpetual position
-    pub fn close_perp_position(ctx: Context<ClosePerpPosition>, params: ClosePerpPositionParams) -> Result<()> {
+    pub fn close_perp_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClosePerpPosition<'info>>,
+        params: ClosePerpPositionParams,
+    ) -> Result<()> {
         instructions::close_perp_position::close_perp_position(ctx, &params)
     }
 
+    // Fully close all of a user's perp positions in a pool in one transaction
+    pub fn close_all_positions<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseAllPositions<'info>>,
+        params: CloseAllPositionsParams,
+    ) -> Result<()> {
+        instructions::close_all_positions::close_all_positions(ctx, &params)
+    }
+
+    // Preview close_perp_position settlement without changing state
+    pub fn simulate_close_perp<'info>(ctx: Context<'_, '_, 'info, 'info, SimulateClosePerp<'info>>, params: SimulateClosePerpParams) -> Result<()> {
+        instructions::simulate_close_perp::simulate_close_perp(ctx, &params)
+    }
+
+    // Preview the collateral open_perp_position needs for a size and leverage
+    pub fn compute_required_collateral(ctx: Context<ComputeRequiredCollateral>, params: ComputeRequiredCollateralParams) -> Result<()> {
+        instructions::compute_required_collateral::compute_required_collateral(ctx, &params)
+    }
+
+    // Move a perpetual position to a new owner wallet
+    pub fn transfer_position_ownership(ctx: Context<TransferPositionOwnership>, params: TransferPositionOwnershipParams) -> Result<()> {
+        instructions::transfer_position_ownership::transfer_position_ownership(ctx, &params)
+    }
+
     //Add collateral
     pub fn add_collateral(ctx: Context<AddCollateral>, params: AddCollateralParams) -> Result<()> {
         instructions::add_collateral::add_collateral(ctx, &params)
@@ -165,6 +298,44 @@ pub mod option_contract {
         instructions::update_borrow_fees::update_borrow_fees(ctx, &params)
     }
 
+    // Tag a perp as the hedge of an option for a borrow fee discount, or remove the tag
+    pub fn set_position_hedge(ctx: Context<SetPositionHedge>, params: SetPositionHedgeParams) -> Result<()> {
+        instructions::set_position_hedge::set_position_hedge(ctx, &params)
+    }
+
+    // Set the owner's default settlement asset for closes that leave receive_sol unset
+    pub fn set_user_prefs(ctx: Context<SetUserPrefs>, params: SetUserPrefsParams) -> Result<()> {
+        instructions::set_user_prefs::set_user_prefs(ctx, &params)
+    }
+
+    // Claim keeper rewards earned from borrow fee updates
+    pub fn claim_keeper_rewards(ctx: Context<ClaimKeeperRewards>, params: ClaimKeeperRewardsParams) -> Result<()> {
+        instructions::claim_keeper_rewards::claim_keeper_rewards(ctx, &params)
+    }
+
+    // Claim referral fees earned from referred opens
+    pub fn claim_referral_fees(ctx: Context<ClaimReferralFees>, params: ClaimReferralFeesParams) -> Result<()> {
+        instructions::claim_referral_fees::claim_referral_fees(ctx, &params)
+    }
+
+    // Checkpoint an LP balance and claim epoch-distributed liquidity fees
+    pub fn claim_lp_fees(ctx: Context<ClaimLpFees>, params: ClaimLpFeesParams) -> Result<()> {
+        instructions::claim_lp_fees::claim_lp_fees(ctx, &params)
+    }
+
+    // Escrow LP tokens so they earn epoch-distributed liquidity fees
+    pub fn stake_lp(ctx: Context<StakeLp>, params: StakeLpParams) -> Result<()> {
+        instructions::stake_lp::stake_lp(ctx, &params)
+    }
+
+    // Return staked LP tokens to the owner
+    pub fn unstake_lp<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UnstakeLp<'info>>,
+        params: UnstakeLpParams,
+    ) -> Result<()> {
+        instructions::unstake_lp::unstake_lp(ctx, &params)
+    }
+
     //Liquidate position
     pub fn liquidate(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
         instructions::liquidate::liquidate(ctx, &params)
@@ -175,6 +346,11 @@ pub mod option_contract {
         instructions::cancel_limit_order::cancel_limit_order(ctx, &params)
     }
 
+    // Keeper expiry of a pending limit order past its expiry_time
+    pub fn expire_limit_order(ctx: Context<ExpireLimitOrder>, params: ExpireLimitOrderParams) -> Result<()> {
+        instructions::expire_limit_order::expire_limit_order(ctx, &params)
+    }
+
     // Execute limit order when conditions are met
     pub fn execute_limit_order(ctx: Context<ExecuteLimitOrder>, params: ExecuteLimitOrderParams) -> Result<()> {
         instructions::execute_limit_order::execute_limit_order(ctx, &params)
@@ -195,8 +371,26 @@ pub mod option_contract {
         instructions::execute_tp_sl_order::execute_tp_sl_order(ctx, &params)
     }
 
+    // Execute TP/SL order on a future - dedicated instruction for keepers
+    pub fn execute_future_tp_sl_order(ctx: Context<ExecuteFutureTpSlOrder>, params: ExecuteFutureTpSlOrderParams) -> Result<()> {
+        instructions::execute_future_tp_sl_order::execute_future_tp_sl_order(ctx, &params)
+    }
+
+    // Close a TP/SL orderbook left behind after its position was closed
+    pub fn close_tp_sl_orderbook(ctx: Context<CloseTpSlOrderbook>, params: CloseTpSlOrderbookParams) -> Result<()> {
+        instructions::close_tp_sl_orderbook::close_tp_sl_orderbook(ctx, &params)
+    }
+
+    // Initialize the shared limit order book for a pool
+    pub fn init_limit_order_book(ctx: Context<InitLimitOrderBook>, params: InitLimitOrderBookParams) -> Result<()> {
+        instructions::init_limit_order_book::init_limit_order_book(ctx, &params)
+    }
+
     // Open future position with fixed interest rate
-    pub fn open_future(ctx: Context<OpenFuture>, params: OpenFutureParams) -> Result<()> {
+    pub fn open_future<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenFuture<'info>>,
+        params: OpenFutureParams,
+    ) -> Result<()> {
         instructions::open_future::open_future(ctx, &params)
     }
 
@@ -215,14 +409,39 @@ pub mod option_contract {
         instructions::close_future::close_future(ctx, &params)
     }
 
+    // Preview a future's current mark, pnl and liquidation distance without changing state
+    pub fn get_future_mark(ctx: Context<GetFutureMark>, params: GetFutureMarkParams) -> Result<()> {
+        instructions::get_future_mark::get_future_mark(ctx, &params)
+    }
+
     // Settle expired future (can be called by anyone - keeper pattern)
     pub fn settle_expired_future(ctx: Context<SettleExpiredFuture>, params: SettleExpiredFutureParams) -> Result<()> {
         instructions::settle_expired_future::settle_expired_future(ctx, &params)
     }
 
+    // Freeze the expiry price of a future for later settlement (keeper)
+    pub fn mark_future_expired(ctx: Context<MarkFutureExpired>, params: MarkFutureExpiredParams) -> Result<()> {
+        instructions::mark_future_expired::mark_future_expired(ctx, &params)
+    }
+
     // Claim settlement from expired/liquidated future
     pub fn claim_future(ctx: Context<ClaimFuture>, params: ClaimFutureParams) -> Result<()> {
         instructions::claim_future::claim_future(ctx, &params)
     }
 
+    // Reclaim rent from a settled or liquidated future once its settlement is claimed
+    pub fn close_settled_future(ctx: Context<CloseSettledFuture>, params: CloseSettledFutureParams) -> Result<()> {
+        instructions::close_settled_future::close_settled_future(ctx, &params)
+    }
+
+    // Move a future to a new owner wallet
+    pub fn transfer_future_ownership(ctx: Context<TransferFutureOwnership>, params: TransferFutureOwnershipParams) -> Result<()> {
+        instructions::transfer_future_ownership::transfer_future_ownership(ctx, &params)
+    }
+
+    // Sum pool notional into the global total and run the circuit breaker (permissionless)
+    pub fn sync_global_notional<'info>(ctx: Context<'_, '_, 'info, 'info, SyncGlobalNotional<'info>>) -> Result<()> {
+        instructions::sync_global_notional::sync_global_notional(ctx)
+    }
+
 }
\ No newline at end of file
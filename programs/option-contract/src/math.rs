@@ -1,7 +1,7 @@
 use anchor_lang::prelude::*;
 use std::fmt::Display;
 
-use crate::errors::MathError;
+use crate::{errors::MathError, state::OraclePrice};
 
 pub fn checked_add<T>(arg1: T, arg2: T) -> Result<T>
 where
@@ -400,3 +400,109 @@ pub fn scaled_to_bps(scaled_pct: u64) -> Result<u32> {
 pub fn bps_to_scaled(bps: u32) -> Result<u64> {
     checked_mul(bps as u64, 10_000) // Convert BPS to scaled percentage
 }
+
+// ===== TOKEN <-> USD CONVERSION =====
+// Integer conversions between 6-decimal USD amounts and raw token amounts
+
+/// Converts a 6-decimal USD amount to raw token units at the given oracle price
+pub fn usd_to_token_amount(usd_amount: u64, oracle_price: &OraclePrice, token_decimals: u8) -> Result<u64> {
+    let price_scaled = oracle_price.scale_to_exponent(PRICE_DECIMALS)?;
+    let amount_6_decimals = checked_div(
+        checked_mul(usd_amount as u128, PRICE_SCALE as u128)?,
+        price_scaled.price as u128,
+    )?;
+
+    if token_decimals > 6 {
+        checked_as_u64(checked_mul(
+            amount_6_decimals,
+            checked_pow(10u128, (token_decimals - 6) as usize)?,
+        )?)
+    } else {
+        checked_as_u64(checked_div(
+            amount_6_decimals,
+            checked_pow(10u128, (6 - token_decimals) as usize)?,
+        )?)
+    }
+}
+
+/// Converts raw token units to a 6-decimal USD amount at the given oracle price
+pub fn token_amount_to_usd(token_amount: u64, oracle_price: &OraclePrice, token_decimals: u8) -> Result<u64> {
+    let price_scaled = oracle_price.scale_to_exponent(PRICE_DECIMALS)?;
+    let amount_6_decimals = if token_decimals > 6 {
+        checked_div(
+            token_amount as u128,
+            checked_pow(10u128, (token_decimals - 6) as usize)?,
+        )?
+    } else {
+        checked_mul(
+            token_amount as u128,
+            checked_pow(10u128, (6 - token_decimals) as usize)?,
+        )?
+    };
+
+    checked_as_u64(checked_div(
+        checked_mul(amount_6_decimals, price_scaled.price as u128)?,
+        PRICE_SCALE as u128,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECIMALS: [u8; 3] = [6, 8, 9];
+    // $0.25, $1, $150.50 and $65,000 at the usual Pyth exponent
+    const PRICES: [u64; 4] = [25_000_000, 100_000_000, 15_050_000_000, 6_500_000_000_000];
+
+    #[test]
+    fn token_usd_round_trip_never_rounds_up() {
+        for decimals in DECIMALS {
+            for price in PRICES {
+                let oracle_price = OraclePrice::new(price, -8);
+                let token_amount = 1_000 * 10u64.pow(decimals as u32) + 12_345;
+
+                let usd = token_amount_to_usd(token_amount, &oracle_price, decimals).unwrap();
+                let back = usd_to_token_amount(usd, &oracle_price, decimals).unwrap();
+                assert!(back <= token_amount, "decimals {} price {}", decimals, price);
+                assert!(token_amount - back <= token_amount / 1_000_000, "decimals {} price {}", decimals, price);
+
+                // Going back loses at most the value of the smallest token step kept
+                let step = 10u64.pow(decimals as u32 - 6);
+                let step_usd = token_amount_to_usd(step, &oracle_price, decimals).unwrap();
+                let usd_back = token_amount_to_usd(back, &oracle_price, decimals).unwrap();
+                assert!(usd_back <= usd && usd - usd_back <= step_usd + 1, "decimals {} price {}", decimals, price);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_helpers_match_the_oracle_price_conversions() {
+        for decimals in DECIMALS {
+            for price in PRICES {
+                let oracle_price = OraclePrice::new(price, -8);
+                let token_amount = 7 * 10u64.pow(decimals as u32);
+                let usd_amount = 1_234_567_890;
+
+                let usd = token_amount_to_usd(token_amount, &oracle_price, decimals).unwrap();
+                let expected_usd = oracle_price.get_asset_amount_usd(token_amount, decimals).unwrap();
+                assert!(usd.abs_diff(expected_usd) <= 1, "decimals {} price {}", decimals, price);
+
+                let tokens = usd_to_token_amount(usd_amount, &oracle_price, decimals).unwrap();
+                let expected_tokens = oracle_price.get_token_amount(usd_amount, decimals).unwrap();
+                // 6-decimal intermediate: coarser tokens only lose the digits below a micro-token
+                let unit = 10u64.pow(decimals as u32 - 6);
+                assert!(tokens.abs_diff(expected_tokens) <= unit, "decimals {} price {}", decimals, price);
+            }
+        }
+    }
+
+    #[test]
+    fn whole_tokens_convert_at_the_oracle_price() {
+        for decimals in DECIMALS {
+            let oracle_price = OraclePrice::new(15_050_000_000, -8);
+            let one_token = 10u64.pow(decimals as u32);
+            assert_eq!(token_amount_to_usd(one_token, &oracle_price, decimals).unwrap(), 150_500_000);
+            assert_eq!(usd_to_token_amount(150_500_000, &oracle_price, decimals).unwrap(), one_token);
+        }
+    }
+}
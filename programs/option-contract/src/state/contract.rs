@@ -1,12 +1,22 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Burn, Transfer, MintTo};
 
+use crate::{errors::ContractError, events::GlobalLimitReached, math};
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Contract {
     pub pools: Vec<Pubkey>,
     pub bump: u8,
-    pub transfer_authority_bump:u8
+    pub transfer_authority_bump:u8,
+    // protocol-wide exposure across perps, futures and options (USD, 6 decimals)
+    pub global_notional_usd: u64,
+    pub max_global_notional_usd: u64, // 0 = no ceiling
+    pub paused: bool,                 // blocks every new open
+    pub paused_by_global_limit: bool, // set by the circuit breaker, cleared once notional recedes
+    pub keeper_reward_bps: u64,       // share of borrow fees caught up by update_borrow_fees paid to the keeper
+    pub referral_fee_share_bps: u64,  // share of open fees credited to the referrer named on the open
+    pub liquidator_reward_bps: u64,   // share of the liquidated size paid to the liquidator, 0 = none
 }
 
 impl anchor_lang::Id for Contract {
@@ -15,13 +25,97 @@ impl anchor_lang::Id for Contract {
     }
 }
 
+// USD_SCALE and USD_DECIMALS must describe the same fixed-point scale
+const _: () = assert!(Contract::USD_SCALE == 10u128.pow(Contract::USD_DECIMALS as u32));
+
 impl Contract {
     pub const LEN: usize = 8 + std::mem::size_of::<Contract>();
+
+    /// Account size for a contract listing `pool_count` pools
+    pub fn get_space(pool_count: usize) -> usize {
+        Self::LEN + pool_count * std::mem::size_of::<Pubkey>()
+    }
     pub const BPS_DECIMALS: u8 = 4;
     pub const BPS_POWER: u128 = 10u64.pow(Self::BPS_DECIMALS as u32) as u128;
     pub const USD_DECIMALS:u8 = 6;
+    pub const USD_SCALE: u128 = 1_000_000; // 1 USD in USD_DECIMALS units
     pub const PRICE_DECIMALS:u8 =6;
     pub const LP_DECIMALS:u8 = 6;
+    pub const MAX_KEEPER_REWARD_BPS: u64 = 1_000; // 10%
+    pub const MAX_REFERRAL_FEE_SHARE_BPS: u64 = 5_000; // 50%
+    pub const MAX_LIQUIDATOR_REWARD_BPS: u64 = 100; // 1%
+    // Keeper batch instructions pass a handful of accounts per item (position, custodies,
+    // oracles, receiving account), so 8 items stays within both the 64-account transaction
+    // limit and the default 200k compute units with headroom for oracle reads.
+    pub const MAX_BATCH_SIZE: usize = 8;
+    // compute units kept back so a batch stops cleanly before the budget runs out
+    pub const BATCH_ITEM_COMPUTE_RESERVE: u64 = 25_000;
+
+    /// Keeper share of a borrow fee catch-up, in USD
+    pub fn get_keeper_reward(&self, borrow_fee_usd: u64) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(borrow_fee_usd as u128, self.keeper_reward_bps as u128)?,
+            Self::BPS_POWER,
+        )?)
+    }
+
+    /// Liquidator reward for liquidating `size_usd` of perp notional, in USD
+    pub fn get_liquidator_reward(&self, size_usd: u64) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(size_usd as u128, self.liquidator_reward_bps as u128)?,
+            Self::BPS_POWER,
+        )?)
+    }
+
+    /// Referrer share of a trade or premium fee, in USD
+    pub fn get_referral_fee(&self, fee_usd: u64) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(fee_usd as u128, self.referral_fee_share_bps as u128)?,
+            Self::BPS_POWER,
+        )?)
+    }
+
+    /// Reject batches larger than MAX_BATCH_SIZE up front instead of failing mid-loop
+    pub fn check_batch_size(len: usize) -> Result<()> {
+        require!(len > 0, ContractError::EmptyBatch);
+        require!(len <= Self::MAX_BATCH_SIZE, ContractError::BatchTooLarge);
+        Ok(())
+    }
+
+    /// Whether there is enough compute left to process another batch item.
+    /// Batch loops break on false and report the remainder as skipped.
+    pub fn has_batch_budget() -> bool {
+        anchor_lang::solana_program::compute_units::sol_remaining_compute_units()
+            >= Self::BATCH_ITEM_COMPUTE_RESERVE
+    }
+
+    /// Store the exposure summed over every pool by sync_global_notional. Reaching the ceiling
+    /// trips the breaker, which blocks opens until a later sync finds the total back under it.
+    pub fn set_global_notional(&mut self, global_notional_usd: u64) -> Result<()> {
+        self.global_notional_usd = global_notional_usd;
+
+        if self.max_global_notional_usd > 0
+            && self.global_notional_usd >= self.max_global_notional_usd
+            && !self.paused
+        {
+            self.paused = true;
+            self.paused_by_global_limit = true;
+            msg!("Global notional limit reached, new opens paused");
+            emit!(GlobalLimitReached {
+                global_notional_usd: self.global_notional_usd,
+                max_global_notional_usd: self.max_global_notional_usd,
+                timestamp: self.get_time()?,
+            });
+        } else if self.paused_by_global_limit
+            && self.global_notional_usd < self.max_global_notional_usd
+        {
+            self.paused = false;
+            self.paused_by_global_limit = false;
+            msg!("Global notional back under the limit, opens resumed");
+        }
+        Ok(())
+    }
+
     pub fn is_empty_account(account_info: &AccountInfo) -> Result<bool> {
         Ok(account_info.try_data_is_empty()? || account_info.try_lamports()? == 0)
     }
@@ -44,13 +138,14 @@ impl Contract {
         anchor_spl::token::close_account(cpi_context.with_signer(seeds))
     }
 
+    /// Raw clock read, for timestamps outside any pool. Elapsed-time math on pool state goes
+    /// through Pool::get_time, which guards against a clock going backwards.
     pub fn get_time(&self) -> Result<i64> {
         let current_timestamp = Clock::get().unwrap().unix_timestamp;
-        if current_timestamp > 0 {
-            Ok(current_timestamp)
-        } else {
-            Err(ProgramError::InvalidAccountData.into())
+        if current_timestamp <= 0 {
+            return Err(ProgramError::InvalidAccountData.into());
         }
+        Ok(current_timestamp)
     }
 
     pub fn transfer_tokens<'info>(
@@ -140,6 +235,31 @@ impl Contract {
         anchor_spl::token::burn(context, amount)
     }
 
+    /// Burn tokens held by the transfer authority
+    pub fn burn_owned_tokens<'info>(
+        &self,
+        mint: AccountInfo<'info>,
+        from: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        token_program: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        let authority_seeds: &[&[&[u8]]] =
+            &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
+
+        let context = CpiContext::new(
+            token_program,
+            Burn {
+                mint,
+                from,
+                authority,
+            },
+        )
+        .with_signer(authority_seeds);
+
+        anchor_spl::token::burn(context, amount)
+    }
+
     pub fn transfer_sol_from_owned<'a>(
         program_owned_source_account: AccountInfo<'a>,
         destination_account: AccountInfo<'a>,
@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::math;
+use crate::{errors::{ContractError, PoolError}, math, state::{OraclePrice, Position}};
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct Fees {
@@ -10,6 +10,26 @@ pub struct Fees {
     pub remove_liquidity: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct CloseFeeTier {
+    pub min_remaining_sec: i64, // tier applies when at least this much time is left to expiry
+    pub fee_bps: u64,           // taken off the refund when closing
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct MarginTier {
+    pub min_size_usd: u64,           // tier applies to perp positions at least this large
+    pub maintenance_margin_bps: u64, // equity share of size below which the position is liquidatable
+}
+
+/// Product holding a share of `Custody::token_locked`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LockedProduct {
+    Perp,
+    Option,
+    Future,
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Custody {
@@ -18,17 +38,162 @@ pub struct Custody {
     pub mint: Pubkey,
     pub token_account: Pubkey,
     pub decimals: u8,
-    pub oracle: Pubkey,
+    pub oracle: Pubkey, // primary oracle
     pub token_owned : u64,
     pub token_locked : u64,
     pub fees: Fees, // Maintaining token ratio constant
     // bumps for address validation
     pub bump: u8,
     pub token_account_bump: u8,
+
+    // Fields below were appended after the first release; custodies created before them are
+    // grown with migrate_account, which zero-fills the tail
+    pub oracle_secondary: Pubkey, // failover oracle, default = none
+    pub oracle_type_primary: u8,
+    pub oracle_type_secondary: u8,
+    pub reserved_for_settlement: u64, // tokens opens must leave free so closes can always pay out
+    pub reserved_for_withdrawals: u64, // tokens held back for queued LP withdrawals
+    // option premium spread around Black-Scholes fair value
+    pub option_buy_markup_bps: u64,     // added to the premium when buying
+    pub option_sell_markdown_bps: u64,  // taken off the refund when closing/editing
+    // close_option fee curve by time-to-expiry, sorted ascending; falls back to the markdown when empty
+    pub option_close_fee_tiers: [CloseFeeTier; Custody::MAX_CLOSE_FEE_TIERS],
+    pub option_close_fee_tier_count: u8,
+    // set by the admin to wind an asset down: blocks new opens and deposits, not exits
+    pub trading_paused: bool,
+    // liquidity fees shared out by epoch rather than through the LP price; the tokens sit in the
+    // custody token account outside token_owned (see Pool::lp_fee_epoch_duration_sec)
+    pub lp_fee_epoch: u64,         // epoch lp_fee_epoch_pot is collecting for, 0 = not started
+    pub lp_fee_epoch_pot: u64,     // fees collected in lp_fee_epoch
+    pub lp_fee_reserve: u64,       // fees of closed epochs not yet claimed
+    // cumulative fee per LP token at the end of each recent epoch, by epoch % LP_FEE_EPOCH_HISTORY
+    pub lp_fee_per_lp: [u128; Custody::LP_FEE_EPOCH_HISTORY as usize],
+    // token_locked split by the product holding it; locks taken before the split was tracked
+    // are only counted once reconcile_custody_locked has run
+    pub token_locked_perp: u64,
+    pub token_locked_option: u64,
+    pub token_locked_future: u64,
+    // perp maintenance margin by position size, sorted ascending; falls back to
+    // Position::LIQUIDATION_MARGIN_BPS when empty
+    pub margin_tiers: [MarginTier; Custody::MAX_MARGIN_TIERS],
+    pub margin_tier_count: u8,
+    // queued LP withdrawals are filled in request order (see WithdrawalRequest)
+    pub withdrawal_queue_head: u64, // queue_index of the oldest request not yet closed
+    pub withdrawal_queue_tail: u64, // queue_index the next request gets
+
+    // Unused space for fields added later, so they fit without another migration
+    pub reserved: [u64; Custody::RESERVED_WORDS],
 }
 
 impl Custody {
     pub const LEN: usize = 8 + std::mem::size_of::<Custody>();
+    // Size of accounts created by the first release, which migrate_account grows to LEN
+    pub const RELEASED_LEN: usize = 152;
+    pub const RESERVED_WORDS: usize = 14;
+    pub const ORACLE_TYPE_PYTH: u8 = 0;
+    pub const MAX_OPTION_SPREAD_BPS: u64 = 5_000; // 50%
+    pub const DEFAULT_OPTION_SELL_MARKDOWN_BPS: u64 = 1_000; // 10%, the former flat platform fee
+    pub const MAX_CLOSE_FEE_TIERS: usize = 4;
+    pub const MAX_MARGIN_TIERS: usize = 4;
+    pub const MAX_MAINTENANCE_MARGIN_BPS: u64 = 1_000; // 10%
+    pub const LP_FEE_EPOCH_HISTORY: u64 = 16; // closed epochs an LP can still claim for
+    pub const LP_FEE_PER_LP_SCALE: u128 = 1_000_000_000_000;
+
+    /// Premium charged to a buyer: fair value plus the buy markup
+    pub fn apply_option_buy_markup(&self, fair_value: f64) -> Result<f64> {
+        math::checked_float_mul(
+            fair_value,
+            1.0 + self.option_buy_markup_bps as f64 / 10_000.0,
+        )
+    }
+
+    /// Refund paid to a seller: token amount less the sell markdown
+    pub fn apply_option_sell_markdown(&self, refund_amount: u64) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                refund_amount as u128,
+                math::checked_sub(10_000u128, self.option_sell_markdown_bps as u128)?,
+            )?,
+            10_000u128,
+        )?)
+    }
+
+    /// Close fee for an option with `remaining_seconds` left: the tier with the largest
+    /// threshold not above the remaining time, or the flat sell markdown if no tiers are set
+    pub fn get_option_close_fee_bps(&self, remaining_seconds: i64) -> u64 {
+        let tier_count = (self.option_close_fee_tier_count as usize).min(Self::MAX_CLOSE_FEE_TIERS);
+        if tier_count == 0 {
+            return self.option_sell_markdown_bps;
+        }
+
+        let mut fee_bps = self.option_close_fee_tiers[0].fee_bps;
+        for tier in self.option_close_fee_tiers[..tier_count].iter() {
+            if remaining_seconds >= tier.min_remaining_sec {
+                fee_bps = tier.fee_bps;
+            }
+        }
+        fee_bps
+    }
+
+    /// Replace the margin tiers. Tiers must be sorted by size, and larger positions never get
+    /// a looser margin than smaller ones.
+    ///
+    /// Open positions keep the liquidation_price stored at their last open, resize or
+    /// collateral change until they are touched again. liquidate checks the margin at the
+    /// current tier too, so a stricter tier applies at once, while a looser one only moves a
+    /// position's liquidation price on its next touch.
+    pub fn set_margin_tiers(&mut self, tiers: &[MarginTier]) -> Result<()> {
+        require!(
+            tiers.len() <= Self::MAX_MARGIN_TIERS,
+            PoolError::InvalidCustodyConfig
+        );
+        for (idx, tier) in tiers.iter().enumerate() {
+            require!(
+                tier.maintenance_margin_bps >= Position::LIQUIDATION_MARGIN_BPS
+                    && tier.maintenance_margin_bps <= Self::MAX_MAINTENANCE_MARGIN_BPS,
+                PoolError::InvalidCustodyConfig
+            );
+            if idx > 0 {
+                require!(
+                    tier.min_size_usd > tiers[idx - 1].min_size_usd
+                        && tier.maintenance_margin_bps >= tiers[idx - 1].maintenance_margin_bps,
+                    PoolError::InvalidCustodyConfig
+                );
+            }
+        }
+
+        self.margin_tiers = [MarginTier::default(); Self::MAX_MARGIN_TIERS];
+        self.margin_tiers[..tiers.len()].copy_from_slice(tiers);
+        self.margin_tier_count = tiers.len() as u8;
+        Ok(())
+    }
+
+    /// Maintenance margin for a perp of `size_usd`: the tier with the largest size threshold
+    /// not above the size, or the flat liquidation margin if no tiers are set
+    pub fn get_maintenance_margin_bps(&self, size_usd: u64) -> u64 {
+        let tier_count = (self.margin_tier_count as usize).min(Self::MAX_MARGIN_TIERS);
+        let mut margin_bps = Position::LIQUIDATION_MARGIN_BPS;
+        for tier in self.margin_tiers[..tier_count].iter() {
+            if size_usd >= tier.min_size_usd {
+                margin_bps = tier.maintenance_margin_bps;
+            }
+        }
+        margin_bps
+    }
+
+    /// Refund paid when closing an option early, less the time-to-expiry close fee
+    pub fn apply_option_close_fee(&self, refund_amount: u64, remaining_seconds: i64) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                refund_amount as u128,
+                math::checked_sub(
+                    10_000u128,
+                    self.get_option_close_fee_bps(remaining_seconds) as u128,
+                )?,
+            )?,
+            10_000u128,
+        )?)
+    }
 
     pub fn validate(&self) -> bool {
         self.token_account != Pubkey::default()
@@ -36,8 +201,243 @@ impl Custody {
             && self.oracle != Pubkey::default()
     }
 
-    pub fn lock_funds(&mut self, amount: u64) -> Result<()> {
+    /// Price of the custody asset from the primary oracle, failing over to the secondary
+    /// oracle when one is configured and its account was supplied.
+    pub fn get_oracle_price(
+        &self,
+        primary: &AccountInfo,
+        secondary: Option<&AccountInfo>,
+        current_time: i64,
+    ) -> Result<OraclePrice> {
+        self.read_oracle_price(primary, secondary, current_time, false)
+    }
+
+    /// EMA price of the custody asset, with the same oracle failover as get_oracle_price
+    pub fn get_oracle_ema_price(
+        &self,
+        primary: &AccountInfo,
+        secondary: Option<&AccountInfo>,
+        current_time: i64,
+    ) -> Result<OraclePrice> {
+        self.read_oracle_price(primary, secondary, current_time, true)
+    }
+
+    /// Price of the custody asset published at `target_time`, within
+    /// OraclePrice::SETTLEMENT_PRICE_WINDOW_SEC, read from `price_update` when one is posted
+    /// for it or else with the same oracle failover as get_oracle_price
+    pub fn get_oracle_price_at(
+        &self,
+        primary: &AccountInfo,
+        secondary: Option<&AccountInfo>,
+        price_update: Option<&AccountInfo>,
+        target_time: i64,
+    ) -> Result<OraclePrice> {
+        let secondary = self.get_secondary_oracle(primary, secondary)?;
+        OraclePrice::new_from_oracle_at_with_failover(
+            (primary, self.oracle_type_primary),
+            secondary,
+            price_update,
+            target_time,
+        )
+    }
+
+    fn read_oracle_price(
+        &self,
+        primary: &AccountInfo,
+        secondary: Option<&AccountInfo>,
+        current_time: i64,
+        use_ema: bool,
+    ) -> Result<OraclePrice> {
+        let secondary = self.get_secondary_oracle(primary, secondary)?;
+        OraclePrice::new_from_oracle_with_failover(
+            (primary, self.oracle_type_primary),
+            secondary,
+            current_time,
+            use_ema,
+        )
+    }
+
+    /// Check the oracle accounts passed against the custody's, returning the failover to use
+    fn get_secondary_oracle<'a, 'info>(
+        &self,
+        primary: &AccountInfo,
+        secondary: Option<&'a AccountInfo<'info>>,
+    ) -> Result<Option<(&'a AccountInfo<'info>, u8)>> {
+        require_keys_eq!(primary.key(), self.oracle, ContractError::InvalidOracleAccount);
+
+        let secondary = match secondary {
+            Some(oracle_account) if self.oracle_secondary != Pubkey::default() => {
+                require_keys_eq!(
+                    oracle_account.key(),
+                    self.oracle_secondary,
+                    ContractError::InvalidOracleAccount
+                );
+                Some((oracle_account, self.oracle_type_secondary))
+            }
+            _ => None,
+        };
+        Ok(secondary)
+    }
+
+    /// Tokens a new open may lock: owned minus locked minus the settlement and withdrawal reserves
+    pub fn available_for_open(&self) -> u64 {
+        self.available_for_open_after_release(0)
+    }
+
+    /// available_for_open once `released_amount` of token_locked is given back, for a lock
+    /// that is replaced rather than added to
+    pub fn available_for_open_after_release(&self, released_amount: u64) -> u64 {
+        self.token_owned
+            .saturating_sub(self.token_locked.saturating_sub(released_amount))
+            .saturating_sub(self.reserved_for_settlement)
+            .saturating_sub(self.reserved_for_withdrawals)
+    }
+
+    /// Cumulative fee per LP token (scaled by LP_FEE_PER_LP_SCALE) at the end of closed epoch
+    /// `epoch`. Epochs older than the history read as the oldest kept, so they earn nothing.
+    pub fn get_lp_fee_per_lp(&self, epoch: u64) -> u128 {
+        let epoch = epoch.max(self.lp_fee_epoch.saturating_sub(Self::LP_FEE_EPOCH_HISTORY));
+        self.lp_fee_per_lp[(epoch % Self::LP_FEE_EPOCH_HISTORY) as usize]
+    }
+
+    /// Close the epoch being collected once `current_epoch` has moved past it: its pot is
+    /// split over `lp_supply` and moves to the reserve, and the cumulative fee per LP token is
+    /// carried through any epochs that passed without activity. With no LP supply the pot
+    /// keeps collecting until there is someone to pay.
+    pub fn advance_lp_fee_epoch(&mut self, current_epoch: u64, lp_supply: u64) -> Result<()> {
+        if self.lp_fee_epoch == 0 {
+            self.lp_fee_epoch = current_epoch;
+            return Ok(());
+        }
+        if current_epoch <= self.lp_fee_epoch || lp_supply == 0 {
+            return Ok(());
+        }
+
+        let closed_fee_per_lp = math::checked_add(
+            self.get_lp_fee_per_lp(self.lp_fee_epoch - 1),
+            math::checked_div(
+                math::checked_mul(self.lp_fee_epoch_pot as u128, Self::LP_FEE_PER_LP_SCALE)?,
+                lp_supply as u128,
+            )?,
+        )?;
+        let first_epoch = self
+            .lp_fee_epoch
+            .max(current_epoch.saturating_sub(Self::LP_FEE_EPOCH_HISTORY));
+        for epoch in first_epoch..current_epoch {
+            self.lp_fee_per_lp[(epoch % Self::LP_FEE_EPOCH_HISTORY) as usize] = closed_fee_per_lp;
+        }
+
+        self.lp_fee_reserve = math::checked_add(self.lp_fee_reserve, self.lp_fee_epoch_pot)?;
+        self.lp_fee_epoch_pot = 0;
+        self.lp_fee_epoch = current_epoch;
+        Ok(())
+    }
+
+    /// Collect a liquidity fee into the pot of `current_epoch`
+    pub fn accrue_lp_fee(&mut self, fee_amount: u64, current_epoch: u64, lp_supply: u64) -> Result<()> {
+        self.advance_lp_fee_epoch(current_epoch, lp_supply)?;
+        self.lp_fee_epoch_pot = math::checked_add(self.lp_fee_epoch_pot, fee_amount)?;
+        Ok(())
+    }
+
+    /// Tokens a close or collateral withdrawal may pay out: owned minus locked minus the
+    /// withdrawal reserve. The settlement reserve exists for payouts, so it is not held back.
+    pub fn available_for_payout(&self) -> u64 {
+        self.token_owned
+            .saturating_sub(self.token_locked)
+            .saturating_sub(self.reserved_for_withdrawals)
+    }
+
+    /// assert_invariants on every custody an instruction mutated, called at the end of each
+    /// instruction. A no-op unless the `invariant-checks` feature is enabled.
+    pub fn check_invariants(custodies: &[&Custody]) -> Result<()> {
+        #[cfg(feature = "invariant-checks")]
+        for custody in custodies {
+            custody.assert_invariants()?;
+        }
+        #[cfg(not(feature = "invariant-checks"))]
+        let _ = custodies;
+        Ok(())
+    }
+
+    /// Accounting invariants every custody mutation must preserve: nothing is locked beyond
+    /// what the custody owns, and no balance wrapped around (which would overflow the sum).
+    pub fn assert_invariants(&self) -> Result<()> {
+        require_gte!(
+            self.token_owned,
+            self.token_locked,
+            PoolError::CustodyInvariantViolated
+        );
+        self.token_locked
+            .checked_add(self.reserved_for_settlement)
+            .and_then(|total| total.checked_add(self.reserved_for_withdrawals))
+            .ok_or(PoolError::CustodyInvariantViolated)?;
+        require_gte!(
+            self.token_locked,
+            self.get_locked_by_products()?,
+            PoolError::CustodyInvariantViolated
+        );
+        Ok(())
+    }
+
+    /// Tokens locked by `product`
+    pub fn get_locked(&self, product: LockedProduct) -> u64 {
+        match product {
+            LockedProduct::Perp => self.token_locked_perp,
+            LockedProduct::Option => self.token_locked_option,
+            LockedProduct::Future => self.token_locked_future,
+        }
+    }
+
+    /// Sum of the per-product locked counters
+    pub fn get_locked_by_products(&self) -> Result<u64> {
+        math::checked_add(
+            math::checked_add(self.token_locked_perp, self.token_locked_option)?,
+            self.token_locked_future,
+        )
+    }
+
+    fn get_locked_mut(&mut self, product: LockedProduct) -> &mut u64 {
+        match product {
+            LockedProduct::Perp => &mut self.token_locked_perp,
+            LockedProduct::Option => &mut self.token_locked_option,
+            LockedProduct::Future => &mut self.token_locked_future,
+        }
+    }
+
+    /// Lock `amount` for `product`
+    pub fn add_locked(&mut self, product: LockedProduct, amount: u64) -> Result<()> {
         self.token_locked = math::checked_add(self.token_locked, amount)?;
+        let locked = self.get_locked_mut(product);
+        *locked = math::checked_add(*locked, amount)?;
+        Ok(())
+    }
+
+    /// Release `amount` locked by `product`. The product counter saturates, as it misses
+    /// locks taken before it was tracked.
+    pub fn remove_locked(&mut self, product: LockedProduct, amount: u64) -> Result<()> {
+        self.token_locked = math::checked_sub(self.token_locked, amount)?;
+        let locked = self.get_locked_mut(product);
+        *locked = locked.saturating_sub(amount);
+        Ok(())
+    }
+
+    /// Settle an option buyback: release the option's `unlock_amount` first so the refund can
+    /// draw on it, then pay `refund_amount` out of what is free. A zero refund always passes,
+    /// so a worthless option can always be closed.
+    pub fn release_option_refund(&mut self, unlock_amount: u64, refund_amount: u64) -> Result<()> {
+        self.remove_locked(LockedProduct::Option, unlock_amount)?;
+        require_gte!(
+            self.token_owned.saturating_sub(self.token_locked),
+            refund_amount,
+            PoolError::InvalidPoolBalanceError
+        );
+        self.token_owned = math::checked_sub(self.token_owned, refund_amount)?;
+        Ok(())
+    }
+
+    pub fn lock_funds(&mut self, product: LockedProduct, amount: u64) -> Result<()> {
+        self.add_locked(product, amount)?;
         if self.token_owned < self.token_locked {
             Err(ProgramError::InsufficientFunds.into())
         } else {
@@ -45,13 +445,136 @@ impl Custody {
         }
     }
 
-    pub fn unlock_funds(&mut self, amount: u64) -> Result<()> {
-        if amount > self.token_locked {
-            self.token_locked = 0;
-        } else {
-            self.token_locked = math::checked_sub(self.token_locked, amount)?;
-        }
+    pub fn unlock_funds(&mut self, product: LockedProduct, amount: u64) -> Result<()> {
+        let amount = amount.min(self.token_locked);
+        self.remove_locked(product, amount)
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(min_size_usd: u64, maintenance_margin_bps: u64) -> MarginTier {
+        MarginTier { min_size_usd, maintenance_margin_bps }
+    }
+
+    #[test]
+    fn untiered_custody_uses_the_flat_margin() {
+        let custody = Custody::default();
+        assert_eq!(custody.get_maintenance_margin_bps(0), Position::LIQUIDATION_MARGIN_BPS);
+        assert_eq!(custody.get_maintenance_margin_bps(u64::MAX), Position::LIQUIDATION_MARGIN_BPS);
+    }
+
+    #[test]
+    fn margin_tier_is_picked_by_position_size() {
+        let base = Position::LIQUIDATION_MARGIN_BPS;
+        let mut custody = Custody::default();
+        custody
+            .set_margin_tiers(&[tier(0, base), tier(100_000_000_000, base + 100), tier(1_000_000_000_000, base + 300)])
+            .unwrap();
+
+        assert_eq!(custody.get_maintenance_margin_bps(1_000_000), base);
+        assert_eq!(custody.get_maintenance_margin_bps(99_999_999_999), base);
+        // Thresholds are inclusive
+        assert_eq!(custody.get_maintenance_margin_bps(100_000_000_000), base + 100);
+        assert_eq!(custody.get_maintenance_margin_bps(999_999_999_999), base + 100);
+        assert_eq!(custody.get_maintenance_margin_bps(1_000_000_000_000), base + 300);
+
+        // Sizes below the first threshold fall back to the flat margin
+        custody.set_margin_tiers(&[tier(50_000_000, base + 50)]).unwrap();
+        assert_eq!(custody.get_maintenance_margin_bps(10_000_000), base);
+        assert_eq!(custody.get_maintenance_margin_bps(50_000_000), base + 50);
+
+        // Clearing the tiers restores the flat margin
+        custody.set_margin_tiers(&[]).unwrap();
+        assert_eq!(custody.get_maintenance_margin_bps(1_000_000_000_000), base);
+    }
+
+    #[test]
+    fn margin_tiers_must_tighten_with_size() {
+        let base = Position::LIQUIDATION_MARGIN_BPS;
+        let mut custody = Custody::default();
+        // Looser margin for a larger size
+        assert!(custody.set_margin_tiers(&[tier(0, base + 100), tier(1_000, base)]).is_err());
+        // Unsorted sizes
+        assert!(custody.set_margin_tiers(&[tier(1_000, base), tier(1_000, base + 100)]).is_err());
+        // Outside the allowed margin range
+        assert!(custody.set_margin_tiers(&[tier(0, base - 1)]).is_err());
+        assert!(custody.set_margin_tiers(&[tier(0, Custody::MAX_MAINTENANCE_MARGIN_BPS + 1)]).is_err());
+        // Too many tiers
+        let tiers: Vec<_> = (0..=Custody::MAX_MARGIN_TIERS as u64).map(|i| tier(i, base)).collect();
+        assert!(custody.set_margin_tiers(&tiers).is_err());
+        assert_eq!(custody.margin_tier_count, 0);
+    }
+
+    #[test]
+    fn invariants_hold_through_a_balanced_sequence() {
+        let mut custody = Custody { token_owned: 1_000, ..Default::default() };
+        custody.lock_funds(LockedProduct::Perp, 400).unwrap();
+        custody.lock_funds(LockedProduct::Option, 300).unwrap();
+        custody.unlock_funds(LockedProduct::Perp, 400).unwrap();
+        custody.token_owned -= 300; // withdrawal of the free balance
+        custody.assert_invariants().unwrap();
+        Custody::check_invariants(&[&custody]).unwrap();
+    }
+
+    #[test]
+    fn invariant_violations_are_caught() {
+        // A withdrawal that leaves less owned than locked
+        let mut custody = Custody { token_owned: 1_000, ..Default::default() };
+        custody.lock_funds(LockedProduct::Future, 800).unwrap();
+        custody.token_owned -= 300;
+        assert!(custody.assert_invariants().is_err());
+
+        // A lock that skips the product counter and a release that only touches it
+        let mut custody = Custody { token_owned: 1_000, ..Default::default() };
+        custody.lock_funds(LockedProduct::Perp, 500).unwrap();
+        custody.token_locked -= 200;
+        assert!(custody.assert_invariants().is_err());
+
+        // Reserves that would wrap the sum with the locked balance
+        let custody = Custody {
+            token_owned: u64::MAX,
+            token_locked: 1,
+            token_locked_perp: 1,
+            reserved_for_settlement: u64::MAX,
+            ..Default::default()
+        };
+        assert!(custody.assert_invariants().is_err());
+        // Enforced at the end of instructions only with the feature on
+        assert_eq!(Custody::check_invariants(&[&custody]).is_err(), cfg!(feature = "invariant-checks"));
+    }
+
+    #[test]
+    fn worthless_option_closes_and_releases_its_lock() {
+        use crate::{state::{ExerciseStyle, OptionDetail}, utils::black_scholes_with_borrow_rate};
+
+        // Fully utilized: everything owned is locked, half of it by the option being closed
+        let mut custody = Custody { token_owned: 2_000_000_000, decimals: 9, ..Default::default() };
+        custody.lock_funds(LockedProduct::Perp, 1_000_000_000).unwrap();
+        custody.lock_funds(LockedProduct::Option, 1_000_000_000).unwrap();
+
+        // Deep out of the money call an hour from expiry
+        let remaining_seconds = 3_600;
+        let value_per_contract = black_scholes_with_borrow_rate(
+            50.0, 200.0, remaining_seconds as f64 / 31_536_000.0, true, 0, 1, true, ExerciseStyle::American,
+        )
+        .unwrap();
+        let refund_raw =
+            OptionDetail::get_locked_refund_amount(value_per_contract * 10.0, &OraclePrice::new(100_000_000, -8), 9)
+                .unwrap();
+        let refund = custody.apply_option_close_fee(refund_raw, remaining_seconds).unwrap();
+        assert!(refund <= 1);
+
+        custody.release_option_refund(1_000_000_000, refund).unwrap();
+        assert_eq!(custody.token_locked, 1_000_000_000);
+        assert_eq!(custody.token_locked_option, 0);
+        assert_eq!(custody.token_owned, 2_000_000_000 - refund);
+
+        // A refund beyond the freed balance is still refused
+        let mut custody = Custody { token_owned: 1_000, ..Default::default() };
+        custody.lock_funds(LockedProduct::Option, 1_000).unwrap();
+        assert_eq!(custody.release_option_refund(400, 401), Err(PoolError::InvalidPoolBalanceError.into()));
     }
 }
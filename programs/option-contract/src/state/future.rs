@@ -1,8 +1,9 @@
-use crate::{math, state::perpetuals::Side};
+use crate::{errors::PoolError, math, state::{perpetuals::Side, Contract}};
 use anchor_lang::prelude::*;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
 pub enum FutureStatus {
+    #[default]
     Pending,     // Limit order waiting for execution
     Active,      // Future is active and tradeable
     Expired,     // Future has expired, awaiting settlement
@@ -10,11 +11,6 @@ pub enum FutureStatus {
     Liquidated,  // Future was liquidated before expiry
 }
 
-impl Default for FutureStatus {
-    fn default() -> Self {
-        Self::Pending
-    }
-}
 
 #[account]
 #[derive(Default, Debug)]
@@ -71,10 +67,23 @@ pub struct Future {
     
     // Metadata
     pub bump: u8,
+
+    // Fields below were appended after the first release; futures created before them are
+    // grown with migrate_account, which zero-fills the tail
+    pub settlement_custody: Pubkey,          // Asset paid out on close/settlement, chosen at open (default = collateral custody)
+
+    // Referral attribution set at open
+    pub referrer: Option<Pubkey>,
+
+    // LP collateral, held in the pool's LP collateral vault instead of a custody
+    pub lp_collateral_amount: u64,           // LP tokens posted, returned on close or settlement
+    pub lp_collateral_usd: u64,              // Their USD value at deposit, before the opening fee
 }
 
 impl Future {
     pub const LEN: usize = 8 + std::mem::size_of::<Future>() + 16; // Extra padding for Option fields
+    // close_percentage scale: FULL_CLOSE = 100%, so 1_000_000 = 1%
+    pub const FULL_CLOSE: u64 = 100_000_000;
     
     // Maximum leverage for futures (lower than perps due to expiry risk)
     pub const MAX_LEVERAGE: f64 = 250.0;
@@ -82,7 +91,43 @@ impl Future {
     pub const MAINTENANCE_MARGIN_BPS: u64 = 20;   // 0.5% maintenance margin
     pub const OPENING_FEE_BPS: u64 = 10;           // 0.1% opening fee
     pub const SETTLEMENT_FEE_BPS: u64 = 5;         // 0.05% settlement fee
+
+    /// `close_percentage` as a percentage for logs, 100.0 for FULL_CLOSE
+    pub fn close_percentage_display(close_percentage: u64) -> f64 {
+        close_percentage as f64 * 100.0 / Self::FULL_CLOSE as f64
+    }
+
+    /// Share of `value` closed by `close_percentage`, all of it on a full close
+    pub fn get_close_portion(value: u64, close_percentage: u64) -> Result<u64> {
+        if close_percentage == Self::FULL_CLOSE {
+            return Ok(value);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(value as u128, close_percentage as u128)?,
+            Self::FULL_CLOSE as u128,
+        )?)
+    }
+
+    /// Custody paid out on close and settlement. Futures opened before the choice was stored
+    /// settle in their collateral asset, as they did then.
+    pub fn get_settlement_custody(&self) -> Pubkey {
+        if self.settlement_custody == Pubkey::default() {
+            self.collateral_custody
+        } else {
+            self.settlement_custody
+        }
+    }
     
+    /// Whether the collateral recorded at open is the SOL custody passed in. It must be one of
+    /// the two, so a mismatched account set can't credit the other asset.
+    pub fn is_sol_collateral(&self, sol_custody: &Pubkey, usdc_custody: &Pubkey) -> Result<bool> {
+        require!(
+            self.collateral_custody == *sol_custody || self.collateral_custody == *usdc_custody,
+            PoolError::InvalidCollateralCustody
+        );
+        Ok(self.collateral_custody == *sol_custody)
+    }
+
     /// Calculate theoretical future price using F = S * exp(r * T)
     pub fn calculate_theoretical_price(
         spot_price: f64,
@@ -121,9 +166,9 @@ impl Future {
         let exp_rt_1 = (r * t_1).exp();
         
         // Convert prices to f64 for calculation
-        let p_e = (self.entry_price as f64) / 1_000_000.0;
-        let p_m = (current_spot_price as f64) / 1_000_000.0;
-        let size = (self.size_usd as f64) / 1_000_000.0;
+        let p_e = (self.entry_price as f64) / math::PRICE_SCALE as f64;
+        let p_m = (current_spot_price as f64) / math::PRICE_SCALE as f64;
+        let size = (self.size_usd as f64) / Contract::USD_SCALE as f64;
         
         // Calculate PNL based on side
         let pnl_usd = match self.side {
@@ -140,7 +185,7 @@ impl Future {
         };
         
         // Convert back to scaled integer
-        Ok((pnl_usd * 1_000_000.0) as i64)
+        Ok((pnl_usd * Contract::USD_SCALE as f64) as i64)
     }
     
     /// Check if future should be liquidated based on maintenance margin
@@ -155,12 +200,7 @@ impl Future {
         let current_equity = if pnl >= 0 {
             self.collateral_usd + (pnl as u64)
         } else {
-            let loss = (-pnl) as u64;
-            if loss >= self.collateral_usd {
-                0 // Insolvent
-            } else {
-                self.collateral_usd - loss
-            }
+            self.collateral_usd.saturating_sub((-pnl) as u64) // 0 = insolvent
         };
         
         // Calculate maintenance margin requirement
@@ -190,9 +230,9 @@ impl Future {
         let exp_ratio = exp_rt_0 / exp_rt_1;
         
         // Convert to f64 for calculation
-        let p_e = (self.entry_price as f64) / 1_000_000.0;
-        let size = (self.size_usd as f64) / 1_000_000.0;
-        let collateral = (self.collateral_usd as f64) / 1_000_000.0;
+        let p_e = (self.entry_price as f64) / math::PRICE_SCALE as f64;
+        let size = (self.size_usd as f64) / Contract::USD_SCALE as f64;
+        let collateral = (self.collateral_usd as f64) / Contract::USD_SCALE as f64;
         
         // Calculate close fee (settlement fee)
         let close_fee = size * (Self::SETTLEMENT_FEE_BPS as f64) / 10_000.0;
@@ -218,7 +258,7 @@ impl Future {
         };
         
         // Convert back to scaled integer (ensure positive)
-        let p_liq_scaled = (p_liq * 1_000_000.0).max(0.0) as u64;
+        let p_liq_scaled = (p_liq * math::PRICE_SCALE as f64).max(0.0) as u64;
         Ok(p_liq_scaled)
     }
     
@@ -273,8 +313,8 @@ impl Future {
         Ok(settlement_amount)
     }
     
-    /// Mark future as expired
-    pub fn mark_expired(&mut self, current_time: i64) -> Result<()> {
+    /// Mark future as expired, freezing the spot price observed at expiry for settlement
+    pub fn mark_expired(&mut self, expiry_spot_price: u64, current_time: i64) -> Result<()> {
         require!(
             self.is_expired(current_time),
             crate::errors::FutureError::FutureNotYetExpired
@@ -285,6 +325,7 @@ impl Future {
         );
         
         self.status = FutureStatus::Expired;
+        self.settlement_price = Some(expiry_spot_price);
         self.update_time = current_time;
         
         Ok(())
@@ -345,4 +386,46 @@ impl Future {
 
 // Note: Future does not implement TradingPosition trait because it requires
 // time-dependent calculations that don't fit the trait's interface.
-// Future has its own methods: calculate_pnl(price, time) and is_liquidatable(price, time)
\ No newline at end of file
+// Future has its own methods: calculate_pnl(price, time) and is_liquidatable(price, time)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_close_is_exactly_one_hundred_percent() {
+        assert_eq!(Future::close_percentage_display(Future::FULL_CLOSE), 100.0);
+        assert_eq!(Future::close_percentage_display(Future::FULL_CLOSE / 4), 25.0);
+        assert_eq!(Future::close_percentage_display(1_000_000), 1.0);
+
+        // A full close takes everything, without rounding
+        assert_eq!(Future::get_close_portion(u64::MAX, Future::FULL_CLOSE).unwrap(), u64::MAX);
+        assert_eq!(Future::get_close_portion(1_000_003, Future::FULL_CLOSE).unwrap(), 1_000_003);
+    }
+
+    #[test]
+    fn partial_close_rounds_down() {
+        assert_eq!(Future::get_close_portion(1_000_000, Future::FULL_CLOSE / 2).unwrap(), 500_000);
+        assert_eq!(Future::get_close_portion(3, Future::FULL_CLOSE / 2).unwrap(), 1);
+        assert_eq!(Future::get_close_portion(1_000_000, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn usdc_collateral_short_returns_collateral_and_settles_in_usdc() {
+        let sol_custody = Pubkey::new_unique();
+        let usdc_custody = Pubkey::new_unique();
+        let future = Future { side: Side::Short, collateral_custody: usdc_custody, ..Default::default() };
+
+        // Opened before the settlement asset was stored, so it settles in the collateral asset
+        assert_eq!(future.get_settlement_custody(), usdc_custody);
+        assert!(!future.is_sol_collateral(&sol_custody, &usdc_custody).unwrap());
+
+        let sol_future = Future { collateral_custody: sol_custody, ..future.clone() };
+        assert!(sol_future.is_sol_collateral(&sol_custody, &usdc_custody).unwrap());
+
+        // A custody pair without the recorded collateral is refused
+        assert_eq!(
+            future.is_sol_collateral(&sol_custody, &Pubkey::new_unique()),
+            Err(PoolError::InvalidCollateralCustody.into())
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use anchor_lang::prelude::*;
+
+/// Keeper rewards owed by a pool, accrued by update_borrow_fees and expire_limit_order and
+/// paid out in USDC by claim_keeper_rewards from the pool's keeper reward budget. Claiming
+/// deducts what was paid from `accrued_usd`, so a reward can only be paid once.
+#[account]
+#[derive(Default, Debug)]
+pub struct KeeperRewards {
+    pub keeper: Pubkey,
+    pub pool: Pubkey,
+    pub accrued_usd: u64,        // Unclaimed reward (USD, 6 decimals)
+    pub total_claimed_usd: u64,
+    pub last_claim_time: i64,
+    pub bump: u8,
+}
+
+impl KeeperRewards {
+    pub const LEN: usize = 8 + std::mem::size_of::<KeeperRewards>();
+}
@@ -0,0 +1,116 @@
+use anchor_lang::prelude::*;
+use crate::errors::PerpetualError;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct RestingOrder {
+    pub position: Pubkey,     // Limit order Position PDA
+    pub trigger_price: u64,   // Trigger price (scaled by 1e6)
+    pub size_usd: u64,        // Order size in USD (6 decimals)
+    pub open_time: i64,       // Used for time priority at equal prices
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct LimitOrderBook {
+    pub pool: Pubkey,
+
+    // Orders triggering when price rises to the level, sorted ascending (next to fill first)
+    pub above_orders: [RestingOrder; 32],
+    // Orders triggering when price falls to the level, sorted descending (next to fill first)
+    pub below_orders: [RestingOrder; 32],
+
+    pub above_count: u8,
+    pub below_count: u8,
+    pub bump: u8,
+}
+
+impl LimitOrderBook {
+    pub const LEN: usize = 8 + std::mem::size_of::<LimitOrderBook>();
+    pub const MAX_ORDERS: usize = 32;
+
+    pub fn initialize(&mut self, pool: Pubkey, bump: u8) -> Result<()> {
+        self.pool = pool;
+        self.above_orders = [RestingOrder::default(); Self::MAX_ORDERS];
+        self.below_orders = [RestingOrder::default(); Self::MAX_ORDERS];
+        self.above_count = 0;
+        self.below_count = 0;
+        self.bump = bump;
+        Ok(())
+    }
+
+    /// Insert a resting order keeping price-time priority
+    pub fn insert(&mut self, order: RestingOrder, trigger_above_threshold: bool) -> Result<usize> {
+        let (orders, count) = if trigger_above_threshold {
+            (&mut self.above_orders, &mut self.above_count)
+        } else {
+            (&mut self.below_orders, &mut self.below_count)
+        };
+        let len = *count as usize;
+        require!(len < Self::MAX_ORDERS, PerpetualError::LimitOrderBookFull);
+
+        // Orders at the same price keep arrival order
+        let index = orders[..len]
+            .iter()
+            .position(|o| {
+                if trigger_above_threshold {
+                    o.trigger_price > order.trigger_price
+                } else {
+                    o.trigger_price < order.trigger_price
+                }
+            })
+            .unwrap_or(len);
+
+        for i in (index..len).rev() {
+            orders[i + 1] = orders[i];
+        }
+        orders[index] = order;
+        *count += 1;
+
+        Ok(index)
+    }
+
+    /// Remove the resting order for a position, returns false if it was not in the book
+    pub fn remove(&mut self, position: &Pubkey) -> bool {
+        for trigger_above_threshold in [true, false] {
+            let (orders, count) = if trigger_above_threshold {
+                (&mut self.above_orders, &mut self.above_count)
+            } else {
+                (&mut self.below_orders, &mut self.below_count)
+            };
+            let len = *count as usize;
+
+            if let Some(index) = orders[..len].iter().position(|o| o.position == *position) {
+                for i in index..len - 1 {
+                    orders[i] = orders[i + 1];
+                }
+                orders[len - 1] = RestingOrder::default();
+                *count -= 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Update the resting size after a partial cancel, returns false if it was not in the book
+    pub fn update_size(&mut self, position: &Pubkey, size_usd: u64) -> bool {
+        let above = self.above_orders[..self.above_count as usize].iter_mut();
+        let below = self.below_orders[..self.below_count as usize].iter_mut();
+
+        match above.chain(below).find(|o| o.position == *position) {
+            Some(order) => {
+                order.size_usd = size_usd;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Next order to fill on each side of the book
+    pub fn best_above(&self) -> Option<&RestingOrder> {
+        (self.above_count > 0).then(|| &self.above_orders[0])
+    }
+
+    pub fn best_below(&self) -> Option<&RestingOrder> {
+        (self.below_count > 0).then(|| &self.below_orders[0])
+    }
+}
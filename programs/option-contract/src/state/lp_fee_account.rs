@@ -0,0 +1,178 @@
+use anchor_lang::prelude::*;
+
+use crate::{math, state::Custody};
+
+/// An LP's share of one custody's epoch-distributed liquidity fees. Each epoch's pot is split
+/// by the staked LP balance (LpStake) held over the epoch, using the balance recorded at each
+/// checkpoint (claim_lp_fees). A balance that dropped between checkpoints only earns at the
+/// lower amount, so newly staked tokens have to be checkpointed before they start earning.
+#[account]
+#[derive(Default, Debug)]
+pub struct LpFeeAccount {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub lp_balance: u64,            // LP token balance at the last checkpoint
+    pub last_checkpoint_time: i64,  // 0 = never checkpointed
+    pub pending_lp_seconds: u128,   // LP-seconds held so far in the epoch of the last checkpoint
+    pub fees_owed: u64,             // Earned, unclaimed fees (custody tokens)
+    pub total_claimed: u64,
+    pub bump: u8,
+}
+
+/// LP tokens an owner has escrowed in the pool's stake vault to earn epoch fees. Staked tokens
+/// can't move between wallets, so every LP token is counted by at most one LpFeeAccount
+/// per custody.
+#[account]
+#[derive(Default, Debug)]
+pub struct LpStake {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub amount: u64, // LP tokens held in the stake vault for the owner
+    pub bump: u8,
+}
+
+impl LpStake {
+    pub const LEN: usize = 8 + std::mem::size_of::<LpStake>();
+}
+
+impl LpFeeAccount {
+    pub const LEN: usize = 8 + std::mem::size_of::<LpFeeAccount>();
+
+    /// Credit the fees earned since the last checkpoint from epochs the custody has closed and
+    /// start holding `lp_balance`. The custody must already be advanced to the current epoch.
+    pub fn checkpoint(
+        &mut self,
+        custody: &Custody,
+        epoch_duration_sec: i64,
+        current_time: i64,
+        lp_balance: u64,
+    ) -> Result<()> {
+        let current_epoch = math::checked_div(current_time, epoch_duration_sec)?;
+
+        if self.last_checkpoint_time > 0 {
+            let balance = self.lp_balance.min(lp_balance) as u128;
+            let last_epoch = math::checked_div(self.last_checkpoint_time, epoch_duration_sec)?;
+
+            if last_epoch < current_epoch {
+                // Finish the epoch of the last checkpoint at its own rate...
+                let last_epoch_end = math::checked_mul(last_epoch + 1, epoch_duration_sec)?;
+                let lp_seconds = math::checked_add(
+                    self.pending_lp_seconds,
+                    math::checked_mul(balance, (last_epoch_end - self.last_checkpoint_time) as u128)?,
+                )?;
+                let last_epoch_fee_per_lp = custody
+                    .get_lp_fee_per_lp(last_epoch as u64)
+                    .saturating_sub(custody.get_lp_fee_per_lp((last_epoch as u64).saturating_sub(1)));
+                let partial_fees = math::checked_div(
+                    math::checked_mul(
+                        math::checked_div(lp_seconds, epoch_duration_sec as u128)?,
+                        last_epoch_fee_per_lp,
+                    )?,
+                    Custody::LP_FEE_PER_LP_SCALE,
+                )?;
+
+                // ...then the whole epochs held since
+                let full_fee_per_lp = custody
+                    .get_lp_fee_per_lp((current_epoch - 1) as u64)
+                    .saturating_sub(custody.get_lp_fee_per_lp(last_epoch as u64));
+                let full_fees = math::checked_div(
+                    math::checked_mul(balance, full_fee_per_lp)?,
+                    Custody::LP_FEE_PER_LP_SCALE,
+                )?;
+
+                self.fees_owed = math::checked_add(
+                    self.fees_owed,
+                    math::checked_as_u64(math::checked_add(partial_fees, full_fees)?)?,
+                )?;
+                let current_epoch_start = math::checked_mul(current_epoch, epoch_duration_sec)?;
+                self.pending_lp_seconds =
+                    math::checked_mul(balance, (current_time - current_epoch_start) as u128)?;
+            } else {
+                self.pending_lp_seconds = math::checked_add(
+                    self.pending_lp_seconds,
+                    math::checked_mul(balance, (current_time - self.last_checkpoint_time) as u128)?,
+                )?;
+            }
+        }
+
+        self.lp_balance = lp_balance;
+        self.last_checkpoint_time = current_time;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH: i64 = 1_000;
+    const LP_SUPPLY: u64 = 1_000;
+
+    // Custody with `pots[i]` collected in epoch i + 1 and advanced to `current_epoch`
+    fn test_custody(pots: &[u64], current_epoch: u64) -> Custody {
+        let mut custody = Custody::default();
+        for (idx, pot) in pots.iter().enumerate() {
+            custody.accrue_lp_fee(*pot, idx as u64 + 1, LP_SUPPLY).unwrap();
+        }
+        custody.advance_lp_fee_epoch(current_epoch, LP_SUPPLY).unwrap();
+        custody
+    }
+
+    #[test]
+    fn first_checkpoint_only_starts_the_clock() {
+        let custody = test_custody(&[500], 1);
+        let mut account = LpFeeAccount::default();
+        account.checkpoint(&custody, EPOCH, 1_200, 1_000).unwrap();
+        assert_eq!(account.fees_owed, 0);
+        assert_eq!(account.pending_lp_seconds, 0);
+        assert_eq!(account.lp_balance, 1_000);
+        assert_eq!(account.last_checkpoint_time, 1_200);
+    }
+
+    #[test]
+    fn closed_epoch_is_paid_by_time_held() {
+        // 500 collected in epoch 1, closed once epoch 2 starts
+        let custody = test_custody(&[500], 2);
+
+        // The whole supply held for the whole epoch earns the whole pot
+        let mut full = LpFeeAccount::default();
+        full.checkpoint(&custody, EPOCH, 1_000, 1_000).unwrap();
+        full.checkpoint(&custody, EPOCH, 2_500, 1_000).unwrap();
+        assert_eq!(full.fees_owed, 500);
+        // The time into epoch 2 is carried for its own pot
+        assert_eq!(full.pending_lp_seconds, 1_000 * 500);
+
+        // Half the supply for half the epoch earns a quarter
+        let mut late = LpFeeAccount::default();
+        late.checkpoint(&custody, EPOCH, 1_500, 500).unwrap();
+        late.checkpoint(&custody, EPOCH, 2_000, 500).unwrap();
+        assert_eq!(late.fees_owed, 125);
+    }
+
+    #[test]
+    fn balance_dropped_between_checkpoints_earns_at_the_lower_amount() {
+        let custody = test_custody(&[500], 2);
+        let mut account = LpFeeAccount::default();
+        account.checkpoint(&custody, EPOCH, 1_000, 1_000).unwrap();
+        account.checkpoint(&custody, EPOCH, 1_500, 0).unwrap();
+        account.checkpoint(&custody, EPOCH, 2_000, 0).unwrap();
+        assert_eq!(account.fees_owed, 0);
+
+        // Tokens staked since the last checkpoint don't earn until checkpointed
+        let mut topped_up = LpFeeAccount::default();
+        topped_up.checkpoint(&custody, EPOCH, 1_000, 0).unwrap();
+        topped_up.checkpoint(&custody, EPOCH, 2_000, 1_000).unwrap();
+        assert_eq!(topped_up.fees_owed, 0);
+    }
+
+    #[test]
+    fn quiet_epochs_are_carried_through() {
+        // 500 in epoch 1, 1_000 in epoch 2, nothing in epoch 3
+        let custody = test_custody(&[500, 1_000], 4);
+        let mut account = LpFeeAccount::default();
+        account.checkpoint(&custody, EPOCH, 1_000, 1_000).unwrap();
+        account.checkpoint(&custody, EPOCH, 4_000, 1_000).unwrap();
+        assert_eq!(account.fees_owed, 1_500);
+    }
+}
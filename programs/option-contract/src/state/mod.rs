@@ -5,9 +5,14 @@ pub use contract::*;
 pub use oracle::*;
 pub use pool::*;
 pub use custody::*;
-pub use perpetuals::{Position, OrderType, Side};
+pub use perpetuals::{CloseSettlement, Position, OrderType, Side};
 pub use tp_sl_orderbook::*;
+pub use limit_order_book::*;
 pub use future::*;
+pub use withdrawal_request::*;
+pub use keeper_rewards::*;
+pub use referral::*;
+pub use lp_fee_account::*;
 
 pub mod option;
 pub mod user;
@@ -18,4 +23,9 @@ pub mod pool;
 pub mod custody;
 pub mod perpetuals;
 pub mod tp_sl_orderbook;
-pub mod future;
\ No newline at end of file
+pub mod limit_order_book;
+pub mod future;
+pub mod withdrawal_request;
+pub mod keeper_rewards;
+pub mod referral;
+pub mod lp_fee_account;
\ No newline at end of file
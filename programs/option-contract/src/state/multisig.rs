@@ -33,6 +33,12 @@ pub enum AdminInstruction {
     SetCustomOraclePrice,
     SetTestTime,
     UpgradeCustody,
+    SetPoolConfig,
+    SetContractConfig,
+    ReconcileCustodyLocked,
+    SetPoolRatios,
+    ReconcileOpenInterest,
+    SetReferral,
 }
 
 impl Multisig {
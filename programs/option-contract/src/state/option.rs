@@ -1,17 +1,44 @@
 use anchor_lang::prelude::*;
-use crate::{utils::option_pricing::*, math::{self, scaled_price_to_f64}};
+use crate::{utils::option_pricing::*, math::{self, scaled_price_to_f64}, state::{Contract, Custody, OraclePrice}, errors::{OptionError, TradingError}};
+
+// Explicit discriminants match the former u8 encoding (0 = call, 1 = put)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OptionType {
+    #[default]
+    Call = 0,
+    Put = 1,
+}
+
+impl OptionType {
+    pub fn from_is_call(is_call: bool) -> Self {
+        if is_call { Self::Call } else { Self::Put }
+    }
+
+    pub fn is_call(&self) -> bool {
+        *self == Self::Call
+    }
+}
+
+// American options can be exercised any time before expiry, European ones only settle at
+// expiry through auto_exercise and claim_option
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ExerciseStyle {
+    #[default]
+    American = 0,
+    European = 1,
+}
 
 #[account]
 pub struct OptionDetail {
     pub index: u64,
     pub owner: Pubkey,
     pub amount: u64,
-    pub quantity: u64,
+    pub quantity: u64,            // Contracts scaled by 10^quantity_decimals
     pub strike_price: u64,        // Strike price scaled by 1e6 (6 decimals)
     pub period: u64,
     pub expired_date: i64,
     pub purchase_date: u64,
-    pub option_type: u8, // 0: call, 1: put
+    pub option_type: OptionType,
 
     pub premium: u64,
     pub premium_asset: Pubkey, // pay_custody key
@@ -39,11 +66,198 @@ pub struct OptionDetail {
     
     // TP/SL Orderbook reference (optional advanced feature)
     pub tp_sl_orderbook: Option<Pubkey>, // Optional reference to TpSlOrderbook account
+
+    // Underlying price frozen when the option is marked expired (scaled by 1e6)
+    pub settlement_price: Option<u64>,
+
+    // Fixed-point quantity: 0 for legacy whole-contract options, QUANTITY_DECIMALS for new ones
+    pub quantity_decimals: u8,
+
+    // Referral attribution set at open
+    pub referrer: Option<Pubkey>,
+
+    // Set at open; European options cannot be exercised early
+    pub exercise_style: ExerciseStyle,
+
+    // Perp of the same owner tagged as this option's hedge (set through set_position_hedge),
+    // at most one per option
+    pub hedge_position: Option<Pubkey>,
+
+    // locked_custody tokens locked for the option, released pro rata with quantity; 0 for
+    // options opened before it was tracked, which release `amount` instead
+    pub locked_amount: u64,
+
+    // Unused space for fields added later, so they fit without another migration
+    pub reserved: [u8; OptionDetail::RESERVED_LEN],
 }
 
 impl OptionDetail {
-    // Updated length calculation: added 8 bytes for entry_price (u64) + 8 bytes for last_update_time (i64) + 18 bytes for TP/SL (Option<u64> * 2) + 33 bytes for Option<Pubkey>
-    pub const LEN: usize = 8 * 15 + 4 + 32 * 5 + 8 + 18 + 33;
+    // Updated length calculation: added 8 bytes for entry_price (u64) + 8 bytes for last_update_time (i64) + 18 bytes for TP/SL (Option<u64> * 2) + 33 bytes for Option<Pubkey> + 9 bytes for settlement_price (Option<u64>) + 1 byte for quantity_decimals + 33 bytes for referrer (Option<Pubkey>) + 1 byte for exercise_style + 33 bytes for hedge_position (Option<Pubkey>) + 8 bytes for locked_amount (u64), both taken from the reserved tail + the rest of the reserved tail
+    pub const LEN: usize = Self::RELEASED_LEN + 9 + 1 + 33 + 1 + 33 + 8 + Self::RESERVED_LEN;
+    // Size of accounts created by the first release, which migrate_account grows to LEN
+    pub const RELEASED_LEN: usize = 8 * 15 + 4 + 32 * 5 + 8 + 18 + 33;
+    pub const RESERVED_LEN: usize = 23;
+    pub const QUANTITY_DECIMALS: u8 = 6;
+    pub const LIMIT_CANCEL_FEE_BPS: u64 = 10; // 0.1% kept when a pending limit option is cancelled
+    pub const ROLL_FEE_DISCOUNT_BPS: u64 = 5_000; // roll_option charges half of close fee + buy markup
+
+    pub fn is_call(&self) -> bool {
+        self.option_type.is_call()
+    }
+
+    /// Stored quantity units per whole contract
+    pub fn quantity_scale(&self) -> u64 {
+        10u64.pow(self.quantity_decimals as u32)
+    }
+
+    /// Number of contracts represented by a stored `quantity`, possibly fractional
+    pub fn contracts(&self, quantity: u64) -> f64 {
+        quantity as f64 / self.quantity_scale() as f64
+    }
+
+    /// Share of the premium `amount` paid for `quantity` of the option
+    pub fn get_amount(&self, quantity: u64) -> Result<u64> {
+        self.get_pro_rata(self.amount, quantity)
+    }
+
+    /// Refund and cancellation fee for `quantity` of a pending limit option: it never carried
+    /// risk, so the reserved premium comes back minus LIMIT_CANCEL_FEE_BPS instead of being
+    /// re-priced like an executed option
+    pub fn get_limit_cancel_refund(&self, quantity: u64) -> Result<(u64, u64)> {
+        let reserved_amount = self.get_amount(quantity)?;
+        let cancel_fee = math::checked_div(
+            math::checked_mul(reserved_amount, Self::LIMIT_CANCEL_FEE_BPS)?,
+            10_000u64,
+        )?;
+        Ok((math::checked_sub(reserved_amount, cancel_fee)?, cancel_fee))
+    }
+
+    /// Locked custody tokens held for `quantity` of the option
+    pub fn get_locked_amount(&self, quantity: u64) -> Result<u64> {
+        let locked_amount = if self.locked_amount > 0 { self.locked_amount } else { self.amount };
+        self.get_pro_rata(locked_amount, quantity)
+    }
+
+    /// Take `quantity` out of the option, returning the premium share and the locked tokens
+    /// it held
+    pub fn reduce_quantity(&mut self, quantity: u64) -> Result<(u64, u64)> {
+        let amount = self.get_amount(quantity)?;
+        let locked_amount = self.get_locked_amount(quantity)?;
+        self.quantity = math::checked_sub(self.quantity, quantity)?;
+        self.amount = math::checked_sub(self.amount, amount)?;
+        self.locked_amount = self.locked_amount.saturating_sub(locked_amount);
+        Ok((amount, locked_amount))
+    }
+
+    fn get_pro_rata(&self, value: u64, quantity: u64) -> Result<u64> {
+        if quantity >= self.quantity {
+            return Ok(value);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(value as u128, quantity as u128)?,
+            self.quantity as u128,
+        )?)
+    }
+
+    /// Notional exposure in USD (6 decimals) of a stored `quantity` at the strike
+    pub fn get_notional_usd(&self, quantity: u64) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(quantity as u128, self.strike_price as u128)?,
+            self.quantity_scale() as u128,
+        )?)
+    }
+
+    /// Locked custody tokens worth `value_usd` of option value. Only the locked custody's price
+    /// and decimals apply, the underlying may use different ones. Float rounding can leave a
+    /// worthless option a hair below zero, which is worth nothing rather than an error.
+    pub fn get_locked_refund_amount(
+        value_usd: f64,
+        locked_token_price: &OraclePrice,
+        locked_decimals: u8,
+    ) -> Result<u64> {
+        let value_usd = math::f64_to_scaled_price(value_usd.max(0.0))?;
+        locked_token_price.get_token_amount(value_usd, locked_decimals)
+    }
+
+    /// Buyback of `quantity` of the option at `underlying_price`: its Black-Scholes value on
+    /// the locked custody's utilisation, in locked tokens, marked down by the underlying
+    /// custody's time-to-expiry close fee. Returns (refund_amount, close_fee_bps).
+    pub fn get_buyback_refund(
+        &self,
+        quantity: u64,
+        current_time: i64,
+        underlying_price: f64,
+        custody: &Custody,
+        locked_custody: &Custody,
+        locked_token_price: &OraclePrice,
+    ) -> Result<(u64, u64)> {
+        let remaining_seconds = self.expired_date.saturating_sub(current_time);
+        let remaining_days = remaining_seconds as f64 / 86400.0;
+        let remaining_years = remaining_days / 365.0;
+
+        let bs_price_per_contract = black_scholes_with_borrow_rate(
+            underlying_price,
+            scaled_price_to_f64(self.strike_price)?,
+            remaining_years,
+            self.is_call(),
+            locked_custody.token_locked, // Current utilization of the locked asset
+            locked_custody.token_owned,
+            self.is_call(),
+            self.exercise_style,
+        )?;
+        let refund_amount_raw = Self::get_locked_refund_amount(
+            bs_price_per_contract * self.contracts(quantity),
+            locked_token_price,
+            locked_custody.decimals,
+        )?;
+        Ok((
+            custody.apply_option_close_fee(refund_amount_raw, remaining_seconds)?,
+            custody.get_option_close_fee_bps(remaining_seconds),
+        ))
+    }
+
+    /// A price or utilisation move between quote and execution raises the premium per
+    /// contract; `max_premium_tokens` bounds it in pay tokens (None = unbounded)
+    pub fn check_premium_slippage(pay_amount: u64, max_premium_tokens: Option<u64>) -> Result<()> {
+        if let Some(max_premium_tokens) = max_premium_tokens {
+            require_gte!(max_premium_tokens, pay_amount, TradingError::SlippageExceededError);
+        }
+        Ok(())
+    }
+
+    /// Manual exercise is for American options before expiry; European ones settle at
+    /// expiry through auto_exercise and claim_option
+    pub fn check_exercisable(&self, current_timestamp: i64) -> Result<()> {
+        require!(
+            self.exercise_style == ExerciseStyle::American,
+            OptionError::EarlyExerciseNotAllowed
+        );
+        require_gt!(self.expired_date, current_timestamp, OptionError::InvalidTimeError);
+        Ok(())
+    }
+
+    /// Intrinsic value at the frozen settlement price, denominated in the locked asset
+    pub fn settlement_payout(&self, locked_token_price: f64) -> Result<u64> {
+        let settlement_price = match self.settlement_price {
+            Some(price) => scaled_price_to_f64(price)?,
+            None => return Ok(0),
+        };
+        let strike_price_f64 = scaled_price_to_f64(self.strike_price)?;
+
+        let price_diff = if self.is_call() {
+            settlement_price - strike_price_f64
+        } else {
+            strike_price_f64 - settlement_price
+        };
+        if price_diff <= 0.0 {
+            // Option expired out of the money - no payout
+            return Ok(0);
+        }
+
+        let intrinsic_value = price_diff * self.contracts(self.quantity);
+        let amount = math::checked_float_div(intrinsic_value, locked_token_price)?;
+        math::checked_as_u64(amount.round())
+    }
 
     /// Update option with current market data (similar to update_position)
     pub fn update_option(
@@ -78,16 +292,17 @@ impl OptionDetail {
             current_price,
             strike_price_f64,
             time_to_expiry,
-            self.option_type == 0, // 0 = call, 1 = put
+            self.is_call(),
             token_locked,
             token_owned,
-            is_sol
+            is_sol,
+            self.exercise_style,
         )?;
 
         // Calculate profit/loss using proper decimal math
         // Use the same scaling as the premium asset to avoid precision loss
         let current_value_scaled = math::checked_decimal_mul(
-            math::checked_as_u64(current_option_value * 1_000_000.0)?, // Convert to micro units
+            math::checked_as_u64(current_option_value * Contract::USD_SCALE as f64)?, // Convert to micro units
             -6, // micro decimals
             1,
             0,
@@ -102,10 +317,10 @@ impl OptionDetail {
 
         // Check if limit price conditions are met for automatic execution
         if !self.executed && self.limit_price > 0 {
-            let should_execute = if self.option_type == 0 { // Call option
-                current_price >= (self.limit_price as f64 / 1_000_000.0) // Assuming 6 decimal scaling
+            let should_execute = if self.is_call() { // Call option
+                current_price >= (self.limit_price as f64 / math::PRICE_SCALE as f64)
             } else { // Put option
-                current_price <= (self.limit_price as f64 / 1_000_000.0)
+                current_price <= (self.limit_price as f64 / math::PRICE_SCALE as f64)
             };
 
             if should_execute {
@@ -120,8 +335,8 @@ impl OptionDetail {
             
             // Check take profit
             if let Some(tp_price) = self.take_profit_price {
-                let tp_price_f64 = tp_price as f64 / 1_000_000.0;
-                if self.option_type == 0 { // Call option
+                let tp_price_f64 = tp_price as f64 / math::PRICE_SCALE as f64;
+                if self.is_call() { // Call option
                     // For calls, take profit when underlying price goes above TP
                     if current_price >= tp_price_f64 {
                         should_execute = true;
@@ -136,8 +351,8 @@ impl OptionDetail {
             
             // Check stop loss
             if let Some(sl_price) = self.stop_loss_price {
-                let sl_price_f64 = sl_price as f64 / 1_000_000.0;
-                if self.option_type == 0 { // Call option
+                let sl_price_f64 = sl_price as f64 / math::PRICE_SCALE as f64;
+                if self.is_call() { // Call option
                     // For calls, stop loss when underlying price goes below SL
                     if current_price <= sl_price_f64 {
                         should_execute = true;
@@ -161,4 +376,183 @@ impl OptionDetail {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_option(option_type: OptionType, strike_price: u64, settlement_price: Option<u64>) -> OptionDetail {
+        OptionDetail {
+            index: 0,
+            owner: Pubkey::default(),
+            amount: 0,
+            quantity: 2_000_000, // 2 contracts
+            strike_price,
+            period: 0,
+            expired_date: 0,
+            purchase_date: 0,
+            option_type,
+            premium: 0,
+            premium_asset: Pubkey::default(),
+            profit: 0,
+            locked_asset: Pubkey::default(),
+            pool: Pubkey::default(),
+            custody: Pubkey::default(),
+            exercised: 0,
+            bought_back: 0,
+            claimed: 0,
+            valid: true,
+            bump: 0,
+            limit_price: 0,
+            executed: false,
+            entry_price: 0,
+            last_update_time: 0,
+            take_profit_price: None,
+            stop_loss_price: None,
+            tp_sl_orderbook: None,
+            settlement_price,
+            quantity_decimals: OptionDetail::QUANTITY_DECIMALS,
+            referrer: None,
+            exercise_style: ExerciseStyle::American,
+            hedge_position: None,
+            locked_amount: 0,
+            reserved: [0; OptionDetail::RESERVED_LEN],
+        }
+    }
+
+    #[test]
+    fn option_type_keeps_the_legacy_encoding() {
+        assert_eq!(OptionType::Call.try_to_vec().unwrap(), vec![0]);
+        assert_eq!(OptionType::Put.try_to_vec().unwrap(), vec![1]);
+        assert_eq!(OptionType::try_from_slice(&[1]).unwrap(), OptionType::Put);
+        assert!(OptionType::try_from_slice(&[2]).is_err());
+
+        assert!(OptionType::from_is_call(true).is_call());
+        assert!(!OptionType::from_is_call(false).is_call());
+    }
+
+    #[test]
+    fn settlement_pays_the_in_the_money_side_only() {
+        // Settled at $120 against a $100 strike, paid in a $10 locked token
+        let call = test_option(OptionType::Call, 100_000_000, Some(120_000_000));
+        let put = test_option(OptionType::Put, 100_000_000, Some(120_000_000));
+        assert_eq!(call.settlement_payout(10.0).unwrap(), 4); // $20 x 2 contracts / $10
+        assert_eq!(put.settlement_payout(10.0).unwrap(), 0);
+
+        let call = test_option(OptionType::Call, 100_000_000, Some(80_000_000));
+        let put = test_option(OptionType::Put, 100_000_000, Some(80_000_000));
+        assert_eq!(call.settlement_payout(10.0).unwrap(), 0);
+        assert_eq!(put.settlement_payout(10.0).unwrap(), 4);
+
+        // Not settled yet
+        assert_eq!(test_option(OptionType::Call, 100_000_000, None).settlement_payout(10.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn pricing_branches_on_the_option_type() {
+        let call = test_option(OptionType::Call, 100_000_000, None);
+        let put = test_option(OptionType::Put, 100_000_000, None);
+        let price = |option: &OptionDetail, spot: f64| {
+            black_scholes_with_borrow_rate(spot, 100.0, 0.25, option.is_call(), 0, 1_000, true, ExerciseStyle::European)
+                .unwrap()
+        };
+
+        // In the money side is worth more, and each side gains as spot moves its way
+        assert!(price(&call, 120.0) > price(&put, 120.0));
+        assert!(price(&put, 80.0) > price(&call, 80.0));
+        assert!(price(&call, 120.0) > price(&call, 100.0));
+        assert!(price(&put, 80.0) > price(&put, 100.0));
+    }
+
+    #[test]
+    fn refund_uses_only_the_locked_price_and_decimals() {
+        // (underlying spot, strike, is_call, locked price at exponent -8, locked decimals):
+        // BTC calls locked in SOL, SOL puts locked in USDC, ETH calls locked in BTC
+        let cases = [
+            (65_000.0, 60_000.0, true, 15_050_000_000u64, 9u8),
+            (150.5, 160.0, false, 100_000_000, 6),
+            (3_400.0, 3_000.0, true, 6_500_000_000_000, 8),
+        ];
+        for (spot, strike, is_call, locked_price, locked_decimals) in cases {
+            let locked_token_price = OraclePrice::new(locked_price, -8);
+            let per_contract = black_scholes_with_borrow_rate(
+                spot, strike, 0.1, is_call, 0, 1_000, is_call, ExerciseStyle::American,
+            )
+            .unwrap();
+            let value_usd = per_contract * 1.5;
+
+            let refund = OptionDetail::get_locked_refund_amount(value_usd, &locked_token_price, locked_decimals).unwrap();
+            let expected = locked_token_price
+                .get_token_amount(math::f64_to_scaled_price(value_usd).unwrap(), locked_decimals)
+                .unwrap();
+            assert_eq!(refund, expected);
+
+            // Worth the option value back in USD, to within one locked token unit
+            let refund_usd = locked_token_price.get_asset_amount_usd(refund, locked_decimals).unwrap();
+            let one_unit_usd = locked_token_price.get_asset_amount_usd(1, locked_decimals).unwrap();
+            let value_scaled = math::f64_to_scaled_price(value_usd).unwrap();
+            assert!(refund_usd <= value_scaled && value_scaled - refund_usd <= one_unit_usd + 1);
+        }
+
+        // A value rounded just below zero refunds nothing
+        let locked_token_price = OraclePrice::new(100_000_000, -8);
+        assert_eq!(OptionDetail::get_locked_refund_amount(-1e-12, &locked_token_price, 6).unwrap(), 0);
+    }
+
+    #[test]
+    fn only_american_options_exercise_before_expiry() {
+        let mut option = test_option(OptionType::Call, 100_000_000, None);
+        option.expired_date = 1_000;
+
+        assert!(option.check_exercisable(999).is_ok());
+        assert_eq!(option.check_exercisable(1_000), Err(OptionError::InvalidTimeError.into()));
+
+        option.exercise_style = ExerciseStyle::European;
+        for now in [0, 999, 1_000] {
+            assert_eq!(option.check_exercisable(now), Err(OptionError::EarlyExerciseNotAllowed.into()));
+        }
+    }
+
+    #[test]
+    fn premium_above_the_quoted_max_is_rejected() {
+        // Premium per contract in 6-decimal pay tokens at $1
+        let premium_tokens = |spot: f64, token_locked: u64| {
+            let premium = black_scholes_with_borrow_rate(
+                spot, 100.0, 0.1, true, token_locked, 1_000, true, ExerciseStyle::American,
+            )
+            .unwrap();
+            (premium * 1_000_000.0) as u64
+        };
+        let quoted = premium_tokens(100.0, 100);
+        assert!(OptionDetail::check_premium_slippage(quoted, Some(quoted)).is_ok());
+        assert!(OptionDetail::check_premium_slippage(u64::MAX, None).is_ok());
+
+        // Spot moving up or utilisation rising before execution makes the call dearer
+        for executed in [premium_tokens(105.0, 100), premium_tokens(100.0, 900)] {
+            assert!(executed > quoted);
+            assert_eq!(
+                OptionDetail::check_premium_slippage(executed, Some(quoted)),
+                Err(TradingError::SlippageExceededError.into())
+            );
+        }
+    }
+
+    #[test]
+    fn pending_limit_refunds_the_reserve_and_executed_reprices() {
+        let mut option = test_option(OptionType::Call, 100_000_000, None);
+        option.amount = 1_000_000; // premium reserved for both contracts
+
+        // Pending: half the reserve for one contract, less the 0.1% fee, whatever the market
+        assert_eq!(option.get_limit_cancel_refund(1_000_000).unwrap(), (499_500, 500));
+        assert_eq!(option.get_limit_cancel_refund(2_000_000).unwrap(), (999_000, 1_000));
+
+        // Executed: the refund follows the option's current value instead
+        let locked_token_price = OraclePrice::new(100_000_000, -8);
+        let executed_refund = |spot: f64| {
+            let value = black_scholes(spot, 100.0, 0.1, true) * option.contracts(1_000_000);
+            OptionDetail::get_locked_refund_amount(value, &locked_token_price, 6).unwrap()
+        };
+        assert!(executed_refund(110.0) > executed_refund(100.0));
+        assert_ne!(executed_refund(100.0), 499_500);
+    }
+}
@@ -1,12 +1,13 @@
 use anchor_lang::prelude::*;
 use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
 use core::cmp::Ordering;
-use crate::{errors::ContractError, math, state::Contract};
+use crate::{errors::ContractError, math, state::{Contract, Custody}};
 
 #[derive(Copy, Clone, Eq, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct OraclePrice {
     pub price: u64,
     pub exponent: i32,
+    pub confidence_bps: u64, // feed confidence interval relative to the price, 0 when not read from a feed
 }
 
 impl PartialOrd for OraclePrice {
@@ -35,18 +36,33 @@ impl OraclePrice {
     pub const ORACLE_EXPONENT_SCALE: i32 = -9;
     pub const ORACLE_PRICE_SCALE: u64 = 1_000_000_000;
     pub const MAX_CONFIDENCE_INTERVAL_BPS: u64 = 500; // 5% max confidence interval
+    // How far from an expiry a price may be published to settle at it
+    pub const SETTLEMENT_PRICE_WINDOW_SEC: i64 = 30;
+    // Feed exponents outside this window would overflow or zero out 10^delta rescaling
+    pub const MIN_ORACLE_EXPONENT: i32 = -12;
+    pub const MAX_ORACLE_EXPONENT: i32 = 0;
     
     pub fn new(price: u64, exponent: i32) -> Self {
-        Self { price, exponent }
+        Self { price, exponent, confidence_bps: 0 }
     }
 
     pub fn new_from_token(amount_and_decimals: (u64, u8)) -> Self {
         Self {
             price: amount_and_decimals.0,
             exponent: -(amount_and_decimals.1 as i32),
+            confidence_bps: 0,
         }
     }
     
+    /// Reject a feed exponent outside MIN_ORACLE_EXPONENT..=MAX_ORACLE_EXPONENT
+    fn validate_exponent(exponent: i32) -> Result<()> {
+        if !(Self::MIN_ORACLE_EXPONENT..=Self::MAX_ORACLE_EXPONENT).contains(&exponent) {
+            msg!("Oracle exponent {} out of range", exponent);
+            return err!(ContractError::InvalidOracleExponent);
+        }
+        Ok(())
+    }
+
     pub fn get_price(&self) -> f64 {
         (self.price as f64) * 10f64.powi(self.exponent)
     }
@@ -56,9 +72,106 @@ impl OraclePrice {
     pub fn new_from_oracle(
         oracle_account: &AccountInfo,
         _current_time: i64, // Keeping for compatibility but Clock is used internally
-        _use_ema: bool,     // Not supported in current version
+        use_ema: bool,      // Read the feed's EMA price instead of the spot price
+    ) -> Result<OraclePrice> {
+        Self::get_pyth_price_from_update_account(oracle_account, use_ema)
+    }
+
+    /// Get price from the primary oracle, falling back to the secondary when the primary
+    /// is stale or has too wide a confidence interval. Errors only if both sources fail.
+    pub fn new_from_oracle_with_failover(
+        primary: (&AccountInfo, u8),
+        secondary: Option<(&AccountInfo, u8)>,
+        current_time: i64,
+        use_ema: bool,
+    ) -> Result<OraclePrice> {
+        Self::with_failover(primary, secondary, |oracle_account, oracle_type| {
+            Self::new_from_oracle_type(oracle_account, oracle_type, current_time, use_ema)
+        })
+    }
+
+    /// Get the spot price published within SETTLEMENT_PRICE_WINDOW_SEC of `target_time`, so
+    /// expiries settle at the price of the expiry however late the settling instruction lands.
+    /// `price_update` is a price update posted for that time carrying the primary's feed;
+    /// without one the primary and secondary are read with the usual failover, which only
+    /// works while they still hold a price from the window.
+    pub fn new_from_oracle_at_with_failover(
+        primary: (&AccountInfo, u8),
+        secondary: Option<(&AccountInfo, u8)>,
+        price_update: Option<&AccountInfo>,
+        target_time: i64,
+    ) -> Result<OraclePrice> {
+        if let Some(price_update) = price_update {
+            require!(primary.1 == Custody::ORACLE_TYPE_PYTH, ContractError::UnsupportedOracle);
+            require!(
+                Self::parse_pyth_price_update(price_update)?.price_message.feed_id
+                    == Self::parse_pyth_price_update(primary.0)?.price_message.feed_id,
+                ContractError::InvalidOracleAccount
+            );
+            msg!("Oracle source: price update {}", price_update.key());
+            return Self::read_pyth_price_at(price_update, target_time);
+        }
+        Self::with_failover(primary, secondary, |oracle_account, oracle_type| {
+            match oracle_type {
+                Custody::ORACLE_TYPE_PYTH => Self::read_pyth_price_at(oracle_account, target_time),
+                _ => err!(ContractError::UnsupportedOracle),
+            }
+        })
+    }
+
+    fn read_pyth_price_at(oracle_account: &AccountInfo, target_time: i64) -> Result<OraclePrice> {
+        let (price, publish_time) = Self::read_pyth_price_update(oracle_account, false)?;
+        msg!("Price published at {} for {}", publish_time, target_time);
+        require!(
+            (publish_time - target_time).abs() <= Self::SETTLEMENT_PRICE_WINDOW_SEC,
+            ContractError::StaleOraclePrice
+        );
+        Ok(price)
+    }
+
+    fn with_failover(
+        primary: (&AccountInfo, u8),
+        secondary: Option<(&AccountInfo, u8)>,
+        read: impl Fn(&AccountInfo, u8) -> Result<OraclePrice>,
+    ) -> Result<OraclePrice> {
+        match read(primary.0, primary.1) {
+            Ok(price) => {
+                msg!("Oracle source: primary {}", primary.0.key());
+                Ok(price)
+            }
+            Err(err) if Self::is_failover_error(&err) => {
+                let Some((oracle_account, oracle_type)) = secondary else {
+                    return Err(err);
+                };
+                msg!("Primary oracle unavailable, trying secondary");
+                let price = read(oracle_account, oracle_type)?;
+                msg!("Oracle source: secondary {}", oracle_account.key());
+                Ok(price)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn new_from_oracle_type(
+        oracle_account: &AccountInfo,
+        oracle_type: u8,
+        current_time: i64,
+        use_ema: bool,
     ) -> Result<OraclePrice> {
-        Self::get_pyth_price_from_update_account(oracle_account)
+        match oracle_type {
+            Custody::ORACLE_TYPE_PYTH => Self::new_from_oracle(oracle_account, current_time, use_ema),
+            _ => err!(ContractError::UnsupportedOracle),
+        }
+    }
+
+    fn is_failover_error(err: &Error) -> bool {
+        match err {
+            Error::AnchorError(e) => {
+                e.error_code_number == u32::from(ContractError::StaleOraclePrice)
+                    || e.error_code_number == u32::from(ContractError::LowConfidencePrice)
+            }
+            _ => false,
+        }
     }
 
     /// Get price with explicit feed ID (recommended for production)
@@ -119,11 +232,13 @@ impl OraclePrice {
             price_message.price > 0,
             ContractError::InvalidOraclePrice
         );
+        Self::validate_exponent(price_message.exponent)?;
         let price_value = price_message.price as u64;
         
         Ok(OraclePrice {
             price: price_value,
             exponent: price_message.exponent,
+            confidence_bps,
         })
     }
 
@@ -166,6 +281,7 @@ impl OraclePrice {
         Ok(OraclePrice {
             price: p,
             exponent: e,
+            confidence_bps: self.confidence_bps,
         })
     }
 
@@ -182,6 +298,7 @@ impl OraclePrice {
                 math::checked_add(base.exponent, Self::ORACLE_EXPONENT_SCALE)?,
                 other.exponent,
             )?,
+            confidence_bps: base.confidence_bps.saturating_add(other.confidence_bps),
         })
     }
 
@@ -189,6 +306,7 @@ impl OraclePrice {
         Ok(OraclePrice {
             price: math::checked_mul(self.price, other.price)?,
             exponent: math::checked_add(self.exponent, other.exponent)?,
+            confidence_bps: self.confidence_bps.saturating_add(other.confidence_bps),
         })
     }
 
@@ -201,11 +319,13 @@ impl OraclePrice {
             Ok(OraclePrice {
                 price: math::checked_div(self.price, math::checked_pow(10, delta as usize)?)?,
                 exponent: target_exponent,
+                confidence_bps: self.confidence_bps,
             })
         } else {
             Ok(OraclePrice {
                 price: math::checked_mul(self.price, math::checked_pow(10, (-delta) as usize)?)?,
                 exponent: target_exponent,
+                confidence_bps: self.confidence_bps,
             })
         }
     }
@@ -227,6 +347,7 @@ impl OraclePrice {
                     return Ok(OraclePrice {
                         price: 1000000u64,
                         exponent: -6,
+                        confidence_bps: min_price.confidence_bps,
                     });
                 }
             }
@@ -235,6 +356,7 @@ impl OraclePrice {
                 Ok(OraclePrice {
                     price: one_usd,
                     exponent: min_price.exponent,
+                    confidence_bps: min_price.confidence_bps,
                 })
             } else {
                 Ok(*min_price)
@@ -248,7 +370,23 @@ impl OraclePrice {
     /// This method tries to auto-detect the feed ID from the price update
     fn get_pyth_price_from_update_account(
         oracle_account: &AccountInfo,
+        use_ema: bool,
     ) -> Result<OraclePrice> {
+        let (price, publish_time) = Self::read_pyth_price_update(oracle_account, use_ema)?;
+
+        // Check staleness
+        let age = Clock::get()?.unix_timestamp - publish_time;
+        require!(
+            age <= Self::MAX_PRICE_AGE_SEC as i64,
+            ContractError::StaleOraclePrice
+        );
+        msg!("Oracle price age: {} seconds", age);
+
+        Ok(price)
+    }
+
+    /// Read a PriceUpdateV2 account, which must be owned by the Pyth receiver
+    fn parse_pyth_price_update(oracle_account: &AccountInfo) -> Result<PriceUpdateV2> {
         require!(
             !Contract::is_empty_account(oracle_account)?,
             ContractError::InvalidOracleAccount
@@ -258,12 +396,12 @@ impl OraclePrice {
         let data = oracle_account.try_borrow_data()
             .map_err(|_| ContractError::InvalidOracleAccount)?;
         
-        // Check account owner is Pyth Receiver program
-        // let expected_owner = pyth_solana_receiver_sdk::ID;
-        // require!(
-        //     oracle_account.owner == &expected_owner,
-        //     ContractError::InvalidOracleAccount
-        // );
+        // Check account owner is Pyth Receiver program, so a look-alike account can't feed a price
+        let expected_owner = pyth_solana_receiver_sdk::ID;
+        require!(
+            oracle_account.owner == &expected_owner,
+            ContractError::InvalidOracleAccount
+        );
 
         // Deserialize using borsh
         let price_update: PriceUpdateV2 = anchor_lang::prelude::borsh::BorshDeserialize::deserialize(&mut &data[8..])
@@ -271,25 +409,31 @@ impl OraclePrice {
                 msg!("Failed to parse as PriceUpdateV2: {:?}", e);
                 ContractError::InvalidOracleAccount
             })?;
+        Ok(price_update)
+    }
+
+    /// Validated price of a PriceUpdateV2 account and its publish time, staleness is left
+    /// to the caller
+    fn read_pyth_price_update(
+        oracle_account: &AccountInfo,
+        use_ema: bool,
+    ) -> Result<(OraclePrice, i64)> {
+        let price_update = Self::parse_pyth_price_update(oracle_account)?;
 
-        let clock = Clock::get()?;
-        
         // Extract the feed ID from the price message (available for debugging)
         let _feed_id = &price_update.price_message.feed_id;
         
-        // Get price with staleness check - using the struct methods
         let price_message = &price_update.price_message;
-        
-        // Check staleness
-        let age = clock.unix_timestamp - price_message.publish_time;
-        require!(
-            age <= Self::MAX_PRICE_AGE_SEC as i64,
-            ContractError::StaleOraclePrice
-        );
+        // The feed's EMA is Pyth's time-weighted average, slow to follow a wick
+        let (feed_price, feed_conf) = if use_ema {
+            (price_message.ema_price, price_message.ema_conf)
+        } else {
+            (price_message.price, price_message.conf)
+        };
         
         // Validate price confidence - confidence should be reasonable relative to price
-        let confidence_bps = if price_message.price > 0 {
-            ((price_message.conf as u128 * 10000) / price_message.price as u128) as u64
+        let confidence_bps = if feed_price > 0 {
+            ((feed_conf as u128 * 10000) / feed_price as u128) as u64
         } else {
             u64::MAX // Invalid if price is zero
         };
@@ -298,21 +442,25 @@ impl OraclePrice {
             ContractError::LowConfidencePrice
         );
         
-        msg!("Pyth price: {}, exponent: {}, confidence: {}, age: {} seconds", 
-             price_message.price, price_message.exponent, price_message.conf,
-             age);
+        msg!("Pyth price: {}, exponent: {}, confidence: {}, ema: {}", 
+             feed_price, price_message.exponent, feed_conf, use_ema);
         
         // Reject negative prices - this indicates oracle failure
         require!(
-            price_message.price > 0,
+            feed_price > 0,
             ContractError::InvalidOraclePrice
         );
-        let price_value = price_message.price as u64;
+        Self::validate_exponent(price_message.exponent)?;
+        let price_value = feed_price as u64;
         
-        Ok(OraclePrice {
-            price: price_value,
-            exponent: price_message.exponent,
-        })
+        Ok((
+            OraclePrice {
+                price: price_value,
+                exponent: price_message.exponent,
+                confidence_bps,
+            },
+            price_message.publish_time,
+        ))
     }
 
     /// Better implementation with explicit feed_id string
@@ -387,11 +535,13 @@ impl OraclePrice {
             price_message.price > 0,
             ContractError::InvalidOraclePrice
         );
+        Self::validate_exponent(price_message.exponent)?;
         let price_value = price_message.price as u64;
         
         Ok(OraclePrice {
             price: price_value,
             exponent: price_message.exponent,
+            confidence_bps,
         })
     }
 }
@@ -415,4 +565,74 @@ impl FeedId {
     
     /// USDT/USD feed ID
     pub const USDT_USD: &'static str = "0x2b89b9dc8fdf9f34709a5b106b472f0f39bb6ca8ce04b0fd7f2e971688e2e53b";
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyth_solana_receiver_sdk::price_update::{PriceFeedMessage, VerificationLevel};
+
+    const PUBLISH_TIME: i64 = 1_700_000_000;
+
+    // Serialized PriceUpdateV2 for a $150 feed published at PUBLISH_TIME
+    fn price_update_data(exponent: i32) -> Vec<u8> {
+        let price = 150 * 10i64.pow(exponent.unsigned_abs().min(12));
+        let price_update = PriceUpdateV2 {
+            write_authority: Pubkey::default(),
+            verification_level: VerificationLevel::Full,
+            price_message: PriceFeedMessage {
+                feed_id: [1; 32],
+                price,
+                conf: 0,
+                exponent,
+                publish_time: PUBLISH_TIME,
+                prev_publish_time: PUBLISH_TIME - 1,
+                ema_price: price,
+                ema_conf: 0,
+            },
+            posted_slot: 0,
+        };
+        let mut data = Vec::new();
+        price_update.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    fn read_at_publish_time(data: &mut [u8], owner: &Pubkey) -> Result<OraclePrice> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 1_000_000;
+        let account = AccountInfo::new(&key, false, false, &mut lamports, data, owner, false, 0);
+        OraclePrice::read_pyth_price_at(&account, PUBLISH_TIME)
+    }
+
+    #[test]
+    fn feed_exponents_outside_the_window_are_rejected() {
+        let owner = pyth_solana_receiver_sdk::ID;
+        for exponent in [OraclePrice::MIN_ORACLE_EXPONENT, -8, OraclePrice::MAX_ORACLE_EXPONENT] {
+            let price = read_at_publish_time(&mut price_update_data(exponent), &owner).unwrap();
+            assert_eq!(price.exponent, exponent);
+            assert!((price.get_price() - 150.0).abs() < 1e-9);
+        }
+
+        for exponent in [OraclePrice::MIN_ORACLE_EXPONENT - 1, -30, OraclePrice::MAX_ORACLE_EXPONENT + 1, 8] {
+            let err = read_at_publish_time(&mut price_update_data(exponent), &owner).unwrap_err();
+            assert_eq!(err, ContractError::InvalidOracleExponent.into(), "exponent {}", exponent);
+        }
+    }
+
+    #[test]
+    fn price_accounts_not_owned_by_the_pyth_receiver_are_rejected() {
+        // Same layout and a valid price, but a look-alike owner
+        let owner = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let mut lamports = 1_000_000;
+        let mut data = price_update_data(-8);
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+
+        let err = OraclePrice::new_from_oracle(&account, PUBLISH_TIME, false).unwrap_err();
+        assert_eq!(err, ContractError::InvalidOracleAccount.into());
+        let err = OraclePrice::new_from_oracle(&account, PUBLISH_TIME, true).unwrap_err();
+        assert_eq!(err, ContractError::InvalidOracleAccount.into());
+
+        let err = read_at_publish_time(&mut price_update_data(-8), &owner).unwrap_err();
+        assert_eq!(err, ContractError::InvalidOracleAccount.into());
+    }
+}
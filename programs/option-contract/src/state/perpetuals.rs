@@ -1,32 +1,27 @@
 use crate::{
+    errors::{PerpetualError, TradingError},
     math::{self},
+    state::{Contract, Custody, ExerciseStyle, OptionDetail, OraclePrice, Pool},
     traits::TradingPosition,
+    utils::option_pricing::black_scholes_with_borrow_rate,
 };
 use anchor_lang::prelude::*;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
 pub enum Side {
+    #[default]
     Long,
     Short,
 }
 
-impl Default for Side {
-    fn default() -> Self {
-        Self::Long
-    }
-}
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default)]
 pub enum OrderType {
+    #[default]
     Market,     // Market position (immediate execution)
     Limit,      // Limit order (pending execution)
 }
 
-impl Default for OrderType {
-    fn default() -> Self {
-        Self::Market
-    }
-}
 
 #[account]
 #[derive(Default, Debug)]
@@ -52,7 +47,7 @@ pub struct Position {
     pub execution_time: Option<i64>,        // When limit order was executed (None for market orders)
     
     // Risk Management (Set at open, used for liquidation)
-    pub liquidation_price: u64,              // Pre-calculated for efficiency
+    pub liquidation_price: u64,              // Pre-calculated at the margin tier of the last open, resize or collateral change
     
     // Borrow Fee Tracking (side-specific)
     pub cumulative_interest_snapshot: u128,  // Pool's cumulative borrow rate at position open (side-specific)
@@ -73,21 +68,296 @@ pub struct Position {
     pub tp_sl_orderbook: Option<Pubkey>,    // Optional reference to TpSlOrderbook account
     
     // Limit Order (for limit perp)
-    pub trigger_price: Option<u64>,         // Price to execute limit order
+    pub trigger_price: Option<u64>,         // Price to execute limit order (worst fill for stop-limit)
     pub trigger_above_threshold: bool,      // true = execute when price >= trigger
     
     pub bump: u8,
+
+    // Fields below were appended after the first release; accounts created before them are
+    // grown with migrate_account, and the zero-filled tail reads as None / 0 / false
+
+    pub bankruptcy_price: u64,              // Price where equity hits zero (bad debt beyond this)
+
+    // Stop-Limit (optional activation stage before the limit is live)
+    pub stop_price: Option<u64>,            // Activation price, direction given by trigger_above_threshold
+    pub stop_activated: bool,               // Set once the stop has been crossed
+    
+    // LP Collateral (pool LP tokens held by the transfer authority instead of custody tokens)
+    pub lp_collateral_amount: u64,          // LP tokens posted, returned on close
+    pub lp_collateral_usd: u64,             // Their USD value at deposit, included in collateral_usd
+
+    // Referral attribution set at open
+    pub referrer: Option<Pubkey>,
+
+    // Option hedge (set through set_position_hedge)
+    pub hedge_option: Option<Pubkey>,       // Option of the same owner this perp offsets
+    pub hedged_usd: u64,                    // Notional of that option, caps the size discounted
+    pub hedge_expiry: i64,                  // Option expiry, the discount lapses after it
+    pub hedge_fee_discount_usd: u64,        // Borrow fees waived by the hedge discount so far
+
+    // Pending limit orders past this can be expired by a keeper, None = good till cancelled
+    pub expiry_time: Option<i64>,
+
+    // Max-loss protection bought at open: until max_loss_expiry the position settles no worse
+    // than max_loss_price and the pool absorbs losses beyond max_loss_usd (0 = unprotected)
+    pub max_loss_usd: u64,
+    pub max_loss_price: u64,
+    pub max_loss_expiry: i64,
+
+    // Pending limit order resting in the pool's LimitOrderBook
+    pub in_limit_order_book: bool,
+}
+
+
+/// Amounts settled by closing part or all of a perp, from Pool::compute_close_settlement
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CloseSettlement {
+    pub is_full_close: bool,
+    pub size_usd: u64,           // Closed notional
+    pub collateral_amount: u64,  // Collateral tokens released
+    pub collateral_usd: u64,
+    pub locked_amount: u64,      // Pool backing released
+    pub trade_fees: u64,         // Share of the trade fees charged at open
+    pub realized_pnl: i64,
+    pub borrow_fees: u64,        // Share of the accrued borrow fees
+    pub close_fee_usd: u64,      // Close fee on the closed notional, at least the trade fee share
+    pub net_settlement_usd: i64, // Collateral + P&L - fees, negative past bankruptcy
+    pub settlement_usd: u64,     // Net settlement floored at zero
 }
 
+impl CloseSettlement {
+    /// Borrow fees the pool actually receives: they come out of collateral and P&L first,
+    /// so past bankruptcy only the part that equity covered is collected
+    pub fn get_collected_borrow_fees(&self) -> u64 {
+        let equity = (self.collateral_usd as i64).saturating_add(self.realized_pnl).max(0) as u64;
+        self.borrow_fees.min(equity)
+    }
+
+    /// Trade and close fees the pool actually receives, out of what is left after borrow fees
+    pub fn get_collected_close_fees(&self) -> u64 {
+        let equity = (self.collateral_usd as i64)
+            .saturating_add(self.realized_pnl)
+            .saturating_sub(self.borrow_fees as i64)
+            .max(0) as u64;
+        self.close_fee_usd.min(equity)
+    }
+}
 
 impl Position {
     pub const LEN: usize = 8 + std::mem::size_of::<Position>() + 33; // Added 33 bytes for Option<Pubkey>
     
     // 250x leverage = 0.4% initial margin
     pub const MAX_LEVERAGE: f64 = 250.0;
+    pub const MAX_LEVERAGE_BPS: u64 = 2_500_000; // MAX_LEVERAGE with 10_000 = 1x
     pub const MIN_INITIAL_MARGIN_BPS: u64 = 40; // 1.0% for 100x leverage
     pub const LIQUIDATION_MARGIN_BPS: u64 = 20; // 0.4% liquidation threshold
     pub const EXITING_FEE_BPS: u64 = 10;
+    pub const DEFAULT_CLOSE_FEE_BPS: u64 = 0; // close fee while close_fee_bps is unset; trade_fees already covers the exit
+    pub const HEALTH_FACTOR_SCALE: u64 = 1_000_000; // 1.0
+    pub const LIMIT_EXPIRY_KEEPER_FEE_BPS: u64 = 10; // 0.1% of the collateral of an expired limit
+    pub const LIQUIDATION_SETTLEMENT_BAND_BPS: u64 = 100; // 1% around the EMA price
+    pub const MAX_LOSS_COVER_PERIOD_SEC: i64 = 30 * 86_400; // horizon the protection is priced for and lasts
+    pub const FULL_CLOSE_PERCENTAGE: u64 = 100_000_000; // close_percentage of a full close (6 decimals)
+    
+    /// Price the owner's residual is settled at on liquidation: the live price held within
+    /// LIQUIDATION_SETTLEMENT_BAND_BPS of the EMA price
+    pub fn get_liquidation_settlement_price(live_price: u64, ema_price: u64) -> Result<u64> {
+        let band = math::checked_as_u64(math::checked_div(
+            math::checked_mul(ema_price as u128, Self::LIQUIDATION_SETTLEMENT_BAND_BPS as u128)?,
+            10_000u128,
+        )?)?;
+        Ok(live_price.clamp(ema_price.saturating_sub(band), ema_price.saturating_add(band)))
+    }
+
+    /// Leverage (10_000 = 1x) and initial margin in bps of `size` against `collateral`, both in
+    /// the same unit. Integer math with leverage rounded up and margin rounded down, so a size
+    /// a hair over the limit is rejected rather than rounded into it.
+    pub fn get_leverage_and_margin_bps(size: u64, collateral: u64) -> Result<(u64, u64)> {
+        require!(collateral > 0 && size > 0, PerpetualError::InvalidLeverage);
+        // u64 * 10_000 always fits in u128; out of range results saturate and fail the bounds
+        let leverage_bps = math::checked_ceil_div(size as u128 * 10_000, collateral as u128)?;
+        let margin_bps = math::checked_div(collateral as u128 * 10_000, size as u128)?;
+        Ok((
+            u64::try_from(leverage_bps).unwrap_or(u64::MAX),
+            u64::try_from(margin_bps).unwrap_or(u64::MAX),
+        ))
+    }
+
+    /// The owner liquidating their own position, as the signer or through the reward account.
+    /// Self-liquidation waives the liquidator reward, so it never pays better than closing and
+    /// the waived reward stays in the owner's settlement.
+    pub fn is_self_liquidation(&self, liquidator: &Pubkey, liquidator_reward_owner: &Pubkey) -> bool {
+        *liquidator == self.owner || *liquidator_reward_owner == self.owner
+    }
+
+    /// New positions must sit between 1x and MAX_LEVERAGE_BPS inclusive
+    pub fn validate_leverage_bps(leverage_bps: u64) -> Result<()> {
+        require!(
+            (10_000..=Self::MAX_LEVERAGE_BPS).contains(&leverage_bps),
+            PerpetualError::InvalidLeverage
+        );
+        Ok(())
+    }
+
+    /// The trigger of a new limit order must wait for price to move from `current_price`
+    /// toward it, in the direction the side implies: a plain limit fills at a better price
+    /// (long below, short above), a stop-limit activates on a breakout (long above, short below)
+    pub fn validate_limit_trigger(
+        side: Side,
+        trigger_price: Option<u64>,
+        stop_price: Option<u64>,
+        trigger_above_threshold: bool,
+        current_price: u64,
+    ) -> Result<()> {
+        let trigger = stop_price.or(trigger_price).ok_or(TradingError::InvalidPrice)?;
+        let expect_above = match side {
+            Side::Long => stop_price.is_some(),
+            Side::Short => stop_price.is_none(),
+        };
+        let valid_trigger = if expect_above { trigger > current_price } else { trigger < current_price };
+        require!(
+            trigger_above_threshold == expect_above && valid_trigger,
+            PerpetualError::InvalidTriggerDirection
+        );
+        Ok(())
+    }
+
+    /// Tokens and USD value of a collateral removal, requested either as `collateral_amount`
+    /// tokens of the payout asset or as `remove_usd` converted at the payout asset's price
+    pub fn get_collateral_removal(
+        collateral_amount: u64,
+        remove_usd: Option<u64>,
+        payout_price: &OraclePrice,
+        payout_decimals: u8,
+    ) -> Result<(u64, u64)> {
+        let (token_amount, usd_amount) = match remove_usd {
+            Some(remove_usd) => {
+                require!(collateral_amount == 0, TradingError::InvalidAmount);
+                (math::usd_to_token_amount(remove_usd, payout_price, payout_decimals)?, remove_usd)
+            }
+            None => (
+                collateral_amount,
+                math::token_amount_to_usd(collateral_amount, payout_price, payout_decimals)?,
+            ),
+        };
+        require!(token_amount > 0, TradingError::InvalidAmount);
+        Ok((token_amount, usd_amount))
+    }
+
+    /// Price at which a position of `size_usd` opened at `entry_price` has lost `max_loss_usd`
+    pub fn get_max_loss_price(entry_price: u64, size_usd: u64, max_loss_usd: u64, side: Side) -> Result<u64> {
+        let size_after_loss = match side {
+            Side::Long => math::checked_sub(size_usd, max_loss_usd)?,
+            Side::Short => math::checked_add(size_usd, max_loss_usd)?,
+        };
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(entry_price as u128, size_after_loss as u128)?,
+            size_usd as u128,
+        )?)
+    }
+
+    /// USD premium for max-loss protection struck at `max_loss_price`: the put (long) or call
+    /// (short) the pool writes, priced on the same terms as open_option for
+    /// MAX_LOSS_COVER_PERIOD_SEC, with the underlying's buy markup
+    pub fn get_max_loss_premium_usd(
+        sol_custody: &Custody,
+        usdc_custody: &Custody,
+        sol_price: f64,
+        entry_price: u64,
+        size_usd: u64,
+        max_loss_price: u64,
+        side: Side,
+    ) -> Result<u64> {
+        let is_call = side == Side::Short;
+        let (token_locked, token_owned) = if is_call {
+            (sol_custody.token_locked, sol_custody.token_owned)
+        } else {
+            (usdc_custody.token_locked, usdc_custody.token_owned)
+        };
+        let premium_per_unit = sol_custody.apply_option_buy_markup(black_scholes_with_borrow_rate(
+            sol_price,
+            math::scaled_price_to_f64(max_loss_price)?,
+            Self::MAX_LOSS_COVER_PERIOD_SEC as f64 / (365.25 * 24.0 * 3600.0),
+            is_call,
+            token_locked,
+            token_owned,
+            true, // the underlying is SOL on both sides
+            ExerciseStyle::American,
+        )?)?;
+        let units = math::checked_float_div(
+            size_usd as f64 / Contract::USD_SCALE as f64,
+            math::scaled_price_to_f64(entry_price)?,
+        )?;
+        math::checked_as_u64(premium_per_unit * units * Contract::USD_SCALE as f64)
+    }
+
+    pub fn is_max_loss_active(&self, current_time: i64) -> bool {
+        self.max_loss_price > 0 && current_time <= self.max_loss_expiry
+    }
+
+    /// True once the price has reached the max-loss floor of a live protection; keepers can then
+    /// close the position through liquidate at the floor
+    pub fn is_max_loss_triggered(&self, current_price: u64, current_time: i64) -> bool {
+        self.is_max_loss_active(current_time)
+            && match self.side {
+                Side::Long => current_price <= self.max_loss_price,
+                Side::Short => current_price >= self.max_loss_price,
+            }
+    }
+
+    /// P&L a close settles at: the price is held at the max-loss floor while the protection is live
+    pub fn calculate_protected_pnl(&self, current_price: u64, current_time: i64) -> Result<i64> {
+        let settlement_price = if self.is_max_loss_active(current_time) {
+            match self.side {
+                Side::Long => current_price.max(self.max_loss_price),
+                Side::Short => current_price.min(self.max_loss_price),
+            }
+        } else {
+            current_price
+        };
+        self.calculate_pnl(settlement_price)
+    }
+
+    /// Share of `amount` that closing `close_percentage` of the position settles
+    pub fn get_close_portion(amount: u64, close_percentage: u64) -> Result<u64> {
+        if close_percentage >= Self::FULL_CLOSE_PERCENTAGE {
+            return Ok(amount);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(amount as u128, close_percentage as u128)?,
+            Self::FULL_CLOSE_PERCENTAGE as u128,
+        )?)
+    }
+
+    /// Book a close computed by Pool::compute_close_settlement. A full close zeroes the
+    /// position and marks it closed; a partial close leaves the rest open.
+    pub fn apply_close(&mut self, settlement: &CloseSettlement, current_time: i64) -> Result<()> {
+        if settlement.is_full_close {
+            self.is_liquidated = true; // Mark as closed
+            self.size_usd = 0;
+            self.collateral_amount = 0;
+            self.collateral_usd = 0;
+            self.locked_amount = 0;
+            self.trade_fees = 0;
+            self.lp_collateral_amount = 0;
+            self.lp_collateral_usd = 0;
+        } else {
+            self.size_usd = math::checked_sub(self.size_usd, settlement.size_usd)?;
+            self.collateral_amount = math::checked_sub(self.collateral_amount, settlement.collateral_amount)?;
+            self.collateral_usd = math::checked_sub(self.collateral_usd, settlement.collateral_usd)?;
+            self.locked_amount = math::checked_sub(self.locked_amount, settlement.locked_amount)?;
+            self.trade_fees = math::checked_sub(self.trade_fees, settlement.trade_fees)?;
+        }
+        self.borrow_fees_paid = math::checked_add(self.borrow_fees_paid, settlement.borrow_fees)?;
+        self.accrued_borrow_fees = math::checked_sub(self.accrued_borrow_fees, settlement.borrow_fees)?;
+        self.update_time = current_time;
+        Ok(())
+    }
+
+    /// Notional borrowed from the pool: position size beyond the posted collateral
+    pub fn get_borrow_size_usd(&self) -> u64 {
+        self.size_usd.saturating_sub(self.collateral_usd)
+    }
     
     pub fn get_initial_leverage(&self) -> Result<u64> {
         if self.collateral_usd == 0 {
@@ -135,51 +405,85 @@ impl Position {
         Ok(())
     }
 
-    // Calculate and accrue time-based borrow fees
-    pub fn calculate_and_accrue_borrow_fees(
-        &mut self,
-        current_time: i64,
-        current_borrow_rate_bps: u32, // Annual percentage rate in basis points
-    ) -> Result<u64> {
-        // Limit orders don't accrue borrow fees
-        if self.order_type == OrderType::Limit {
+    /// Borrow fee on the current size for `elapsed_sec` at `borrow_rate_bps` (annual)
+    pub fn get_borrow_fee_at_rate(&self, elapsed_sec: i64, borrow_rate_bps: u32) -> Result<u64> {
+        if self.order_type == OrderType::Limit || elapsed_sec <= 0 {
             return Ok(0);
         }
-        
-        if current_time <= self.last_borrow_fees_update_time {
-            return Ok(0); // No time elapsed
-        }
-
-        let time_elapsed_seconds = math::checked_sub(current_time, self.last_borrow_fees_update_time)? as u128;
-        
-        // Convert APR to per-second rate: rate_bps / (365 * 24 * 3600 * 10000)
-        // Formula: (position_size_usd * rate_bps * time_elapsed_seconds) / (365 * 24 * 3600 * 10000)
-        let seconds_per_year = 365u128 * 24 * 3600; // 31,536,000 seconds per year
-        let basis_points_scale = 10_000u128;
-        
-        let borrow_fee_accrued = math::checked_div(
+        // (size_usd * rate_bps * elapsed) / (seconds per year * 10_000)
+        math::checked_as_u64(math::checked_div(
             math::checked_mul(
-                math::checked_mul(self.size_usd as u128, current_borrow_rate_bps as u128)?,
-                time_elapsed_seconds
+                math::checked_mul(self.size_usd as u128, borrow_rate_bps as u128)?,
+                elapsed_sec as u128,
             )?,
-            math::checked_mul(seconds_per_year, basis_points_scale)?
-        )?;
+            math::checked_mul(Pool::SECONDS_PER_YEAR, 10_000u128)?,
+        )?)
+    }
 
-        let borrow_fee_accrued_u64 = math::checked_as_u64(borrow_fee_accrued)?;
+    /// Borrow fee on the current size for the growth of its side's cumulative borrow index
+    /// since the position last settled
+    pub fn get_index_borrow_fee(&self, borrow_index: u128) -> Result<u64> {
+        if self.order_type == OrderType::Limit {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                self.size_usd as u128,
+                math::checked_sub(borrow_index, self.cumulative_interest_snapshot)?,
+            )?,
+            Pool::BORROW_INDEX_SCALE,
+        )?)
+    }
 
-        // Update accrued fees and timestamp
-        self.accrued_borrow_fees = math::checked_add(self.accrued_borrow_fees, borrow_fee_accrued_u64)?;
-        self.last_borrow_fees_update_time = current_time;
-        self.update_time = current_time;
+    /// Point the hedge at `option`'s current notional and expiry, or drop it once the option
+    /// is closed, exercised or otherwise no longer valid
+    pub fn sync_option_hedge(&mut self, option_key: Pubkey, option: &OptionDetail) -> Result<()> {
+        require!(self.hedge_option == Some(option_key), PerpetualError::InvalidHedge);
+        if option.valid {
+            self.hedged_usd = option.get_notional_usd(option.quantity)?;
+            self.hedge_expiry = option.expired_date;
+        } else {
+            self.clear_option_hedge();
+        }
+        Ok(())
+    }
 
-        Ok(borrow_fee_accrued_u64)
+    pub fn clear_option_hedge(&mut self) {
+        self.hedge_option = None;
+        self.hedged_usd = 0;
+        self.hedge_expiry = 0;
     }
 
+    /// Stop-limit orders only become fillable once the stop price has been crossed
+    pub fn is_stop_limit_order(&self) -> bool {
+        self.stop_price.is_some()
+    }
+    
+    pub fn is_stop_triggered(&self, current_price: u64) -> bool {
+        match self.stop_price {
+            Some(stop_price) if self.trigger_above_threshold => current_price >= stop_price,
+            Some(stop_price) => current_price <= stop_price,
+            None => false,
+        }
+    }
+    
     pub fn should_execute_limit_order(&self, current_price: u64) -> bool {
         if self.order_type != OrderType::Limit {
             return false;
         }
         
+        // Activated stop-limit: fill no worse than the limit price
+        if self.is_stop_limit_order() {
+            if !self.stop_activated {
+                return false;
+            }
+            return match (self.trigger_price, self.side) {
+                (Some(limit_price), Side::Long) => current_price <= limit_price,
+                (Some(limit_price), Side::Short) => current_price >= limit_price,
+                (None, _) => false,
+            };
+        }
+        
         if let Some(trigger_price) = self.trigger_price {
             if self.trigger_above_threshold {
                 current_price >= trigger_price
@@ -195,7 +499,9 @@ impl Position {
         self.order_type = OrderType::Market;
         self.entry_price = execution_price;
         self.trigger_price = None;
+        self.stop_price = None;
         self.execution_time = Some(current_time);  // Track when limit order was executed
+        self.expiry_time = None;
         self.update_time = current_time;
         Ok(())
     }
@@ -216,6 +522,22 @@ impl Position {
         self.order_type == OrderType::Limit && self.execution_time.is_none()
     }
     
+    /// Check if this position is live: a market order, or a limit order that has been filled.
+    /// Only these carry PnL and fees on close; a pending limit is refunded by cancel_limit_order.
+    pub fn is_executed(&self) -> bool {
+        self.order_type == OrderType::Market && self.execution_time.is_some()
+    }
+    
+    /// A limit order resting in the shared book is only filled, cancelled or expired together
+    /// with the book, so the book never keeps an entry for a settled order
+    pub fn require_limit_order_book(&self, book_passed: bool) -> Result<()> {
+        require!(
+            !self.in_limit_order_book || book_passed,
+            PerpetualError::LimitOrderBookRequired
+        );
+        Ok(())
+    }
+
     /// Check if this is an executed limit order (now market position)
     pub fn is_executed_limit_order(&self) -> bool {
         self.order_type == OrderType::Market && 
@@ -234,7 +556,24 @@ impl Position {
         }
     }
     
-    pub fn is_liquidatable_by_margin(&self, current_price: u64) -> Result<bool> {
+    /// True once the price has moved past the bankruptcy price, i.e. the loss exceeds collateral
+    pub fn is_past_bankruptcy(&self, current_price: u64) -> bool {
+        match self.side {
+            Side::Long => current_price <= self.bankruptcy_price,
+            Side::Short => current_price >= self.bankruptcy_price,
+        }
+    }
+    
+    /// Margin-based liquidation check. `liquidation_buffer_bps` raises the trigger above the
+    /// maintenance margin so keepers can act before the position is underwater after fees.
+    /// `maintenance_margin_bps` is the custody's tier for this size (see
+    /// `Custody::get_maintenance_margin_bps`).
+    pub fn is_liquidatable_by_margin(
+        &self,
+        current_price: u64,
+        maintenance_margin_bps: u64,
+        liquidation_buffer_bps: u64,
+    ) -> Result<bool> {
         if self.order_type == OrderType::Limit {
             return Ok(false);
         }
@@ -243,12 +582,7 @@ impl Position {
         let current_equity = if pnl >= 0 {
             self.collateral_usd + pnl as u64
         } else {
-            let loss = (-pnl) as u64;
-            if loss >= self.collateral_usd {
-                0
-            } else {
-                self.collateral_usd - loss
-            }
+            self.collateral_usd.saturating_sub((-pnl) as u64)
         };
         
         let margin_ratio_bps = math::checked_as_u64(math::checked_div(
@@ -256,7 +590,63 @@ impl Position {
             self.size_usd as u128,
         )?)?;
         
-        Ok(margin_ratio_bps <= Self::LIQUIDATION_MARGIN_BPS)
+        let trigger_margin_bps = math::checked_add(maintenance_margin_bps, liquidation_buffer_bps)?;
+        
+        Ok(margin_ratio_bps <= trigger_margin_bps)
+    }
+    
+    /// Equity after fees divided by the maintenance margin requirement (the size tier's margin
+    /// plus the pool's buffer), scaled by `HEALTH_FACTOR_SCALE`. At or below 1.0 the position can
+    /// be liquidated by margin.
+    pub fn health_factor(
+        &self,
+        current_price: u64,
+        fees_usd: u64,
+        maintenance_margin_bps: u64,
+        liquidation_buffer_bps: u64,
+    ) -> Result<u64> {
+        let maintenance_margin_usd = math::checked_div(
+            math::checked_mul(
+                self.size_usd as u128,
+                math::checked_add(maintenance_margin_bps, liquidation_buffer_bps)? as u128,
+            )?,
+            10_000u128,
+        )?;
+        if maintenance_margin_usd == 0 {
+            return Ok(u64::MAX);
+        }
+        
+        let pnl = self.calculate_pnl(current_price)?;
+        let equity = (self.collateral_usd as i128 + pnl as i128 - fees_usd as i128).max(0) as u128;
+        
+        let health_factor = math::checked_div(
+            math::checked_mul(equity, Self::HEALTH_FACTOR_SCALE as u128)?,
+            maintenance_margin_usd,
+        )?;
+        Ok(health_factor.min(u64::MAX as u128) as u64)
+    }
+    
+    /// Rejects the operation if it would leave the position liquidatable. Fees counted are the
+    /// accrued borrow fees and the exit fee, which are both deducted at liquidation.
+    pub fn require_healthy(
+        &self,
+        current_price: u64,
+        maintenance_margin_bps: u64,
+        liquidation_buffer_bps: u64,
+    ) -> Result<()> {
+        let fees_usd = math::checked_add(self.accrued_borrow_fees, self.trade_fees)?;
+        let health_factor = self.health_factor(
+            current_price,
+            fees_usd,
+            maintenance_margin_bps,
+            liquidation_buffer_bps,
+        )?;
+        msg!("Health factor: {}", health_factor as f64 / Self::HEALTH_FACTOR_SCALE as f64);
+        require!(
+            health_factor > Self::HEALTH_FACTOR_SCALE,
+            PerpetualError::HealthFactorTooLow
+        );
+        Ok(())
     }
     
     pub fn calculate_pnl(&self, current_price: u64) -> Result<i64> {
@@ -325,4 +715,217 @@ impl TradingPosition for Position {
     fn update_timestamp(&mut self, current_time: i64) {
         self.update_time = current_time;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Custody, MarginTier};
+
+    #[test]
+    fn integer_leverage_path_rejects_just_past_the_limit() {
+        let leverage = |size, collateral| {
+            Position::get_leverage_and_margin_bps(size, collateral)
+                .and_then(|(bps, _)| Position::validate_leverage_bps(bps))
+        };
+        let invalid: Result<()> = Err(PerpetualError::InvalidLeverage.into());
+
+        // Exactly 250x passes for collateral of any size, one unit more does not
+        for collateral in [1u64, 3, 1_000_000, 123_456_789_000] {
+            let at_limit = collateral * 250;
+            assert!(leverage(at_limit, collateral).is_ok());
+            assert_eq!(leverage(at_limit + 1, collateral), invalid);
+        }
+        // 1x is the floor; leverage rounds up, so it takes a full bps under to fall below
+        assert!(leverage(1_000_000, 1_000_000).is_ok());
+        assert!(leverage(999_999, 1_000_000).is_ok());
+        assert_eq!(leverage(999_900, 1_000_000), invalid);
+        // Tiny collateral against a huge size saturates and still fails
+        assert_eq!(leverage(u64::MAX, 1), invalid);
+    }
+
+    #[test]
+    fn limit_trigger_direction_must_match_the_side() {
+        let current_price = 100_000_000;
+        let below = Some(90_000_000);
+        let above = Some(110_000_000);
+        let check = |side, trigger_price, stop_price, above_threshold| {
+            Position::validate_limit_trigger(side, trigger_price, stop_price, above_threshold, current_price)
+        };
+
+        // Plain limits buy the dip and sell the rally
+        check(Side::Long, below, None, false).unwrap();
+        check(Side::Short, above, None, true).unwrap();
+        assert!(check(Side::Long, below, None, true).is_err());
+        assert!(check(Side::Long, above, None, false).is_err());
+        assert!(check(Side::Short, above, None, false).is_err());
+        assert!(check(Side::Short, below, None, true).is_err());
+
+        // Stop-limits activate on a breakout, whatever the limit price
+        check(Side::Long, above, above, true).unwrap();
+        check(Side::Short, below, below, false).unwrap();
+        assert!(check(Side::Long, above, above, false).is_err());
+        assert!(check(Side::Long, above, below, true).is_err());
+        assert!(check(Side::Short, below, below, true).is_err());
+        assert!(check(Side::Short, below, above, false).is_err());
+
+        // A trigger at the current price would fill immediately
+        assert!(check(Side::Long, Some(current_price), None, false).is_err());
+        assert!(check(Side::Long, None, None, false).is_err());
+    }
+
+    #[test]
+    fn collateral_removal_accepts_tokens_or_usd() {
+        let sol_price = OraclePrice::new(15_000_000_000, -8); // $150
+        let usdc_price = OraclePrice::new(100_000_000, -8);
+
+        // Token amount of the payout asset
+        assert_eq!(
+            Position::get_collateral_removal(2_000_000_000, None, &sol_price, 9).unwrap(),
+            (2_000_000_000, 300_000_000)
+        );
+        assert_eq!(
+            Position::get_collateral_removal(100_000_000, None, &usdc_price, 6).unwrap(),
+            (100_000_000, 100_000_000)
+        );
+
+        // USD value, converted at the payout asset's price
+        assert_eq!(
+            Position::get_collateral_removal(0, Some(300_000_000), &sol_price, 9).unwrap(),
+            (2_000_000_000, 300_000_000)
+        );
+        assert_eq!(
+            Position::get_collateral_removal(0, Some(100_000_000), &usdc_price, 6).unwrap(),
+            (100_000_000, 100_000_000)
+        );
+
+        // Exactly one denomination, and something to remove
+        assert!(Position::get_collateral_removal(1_000, Some(100_000_000), &usdc_price, 6).is_err());
+        assert!(Position::get_collateral_removal(0, None, &usdc_price, 6).is_err());
+        assert!(Position::get_collateral_removal(0, Some(0), &usdc_price, 6).is_err());
+    }
+
+    #[test]
+    fn larger_positions_liquidate_at_a_higher_margin() {
+        let base = Position::LIQUIDATION_MARGIN_BPS;
+        let mut custody = Custody::default();
+        custody
+            .set_margin_tiers(&[
+                MarginTier { min_size_usd: 0, maintenance_margin_bps: base },
+                MarginTier { min_size_usd: 1_000_000_000_000, maintenance_margin_bps: 150 },
+            ])
+            .unwrap();
+        let position = |size_usd: u64| Position {
+            order_type: OrderType::Market,
+            side: Side::Long,
+            entry_price: 100_000_000,
+            size_usd,
+            collateral_usd: size_usd / 10, // 10x
+            ..Default::default()
+        };
+        let small = position(1_000_000_000); // $1k
+        let large = position(2_000_000_000_000); // $2M
+
+        // A 9% drop leaves both at a 1% margin ratio, which only the large tier liquidates
+        let price = 91_000_000;
+        let small_margin = custody.get_maintenance_margin_bps(small.size_usd);
+        let large_margin = custody.get_maintenance_margin_bps(large.size_usd);
+        assert_eq!((small_margin, large_margin), (base, 150));
+        assert!(!small.is_liquidatable_by_margin(price, small_margin, 0).unwrap());
+        assert!(large.is_liquidatable_by_margin(price, large_margin, 0).unwrap());
+
+        // The liquidation price of the larger position sits closer to entry
+        let small_price = crate::utils::calculate_liquidation_price(100_000_000, 10.0, Side::Long, small_margin).unwrap();
+        let large_price = crate::utils::calculate_liquidation_price(100_000_000, 10.0, Side::Long, large_margin).unwrap();
+        assert!(large_price > small_price);
+    }
+
+    #[test]
+    fn owner_liquidating_themselves_gets_no_reward() {
+        let owner = Pubkey::new_unique();
+        let keeper = Pubkey::new_unique();
+        let position = Position { owner, size_usd: 1_000_000_000, ..Default::default() };
+        let contract = Contract { liquidator_reward_bps: 50, ..Default::default() };
+        let net_settlement_usd: i64 = 20_000_000;
+
+        let owner_settlement = |liquidator: &Pubkey, reward_owner: &Pubkey| {
+            let reward_usd = if position.is_self_liquidation(liquidator, reward_owner) {
+                0
+            } else {
+                contract.get_liquidator_reward(position.size_usd).unwrap()
+            };
+            (reward_usd, net_settlement_usd - reward_usd as i64)
+        };
+
+        // A keeper is paid out of the residual
+        assert_eq!(owner_settlement(&keeper, &keeper), (5_000_000, 15_000_000));
+        // The owner signing, or routing the reward to their own account, keeps the full residual
+        assert_eq!(owner_settlement(&owner, &owner), (0, net_settlement_usd));
+        assert_eq!(owner_settlement(&owner, &keeper), (0, net_settlement_usd));
+        assert_eq!(owner_settlement(&keeper, &owner), (0, net_settlement_usd));
+    }
+
+    #[test]
+    fn liquidation_buffer_triggers_before_maintenance_while_collateral_remains() {
+        let margin = Position::LIQUIDATION_MARGIN_BPS;
+        let buffer = 100;
+        let position = Position {
+            order_type: OrderType::Market,
+            side: Side::Long,
+            entry_price: 100_000_000,
+            size_usd: 1_000_000_000,
+            collateral_usd: 100_000_000, // 10x
+            ..Default::default()
+        };
+
+        // 9.1% down leaves 90 bps of equity: above maintenance, inside the buffer
+        let price = 90_900_000;
+        assert!(!position.is_liquidatable_by_margin(price, margin, 0).unwrap());
+        assert!(position.is_liquidatable_by_margin(price, margin, buffer).unwrap());
+        assert!(position.calculate_pnl(price).unwrap() > -(position.collateral_usd as i64));
+
+        // Outside the buffer neither triggers
+        let price = 91_500_000;
+        assert!(!position.is_liquidatable_by_margin(price, margin, 0).unwrap());
+        assert!(!position.is_liquidatable_by_margin(price, margin, buffer).unwrap());
+    }
+
+    #[test]
+    fn leverage_rounds_up_and_margin_rounds_down() {
+        assert_eq!(Position::get_leverage_and_margin_bps(10_000, 1_000).unwrap(), (100_000, 1_000));
+        assert_eq!(Position::get_leverage_and_margin_bps(1_000, 3).unwrap(), (3_333_334, 30));
+        assert!(Position::get_leverage_and_margin_bps(0, 1_000).is_err());
+        assert!(Position::get_leverage_and_margin_bps(1_000, 0).is_err());
+        // Out of range leverage saturates instead of failing the math
+        assert_eq!(Position::get_leverage_and_margin_bps(u64::MAX, 1).unwrap().0, u64::MAX);
+    }
+
+    #[test]
+    fn max_leverage_boundary_is_exact() {
+        let collateral = 1_000_000;
+        let at_limit = collateral * (Position::MAX_LEVERAGE_BPS / 10_000);
+        let (leverage_bps, _) = Position::get_leverage_and_margin_bps(at_limit, collateral).unwrap();
+        assert_eq!(leverage_bps, Position::MAX_LEVERAGE_BPS);
+
+        // One unit over rounds up past the limit rather than into it
+        let (leverage_bps, _) = Position::get_leverage_and_margin_bps(at_limit + 1, collateral).unwrap();
+        assert!(leverage_bps > Position::MAX_LEVERAGE_BPS);
+    }
+
+    #[test]
+    fn max_loss_price_moves_against_the_side() {
+        let entry_price = 100_000_000;
+        let size_usd = 1_000_000_000;
+        let max_loss_usd = 100_000_000; // 10% of size
+        assert_eq!(
+            Position::get_max_loss_price(entry_price, size_usd, max_loss_usd, Side::Long).unwrap(),
+            90_000_000
+        );
+        assert_eq!(
+            Position::get_max_loss_price(entry_price, size_usd, max_loss_usd, Side::Short).unwrap(),
+            110_000_000
+        );
+        // A long can't lose more than its size
+        assert!(Position::get_max_loss_price(entry_price, size_usd, size_usd + 1, Side::Long).is_err());
+    }
+}
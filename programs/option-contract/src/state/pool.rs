@@ -2,9 +2,9 @@ use std::cmp::Ordering;
 
 use anchor_lang::prelude::*;
 
-use crate::{errors::PoolError, math, utils::{self, BorrowRateCurve, Fraction}};
+use crate::{errors::{ContractError, FutureError, OptionError, PerpetualError, PoolError, TradingError}, math, utils::{self, BorrowRateCurve, Fraction}};
 
-use super::{Contract, Custody, OraclePrice};
+use super::{CloseSettlement, Contract, Custody, Future, LockedProduct, OptionDetail, OraclePrice, Position, Side, TpSlOrderbook};
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct TokenRatios {
@@ -13,6 +13,29 @@ pub struct TokenRatios {
     pub max: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct OptionCustodyCombo {
+    pub underlying: Pubkey, // custody priced by the option
+    pub locked: Pubkey,     // custody backing the payout
+    pub premium: Pubkey,    // custody the premium is paid in
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct StrikeExpiryBucket {
+    pub strike_price: u64, // scaled strike
+    pub expiry: i64,
+    pub notional_usd: u64, // open option notional at this strike and expiry (0 = free slot)
+}
+
+#[derive(Copy, Clone, PartialEq, Default, Debug)]
+pub struct LpDepositQuote {
+    pub fee_amount: u64,       // add liquidity fee kept from the deposit
+    pub deposit_amount: u64,   // tokens added to token_owned
+    pub token_amount_usd: u64, // value of deposit_amount
+    pub lp_amount: u64,
+    pub bonus_lp_amount: u64,  // rebalance bonus, drawn from rebalance_incentive_budget
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Pool {
@@ -26,7 +49,8 @@ pub struct Pool {
     // Borrow/Funding rate curve for dynamic rate calculation
     pub borrow_rate_curve: BorrowRateCurve,
     
-    // Cumulative borrow interest rates for position tracking
+    // Cumulative borrow fee per unit of size by side (BORROW_INDEX_SCALE = 100%), advanced
+    // by update_borrow_index
     pub cumulative_interest_rate_long: u128,
     pub cumulative_interest_rate_short: u128,
     pub last_rate_update: i64,
@@ -45,11 +69,464 @@ pub struct Pool {
     pub total_option_notional_usd: u128,      // Total USD value of all open options
     pub total_option_time_value: u128,        // Sum of (notional * time_to_expiry) for all options
     pub last_fixed_rate_update: i64,          // Last time fixed rates were updated
+
+    // Designated perp/future assets (set at add_pool)
+    pub sol_mint: Pubkey,                     // Underlying (base) asset mint
+    pub usdc_mint: Pubkey,                    // Stable (quote) asset mint
+
+    // Risk configuration (set via set_pool_config)
+    pub liquidation_buffer_bps: u64,          // Extra margin above maintenance at which perps become liquidatable
+    pub collateral_adjust_fee_bps: u64,       // Fee charged on add/remove collateral, kept by the pool
+    pub min_update_interval_sec: i64,         // Minimum gap between keeper borrow fee updates per position
+    pub perp_trade_fee_bps: u64,              // Perp trade fee on size, charged at close (0 = Position::EXITING_FEE_BPS)
+
+    // Last full AUM recompute; liquidity events in between adjust aum_usd by their own delta
+    pub aum_reconciled_time: i64,
+
+    // Allowed futures expiry range from open (set at add_pool, 0 = default)
+    pub min_future_duration_sec: i64,
+    pub max_future_duration_sec: i64,
+
+    // Bonus LP minted on deposits into custodies below ratios.min (set via set_pool_config)
+    pub rebalance_incentive_bps: u64,
+    pub rebalance_incentive_budget: u64, // LP tokens left to hand out as bonus
+
+    // Largest share of free custody liquidity a single perp may lock (0 = no cap)
+    pub max_position_fraction_bps: u64,
+
+    // Custody triples options may be written with (empty = any registered custodies)
+    pub option_custody_combos: [OptionCustodyCombo; Pool::MAX_OPTION_CUSTODY_COMBOS],
+    pub option_custody_combo_count: u8,
+
+    // LP tokens escrowed in queued withdrawal requests
+    pub pending_withdrawal_lp: u64,
+
+    // Bounds on the borrow rate a perp is charged, in annual bps accrued per second: max caps
+    // the side rate feeding the borrow index (0 = uncapped), min is the least a position pays
+    // after any hedge discount over each settled period
+    pub max_funding_rate_bps: u64,
+    pub min_funding_rate_bps: u64,
+
+    // Grace period after a perp opens during which only insolvent positions can be liquidated
+    pub liquidation_cooldown_sec: i64,
+
+    // Open option notional per (strike, expiry) and the ceiling on any one of them (0 = no cap)
+    pub option_strike_expiry_buckets: [StrikeExpiryBucket; Pool::MAX_STRIKE_EXPIRY_BUCKETS],
+    pub max_strike_expiry_notional_usd: u64,
+
+    // Share of each custody's token_owned that opens can never lock (set via set_pool_config)
+    pub reserve_ratio_bps: u64,
+
+    // Base fee on perp settlements that drain a custody below its target ratio (0 = off)
+    pub settlement_haircut_bps: u64,
+
+    // When the cumulative borrow indexes started accruing; positions last settled before it
+    // pay that gap at the rate of their next settlement (0 = not started yet)
+    pub borrow_index_start_time: i64,
+
+    // Liquidity add/remove fees are paid to LPs by epoch, weighted by LP balance held over the
+    // epoch, through claim_lp_fees (0 = off, fees stay out of the distribution)
+    pub lp_fee_epoch_duration_sec: i64,
+
+    // Option expiries must fall on offset + k * interval (UTC), e.g. 86_400 for daily or
+    // 604_800 with a 4-day offset for Monday expiries (0 = any expiry)
+    pub option_expiry_interval_sec: i64,
+    pub option_expiry_offset_sec: i64,
+
+    // Option premium markup at the top size tier, charged on buys that lock a large share of
+    // the free liquidity in the locked custody (0 = off)
+    pub max_option_size_impact_bps: u64,
+
+    // TP and SL orders allowed per side in new orderbooks (0 = TpSlOrderbook::MAX_ORDERS)
+    pub max_tp_sl_orders: u8,
+
+    // Smallest perp size in USD that can be opened or left open after a reduction (0 = $1 floor only)
+    pub min_position_usd: u64,
+
+    // Borrow rate discount for a perp fully covering an offsetting option of its owner,
+    // scaled down by the share covered (0 = off)
+    pub hedge_borrow_fee_discount_bps: u64,
+
+    // Extra open fee per bps of oracle confidence interval, in bps of that interval, charged
+    // below OraclePrice::MAX_CONFIDENCE_INTERVAL_BPS (0 = off)
+    pub confidence_fee_multiplier_bps: u64,
+
+    // Fee on closed notional charged alike by perp and future closes, see get_close_fee
+    // (0 = Position::DEFAULT_CLOSE_FEE_BPS for perps, Future::SETTLEMENT_FEE_BPS for futures)
+    pub close_fee_bps: u64,
+
+    // Listed options: while set, option opens, edits and rolls must use one of the allowed
+    // scaled strikes and expiries (sorted ascending, unused slots 0)
+    pub option_grid_only: bool,
+    pub allowed_option_strikes: [u64; Pool::MAX_ALLOWED_OPTION_GRID_ENTRIES],
+    pub allowed_option_expiries: [i64; Pool::MAX_ALLOWED_OPTION_GRID_ENTRIES],
+
+    // Keeper rewards the pool can still pay out, funded from fees it actually collected:
+    // the keeper share of borrow fees settled at close and limit order expiry fees
+    pub keeper_reward_budget_usd: u64,
+
+    // Open option notional the pool has written by type, the option side of the net exposure
+    // option hedges are measured against (options opened before these were tracked are left out)
+    pub call_option_notional_usd: u64,
+    pub put_option_notional_usd: u64,
+
+    // Perp, future and option notional open against the pool, summed into
+    // Contract::global_notional_usd by sync_global_notional so opens and closes never write the
+    // contract (exposure opened before this was tracked is left out)
+    pub notional_usd: u64,
+    pub synced_notional_usd: u64, // notional_usd as of the last sync
+
+    // Latest clock time seen by an instruction writing this pool, kept here rather than on the
+    // contract so trading paths never write the contract
+    pub last_seen_time: i64,
 }
 
 impl Pool {
     pub const LEN: usize = 8 + 64 + std::mem::size_of::<Pool>();
 
+    /// Account size for a pool holding `custody_count` custodies and `ratio_count` ratios
+    pub fn get_space(custody_count: usize, ratio_count: usize) -> usize {
+        Self::LEN
+            + custody_count * std::mem::size_of::<Pubkey>()
+            + ratio_count * std::mem::size_of::<TokenRatios>()
+    }
+    pub const MAX_LIQUIDATION_BUFFER_BPS: u64 = 500; // 5%
+    pub const MAX_COLLATERAL_ADJUST_FEE_BPS: u64 = 100; // 1%
+    pub const MAX_PERP_TRADE_FEE_BPS: u64 = 100; // 1%
+    pub const MAX_MIN_UPDATE_INTERVAL_SEC: i64 = 86_400; // 1 day
+    pub const MAX_LIQUIDATION_COOLDOWN_SEC: i64 = 300; // 5 minutes
+    pub const BALANCED_DEPOSIT_TOLERANCE_BPS: u64 = 100; // 1% deviation from target ratio per leg
+    pub const AUM_RECONCILE_INTERVAL_SEC: i64 = 2; // Max age of aum_usd for incremental updates, a few slots
+    pub const DEFAULT_MIN_FUTURE_DURATION_SEC: i64 = 3_600; // 1 hour
+    pub const DEFAULT_MAX_FUTURE_DURATION_SEC: i64 = 365 * 24 * 3_600; // 1 year
+    pub const MAX_REBALANCE_INCENTIVE_BPS: u64 = 200; // 2%
+    pub const MAX_POSITION_FRACTION_BPS: u64 = 10_000; // 100%
+    pub const MAX_OPTION_CUSTODY_COMBOS: usize = 8;
+    pub const MAX_STRIKE_EXPIRY_BUCKETS: usize = 16;
+    pub const MAX_RESERVE_RATIO_BPS: u64 = 5_000; // 50%
+    pub const MAX_SETTLEMENT_HAIRCUT_BPS: u64 = 100; // 1%
+    pub const MIN_LP_FEE_EPOCH_DURATION_SEC: i64 = 3_600; // 1 hour
+    pub const MAX_LP_FEE_EPOCH_DURATION_SEC: i64 = 30 * 86_400; // 30 days
+    pub const MIN_OPTION_EXPIRY_INTERVAL_SEC: i64 = 3_600; // 1 hour
+    pub const MAX_OPTION_EXPIRY_INTERVAL_SEC: i64 = 30 * 86_400; // 30 days
+    pub const MAX_OPTION_SIZE_IMPACT_BPS: u64 = 2_000; // 20%
+    const FIXED_RATE_PREMIUM_MAX_BPS: u32 = 5_000; // top tier of calculate_fixed_rate_premium
+    pub const MAX_MIN_POSITION_USD: u64 = 10_000 * Contract::USD_SCALE as u64; // $10k
+    pub const MAX_HEDGE_BORROW_FEE_DISCOUNT_BPS: u64 = 5_000; // 50%
+    pub const MAX_CONFIDENCE_FEE_MULTIPLIER_BPS: u64 = 50_000; // 5x the confidence interval
+    pub const MAX_CLOSE_FEE_BPS: u64 = 100; // 1%
+    pub const MAX_ALLOWED_OPTION_GRID_ENTRIES: usize = 16;
+    pub const SECONDS_PER_YEAR: u128 = 365 * 24 * 3_600;
+    pub const BORROW_INDEX_SCALE: u128 = 1_000_000_000_000_000; // index growth charging 100% of size
+
+    /// Perp trade fee rate, Position::EXITING_FEE_BPS for pools that never set one
+    pub fn get_perp_trade_fee_bps(&self) -> u64 {
+        if self.perp_trade_fee_bps > 0 {
+            self.perp_trade_fee_bps
+        } else {
+            Position::EXITING_FEE_BPS
+        }
+    }
+
+    /// Perp trade fee in USD for a position of `size_usd`
+    pub fn get_perp_trade_fee(&self, size_usd: u64) -> Result<u64> {
+        math::checked_div(
+            math::checked_mul(size_usd, self.get_perp_trade_fee_bps())?,
+            10_000,
+        )
+    }
+
+    /// Fees recorded on a perp open of `size_usd`: the trade fee plus the confidence fee for a
+    /// feed `confidence_bps` wide
+    pub fn get_perp_open_fee(&self, size_usd: u64, confidence_bps: u64) -> Result<u64> {
+        math::checked_add(
+            self.get_perp_trade_fee(size_usd)?,
+            self.get_confidence_fee(size_usd, confidence_bps)?,
+        )
+    }
+
+    /// Close fee in USD on `size_usd` of closed notional: close_fee_bps of the closed size,
+    /// or `default_bps` while it is unset, so perps and futures pay the same once it is set.
+    /// The trade fee a perp priced at open only remains as a floor on it.
+    pub fn get_close_fee(&self, size_usd: u64, default_bps: u64) -> Result<u64> {
+        let close_fee_bps = if self.close_fee_bps > 0 {
+            self.close_fee_bps
+        } else {
+            default_bps
+        };
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(size_usd as u128, close_fee_bps as u128)?,
+            10_000u128,
+        )?)
+    }
+
+    /// Risk premium in USD on an open of `size_usd` priced with a feed `confidence_bps` wide
+    pub fn get_confidence_fee(&self, size_usd: u64, confidence_bps: u64) -> Result<u64> {
+        if self.confidence_fee_multiplier_bps == 0 || confidence_bps == 0 {
+            return Ok(0);
+        }
+        let fee_bps = math::checked_div(
+            math::checked_mul(confidence_bps as u128, self.confidence_fee_multiplier_bps as u128)?,
+            10_000u128,
+        )?;
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(size_usd as u128, fee_bps)?,
+            10_000u128,
+        )?)
+    }
+
+    /// Append allowed option custody triples; every custody must already be in the pool
+    pub fn add_option_custody_combos(&mut self, combos: &[OptionCustodyCombo]) -> Result<()> {
+        for combo in combos {
+            self.get_token_id(&combo.underlying)?;
+            self.get_token_id(&combo.locked)?;
+            self.get_token_id(&combo.premium)?;
+
+            let count = self.option_custody_combo_count as usize;
+            if self.option_custody_combos[..count].contains(combo) {
+                continue;
+            }
+            require!(
+                count < Self::MAX_OPTION_CUSTODY_COMBOS,
+                PoolError::InvalidPoolConfig
+            );
+            self.option_custody_combos[count] = *combo;
+            self.option_custody_combo_count += 1;
+        }
+        Ok(())
+    }
+
+    /// Check an option's (underlying, locked, premium) custodies against the allowlist
+    pub fn validate_option_custodies(
+        &self,
+        underlying: &Pubkey,
+        locked: &Pubkey,
+        premium: &Pubkey,
+    ) -> Result<()> {
+        let count = self.option_custody_combo_count as usize;
+        if count == 0 {
+            return Ok(());
+        }
+        require!(
+            self.option_custody_combos[..count].iter().any(|combo| {
+                combo.underlying == *underlying && combo.locked == *locked && combo.premium == *premium
+            }),
+            OptionError::InvalidCustodyCombination
+        );
+        Ok(())
+    }
+
+    /// Add option notional to its (strike, expiry) bucket and to the pool's call or put
+    /// notional, and return the bucket total. Slots of fully closed or already expired series
+    /// are reused. When every slot is taken the open is rejected if a cap is set, otherwise
+    /// the series is simply not tracked.
+    pub fn add_strike_expiry_notional(
+        &mut self,
+        strike_price: u64,
+        expiry: i64,
+        is_call: bool,
+        notional_usd: u64,
+        current_time: i64,
+    ) -> Result<u64> {
+        if is_call {
+            self.call_option_notional_usd = math::checked_add(self.call_option_notional_usd, notional_usd)?;
+        } else {
+            self.put_option_notional_usd = math::checked_add(self.put_option_notional_usd, notional_usd)?;
+        }
+
+        let cap = self.max_strike_expiry_notional_usd;
+        let slot = match self
+            .option_strike_expiry_buckets
+            .iter()
+            .position(|b| b.notional_usd > 0 && b.strike_price == strike_price && b.expiry == expiry)
+        {
+            Some(slot) => Some(slot),
+            None => self
+                .option_strike_expiry_buckets
+                .iter()
+                .position(|b| b.notional_usd == 0 || b.expiry < current_time),
+        };
+
+        let Some(slot) = slot else {
+            require!(cap == 0, OptionError::StrikeExpiryCapExceeded);
+            return Ok(notional_usd);
+        };
+
+        let bucket = &mut self.option_strike_expiry_buckets[slot];
+        if bucket.strike_price != strike_price || bucket.expiry != expiry {
+            *bucket = StrikeExpiryBucket { strike_price, expiry, notional_usd: 0 };
+        }
+        let bucket_notional_usd = math::checked_add(bucket.notional_usd, notional_usd)?;
+        require!(
+            cap == 0 || bucket_notional_usd <= cap,
+            OptionError::StrikeExpiryCapExceeded
+        );
+        bucket.notional_usd = bucket_notional_usd;
+        Ok(bucket_notional_usd)
+    }
+
+    /// Release option notional from the pool's call or put notional and from its (strike,
+    /// expiry) bucket, if that is still tracked
+    pub fn remove_strike_expiry_notional(&mut self, strike_price: u64, expiry: i64, is_call: bool, notional_usd: u64) {
+        if is_call {
+            self.call_option_notional_usd = self.call_option_notional_usd.saturating_sub(notional_usd);
+        } else {
+            self.put_option_notional_usd = self.put_option_notional_usd.saturating_sub(notional_usd);
+        }
+        if let Some(bucket) = self
+            .option_strike_expiry_buckets
+            .iter_mut()
+            .find(|b| b.notional_usd > 0 && b.strike_price == strike_price && b.expiry == expiry)
+        {
+            bucket.notional_usd = bucket.notional_usd.saturating_sub(notional_usd);
+            if bucket.notional_usd == 0 {
+                *bucket = StrikeExpiryBucket::default();
+            }
+        }
+    }
+
+    /// Reject an option expiry that is off the pool's expiry grid
+    pub fn validate_option_expiry(&self, expiry: i64) -> Result<()> {
+        if self.option_expiry_interval_sec > 0
+            && (expiry - self.option_expiry_offset_sec).rem_euclid(self.option_expiry_interval_sec) != 0
+        {
+            msg!("Expiry {} is off grid, next valid expiry: {}", expiry, self.get_next_option_expiry(expiry)?);
+            return err!(OptionError::InvalidExpiryDate);
+        }
+        Ok(())
+    }
+
+    /// In grid-only mode, reject options whose strike or expiry is not listed
+    pub fn validate_option_grid(&self, strike: f64, expiry: i64) -> Result<()> {
+        if !self.option_grid_only {
+            return Ok(());
+        }
+        let strike_price = math::f64_to_scaled_price(strike)?;
+        let strike_listed = self.allowed_option_strikes.iter().any(|s| *s > 0 && *s == strike_price);
+        let expiry_listed = self.allowed_option_expiries.iter().any(|e| *e > 0 && *e == expiry);
+        if !strike_listed || !expiry_listed {
+            msg!("Strike {} / expiry {} is not on the listed grid", strike_price, expiry);
+            return err!(OptionError::UnsupportedStrikeOrExpiry);
+        }
+        Ok(())
+    }
+
+    /// First valid option expiry strictly after `after`
+    pub fn get_next_option_expiry(&self, after: i64) -> Result<i64> {
+        if self.option_expiry_interval_sec <= 0 {
+            return math::checked_add(after, 1);
+        }
+        let since_offset = after - self.option_expiry_offset_sec;
+        math::checked_add(
+            after,
+            self.option_expiry_interval_sec - since_offset.rem_euclid(self.option_expiry_interval_sec),
+        )
+    }
+
+    /// LP fee epoch containing `current_time`, None while epoch distribution is off
+    pub fn get_lp_fee_epoch(&self, current_time: i64) -> Option<u64> {
+        if self.lp_fee_epoch_duration_sec <= 0 {
+            return None;
+        }
+        Some((current_time / self.lp_fee_epoch_duration_sec) as u64)
+    }
+
+    /// Tokens a new position or option may lock in `custody`: its free liquidity less the
+    /// reserve_ratio_bps share of token_owned held back for withdrawals and settlements
+    pub fn get_borrowable_amount(&self, custody: &Custody) -> Result<u64> {
+        self.get_borrowable_amount_after_release(custody, 0)
+    }
+
+    /// get_borrowable_amount once `released_amount` of the custody's lock is given back, for
+    /// an existing lock that is resized or renewed
+    pub fn get_borrowable_amount_after_release(
+        &self,
+        custody: &Custody,
+        released_amount: u64,
+    ) -> Result<u64> {
+        let reserve_amount = math::checked_as_u64(math::checked_div(
+            math::checked_mul(custody.token_owned as u128, self.reserve_ratio_bps as u128)?,
+            Contract::BPS_POWER,
+        )?)?;
+        Ok(custody
+            .available_for_open_after_release(released_amount)
+            .saturating_sub(reserve_amount))
+    }
+
+    /// Reject a perp opened below min_position_usd: dust positions cost keepers more than
+    /// their liquidation reward
+    pub fn check_min_position_size(&self, size_usd: u64) -> Result<()> {
+        require_gte!(size_usd, self.min_position_usd, TradingError::PositionTooSmall);
+        Ok(())
+    }
+
+    /// Reject a perp whose size_usd would exceed max_position_fraction_bps of the free liquidity
+    /// in its backing custody, so no single position dominates the pool. `released_amount` is the
+    /// position's own current lock, which counts as free when it grows.
+    pub fn check_position_concentration(
+        &self,
+        size_usd: u64,
+        custody: &Custody,
+        released_amount: u64,
+        price: &OraclePrice,
+    ) -> Result<()> {
+        if self.max_position_fraction_bps == 0 {
+            return Ok(());
+        }
+        let available_liquidity_usd = math::token_amount_to_usd(
+            self.get_borrowable_amount_after_release(custody, released_amount)?,
+            price,
+            custody.decimals,
+        )?;
+        let max_size_usd = math::checked_as_u64(math::checked_div(
+            math::checked_mul(available_liquidity_usd as u128, self.max_position_fraction_bps as u128)?,
+            Contract::BPS_POWER,
+        )?)?;
+        require_gte!(
+            max_size_usd,
+            size_usd,
+            PerpetualError::PositionTooLargeForPool
+        );
+        Ok(())
+    }
+
+    /// Cap a rate (annual bps) at the pool's max funding rate
+    pub fn cap_funding_rate_bps(&self, rate_bps: u32) -> u32 {
+        if self.max_funding_rate_bps > 0 {
+            (rate_bps as u64).min(self.max_funding_rate_bps) as u32
+        } else {
+            rate_bps
+        }
+    }
+
+    /// USD value of `lp_amount` LP tokens at the stored AUM
+    pub fn get_lp_token_value_usd(&self, lp_amount: u64, lp_supply: u64) -> Result<u64> {
+        require!(lp_supply > 0, PoolError::InvalidPoolBalanceError);
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.aum_usd, lp_amount as u128)?,
+            lp_supply as u128,
+        )?)
+    }
+
+    /// USD value of one whole LP token at the stored AUM, the 1:1 first-deposit rate while no
+    /// LP tokens exist
+    pub fn get_lp_share_price_usd(&self, lp_supply: u64) -> Result<u64> {
+        let one_lp_token = math::checked_pow(10u64, Contract::LP_DECIMALS as usize)?;
+        if lp_supply == 0 {
+            return Ok(one_lp_token);
+        }
+        self.get_lp_token_value_usd(one_lp_token, lp_supply)
+    }
+
+    /// LP tokens worth `amount_usd` at the stored AUM
+    pub fn get_lp_token_amount(&self, amount_usd: u64, lp_supply: u64) -> Result<u64> {
+        require!(self.aum_usd > 0, PoolError::InvalidPoolBalanceError);
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(amount_usd as u128, lp_supply as u128)?,
+            self.aum_usd,
+        )?)
+    }
+
     pub fn get_token_id(&self, custody: &Pubkey) -> Result<usize> {
         self.custodies
             .iter()
@@ -149,10 +626,21 @@ impl Pool {
         Ok(available_amount >= amount)
     }
 
+    /// Failover oracles passed after `blocks` per-custody blocks of remaining accounts, one per
+    /// custody in pool order (any account for custodies without one), empty when omitted
+    pub fn get_failover_oracles<'info>(
+        &self,
+        accounts: &'info [AccountInfo<'info>],
+        blocks: usize,
+    ) -> &'info [AccountInfo<'info>] {
+        accounts.get(blocks * self.custodies.len()..).unwrap_or_default()
+    }
+
     // Calculate Pool AUM
     pub fn get_assets_under_management_usd<'info>(
         &self,
         accounts: &'info [AccountInfo<'info>],
+        failover_oracles: &'info [AccountInfo<'info>],
         curtime: i64,
     ) -> Result<u128> {
         let mut pool_amount_usd: u128 = 0;
@@ -165,9 +653,11 @@ impl Pool {
             require_keys_eq!(accounts[idx].key(), custody);
             let custody = Account::<Custody>::try_from(custody_info)?;
 
-            require_keys_eq!(accounts[oracle_idx].key(), custody.oracle);
-
-            let token_price = OraclePrice::new_from_oracle(&accounts[oracle_idx], curtime, false)?;
+            let token_price = custody.get_oracle_price(
+                &accounts[oracle_idx],
+                failover_oracles.get(idx),
+                curtime,
+            )?;
             let token_amount_usd =
                 token_price.get_asset_amount_usd(custody.token_owned, custody.decimals)?;
             msg!("token_amount_usd: {}", token_amount_usd);
@@ -182,6 +672,55 @@ impl Pool {
         Ok(pool_amount_usd)
     }
 
+    /// Check a futures expiry against the pool's duration range, falling back to the defaults
+    /// for pools created before the range was configurable
+    pub fn validate_future_expiry(&self, expiry_timestamp: i64, current_time: i64) -> Result<()> {
+        let min_duration = if self.min_future_duration_sec > 0 {
+            self.min_future_duration_sec
+        } else {
+            Self::DEFAULT_MIN_FUTURE_DURATION_SEC
+        };
+        let max_duration = if self.max_future_duration_sec > 0 {
+            self.max_future_duration_sec
+        } else {
+            Self::DEFAULT_MAX_FUTURE_DURATION_SEC
+        };
+
+        require!(
+            expiry_timestamp <= math::checked_add(current_time, max_duration)?,
+            FutureError::ExpiryTooFar
+        );
+        require!(
+            expiry_timestamp >= math::checked_add(current_time, min_duration)?,
+            FutureError::ExpiryTooClose
+        );
+        Ok(())
+    }
+
+    /// Refresh `aum_usd` ahead of a liquidity event. With every custody and oracle passed in
+    /// `accounts` the AUM is fully recomputed; with none, the stored value is reused only within
+    /// a few slots of the last full recompute, so LP tokens are never minted or burned against
+    /// a price move the stored AUM has missed. Returns true when the incremental path is taken.
+    pub fn refresh_aum_usd<'info>(
+        &mut self,
+        accounts: &'info [AccountInfo<'info>],
+        failover_oracles: &'info [AccountInfo<'info>],
+        curtime: i64,
+    ) -> Result<bool> {
+        if accounts.is_empty() {
+            require!(
+                math::checked_sub(curtime, self.aum_reconciled_time)?
+                    <= Self::AUM_RECONCILE_INTERVAL_SEC,
+                PoolError::AumReconcileRequired
+            );
+            return Ok(true);
+        }
+
+        self.aum_usd = self.get_assets_under_management_usd(accounts, failover_oracles, curtime)?;
+        self.aum_reconciled_time = curtime;
+        Ok(false)
+    }
+
     pub fn get_add_liquidity_fee(
         &self,
         token_id: usize,
@@ -306,6 +845,36 @@ impl Pool {
         )
     }
 
+    /// Extra LP tokens for a deposit into a custody sitting below its minimum ratio,
+    /// on top of the reduced fee. Capped by the remaining incentive budget.
+    pub fn get_rebalance_bonus(
+        &self,
+        token_id: usize,
+        amount_add: u64,
+        lp_amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        if self.rebalance_incentive_bps == 0 || self.rebalance_incentive_budget == 0 {
+            return Ok(0);
+        }
+
+        let current_ratio = self.get_current_ratio(custody, token_price)?;
+        if current_ratio >= self.ratios[token_id].min {
+            return Ok(0);
+        }
+        let new_ratio = self.get_new_ratio(amount_add, 0, custody, token_price)?;
+        if new_ratio <= current_ratio {
+            return Ok(0);
+        }
+
+        let bonus = math::checked_as_u64(math::checked_div(
+            math::checked_mul(lp_amount as u128, self.rebalance_incentive_bps as u128)?,
+            Contract::BPS_POWER,
+        )?)?;
+        Ok(std::cmp::min(bonus, self.rebalance_incentive_budget))
+    }
+
     pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
         if fee == 0 || amount == 0 {
             return Ok(0);
@@ -333,6 +902,235 @@ impl Pool {
         )
     }
 
+    /// Haircut on a perp settlement of `amount` paid out of `custody`, priced like a
+    /// liquidity removal with settlement_haircut_bps as the base fee. Only charged when the
+    /// payout leaves the custody below its target ratio; the haircut stays with the LPs.
+    pub fn get_settlement_haircut(
+        &self,
+        token_id: usize,
+        amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        if self.settlement_haircut_bps == 0 || amount == 0 {
+            return Ok(0);
+        }
+        let new_ratio = self.get_new_ratio(0, amount, custody, token_price)?;
+        if new_ratio >= self.ratios[token_id].target {
+            return Ok(0);
+        }
+        self.get_fee(
+            token_id,
+            self.settlement_haircut_bps,
+            0u64,
+            amount,
+            custody,
+            token_price,
+        )
+    }
+
+    /// Settlement for closing `close_percentage` (Position::FULL_CLOSE_PERCENTAGE = all) of
+    /// `position` at `exit_price`, after settling its borrow fees. close_perp_position, TP/SL
+    /// execution, close_all_positions, liquidate and simulate_close_perp all price a close
+    /// through this, so a preview always matches the close it previews.
+    pub fn compute_close_settlement(
+        &mut self,
+        position: &mut Position,
+        close_percentage: u64,
+        exit_price: u64,
+        current_time: i64,
+        sol_custody: &Custody,
+        usdc_custody: &Custody,
+    ) -> Result<CloseSettlement> {
+        require!(
+            close_percentage > 0 && close_percentage <= Position::FULL_CLOSE_PERCENTAGE,
+            TradingError::InvalidAmount
+        );
+        let portion = |amount: u64| Position::get_close_portion(amount, close_percentage);
+
+        let pnl = position.calculate_protected_pnl(exit_price, current_time)?;
+        self.update_position_borrow_fees(position, current_time, sol_custody, usdc_custody)?;
+
+        let size_usd = portion(position.size_usd)?;
+        let collateral_usd = portion(position.collateral_usd)?;
+        let realized_pnl = if pnl >= 0 {
+            math::checked_as_i64(portion(pnl as u64)?)?
+        } else {
+            -math::checked_as_i64(portion(pnl.unsigned_abs())?)?
+        };
+        // Everything accrued so far is owed, including fees earlier keeper updates accrued
+        let borrow_fees = portion(position.accrued_borrow_fees)?;
+        let trade_fees = portion(position.trade_fees)?;
+        let close_fee_usd = self
+            .get_close_fee(size_usd, Position::DEFAULT_CLOSE_FEE_BPS)?
+            .max(trade_fees);
+
+        let net_settlement_usd = math::checked_as_i64(collateral_usd)?
+            .saturating_add(realized_pnl)
+            .saturating_sub(math::checked_as_i64(borrow_fees)?)
+            .saturating_sub(math::checked_as_i64(close_fee_usd)?);
+
+        Ok(CloseSettlement {
+            is_full_close: close_percentage == Position::FULL_CLOSE_PERCENTAGE,
+            size_usd,
+            collateral_amount: portion(position.collateral_amount)?,
+            collateral_usd,
+            locked_amount: portion(position.locked_amount)?,
+            trade_fees,
+            realized_pnl,
+            borrow_fees,
+            close_fee_usd,
+            net_settlement_usd,
+            settlement_usd: net_settlement_usd.max(0) as u64,
+        })
+    }
+
+    /// Release `locked_amount` of perp backing on the `side` custody, then check the payout
+    /// custody can pay `payout_tokens` out of what is free. The lock goes first so a winner in a
+    /// fully utilised custody is paid out of its own backing and the settlement reserve.
+    pub fn release_perp_payout(
+        side: Side,
+        locked_amount: u64,
+        receive_sol: bool,
+        payout_tokens: u64,
+        sol_custody: &mut Custody,
+        usdc_custody: &mut Custody,
+    ) -> Result<()> {
+        if side == Side::Long {
+            sol_custody.unlock_funds(LockedProduct::Perp, locked_amount)?;
+        } else {
+            usdc_custody.unlock_funds(LockedProduct::Perp, locked_amount)?;
+        }
+        let payout_available = if receive_sol {
+            sol_custody.available_for_payout()
+        } else {
+            usdc_custody.available_for_payout()
+        };
+        require_gte!(payout_available, payout_tokens, TradingError::InsufficientPoolLiquidity);
+        Ok(())
+    }
+
+    /// Settlement for closing `close_percentage` (Future::FULL_CLOSE = all) of `future` at
+    /// `spot_price`: collateral plus P&L less the close fee on the closed notional. Futures
+    /// carry no borrow or trade fees. close_future and close_all_positions both price a future
+    /// close through this.
+    pub fn compute_future_close_settlement(
+        &self,
+        future: &Future,
+        close_percentage: u64,
+        spot_price: u64,
+        current_time: i64,
+    ) -> Result<CloseSettlement> {
+        require!(
+            close_percentage > 0 && close_percentage <= Future::FULL_CLOSE,
+            TradingError::InvalidAmount
+        );
+        let portion = |amount: u64| Future::get_close_portion(amount, close_percentage);
+
+        let pnl = future.calculate_pnl(spot_price, current_time)?;
+        let pnl_portion = math::checked_as_i64(portion(pnl.unsigned_abs())?)?;
+        let realized_pnl = if pnl >= 0 { pnl_portion } else { -pnl_portion };
+
+        let size_usd = portion(future.size_usd)?;
+        let collateral_usd = portion(future.collateral_usd)?;
+        let close_fee_usd = self.get_close_fee(size_usd, Future::SETTLEMENT_FEE_BPS)?;
+        let net_settlement_usd = math::checked_as_i64(collateral_usd)?
+            .saturating_add(realized_pnl)
+            .saturating_sub(math::checked_as_i64(close_fee_usd)?);
+
+        Ok(CloseSettlement {
+            is_full_close: close_percentage == Future::FULL_CLOSE,
+            size_usd,
+            collateral_amount: portion(future.collateral_amount)?,
+            collateral_usd,
+            locked_amount: portion(future.locked_amount)?,
+            trade_fees: 0,
+            realized_pnl,
+            borrow_fees: 0,
+            close_fee_usd,
+            net_settlement_usd,
+            settlement_usd: net_settlement_usd.max(0) as u64,
+        })
+    }
+
+    /// Add the keeper share of `collected_fees_usd` to the keeper reward budget
+    pub fn fund_keeper_rewards(&mut self, contract: &Contract, collected_fees_usd: u64) -> Result<u64> {
+        let reward_usd = contract.get_keeper_reward(collected_fees_usd)?;
+        self.keeper_reward_budget_usd = math::checked_add(self.keeper_reward_budget_usd, reward_usd)?;
+        Ok(reward_usd)
+    }
+
+    /// Tokens of `custody` paid out for `settlement_usd`, and the settlement haircut kept
+    /// from them. A settlement deposited back as LP tokens pays it too, since those tokens can
+    /// be redeemed from the same custody right away.
+    pub fn get_settlement_payout(
+        &self,
+        token_id: usize,
+        settlement_usd: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<(u64, u64)> {
+        let gross_tokens = math::usd_to_token_amount(settlement_usd, token_price, custody.decimals)?;
+        let haircut = self.get_settlement_haircut(token_id, gross_tokens, custody, token_price)?;
+        Ok((math::checked_sub(gross_tokens, haircut)?, haircut))
+    }
+
+    /// Split a settlement of a position or future holding LP collateral: a profit over the LP
+    /// value is paid in the underlying, a loss is covered by burning LP tokens worth the
+    /// shortfall at the stored AUM. Returns (payout_usd, lp_returned, lp_burned); the LP supply
+    /// is only needed when LP tokens are burned.
+    pub fn get_lp_collateral_settlement(
+        &self,
+        lp_collateral_amount: u64,
+        lp_collateral_usd: u64,
+        settlement_usd: u64,
+        lp_supply: Option<u64>,
+    ) -> Result<(u64, u64, u64)> {
+        if lp_collateral_amount == 0 {
+            return Ok((settlement_usd, 0, 0));
+        }
+        if settlement_usd >= lp_collateral_usd {
+            return Ok((settlement_usd - lp_collateral_usd, lp_collateral_amount, 0));
+        }
+        let lp_supply = lp_supply.ok_or(PerpetualError::LpCollateralAccountsMissing)?;
+        let lp_to_burn = self
+            .get_lp_token_amount(lp_collateral_usd - settlement_usd, lp_supply)?
+            .min(lp_collateral_amount);
+        Ok((0, lp_collateral_amount - lp_to_burn, lp_to_burn))
+    }
+
+    /// LP tokens minted for depositing `amount` into `custody`, priced the way add_liquidity
+    /// prices a deposit against the current pool.aum_usd
+    pub fn get_lp_deposit_quote(
+        &self,
+        token_id: usize,
+        amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+        lp_supply: u64,
+    ) -> Result<LpDepositQuote> {
+        let fee_amount = self.get_add_liquidity_fee(token_id, amount, custody, token_price)?;
+        let deposit_amount = math::checked_sub(amount, fee_amount)?;
+        let token_amount_usd = token_price.get_asset_amount_usd(deposit_amount, custody.decimals)?;
+        let lp_amount = if self.aum_usd == 0 {
+            token_amount_usd
+        } else {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(token_amount_usd as u128, lp_supply as u128)?,
+                self.aum_usd,
+            )?)?
+        };
+        let bonus_lp_amount =
+            self.get_rebalance_bonus(token_id, deposit_amount, lp_amount, custody, token_price)?;
+        Ok(LpDepositQuote {
+            fee_amount,
+            deposit_amount,
+            token_amount_usd,
+            lp_amount,
+            bonus_lp_amount,
+        })
+    }
+
     // Calculate per-token utilization and borrow rate
     pub fn get_token_borrow_rate(&self, custody: &Custody) -> Result<Fraction> {
         if custody.token_owned == 0 {
@@ -348,9 +1146,96 @@ impl Pool {
         self.borrow_rate_curve.get_borrow_rate(utilization)
     }
     
+    /// Annual borrow rate in bps charged to perps on the side borrowing from `custody`
+    pub fn get_perp_borrow_rate_bps(&self, custody: &Custody) -> Result<u32> {
+        let borrow_rate = self.get_token_borrow_rate(custody)?;
+        Ok(self.cap_funding_rate_bps(borrow_rate.to_bps().unwrap_or(0u32)))
+    }
+
+    /// Advance the long and short cumulative borrow indexes to `current_time`. Every perp
+    /// path settles borrow fees through this before it changes utilization, so each stretch
+    /// of time is charged at the rate in force during it instead of the whole gap being
+    /// charged at the rate seen when the position is next touched.
+    pub fn update_borrow_index(
+        &mut self,
+        sol_custody: &Custody,
+        usdc_custody: &Custody,
+        current_time: i64,
+    ) -> Result<()> {
+        // Pools created before the index start it from zero on first use
+        if self.borrow_index_start_time == 0 {
+            self.borrow_index_start_time = current_time;
+            self.cumulative_interest_rate_long = 0;
+            self.cumulative_interest_rate_short = 0;
+            self.last_rate_update = current_time;
+            return Ok(());
+        }
+        if current_time <= self.last_rate_update {
+            return Ok(());
+        }
+
+        let elapsed = math::checked_sub(current_time, self.last_rate_update)? as u128;
+        let index_delta = |rate_bps: u32| -> Result<u128> {
+            math::checked_div(
+                math::checked_mul(math::checked_mul(rate_bps as u128, elapsed)?, Self::BORROW_INDEX_SCALE)?,
+                math::checked_mul(Self::SECONDS_PER_YEAR, 10_000u128)?,
+            )
+        };
+        // Longs borrow SOL, shorts borrow USDC
+        let long_delta = index_delta(self.get_perp_borrow_rate_bps(sol_custody)?)?;
+        let short_delta = index_delta(self.get_perp_borrow_rate_bps(usdc_custody)?)?;
+        self.cumulative_interest_rate_long = math::checked_add(self.cumulative_interest_rate_long, long_delta)?;
+        self.cumulative_interest_rate_short = math::checked_add(self.cumulative_interest_rate_short, short_delta)?;
+        self.last_rate_update = current_time;
+        Ok(())
+    }
+
+    /// Cumulative borrow index of `side`, as last advanced by update_borrow_index
+    pub fn get_borrow_index(&self, side: crate::state::Side) -> u128 {
+        match side {
+            crate::state::Side::Long => self.cumulative_interest_rate_long,
+            crate::state::Side::Short => self.cumulative_interest_rate_short,
+        }
+    }
+
+    /// Borrow fee discount of a perp tagged as an option hedge. Only the part of the perp that
+    /// offsets the pool's net exposure is discounted: with the perp left out, the pool must be
+    /// net short delta for a short hedge (written calls) or net long for a long one (written
+    /// puts), counting perp open interest from the pool's side and its call and put notional.
+    /// That part is further capped by the option's notional, and the discount lapses at expiry.
+    pub fn get_hedge_discount_bps(
+        &self,
+        position: &crate::state::Position,
+        current_time: i64,
+    ) -> Result<u64> {
+        if position.hedge_option.is_none()
+            || current_time > position.hedge_expiry
+            || position.size_usd == 0
+        {
+            return Ok(0);
+        }
+
+        // Pool delta in USD: it takes the other side of every perp and is short what it wrote
+        let pool_delta_usd = self.short_open_interest_usd as i128 - self.long_open_interest_usd as i128
+            + self.put_option_notional_usd as i128
+            - self.call_option_notional_usd as i128;
+        let offset_usd = match position.side {
+            crate::state::Side::Short => -(pool_delta_usd - position.size_usd as i128),
+            crate::state::Side::Long => pool_delta_usd + position.size_usd as i128,
+        };
+        let covered_usd = offset_usd
+            .clamp(0, position.size_usd as i128)
+            .min(position.hedged_usd as i128) as u128;
+
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.hedge_borrow_fee_discount_bps as u128, covered_usd)?,
+            position.size_usd as u128,
+        )?)
+    }
+
     // Update position borrow fees before any position modification
     pub fn update_position_borrow_fees(
-        &self,
+        &mut self,
         position: &mut crate::state::Position,
         current_time: i64,
         sol_custody: &Custody,
@@ -360,26 +1245,221 @@ impl Pool {
         if position.order_type == crate::state::OrderType::Limit {
             return Ok(0);
         }
-        
-        // Determine which custody to use based on position side
-        let relevant_custody = match position.side {
-            crate::state::Side::Long => sol_custody, // Long positions borrow SOL
-            crate::state::Side::Short => usdc_custody, // Short positions borrow USDC
+
+        self.update_borrow_index(sol_custody, usdc_custody, current_time)?;
+
+        // A position last settled before the index existed pays the gap up to its start once,
+        // at the current rate as before, and then follows the index from zero
+        let mut borrow_fee = 0;
+        if position.last_borrow_fees_update_time <= self.borrow_index_start_time {
+            let relevant_custody = match position.side {
+                crate::state::Side::Long => sol_custody, // Long positions borrow SOL
+                crate::state::Side::Short => usdc_custody, // Short positions borrow USDC
+            };
+            borrow_fee = position.get_borrow_fee_at_rate(
+                math::checked_sub(self.borrow_index_start_time, position.last_borrow_fees_update_time)?,
+                self.get_perp_borrow_rate_bps(relevant_custody)?,
+            )?;
+            position.cumulative_interest_snapshot = 0;
+        }
+        self.settle_borrow_fees_at_index(position, borrow_fee, current_time)
+    }
+
+    /// Settle a position's borrow fees up to the last borrow index update, at its current
+    /// hedge discount, before an option path changes that discount. Those paths don't carry
+    /// both custodies to advance the index, so the stretch since its last update is charged
+    /// at the new discount by the next settlement, as is the pre-index gap of an old position.
+    pub fn settle_position_borrow_fees_to_index(&self, position: &mut crate::state::Position) -> Result<u64> {
+        if position.order_type == crate::state::OrderType::Limit
+            || position.last_borrow_fees_update_time <= self.borrow_index_start_time
+            || position.last_borrow_fees_update_time >= self.last_rate_update
+        {
+            return Ok(0);
+        }
+        self.settle_borrow_fees_at_index(position, 0, self.last_rate_update)
+    }
+
+    /// Carry a change to `option` over to the perp tagged as its hedge: settle the perp at its
+    /// old discount, then rescale the hedge to the option's notional and expiry or drop it
+    /// once the option is no longer valid. A hedge whose perp was closed, transferred or
+    /// re-tagged is simply dropped from the option.
+    pub fn sync_option_hedge(
+        &self,
+        option: &mut OptionDetail,
+        option_key: Pubkey,
+        hedge_position: Option<&AccountInfo>,
+    ) -> Result<()> {
+        let Some(hedge_key) = option.hedge_position else {
+            return Ok(());
         };
-        
-        // Get current borrow rate for time-based calculation
-        let current_borrow_rate = self.get_token_borrow_rate(relevant_custody)?;
-        let current_borrow_rate_bps = current_borrow_rate.to_bps().unwrap_or(0u32);
-        
-        // Calculate and accrue time-based borrow fees
-        position.calculate_and_accrue_borrow_fees(current_time, current_borrow_rate_bps)
+        let hedge_position = hedge_position.ok_or(PerpetualError::InvalidHedge)?;
+        require_keys_eq!(hedge_position.key(), hedge_key, PerpetualError::InvalidHedge);
+
+        if hedge_position.owner == &crate::ID && !hedge_position.data_is_empty() {
+            let mut data = hedge_position.try_borrow_mut_data()?;
+            if let Ok(mut position) = Position::try_deserialize(&mut &data[..]) {
+                if position.hedge_option == Some(option_key) {
+                    self.settle_position_borrow_fees_to_index(&mut position)?;
+                    position.sync_option_hedge(option_key, option)?;
+                    position.try_serialize(&mut &mut data[..])?;
+                    if position.hedge_option.is_some() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        option.hedge_position = None;
+        Ok(())
+    }
+
+    fn settle_borrow_fees_at_index(
+        &self,
+        position: &mut crate::state::Position,
+        gap_fee: u64,
+        current_time: i64,
+    ) -> Result<u64> {
+        let borrow_index = self.get_borrow_index(position.side);
+        let elapsed = current_time.saturating_sub(position.last_borrow_fees_update_time);
+        let borrow_fee = math::checked_add(gap_fee, position.get_index_borrow_fee(borrow_index)?)?;
+
+        // A live option hedge offsets pool risk, so part of the fee is waived
+        let hedge_discount_bps = self.get_hedge_discount_bps(position, current_time)?;
+        let mut charged_fee = math::checked_sub(
+            borrow_fee,
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(borrow_fee as u128, hedge_discount_bps as u128)?,
+                Contract::BPS_POWER,
+            )?)?,
+        )?;
+
+        // The floor applies to what is actually charged over the settled period
+        let min_rate_bps = self.min_funding_rate_bps.min(u32::MAX as u64) as u32;
+        charged_fee = charged_fee.max(position.get_borrow_fee_at_rate(elapsed, min_rate_bps)?);
+
+        // Waived share kept on the position for audit
+        position.hedge_fee_discount_usd = math::checked_add(
+            position.hedge_fee_discount_usd,
+            borrow_fee.saturating_sub(charged_fee),
+        )?;
+        let borrow_fee = charged_fee;
+
+        position.update_accrued_borrow_fees(borrow_fee, borrow_index, current_time)?;
+        Ok(borrow_fee)
+    }
+
+    /// Apply a perp size change to open interest. Borrow fees must already be settled at
+    /// `current_time`: the rate depends on utilization, so settling after the change would
+    /// charge the elapsed period at the post-change rate.
+    pub fn update_open_interest(
+        &mut self,
+        position: &crate::state::Position,
+        size_delta_usd: u64,
+        is_increase: bool,
+        current_time: i64,
+    ) -> Result<()> {
+        require!(
+            position.order_type == crate::state::OrderType::Limit
+                || position.last_borrow_fees_update_time >= current_time,
+            PerpetualError::BorrowFeesNotSettled
+        );
+
+        let open_interest_usd = match position.side {
+            crate::state::Side::Long => &mut self.long_open_interest_usd,
+            crate::state::Side::Short => &mut self.short_open_interest_usd,
+        };
+        *open_interest_usd = if is_increase {
+            math::checked_add(*open_interest_usd, size_delta_usd as u128)?
+        } else {
+            math::checked_sub(*open_interest_usd, size_delta_usd as u128)?
+        };
+        Ok(())
+    }
+
+    /// Overwrite open interest with the size of the live `positions` plus the carried totals
+    /// of those not passed, returning the previous (long, short) pair. Pending limit orders
+    /// and liquidated positions hold no open interest.
+    pub fn reconcile_open_interest<'a>(
+        &mut self,
+        positions: impl IntoIterator<Item = &'a crate::state::Position>,
+        carried_long_open_interest_usd: u128,
+        carried_short_open_interest_usd: u128,
+    ) -> Result<(u128, u128)> {
+        let mut long_open_interest_usd = carried_long_open_interest_usd;
+        let mut short_open_interest_usd = carried_short_open_interest_usd;
+        for position in positions {
+            if position.is_liquidated || !position.is_executed() {
+                continue;
+            }
+            let open_interest_usd = match position.side {
+                crate::state::Side::Long => &mut long_open_interest_usd,
+                crate::state::Side::Short => &mut short_open_interest_usd,
+            };
+            *open_interest_usd = math::checked_add(*open_interest_usd, position.size_usd as u128)?;
+        }
+
+        let previous = (self.long_open_interest_usd, self.short_open_interest_usd);
+        self.long_open_interest_usd = long_open_interest_usd;
+        self.short_open_interest_usd = short_open_interest_usd;
+        Ok(previous)
     }
 
-    
     // Get current borrow rate for a specific custody token
     pub fn get_current_borrow_rate(&self, custody: &Custody) -> Result<Fraction> {
         self.get_token_borrow_rate(custody)
     }
+
+    /// Current time for every time-dependent path on this pool: the clock, checked and recorded
+    /// by observe_time. The record only persists when the instruction writes the pool.
+    pub fn get_time(&mut self, contract: &Contract) -> Result<i64> {
+        self.observe_time(contract.get_time()?)
+    }
+
+    /// Accept `current_time` if it is not behind last_seen_time and record it. A clock going
+    /// backwards would turn elapsed-time math negative, so it fails with ClockWentBackwards.
+    pub fn observe_time(&mut self, current_time: i64) -> Result<i64> {
+        if current_time < self.last_seen_time {
+            msg!("Clock {} is behind pool last seen time {}", current_time, self.last_seen_time);
+            return err!(ContractError::ClockWentBackwards);
+        }
+        self.last_seen_time = current_time;
+        Ok(current_time)
+    }
+
+    /// Global notional as of the last sync, moved by this pool's opens and closes since
+    pub fn get_global_notional_estimate(&self, contract: &Contract) -> u64 {
+        contract
+            .global_notional_usd
+            .saturating_sub(self.synced_notional_usd)
+            .saturating_add(self.notional_usd)
+    }
+
+    /// Record new exposure from an open. It is blocked while the contract is paused or the
+    /// global estimate has reached the ceiling; the open that reaches it goes through, and the
+    /// next sync_global_notional trips the breaker for every pool.
+    pub fn add_notional(&mut self, contract: &Contract, notional_usd: u64) -> Result<()> {
+        require!(!contract.paused, ContractError::ContractPaused);
+        require!(
+            contract.max_global_notional_usd == 0
+                || self.get_global_notional_estimate(contract) < contract.max_global_notional_usd,
+            ContractError::GlobalNotionalLimitReached
+        );
+        self.notional_usd = math::checked_add(self.notional_usd, notional_usd)?;
+        Ok(())
+    }
+
+    /// Release exposure on a close
+    pub fn remove_notional(&mut self, notional_usd: u64) {
+        self.notional_usd = self.notional_usd.saturating_sub(notional_usd);
+    }
+
+    /// True while any perp, option or future is open against the pool
+    pub fn has_open_positions(&self) -> bool {
+        self.long_open_interest_usd > 0
+            || self.short_open_interest_usd > 0
+            || self.call_option_notional_usd > 0
+            || self.put_option_notional_usd > 0
+            || self.total_future_notional_usd > 0
+    }
     
     // Get current open interest 
     pub fn get_open_interest_usd(&self) -> Result<(u128, u128)> {
@@ -424,7 +1504,7 @@ impl Pool {
             total_possible
         )?;
         
-        Ok(math::checked_as_u64(utilization_2d.min(10_000))?)
+        math::checked_as_u64(utilization_2d.min(10_000))
     }
     
     /// Calculate fixed interest rate for new futures/options using 2D utilization
@@ -439,7 +1519,7 @@ impl Pool {
         let fixed_rate_premium = self.calculate_fixed_rate_premium(utilization_2d_bps)?;
         
         // Fixed rate = base rate + premium based on 2D utilization
-        Ok(math::checked_add(base_rate_bps, fixed_rate_premium)?)
+        math::checked_add(base_rate_bps, fixed_rate_premium)
     }
     
     /// Calculate premium for fixed rates based on 2D utilization
@@ -456,10 +1536,59 @@ impl Pool {
             8001..=9000 => Ok(800),  // 80-90%: 8% premium
             9001..=9500 => Ok(1500), // 90-95%: 15% premium
             9501..=9800 => Ok(3000), // 95-98%: 30% premium
-            _ => Ok(5000),           // 98%+: 50% premium (very high to discourage)
+            _ => Ok(Self::FIXED_RATE_PREMIUM_MAX_BPS), // 98%+: 50% premium (very high to discourage)
         }
     }
     
+    /// Per-side order cap for a new TP/SL orderbook
+    pub fn get_max_tp_sl_orders(&self) -> u8 {
+        if self.max_tp_sl_orders == 0 {
+            TpSlOrderbook::MAX_ORDERS as u8
+        } else {
+            self.max_tp_sl_orders
+        }
+    }
+
+    /// Premium markup in bps for an option locking `lock_amount` out of `available_liquidity`
+    pub fn get_option_size_impact_bps(&self, lock_amount: u64, available_liquidity: u64) -> Result<u64> {
+        if self.max_option_size_impact_bps == 0 {
+            return Ok(0);
+        }
+        let lock_share_bps = if available_liquidity == 0 {
+            Contract::BPS_POWER as u64
+        } else {
+            math::checked_as_u64(
+                math::checked_div(
+                    math::checked_mul(lock_amount as u128, Contract::BPS_POWER)?,
+                    available_liquidity as u128,
+                )?
+                .min(Contract::BPS_POWER),
+            )?
+        };
+        // Same progressive curve as fixed rates, scaled so its top tier is the configured max
+        let tier_bps = self.calculate_fixed_rate_premium(lock_share_bps)?;
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.max_option_size_impact_bps as u128, tier_bps as u128)?,
+            Self::FIXED_RATE_PREMIUM_MAX_BPS as u128,
+        )?)
+    }
+
+    /// Marks `premium` up by the size impact of locking `lock_amount` out of `available_liquidity`,
+    /// returns the marked-up premium and the impact in bps
+    pub fn apply_option_size_impact(
+        &self,
+        premium: f64,
+        lock_amount: u64,
+        available_liquidity: u64,
+    ) -> Result<(f64, u64)> {
+        let size_impact_bps = self.get_option_size_impact_bps(lock_amount, available_liquidity)?;
+        let marked_up_premium = math::checked_float_mul(
+            premium,
+            1.0 + size_impact_bps as f64 / Contract::BPS_POWER as f64,
+        )?;
+        Ok((marked_up_premium, size_impact_bps))
+    }
+
     /// Add future position to pool tracking
     pub fn add_future_position(
         &mut self,
@@ -567,3 +1696,684 @@ impl Pool {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Future, FutureStatus, OrderType, Position, Referral};
+
+    const DAY: i64 = 86_400;
+
+    fn test_pool(start_time: i64) -> Pool {
+        let mut pool = Pool::default();
+        pool.initialize_borrow_rate_curve().unwrap();
+        pool.borrow_index_start_time = start_time;
+        pool.last_rate_update = start_time;
+        pool
+    }
+
+    fn test_custody(token_locked: u64, token_owned: u64) -> Custody {
+        Custody { token_locked, token_owned, ..Default::default() }
+    }
+
+    fn long_position(size_usd: u64, open_time: i64) -> Position {
+        Position {
+            order_type: OrderType::Market,
+            side: Side::Long,
+            size_usd,
+            last_borrow_fees_update_time: open_time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn borrowable_amount_stops_at_the_reserve_ratio() {
+        let mut pool = test_pool(1_000);
+        pool.reserve_ratio_bps = 2_000;
+        let custody = test_custody(700_000, 1_000_000);
+
+        // 300k is free, but 200k of it is the reserve
+        assert_eq!(custody.available_for_open(), 300_000);
+        assert_eq!(pool.get_borrowable_amount(&custody).unwrap(), 100_000);
+
+        // Past the boundary nothing more can be locked even though raw liquidity remains
+        let full = test_custody(800_000, 1_000_000);
+        assert_eq!(full.available_for_open(), 200_000);
+        assert_eq!(pool.get_borrowable_amount(&full).unwrap(), 0);
+
+        // A resized lock counts its own release first
+        assert_eq!(
+            pool.get_borrowable_amount_after_release(&full, 150_000).unwrap(),
+            150_000
+        );
+    }
+
+    #[test]
+    fn stored_aum_is_reused_only_within_a_few_slots() {
+        let mut pool = test_pool(1_000);
+        pool.aum_usd = 1_000_000;
+        pool.aum_reconciled_time = 1_000;
+
+        for curtime in 1_000..=1_000 + Pool::AUM_RECONCILE_INTERVAL_SEC {
+            assert!(pool.refresh_aum_usd(&[], &[], curtime).unwrap());
+        }
+        assert!(pool
+            .refresh_aum_usd(&[], &[], 1_001 + Pool::AUM_RECONCILE_INTERVAL_SEC)
+            .is_err());
+        assert_eq!(pool.aum_usd, 1_000_000);
+        assert_eq!(pool.aum_reconciled_time, 1_000);
+    }
+
+    #[test]
+    fn borrow_index_charges_each_stretch_at_its_own_rate() {
+        let mut pool = test_pool(1_000);
+        let idle = test_custody(0, 1_000_000);
+        let busy = test_custody(900_000, 1_000_000);
+        let usdc = test_custody(0, 1_000_000);
+        let mut position = long_position(1_000_000_000, 1_000);
+
+        // Nobody touches the position for 60 days while utilization changes halfway through
+        pool.update_borrow_index(&idle, &usdc, 1_000 + 30 * DAY).unwrap();
+        let fee = pool
+            .update_position_borrow_fees(&mut position, 1_000 + 60 * DAY, &busy, &usdc)
+            .unwrap();
+
+        let idle_rate = pool.get_perp_borrow_rate_bps(&idle).unwrap();
+        let busy_rate = pool.get_perp_borrow_rate_bps(&busy).unwrap();
+        assert!(busy_rate > idle_rate);
+        let expected = position.get_borrow_fee_at_rate(30 * DAY, idle_rate).unwrap()
+            + position.get_borrow_fee_at_rate(30 * DAY, busy_rate).unwrap();
+        let spot_only = position.get_borrow_fee_at_rate(60 * DAY, busy_rate).unwrap();
+        assert!(fee.abs_diff(expected) <= 1, "fee {} expected {}", fee, expected);
+        assert!(fee < spot_only);
+        assert_eq!(position.accrued_borrow_fees, fee);
+        assert_eq!(position.cumulative_interest_snapshot, pool.cumulative_interest_rate_long);
+        assert_eq!(position.last_borrow_fees_update_time, 1_000 + 60 * DAY);
+
+        // Settling again in the same second charges nothing
+        let again = pool
+            .update_position_borrow_fees(&mut position, 1_000 + 60 * DAY, &busy, &usdc)
+            .unwrap();
+        assert_eq!(again, 0);
+    }
+
+    #[test]
+    fn position_from_before_the_index_pays_its_gap_once() {
+        // Pool created before the index: it starts on first use
+        let mut pool = test_pool(0);
+        pool.cumulative_interest_rate_long = 42;
+        let sol = test_custody(500_000, 1_000_000);
+        let usdc = test_custody(0, 1_000_000);
+        let mut position = long_position(1_000_000_000, 1_000);
+        position.cumulative_interest_snapshot = 750; // stale value from the old accounting
+
+        let start = 1_000 + 10 * DAY;
+        let fee = pool.update_position_borrow_fees(&mut position, start, &sol, &usdc).unwrap();
+        assert_eq!(pool.borrow_index_start_time, start);
+        assert_eq!(pool.cumulative_interest_rate_long, 0);
+        let rate = pool.get_perp_borrow_rate_bps(&sol).unwrap();
+        assert_eq!(fee, position.get_borrow_fee_at_rate(10 * DAY, rate).unwrap());
+        assert_eq!(position.cumulative_interest_snapshot, 0);
+
+        // From here on it follows the index
+        let fee = pool.update_position_borrow_fees(&mut position, start + DAY, &sol, &usdc).unwrap();
+        assert!(fee.abs_diff(position.get_borrow_fee_at_rate(DAY, rate).unwrap()) <= 1);
+    }
+
+    #[test]
+    fn funding_bounds_cap_the_index_and_floor_the_charged_fee() {
+        let mut pool = test_pool(1_000);
+        let busy = test_custody(950_000, 1_000_000);
+        let usdc = test_custody(0, 1_000_000);
+        let uncapped = pool.get_perp_borrow_rate_bps(&busy).unwrap();
+        pool.max_funding_rate_bps = 500;
+        assert!(uncapped > 500);
+        assert_eq!(pool.get_perp_borrow_rate_bps(&busy).unwrap(), 500);
+
+        let mut position = long_position(1_000_000_000, 1_000);
+        let fee = pool.update_position_borrow_fees(&mut position, 1_000 + DAY, &busy, &usdc).unwrap();
+        assert!(fee.abs_diff(position.get_borrow_fee_at_rate(DAY, 500).unwrap()) <= 1);
+
+        // A hedge discount can't take the charge below the floor; the rest is still waived
+        pool.min_funding_rate_bps = 400;
+        pool.hedge_borrow_fee_discount_bps = 5_000;
+        pool.long_open_interest_usd = position.size_usd as u128;
+        pool.put_option_notional_usd = position.size_usd;
+        position.hedge_option = Some(Pubkey::new_unique());
+        position.hedged_usd = position.size_usd;
+        position.hedge_expiry = 1_000 + 10 * DAY;
+        let fee = pool.update_position_borrow_fees(&mut position, 1_000 + 2 * DAY, &busy, &usdc).unwrap();
+        let floor = position.get_borrow_fee_at_rate(DAY, 400).unwrap();
+        assert_eq!(fee, floor);
+        let full = position.get_borrow_fee_at_rate(DAY, 500).unwrap();
+        assert!(position.hedge_fee_discount_usd.abs_diff(full - floor) <= 1);
+    }
+
+    #[test]
+    fn hedge_discount_covers_only_the_net_pool_exposure() {
+        let mut pool = test_pool(1_000);
+        pool.hedge_borrow_fee_discount_bps = 5_000;
+        let size = 1_000_000_000;
+        let mut position = long_position(size, 1_000);
+        position.hedge_option = Some(Pubkey::new_unique());
+        position.hedged_usd = size;
+        position.hedge_expiry = 1_000 + DAY;
+        pool.long_open_interest_usd = size as u128;
+
+        // A put written by the pool is offset by the long up to its notional
+        pool.put_option_notional_usd = size;
+        assert_eq!(pool.get_hedge_discount_bps(&position, 1_000).unwrap(), 5_000);
+
+        // Other longs already offset half of it
+        pool.long_open_interest_usd = (size + size / 2) as u128;
+        assert_eq!(pool.get_hedge_discount_bps(&position, 1_000).unwrap(), 2_500);
+
+        // Calls written by the pool cancel the put exposure, so the long offsets nothing
+        pool.long_open_interest_usd = size as u128;
+        pool.call_option_notional_usd = size;
+        assert_eq!(pool.get_hedge_discount_bps(&position, 1_000).unwrap(), 0);
+
+        // The option notional caps the covered share, and the discount lapses at expiry
+        pool.call_option_notional_usd = 0;
+        position.hedged_usd = size / 4;
+        assert_eq!(pool.get_hedge_discount_bps(&position, 1_000).unwrap(), 1_250);
+        assert_eq!(pool.get_hedge_discount_bps(&position, 1_000 + DAY + 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn close_settlement_charges_fees_accrued_by_earlier_updates() {
+        let mut pool = test_pool(1_000);
+        let sol = test_custody(0, 1_000_000);
+        let usdc = test_custody(0, 1_000_000);
+        let mut position = long_position(1_000_000_000, 1_000);
+        position.entry_price = 150_000_000;
+        position.collateral_usd = 100_000_000;
+        position.collateral_amount = 500_000;
+        position.locked_amount = 6_000_000;
+        position.trade_fees = 1_000_000;
+        // A keeper update already accrued these; the close must still charge them
+        position.accrued_borrow_fees = 4_000_000;
+
+        let half = Position::FULL_CLOSE_PERCENTAGE / 2;
+        let settlement = pool
+            .compute_close_settlement(&mut position, half, 150_000_000, 1_000, &sol, &usdc)
+            .unwrap();
+        assert!(!settlement.is_full_close);
+        assert_eq!(settlement.size_usd, 500_000_000);
+        assert_eq!(settlement.collateral_amount, 250_000);
+        assert_eq!(settlement.locked_amount, 3_000_000);
+        assert_eq!(settlement.realized_pnl, 0);
+        assert_eq!(settlement.borrow_fees, 2_000_000);
+        assert_eq!(settlement.close_fee_usd, 500_000);
+        assert_eq!(
+            settlement.settlement_usd,
+            50_000_000 - 2_000_000 - settlement.close_fee_usd
+        );
+
+        position.apply_close(&settlement, 1_000).unwrap();
+        assert_eq!(position.size_usd, 500_000_000);
+        assert_eq!(position.accrued_borrow_fees, 2_000_000);
+        assert_eq!(position.borrow_fees_paid, 2_000_000);
+
+        let settlement = pool
+            .compute_close_settlement(
+                &mut position,
+                Position::FULL_CLOSE_PERCENTAGE,
+                150_000_000,
+                1_000,
+                &sol,
+                &usdc,
+            )
+            .unwrap();
+        assert!(settlement.is_full_close);
+        assert_eq!(settlement.borrow_fees, 2_000_000);
+        position.apply_close(&settlement, 1_000).unwrap();
+        assert!(position.is_liquidated);
+        assert_eq!(position.size_usd, 0);
+        assert_eq!(position.accrued_borrow_fees, 0);
+
+        assert!(pool
+            .compute_close_settlement(&mut position, 0, 150_000_000, 1_000, &sol, &usdc)
+            .is_err());
+    }
+
+    #[test]
+    fn keeper_budget_only_grows_by_fees_equity_covered() {
+        let mut pool = test_pool(1_000);
+        let contract = Contract { keeper_reward_bps: 1_000, ..Default::default() };
+        let settlement = CloseSettlement {
+            collateral_usd: 10_000_000,
+            realized_pnl: -7_000_000,
+            borrow_fees: 5_000_000,
+            ..Default::default()
+        };
+        // Only 3 USD of equity was left to pay the 5 USD of borrow fees
+        assert_eq!(settlement.get_collected_borrow_fees(), 3_000_000);
+        let reward = pool.fund_keeper_rewards(&contract, settlement.get_collected_borrow_fees()).unwrap();
+        assert_eq!(reward, 300_000);
+        assert_eq!(pool.keeper_reward_budget_usd, 300_000);
+
+        let bankrupt = CloseSettlement { realized_pnl: -20_000_000, ..settlement };
+        assert_eq!(bankrupt.get_collected_borrow_fees(), 0);
+    }
+
+    #[test]
+    fn perps_and_futures_pay_the_same_close_fee() {
+        let mut pool = test_pool(1_000);
+        let size_usd = 1_000_000_000;
+        assert_eq!(pool.get_close_fee(size_usd, Position::DEFAULT_CLOSE_FEE_BPS).unwrap(), 0);
+        assert_eq!(pool.get_close_fee(size_usd, Future::SETTLEMENT_FEE_BPS).unwrap(), size_usd * Future::SETTLEMENT_FEE_BPS / 10_000);
+
+        pool.close_fee_bps = 30;
+        let perp = pool.get_close_fee(size_usd, Position::DEFAULT_CLOSE_FEE_BPS).unwrap();
+        let future = pool.get_close_fee(size_usd, Future::SETTLEMENT_FEE_BPS).unwrap();
+        assert_eq!(perp, 3_000_000);
+        assert_eq!(perp, future);
+
+        // A perp settles the same close fee as a future of equal notional, its 10 bps trade fee
+        // from open is not charged on top
+        let sol = test_custody(0, 1_000_000);
+        let usdc = test_custody(0, 1_000_000);
+        let mut position = long_position(size_usd, 1_000);
+        position.entry_price = 150_000_000;
+        position.collateral_usd = 100_000_000;
+        position.trade_fees = 1_000_000;
+        let settlement = pool
+            .compute_close_settlement(&mut position, Position::FULL_CLOSE_PERCENTAGE, 150_000_000, 1_000, &sol, &usdc)
+            .unwrap();
+        assert_eq!(settlement.close_fee_usd, future);
+
+        // The trade fee only stays as a floor
+        let mut position = long_position(size_usd, 1_000);
+        position.entry_price = 150_000_000;
+        position.collateral_usd = 100_000_000;
+        position.trade_fees = 5_000_000;
+        let settlement = pool
+            .compute_close_settlement(&mut position, Position::FULL_CLOSE_PERCENTAGE, 150_000_000, 1_000, &sol, &usdc)
+            .unwrap();
+        assert_eq!(settlement.close_fee_usd, 5_000_000);
+    }
+
+    #[test]
+    fn unset_perp_trade_fee_falls_back_to_the_exit_fee() {
+        let mut pool = test_pool(1_000);
+        assert_eq!(pool.get_perp_trade_fee(1_000_000_000).unwrap(), 1_000_000);
+        pool.perp_trade_fee_bps = 25;
+        assert_eq!(pool.get_perp_trade_fee(1_000_000_000).unwrap(), 2_500_000);
+    }
+
+    #[test]
+    fn perp_referral_is_credited_from_the_collected_trade_fee() {
+        let pool = test_pool(1_000);
+        let contract = Contract { referral_fee_share_bps: 2_000, ..Default::default() };
+        let referrer = Pubkey::new_unique();
+        let mut referral = Referral { referrer, active: true, ..Default::default() };
+        let mut position = long_position(1_000_000_000, 1_000);
+        position.referrer = Some(referrer);
+        let settlement = CloseSettlement {
+            size_usd: 1_000_000_000,
+            collateral_usd: 100_000_000,
+            close_fee_usd: 1_000_000,
+            ..Default::default()
+        };
+        let fee = Referral::accrue_perp_close(Some(&mut referral), &position, &settlement, &pool, &contract).unwrap();
+        assert_eq!(fee, 200_000);
+        assert_eq!(referral.referral_fees_owed, 200_000);
+
+        // Nothing is owed on a close that left no equity to pay the fee
+        let bankrupt = CloseSettlement { realized_pnl: -100_000_000, ..settlement };
+        let fee = Referral::accrue_perp_close(Some(&mut referral), &position, &bankrupt, &pool, &contract).unwrap();
+        assert_eq!(fee, 0);
+
+        // A referred position can't be closed without its referrer's registration
+        assert!(Referral::accrue_perp_close(None, &position, &settlement, &pool, &contract).is_err());
+        position.referrer = None;
+        assert_eq!(Referral::accrue_perp_close(None, &position, &settlement, &pool, &contract).unwrap(), 0);
+    }
+
+    #[test]
+    fn option_size_impact_follows_the_fixed_rate_curve() {
+        let mut pool = test_pool(1_000);
+        assert_eq!(pool.get_option_size_impact_bps(9_900, 10_000).unwrap(), 0);
+
+        pool.max_option_size_impact_bps = 1_000;
+        // Up to 20% of free liquidity is free, 40-60% is the 1.5% tier of a 50% top
+        assert_eq!(pool.get_option_size_impact_bps(2_000, 10_000).unwrap(), 0);
+        assert_eq!(pool.get_option_size_impact_bps(5_000, 10_000).unwrap(), 30);
+        // Locking everything, or a pool with nothing free, pays the full max
+        assert_eq!(pool.get_option_size_impact_bps(9_900, 10_000).unwrap(), 1_000);
+        assert_eq!(pool.get_option_size_impact_bps(1, 0).unwrap(), 1_000);
+
+        let (premium, impact_bps) = pool.apply_option_size_impact(2.0, 10_000, 10_000).unwrap();
+        assert_eq!(impact_bps, 1_000);
+        assert!((premium - 2.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn position_concentration_caps_notional_against_free_liquidity() {
+        let mut pool = test_pool(1_000);
+        let mut usdc = test_custody(2_000_000_000, 10_000_000_000);
+        usdc.decimals = 6;
+        let price = OraclePrice::new(1_000_000, -6);
+        let usd = |dollars: u64| dollars * Contract::USD_SCALE as u64;
+        assert!(pool.check_position_concentration(usd(50_000), &usdc, 0, &price).is_ok());
+
+        // $8k free at 10%: a new position stops at $800 of notional
+        pool.max_position_fraction_bps = 1_000;
+        assert!(pool.check_position_concentration(usd(800), &usdc, 0, &price).is_ok());
+        assert!(pool.check_position_concentration(usd(801), &usdc, 0, &price).is_err());
+
+        // A position growing out of its own $2k lock is measured against $10k free
+        assert!(pool.check_position_concentration(usd(1_000), &usdc, 2_000_000_000, &price).is_ok());
+        assert!(pool.check_position_concentration(usd(1_001), &usdc, 2_000_000_000, &price).is_err());
+    }
+
+    #[test]
+    fn pool_notional_is_held_to_the_synced_global_ceiling() {
+        let mut pool = test_pool(1_000);
+        let mut contract = Contract { max_global_notional_usd: 1_000, ..Default::default() };
+        pool.add_notional(&contract, 600).unwrap();
+
+        // Another pool's exposure arrives through a sync
+        pool.synced_notional_usd = pool.notional_usd;
+        contract.set_global_notional(900).unwrap();
+        assert!(!contract.paused);
+        assert_eq!(pool.get_global_notional_estimate(&contract), 900);
+
+        // The open reaching the ceiling goes through, the next one is held back
+        pool.add_notional(&contract, 200).unwrap();
+        assert!(pool.add_notional(&contract, 1).is_err());
+
+        // Closes bring the estimate back under it
+        pool.remove_notional(300);
+        assert_eq!(pool.get_global_notional_estimate(&contract), 800);
+        pool.add_notional(&contract, 100).unwrap();
+    }
+
+    #[test]
+    fn incremental_aum_stays_within_rounding_of_the_full_recompute() {
+        let token_price = OraclePrice::new(15_050_000_000, -8);
+        let decimals = 9;
+        let mut pool = test_pool(1_000);
+        let mut custody = test_custody(0, 1_000 * 1_000_000_000);
+        pool.aum_usd = token_price.get_asset_amount_usd(custody.token_owned, decimals).unwrap() as u128;
+        pool.aum_reconciled_time = 1_000;
+
+        // Deposits and withdrawals adjust aum_usd by their own value, as the instructions do
+        let mut seed: u64 = 42;
+        let operations = 1_000u128;
+        for step in 0..operations {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            let amount = (seed >> 33) % 5_000_000_000 + 1;
+            assert!(pool.refresh_aum_usd(&[], &[], 1_000).unwrap());
+            let amount_usd = token_price.get_asset_amount_usd(amount, decimals).unwrap() as u128;
+            if step % 3 == 2 && custody.token_owned > amount {
+                custody.token_owned -= amount;
+                pool.aum_usd = pool.aum_usd.saturating_sub(amount_usd);
+            } else {
+                custody.token_owned += amount;
+                pool.aum_usd += amount_usd;
+            }
+        }
+
+        // Each step rounds by under one micro-dollar
+        let full = token_price.get_asset_amount_usd(custody.token_owned, decimals).unwrap() as u128;
+        assert!(pool.aum_usd.abs_diff(full) <= operations, "{} vs {}", pool.aum_usd, full);
+    }
+
+    #[test]
+    fn size_increase_settles_borrow_fees_at_the_pre_change_size_and_rate() {
+        let mut pool = test_pool(1_000);
+        let before = test_custody(100_000, 1_000_000);
+        let after = test_custody(900_000, 1_000_000);
+        let usdc = test_custody(0, 1_000_000);
+        let mut position = long_position(1_000_000_000, 1_000);
+        let change_time = 1_000 + 30 * DAY;
+
+        // Open interest can't move before the elapsed period is settled
+        assert!(pool.update_open_interest(&position, 1_000_000_000, true, change_time).is_err());
+
+        let mut settled_after = position.clone();
+        let fee = pool.update_position_borrow_fees(&mut position, change_time, &before, &usdc).unwrap();
+        pool.update_open_interest(&position, 1_000_000_000, true, change_time).unwrap();
+        assert_eq!(pool.long_open_interest_usd, 1_000_000_000);
+
+        let expected = position
+            .get_borrow_fee_at_rate(30 * DAY, pool.get_perp_borrow_rate_bps(&before).unwrap())
+            .unwrap();
+        assert!(fee.abs_diff(expected) <= 1, "fee {} expected {}", fee, expected);
+
+        // Settling after the change would charge the doubled size at the busier rate
+        settled_after.size_usd *= 2;
+        let late_fee = test_pool(1_000)
+            .update_position_borrow_fees(&mut settled_after, change_time, &after, &usdc)
+            .unwrap();
+        assert!(late_fee > fee * 2);
+    }
+
+    #[test]
+    fn min_position_size_is_inclusive() {
+        let mut pool = test_pool(1_000);
+        // Off by default
+        pool.check_min_position_size(1).unwrap();
+
+        pool.min_position_usd = 10 * Contract::USD_SCALE as u64;
+        pool.check_min_position_size(10 * Contract::USD_SCALE as u64).unwrap();
+        let err = pool.check_min_position_size(10 * Contract::USD_SCALE as u64 - 1).unwrap_err();
+        assert_eq!(err, TradingError::PositionTooSmall.into());
+    }
+
+    #[test]
+    fn reconcile_repairs_desynced_open_interest() {
+        let mut pool = test_pool(0);
+        let executed = |side, size_usd| Position {
+            order_type: OrderType::Market,
+            side,
+            size_usd,
+            execution_time: Some(0),
+            ..Default::default()
+        };
+        let mut positions = vec![executed(Side::Long, 1_000), executed(Side::Long, 2_000), executed(Side::Short, 500)];
+        for position in &positions {
+            pool.update_open_interest(position, position.size_usd, true, 0).unwrap();
+        }
+        // Closing the second long without its decrement leaves long OI inflated
+        positions.remove(1);
+        assert_eq!((pool.long_open_interest_usd, pool.short_open_interest_usd), (3_000, 500));
+
+        // Pending and liquidated positions are passed too but hold no open interest
+        let mut liquidated = executed(Side::Short, 700);
+        liquidated.is_liquidated = true;
+        let pending = Position { order_type: OrderType::Limit, size_usd: 900, ..Default::default() };
+        positions.extend([liquidated, pending]);
+
+        let previous = pool.reconcile_open_interest(&positions, 0, 0).unwrap();
+        assert_eq!(previous, (3_000, 500));
+        assert_eq!((pool.long_open_interest_usd, pool.short_open_interest_usd), (1_000, 500));
+
+        // Positions left out of the transaction are carried in by their summed size
+        pool.reconcile_open_interest(&positions[..1], 0, 500).unwrap();
+        assert_eq!((pool.long_open_interest_usd, pool.short_open_interest_usd), (1_000, 500));
+    }
+
+    #[test]
+    fn profitable_close_pays_out_at_full_utilization() {
+        let mut pool = test_pool(0);
+        let usdc_price = OraclePrice::new(1_000_000, -6);
+        let fully_locked = |pool: &Pool, reserved_for_settlement| {
+            let mut usdc = Custody {
+                token_owned: 1_000_000_000,
+                reserved_for_settlement,
+                decimals: 6,
+                ..Default::default()
+            };
+            // Opens lock everything they may, which leaves the settlement reserve free
+            for _ in 0..4 {
+                let lock = pool.get_borrowable_amount(&usdc).unwrap().min(300_000_000);
+                usdc.lock_funds(LockedProduct::Perp, lock).unwrap();
+            }
+            assert_eq!(pool.get_borrowable_amount(&usdc).unwrap(), 0);
+            usdc
+        };
+
+        // A $1,000 short from $100 backed by 300 USDC, closed at $75 for a $250 profit
+        let mut position = long_position(1_000_000_000, 0);
+        position.side = Side::Short;
+        position.entry_price = 100_000_000;
+        position.collateral_usd = 100_000_000;
+        position.locked_amount = 300_000_000;
+        let mut sol = Custody::default();
+        let mut usdc = fully_locked(&pool, 100_000_000);
+        assert_eq!(usdc.token_locked, 900_000_000);
+        let settlement = pool
+            .compute_close_settlement(&mut position, Position::FULL_CLOSE_PERCENTAGE, 75_000_000, 0, &sol, &usdc)
+            .unwrap();
+        let payout = math::usd_to_token_amount(settlement.settlement_usd, &usdc_price, usdc.decimals).unwrap();
+        assert_eq!(payout, 350_000_000);
+
+        // It only fits once its lock is released, which the close does first
+        assert!(usdc.available_for_payout() < payout);
+        Pool::release_perp_payout(Side::Short, settlement.locked_amount, false, payout, &mut sol, &mut usdc).unwrap();
+        assert_eq!(usdc.token_locked, 600_000_000);
+        usdc.token_owned -= payout;
+        usdc.assert_invariants().unwrap();
+
+        // Without the reserve the same close runs dry
+        let mut unreserved = fully_locked(&pool, 0);
+        assert_eq!(unreserved.token_locked, 1_000_000_000);
+        assert_eq!(
+            Pool::release_perp_payout(Side::Short, settlement.locked_amount, false, payout, &mut sol, &mut unreserved),
+            Err(TradingError::InsufficientPoolLiquidity.into())
+        );
+    }
+
+    #[test]
+    fn lp_share_price_is_aum_per_whole_lp_token() {
+        let mut pool = test_pool(0);
+        // No LP tokens yet: the first deposit mints 1:1
+        assert_eq!(pool.get_lp_share_price_usd(0).unwrap(), 1_000_000);
+
+        pool.aum_usd = 3_000_000_000; // $3,000
+        assert_eq!(pool.get_lp_share_price_usd(1_500_000_000).unwrap(), 2_000_000);
+        // Rounds down, never overstating a share
+        assert_eq!(pool.get_lp_share_price_usd(7_000_000_000).unwrap(), 428_571);
+    }
+
+    #[test]
+    fn lp_collateral_is_returned_in_kind_and_burned_for_a_loss() {
+        let mut pool = test_pool(0);
+        pool.aum_usd = 2_000_000_000; // $2 per LP token
+        let lp_supply = Some(1_000_000_000);
+        // 100 LP tokens posted, worth $200 at deposit
+        let (lp_amount, lp_usd) = (100_000_000, 200_000_000);
+
+        // A profit over the LP value is paid in the settlement asset, every LP token comes back
+        assert_eq!(
+            pool.get_lp_collateral_settlement(lp_amount, lp_usd, 250_000_000, lp_supply).unwrap(),
+            (50_000_000, lp_amount, 0)
+        );
+        // A $50 shortfall burns 25 LP tokens
+        assert_eq!(
+            pool.get_lp_collateral_settlement(lp_amount, lp_usd, 150_000_000, lp_supply).unwrap(),
+            (0, 75_000_000, 25_000_000)
+        );
+        // Never burns more than was posted, even if the AUM fell since
+        pool.aum_usd = 1_000_000_000;
+        assert_eq!(
+            pool.get_lp_collateral_settlement(lp_amount, lp_usd, 0, lp_supply).unwrap(),
+            (0, 0, lp_amount)
+        );
+        // Burning needs the supply from the LP mint
+        assert_eq!(
+            pool.get_lp_collateral_settlement(lp_amount, lp_usd, 150_000_000, None),
+            Err(PerpetualError::LpCollateralAccountsMissing.into())
+        );
+        // Custody collateral passes straight through
+        assert_eq!(pool.get_lp_collateral_settlement(0, 0, 150_000_000, None).unwrap(), (150_000_000, 0, 0));
+    }
+
+    #[test]
+    fn clock_going_backwards_is_rejected() {
+        let mut pool = test_pool(0);
+        assert_eq!(pool.observe_time(1_700_000_000).unwrap(), 1_700_000_000);
+        assert_eq!(pool.last_seen_time, 1_700_000_000);
+        // The same second again is fine, a later one moves the record forward
+        assert_eq!(pool.observe_time(1_700_000_000).unwrap(), 1_700_000_000);
+        assert_eq!(pool.observe_time(1_700_000_060).unwrap(), 1_700_000_060);
+
+        // A clock behind the record trips the guard and leaves the record alone
+        assert_eq!(pool.observe_time(1_700_000_059), Err(ContractError::ClockWentBackwards.into()));
+        assert_eq!(pool.last_seen_time, 1_700_000_060);
+    }
+
+    #[test]
+    fn future_close_settlement_nets_collateral_pnl_and_the_settlement_fee() {
+        let pool = test_pool(0);
+        // $1,000 long at $100 on $100 collateral, no carry
+        let future = Future {
+            side: Side::Long,
+            status: FutureStatus::Active,
+            entry_price: 100_000_000,
+            size_usd: 1_000_000_000,
+            collateral_usd: 100_000_000,
+            collateral_amount: 100_000_000,
+            locked_amount: 10_000_000,
+            time_to_expiry_at_open: 86_400,
+            expiry_time: 86_400,
+            ..Default::default()
+        };
+
+        // +$100 at $110, less 5 bps on the closed size
+        let full = pool.compute_future_close_settlement(&future, Future::FULL_CLOSE, 110_000_000, 0).unwrap();
+        assert!(full.is_full_close);
+        assert_eq!(full.realized_pnl, 100_000_000);
+        assert_eq!(full.close_fee_usd, 500_000);
+        assert_eq!(full.settlement_usd, 199_500_000);
+        assert_eq!(full.locked_amount, 10_000_000);
+
+        // Half of everything on a half close
+        let half = pool.compute_future_close_settlement(&future, Future::FULL_CLOSE / 2, 110_000_000, 0).unwrap();
+        assert!(!half.is_full_close);
+        assert_eq!(half.size_usd, 500_000_000);
+        assert_eq!(half.collateral_amount, 50_000_000);
+        assert_eq!(half.settlement_usd, 99_750_000);
+
+        // A loss past the collateral settles for nothing
+        let wiped = pool.compute_future_close_settlement(&future, Future::FULL_CLOSE, 80_000_000, 0).unwrap();
+        assert!(wiped.net_settlement_usd < 0);
+        assert_eq!(wiped.settlement_usd, 0);
+
+        assert!(pool.compute_future_close_settlement(&future, 0, 110_000_000, 0).is_err());
+    }
+
+    #[test]
+    fn close_fee_falls_back_to_the_default_while_unset() {
+        let mut pool = test_pool(1_000);
+        assert_eq!(pool.get_close_fee(1_000_000, 10).unwrap(), 1_000);
+        assert_eq!(pool.get_close_fee(1_000_000, 0).unwrap(), 0);
+
+        pool.close_fee_bps = 25;
+        assert_eq!(pool.get_close_fee(1_000_000, 10).unwrap(), 2_500);
+        assert_eq!(pool.get_close_fee(0, 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn grid_only_pools_accept_listed_strikes_and_expiries() {
+        let expiry = 1_700_000_000;
+        let mut pool = test_pool(1_000);
+        // Grid off: anything goes
+        pool.validate_option_grid(151.5, expiry + 1).unwrap();
+
+        pool.option_grid_only = true;
+        pool.allowed_option_strikes[0] = 150_000_000;
+        pool.allowed_option_strikes[1] = 160_000_000;
+        pool.allowed_option_expiries[0] = expiry;
+
+        pool.validate_option_grid(150.0, expiry).unwrap();
+        pool.validate_option_grid(160.0, expiry).unwrap();
+        assert!(pool.validate_option_grid(155.0, expiry).is_err());
+        assert!(pool.validate_option_grid(150.0, expiry + 1).is_err());
+        // Empty slots never match
+        assert!(pool.validate_option_grid(0.0, 0).is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use anchor_lang::prelude::*;
+
+use crate::{errors::TradingError, math};
+
+use super::{CloseSettlement, Contract, Pool, Position};
+
+/// A referrer registered for a pool by the admins through set_referral. Only active
+/// referrals can be named on new opens. Fees are credited as the pool collects them, i.e.
+/// perp trade fees at close and option markups and future opening fees at open, and paid out
+/// in USDC by claim_referral_fees. Claiming zeroes `referral_fees_owed`.
+#[account]
+#[derive(Default, Debug)]
+pub struct Referral {
+    pub referrer: Pubkey,
+    pub pool: Pubkey,
+    pub referral_fees_owed: u64, // Unclaimed fee share (USD, 6 decimals)
+    pub total_claimed_usd: u64,
+    pub last_claim_time: i64,
+    pub bump: u8,
+    pub active: bool, // cleared by set_referral to stop new opens naming this referrer
+}
+
+impl Referral {
+    pub const LEN: usize = 8 + std::mem::size_of::<Referral>();
+
+    /// Credit a fee share
+    pub fn accrue(&mut self, fee_usd: u64) -> Result<()> {
+        self.referral_fees_owed = math::checked_add(self.referral_fees_owed, fee_usd)?;
+        Ok(())
+    }
+
+    /// Check that `referral` is the active registration of the referrer named on an open
+    pub fn validate_open(referral: Option<&Referral>, referrer: Pubkey, owner: Pubkey) -> Result<()> {
+        require_keys_neq!(referrer, owner, TradingError::SelfReferral);
+        let referral = referral.ok_or(TradingError::ReferralAccountMissing)?;
+        require_keys_eq!(referral.referrer, referrer, TradingError::ReferralAccountMissing);
+        require!(referral.active, TradingError::ReferralNotRegistered);
+        Ok(())
+    }
+
+    /// Credit the referrer of a closing perp its share of the trade fee the close actually
+    /// collected. Returns the credited fee, 0 for positions without a referrer.
+    pub fn accrue_perp_close(
+        referral: Option<&mut Referral>,
+        position: &Position,
+        settlement: &CloseSettlement,
+        pool: &Pool,
+        contract: &Contract,
+    ) -> Result<u64> {
+        let Some(referrer) = position.referrer else {
+            return Ok(0);
+        };
+        let referral = referral.ok_or(TradingError::ReferralAccountMissing)?;
+        require_keys_eq!(referral.referrer, referrer, TradingError::ReferralAccountMissing);
+        let trade_fee_usd = settlement
+            .get_collected_close_fees()
+            .min(pool.get_perp_trade_fee(settlement.size_usd)?);
+        let fee_usd = contract.get_referral_fee(trade_fee_usd)?;
+        referral.accrue(fee_usd)?;
+        Ok(fee_usd)
+    }
+}
@@ -15,7 +15,7 @@ pub struct TpSlOrderbook {
     // Identity
     pub owner: Pubkey,              // Position owner
     pub position: Pubkey,           // Associated position account
-    pub contract_type: u8,          // 0 = Perp, 1 = Option
+    pub contract_type: u8,          // 0 = Perp, 1 = Option, 2 = Future
     
     // Orders (max 10 each)
     pub take_profit_orders: [TpSlOrder; 10],
@@ -33,22 +33,41 @@ pub struct TpSlOrderbook {
     pub last_execution_time: i64,             // Last execution timestamp
     
     pub bump: u8,
+
+    // Appended after the first release; it fits in the unused tail of orderbooks created
+    // before it, which read 0 here
+    pub max_orders: u8,             // Active orders allowed per side, set at init (0 = MAX_ORDERS)
 }
 
 impl TpSlOrderbook {
     pub const LEN: usize = 8 + std::mem::size_of::<TpSlOrderbook>();
     pub const MAX_ORDERS: usize = 10;
+
+    /// Active orders allowed per side, MAX_ORDERS for orderbooks created before the limit was stored
+    pub fn get_max_orders(&self) -> u8 {
+        if self.max_orders == 0 {
+            Self::MAX_ORDERS as u8
+        } else {
+            self.max_orders
+        }
+    }
     
     pub fn initialize(
         &mut self,
         owner: Pubkey,
         position: Pubkey,
         contract_type: u8,
+        max_orders: u8,
         bump: u8,
     ) -> Result<()> {
+        require!(
+            max_orders > 0 && max_orders as usize <= Self::MAX_ORDERS,
+            TradingError::InvalidAmount
+        );
         self.owner = owner;
         self.position = position;
         self.contract_type = contract_type;
+        self.max_orders = max_orders;
         self.bump = bump;
         self.active_tp_count = 0;
         self.active_sl_count = 0;
@@ -73,12 +92,15 @@ impl TpSlOrderbook {
         size_percent: u64,
         receive_sol: bool,
     ) -> Result<usize> {
-        require!(self.active_tp_count < Self::MAX_ORDERS as u8, TradingError::OrderbookFull);
+        require!(self.active_tp_count < self.get_max_orders(), TradingError::OrderbookFull);
         require!(size_percent > 0 && size_percent <= 100_000_000, TradingError::InvalidAmount);
         
         // Find first inactive slot
         for i in 0..Self::MAX_ORDERS {
             if !self.take_profit_orders[i].is_active {
+                // Scaling out across several levels may not close more than the whole position
+                let new_total = self.total_tp_percent + size_percent;
+                require!(new_total <= 100_000_000, TradingError::TakeProfitOverAllocated);
                 self.take_profit_orders[i] = TpSlOrder {
                     price,
                     size_percent,
@@ -86,7 +108,7 @@ impl TpSlOrderbook {
                     is_active: true,
                 };
                 self.active_tp_count += 1;
-                self.total_tp_percent += size_percent;
+                self.total_tp_percent = new_total;
                 return Ok(i);
             }
         }
@@ -100,7 +122,7 @@ impl TpSlOrderbook {
         size_percent: u64,
         receive_sol: bool,
     ) -> Result<usize> {
-        require!(self.active_sl_count < Self::MAX_ORDERS as u8, TradingError::OrderbookFull);
+        require!(self.active_sl_count < self.get_max_orders(), TradingError::OrderbookFull);
         require!(size_percent > 0 && size_percent <= 100_000_000, TradingError::InvalidAmount);
         
         // Find first inactive slot
@@ -163,6 +185,7 @@ impl TpSlOrderbook {
         if let Some(size_percent) = new_size_percent {
             require!(size_percent > 0 && size_percent <= 100_000_000, TradingError::InvalidAmount);
             let new_total = self.total_tp_percent - order.size_percent + size_percent;
+            require!(new_total <= 100_000_000, TradingError::TakeProfitOverAllocated);
             
             self.total_tp_percent = new_total;
             order.size_percent = size_percent;
@@ -248,4 +271,95 @@ impl TpSlOrderbook {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Orderbook as first released, without max_orders
+    #[allow(dead_code)]
+    struct LegacyTpSlOrderbook {
+        owner: Pubkey,
+        position: Pubkey,
+        contract_type: u8,
+        take_profit_orders: [TpSlOrder; 10],
+        stop_loss_orders: [TpSlOrder; 10],
+        active_tp_count: u8,
+        active_sl_count: u8,
+        total_tp_percent: u64,
+        total_sl_percent: u64,
+        last_executed_tp_index: Option<u8>,
+        last_executed_sl_index: Option<u8>,
+        last_execution_time: i64,
+        bump: u8,
+    }
+
+    #[test]
+    fn released_orderbooks_fit_max_orders() {
+        let released_len = 8 + std::mem::size_of::<LegacyTpSlOrderbook>();
+
+        // Largest serialized form, with both Options set
+        let orderbook = TpSlOrderbook {
+            last_executed_tp_index: Some(1),
+            last_executed_sl_index: Some(1),
+            max_orders: 4,
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        orderbook.try_serialize(&mut data).unwrap();
+        assert!(data.len() <= released_len);
+    }
+
+    #[test]
+    fn unset_max_orders_allows_the_full_book() {
+        let mut orderbook = TpSlOrderbook::default();
+        assert_eq!(orderbook.get_max_orders() as usize, TpSlOrderbook::MAX_ORDERS);
+
+        orderbook.max_orders = 2;
+        assert_eq!(orderbook.get_max_orders(), 2);
+    }
+
+    #[test]
+    fn take_profit_levels_may_not_close_more_than_the_position() {
+        let mut orderbook = TpSlOrderbook::default();
+        orderbook.initialize(Pubkey::default(), Pubkey::default(), 0, 10, 0).unwrap();
+
+        // Scale out at three targets, each with its own receive asset
+        assert_eq!(orderbook.add_take_profit_order(110_000_000, 25_000_000, true).unwrap(), 0);
+        assert_eq!(orderbook.add_take_profit_order(120_000_000, 25_000_000, false).unwrap(), 1);
+        assert_eq!(orderbook.add_take_profit_order(130_000_000, 50_000_000, true).unwrap(), 2);
+        assert_eq!(orderbook.total_tp_percent, 100_000_000);
+        assert!(orderbook.take_profit_orders[0].receive_sol);
+        assert!(!orderbook.take_profit_orders[1].receive_sol);
+
+        let err = orderbook.add_take_profit_order(140_000_000, 1, false).unwrap_err();
+        assert_eq!(err, TradingError::TakeProfitOverAllocated.into());
+        assert_eq!(orderbook.active_tp_count, 3);
+        assert_eq!(orderbook.total_tp_percent, 100_000_000);
+    }
+
+    #[test]
+    fn orderbook_rejects_orders_past_its_capacity() {
+        let mut orderbook = TpSlOrderbook::default();
+        orderbook.initialize(Pubkey::default(), Pubkey::default(), 0, 3, 0).unwrap();
+        for i in 0..3 {
+            orderbook.add_take_profit_order(110_000_000 + i, 1_000_000, false).unwrap();
+        }
+        let err = orderbook.add_take_profit_order(120_000_000, 1_000_000, false).unwrap_err();
+        assert_eq!(err, TradingError::OrderbookFull.into());
+
+        // The default capacity is the full book
+        let mut orderbook = TpSlOrderbook::default();
+        for i in 0..TpSlOrderbook::MAX_ORDERS as u64 {
+            orderbook.add_take_profit_order(110_000_000 + i, 1_000_000, false).unwrap();
+        }
+        let err = orderbook.add_take_profit_order(120_000_000, 1_000_000, false).unwrap_err();
+        assert_eq!(err, TradingError::OrderbookFull.into());
+
+        // Capacity is bounded by the account layout
+        assert!(orderbook
+            .initialize(Pubkey::default(), Pubkey::default(), 0, TpSlOrderbook::MAX_ORDERS as u8 + 1, 0)
+            .is_err());
+    }
+}
@@ -6,8 +6,10 @@ pub struct User {
     pub bump: u8,
     pub perp_position_index: u64,    // Next perp position index to assign
     pub future_index: u64,           // Next future index to assign
+    // Appended after the first release, accounts created before it are grown with migrate_account
+    pub default_receive_sol: bool,   // Settlement asset used when a close leaves receive_sol unset
 }
 
 impl User {
-    pub const LEN: usize = 8 + 1 + 8 + 8 + 8;  // option_index + bump + perp_position_index + future_index
+    pub const LEN: usize = 8 + 1 + 8 + 8 + 8 + 1;  // option_index + bump + perp_position_index + future_index + default_receive_sol
 }
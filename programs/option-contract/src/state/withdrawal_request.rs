@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    math,
+    state::{Contract, Custody},
+};
+
+/// A queued LP withdrawal from one custody. The LP tokens are escrowed with the transfer
+/// authority and `reserved_amount` of the custody is kept free of new locks until the
+/// request is filled or cancelled. Requests are filled in `queue_index` order; one cancelled
+/// behind the head of the queue stays as an empty placeholder until fulfill passes it.
+#[account]
+#[derive(Default, Debug)]
+pub struct WithdrawalRequest {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub lp_amount: u64,        // LP tokens still waiting to be redeemed
+    pub reserved_amount: u64,  // Custody tokens held back for the remaining LP tokens
+    pub request_time: i64,
+    pub last_fill_time: i64,
+    pub queue_index: u64,      // Position in the custody's withdrawal queue
+    pub bump: u8,
+}
+
+impl WithdrawalRequest {
+    pub const LEN: usize = 8 + std::mem::size_of::<WithdrawalRequest>();
+    // Past this age a request is filled even if it pushes the custody out of its token ratio
+    pub const RATIO_CHECK_TIMEOUT_SEC: i64 = 3 * 86_400; // 3 days
+    // LP tokens stay escrowed at least this long, so reserving liquidity isn't free to repeat
+    pub const MIN_CANCEL_DELAY_SEC: i64 = 86_400; // 1 day
+    // Share of token_owned all queued requests of a custody may hold back together
+    pub const MAX_RESERVED_BPS: u64 = 2_000; // 20%
+
+    /// Share of the reservation released when `lp_filled` of the remaining LP tokens are redeemed
+    pub fn get_released_reservation(&self, lp_filled: u64) -> Result<u64> {
+        if lp_filled >= self.lp_amount {
+            return Ok(self.reserved_amount);
+        }
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.reserved_amount as u128, lp_filled as u128)?,
+            self.lp_amount as u128,
+        )?)
+    }
+
+    pub fn is_ratio_check_waived(&self, current_time: i64) -> Result<bool> {
+        Ok(math::checked_sub(current_time, self.request_time)? >= Self::RATIO_CHECK_TIMEOUT_SEC)
+    }
+
+    pub fn can_cancel(&self, current_time: i64) -> Result<bool> {
+        Ok(math::checked_sub(current_time, self.request_time)? >= Self::MIN_CANCEL_DELAY_SEC)
+    }
+
+    /// Close a request account, returning its rent to `owner`
+    pub fn close_account(request: &AccountInfo, owner: &AccountInfo) -> Result<()> {
+        let request_rent = request.lamports();
+        **request.try_borrow_mut_lamports()? = 0;
+        **owner.try_borrow_mut_lamports()? = owner
+            .lamports()
+            .checked_add(request_rent)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        request.try_borrow_mut_data()?.fill(0);
+        Ok(())
+    }
+
+    /// A request cancelled behind the queue head, left for fulfill to close in order
+    pub fn is_cancelled(&self) -> bool {
+        self.lp_amount == 0
+    }
+
+    /// Largest reservation `custody` can still take within MAX_RESERVED_BPS of token_owned
+    pub fn get_reserve_headroom(custody: &Custody) -> Result<u64> {
+        let max_reserved = math::checked_as_u64(math::checked_div(
+            math::checked_mul(custody.token_owned as u128, Self::MAX_RESERVED_BPS as u128)?,
+            Contract::BPS_POWER,
+        )?)?;
+        Ok(max_reserved.saturating_sub(custody.reserved_for_withdrawals))
+    }
+}
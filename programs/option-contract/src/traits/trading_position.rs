@@ -128,7 +128,7 @@ pub mod position_utils {
         decimals: u8,
     ) -> Result<u64> {
         let amount_normalized = amount as f64 / (10_u64.pow(decimals as u32) as f64);
-        let price_normalized = price as f64 / 1_000_000.0; // Assuming 6 decimal price
+        let price_normalized = price as f64 / math::PRICE_SCALE as f64;
         let value_usd = amount_normalized * price_normalized;
         Ok(value_usd as u64)
     }
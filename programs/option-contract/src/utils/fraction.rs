@@ -49,11 +49,7 @@ impl Fraction {
     }
 
     pub fn checked_div(self, scalar: u128) -> Option<Self> {
-        if scalar == 0 {
-            None
-        } else {
-            Some(Fraction { value: self.value / scalar })
-        }
+        self.value.checked_div(scalar).map(|v| Fraction { value: v })
     }
 }
 
@@ -68,7 +64,7 @@ impl std::ops::Sub for Fraction {
     type Output = Self;
     fn sub(self, other: Self) -> Self {
         // Use checked_sub to prevent underflow, falling back to 0
-        Fraction { value: self.value.checked_sub(other.value).unwrap_or(0) }
+        Fraction { value: self.value.saturating_sub(other.value) }
     }
 }
 
@@ -83,10 +79,6 @@ impl std::ops::Mul<u128> for Fraction {
 impl std::ops::Div<u128> for Fraction {
     type Output = Self;
     fn div(self, scalar: u128) -> Self {
-        if scalar == 0 {
-            Fraction { value: 0 }
-        } else {
-            Fraction { value: self.value / scalar }
-        }
+        Fraction { value: self.value.checked_div(scalar).unwrap_or(0) }
     }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use anchor_lang::prelude::*;
-use crate::{utils::pool::*};
+use crate::{state::ExerciseStyle, utils::pool::*};
 
 pub fn normal_cdf(z: f64) -> f64 {
     let beta1 = -0.0004406;
@@ -33,7 +33,15 @@ pub fn black_scholes(
     }
 }
 
-/// Enhanced Black-Scholes with dynamic risk-free rate from borrow curves
+/// Annual volatility black_scholes_with_borrow_rate prices with
+pub fn get_option_volatility(is_sol: bool) -> f64 {
+    if is_sol { 0.8 } else { 0.3 } // Keep volatility simple for now
+}
+
+/// Enhanced Black-Scholes with dynamic risk-free rate from borrow curves. The formula values
+/// a European option; American options are floored at intrinsic value since they can be
+/// exercised immediately.
+#[allow(clippy::too_many_arguments)]
 pub fn black_scholes_with_borrow_rate(
     s: f64,               // Current price
     k: f64,               // Strike price  
@@ -42,10 +50,22 @@ pub fn black_scholes_with_borrow_rate(
     token_locked: u64,    // Current locked tokens
     token_owned: u64,     // Total owned tokens
     is_sol: bool,         // Asset type
+    exercise_style: ExerciseStyle,
 ) -> Result<f64> {
+    // Degenerate inputs would hit ln(S/K) or divide by sqrt(t), producing NaN/inf.
+    // Fall back to intrinsic value (zero for a worthless leg) instead.
+    let intrinsic = if call {
+        (s.max(0.0) - k.max(0.0)).max(0.0)
+    } else {
+        (k.max(0.0) - s.max(0.0)).max(0.0)
+    };
+    if !(s > 0.0 && k > 0.0 && t > 0.0) {
+        return Ok(intrinsic);
+    }
+
     // Calculate dynamic risk-free rate from borrow curve
     let r = calculate_borrow_rate(token_locked, token_owned, is_sol)? / 100.0;
-    let sigma = if is_sol { 0.8 } else { 0.3 }; // Keep volatility simple for now
+    let sigma = get_option_volatility(is_sol);
 
     let d1 = ((s / k).ln() + (r + 0.5 * sigma * sigma) * t) / (sigma * t.sqrt());
     let d2 = d1 - sigma * t.sqrt();
@@ -61,5 +81,64 @@ pub fn black_scholes_with_borrow_rate(
         k * (-r * t).exp() * n_neg_d2 - s * n_neg_d1
     };
 
-    Ok(price)
+    if !price.is_finite() {
+        return Ok(intrinsic);
+    }
+
+    // Early exercise premium: an American holder can always take intrinsic value now
+    let price = match exercise_style {
+        ExerciseStyle::American => price.max(intrinsic),
+        ExerciseStyle::European => price,
+    };
+
+    Ok(price.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fully utilized SOL custody: the borrow curve tops out, so the discounted strike of a
+    // deep in-the-money put sits well below intrinsic value
+    const LOCKED: u64 = 1_000;
+    const OWNED: u64 = 1_000;
+
+    #[test]
+    fn american_put_is_floored_at_intrinsic() {
+        let american = black_scholes_with_borrow_rate(
+            50.0, 100.0, 1.0, false, LOCKED, OWNED, true, ExerciseStyle::American,
+        )
+        .unwrap();
+        let european = black_scholes_with_borrow_rate(
+            50.0, 100.0, 1.0, false, LOCKED, OWNED, true, ExerciseStyle::European,
+        )
+        .unwrap();
+
+        assert!(european < 50.0);
+        assert_eq!(american, 50.0);
+    }
+
+    #[test]
+    fn styles_agree_when_time_value_dominates() {
+        let american = black_scholes_with_borrow_rate(
+            100.0, 100.0, 0.25, true, 0, OWNED, true, ExerciseStyle::American,
+        )
+        .unwrap();
+        let european = black_scholes_with_borrow_rate(
+            100.0, 100.0, 0.25, true, 0, OWNED, true, ExerciseStyle::European,
+        )
+        .unwrap();
+
+        assert!(european > 0.0);
+        assert_eq!(american, european);
+    }
+
+    #[test]
+    fn degenerate_inputs_return_intrinsic_for_both_styles() {
+        for style in [ExerciseStyle::American, ExerciseStyle::European] {
+            let expired =
+                black_scholes_with_borrow_rate(120.0, 100.0, 0.0, true, 0, OWNED, true, style).unwrap();
+            assert_eq!(expired, 20.0);
+        }
+    }
 }
\ No newline at end of file
@@ -1,15 +1,18 @@
 use crate::{
-    errors::PerpetualError, math::{self, f64_to_scaled_price}, state::{Position, Side}
+    errors::PerpetualError, math::{self, f64_to_scaled_price}, state::Side
 };
 use anchor_lang::prelude::*;
 
+/// Price at which equity falls to `maintenance_margin_bps` of size (perps take it from
+/// `Custody::get_maintenance_margin_bps`).
 pub fn calculate_liquidation_price(
     entry_price: u64,
     leverage: f64,
-    side: Side
+    side: Side,
+    maintenance_margin_bps: u64,
 ) -> Result<u64> {
     let entry_price_f64 = math::checked_float_div(entry_price as f64, crate::math::PRICE_SCALE as f64)?;
-    let margin_ratio = Position::LIQUIDATION_MARGIN_BPS as f64 / 10_000.0;
+    let margin_ratio = maintenance_margin_bps as f64 / 10_000.0;
     
     let max_loss_ratio = (1.0 / leverage) - margin_ratio;
     
@@ -22,3 +25,23 @@ pub fn calculate_liquidation_price(
     
     f64_to_scaled_price(liquidation_price_f64)
 }
+
+/// Price at which the position's equity reaches zero (losses equal collateral),
+/// as opposed to the liquidation price where maintenance margin is breached.
+pub fn calculate_bankruptcy_price(
+    entry_price: u64,
+    leverage: f64,
+    side: Side
+) -> Result<u64> {
+    require!(leverage > 0.0, PerpetualError::InvalidLeverage);
+    
+    let entry_price_f64 = math::checked_float_div(entry_price as f64, crate::math::PRICE_SCALE as f64)?;
+    let max_loss_ratio = 1.0 / leverage;
+    
+    let bankruptcy_price_f64 = match side {
+        Side::Long => (entry_price_f64 * (1.0 - max_loss_ratio)).max(0.0),
+        Side::Short => entry_price_f64 * (1.0 + max_loss_ratio)
+    };
+    
+    f64_to_scaled_price(bankruptcy_price_f64)
+}
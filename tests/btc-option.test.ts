@@ -0,0 +1,165 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { OptionContract } from "../target/types/option_contract";
+import { expect } from "chai";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  getAssociatedTokenAddressSync,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+// Options take their underlying, locked and premium custodies from the accounts passed in, so a
+// call can be written on a BTC custody in the SOL-USDC pool and priced off the BTC feed.
+describe("BTC Underlying Option", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+  const program = anchor.workspace.OptionContract as Program<OptionContract>;
+
+  const WSOLMint = new PublicKey("6fiDYq4uZgQQNUZVaBBcwu9jAUTWWBb7U8nmxt6BCaHY");
+  const USDCMint = new PublicKey("Fe7yM1wqx5ySZmSHJjNzkLuvBCU8BEnYpmxcpGwwBkZq");
+
+  const poolName = "SOL-USDC";
+  const PRICE_SCALE = 1_000_000; // math::PRICE_SCALE
+
+  let userWallet: Keypair;
+  let contractPDA: PublicKey;
+  let poolPDA: PublicKey;
+  let transferAuthorityPDA: PublicKey;
+  let userPDA: PublicKey;
+  let usdcCustodyPDA: PublicKey;
+  let userUSDCAccount: PublicKey;
+
+  // Spot price of a Pyth PriceUpdateV2 account, skipping the discriminator, write authority
+  // and the verification level (Partial carries one extra byte)
+  const readPythPrice = async (oracle: PublicKey) => {
+    const data = (await provider.connection.getAccountInfo(oracle)).data;
+    let offset = 8 + 32;
+    offset += data.readUInt8(offset) === 0 ? 2 : 1;
+    offset += 32; // feed id
+    const price = Number(data.readBigInt64LE(offset));
+    const exponent = data.readInt32LE(offset + 16);
+    return price * Math.pow(10, exponent);
+  };
+
+  before(async () => {
+    userWallet = provider.wallet.payer;
+
+    [contractPDA] = PublicKey.findProgramAddressSync([Buffer.from("contract")], program.programId);
+    [poolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), Buffer.from(poolName)],
+      program.programId
+    );
+    [transferAuthorityPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("transfer_authority")],
+      program.programId
+    );
+    [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_v3"), userWallet.publicKey.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    userUSDCAccount = getAssociatedTokenAddressSync(USDCMint, userWallet.publicKey);
+  });
+
+  it("Should price a BTC-underlying call off the BTC oracle", async function () {
+    // the pool's third custody, next to SOL and USDC, is the BTC one
+    const pool = await program.account.pool.fetch(poolPDA);
+    let btcCustodyPDA: PublicKey = null;
+    let btcCustody = null;
+    for (const custodyKey of pool.custodies) {
+      const custody = await program.account.custody.fetch(custodyKey);
+      if (!custody.mint.equals(WSOLMint) && !custody.mint.equals(USDCMint)) {
+        btcCustodyPDA = custodyKey;
+        btcCustody = custody;
+        break;
+      }
+    }
+    if (btcCustodyPDA === null) {
+      console.log("Pool holds no BTC custody, skipping");
+      this.skip();
+    }
+    const usdcCustody = await program.account.custody.fetch(usdcCustodyPDA);
+    const btcPrice = await readPythPrice(btcCustody.oracle);
+
+    // an at-the-money call a week out, on the pool's expiry grid when it has one
+    const now = Math.floor(Date.now() / 1000);
+    let expiry = now + 7 * 24 * 3600;
+    const interval = pool.optionExpiryIntervalSec.toNumber();
+    if (interval > 0) {
+      const offset = pool.optionExpiryOffsetSec.toNumber();
+      expiry += interval - ((((expiry - offset) % interval) + interval) % interval);
+    }
+    const period = Math.ceil((expiry - now) / (24 * 3600));
+    const strike = Math.round(btcPrice);
+
+    const userData = await program.account.user.fetch(userPDA);
+    const [optionPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("option"),
+        userWallet.publicKey.toBuffer(),
+        userData.optionIndex.addn(1).toArrayLike(Buffer, "le", 8),
+        poolPDA.toBuffer(),
+        btcCustodyPDA.toBuffer(),
+      ],
+      program.programId
+    );
+    const [usdcCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+
+    // BTC is the underlying and, for a call, also the locked asset; the premium is paid in USDC
+    await program.methods
+      .openOption({
+        amount: new anchor.BN(10_000_000), // 10 USDC
+        strike,
+        period: new anchor.BN(period),
+        expiredTime: new anchor.BN(expiry),
+        poolName,
+        takeProfitPrice: null,
+        stopLossPrice: null,
+        referrer: null,
+        maxPremiumTokens: null,
+        exerciseStyle: { american: {} },
+      })
+      .accountsPartial({
+        owner: userWallet.publicKey,
+        fundingAccount: userUSDCAccount,
+        transferAuthority: transferAuthorityPDA,
+        contract: contractPDA,
+        pool: poolPDA,
+        custody: btcCustodyPDA,
+        custodyOracleAccount: btcCustody.oracle,
+        user: userPDA,
+        optionDetail: optionPDA,
+        payCustody: usdcCustodyPDA,
+        payCustodyTokenAccount: usdcCustodyTokenAccountPDA,
+        payCustodyOracleAccount: usdcCustody.oracle,
+        lockedCustody: btcCustodyPDA,
+        custodyMint: btcCustody.mint,
+        payCustodyMint: USDCMint,
+        lockedCustodyMint: btcCustody.mint,
+        tpSlOrderbook: null,
+        referral: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        custodyOracleSecondary: null,
+        payCustodyOracleSecondary: null,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    const option = await program.account.optionDetail.fetch(optionPDA);
+    expect(option.custody.equals(btcCustodyPDA)).to.be.true;
+    expect(option.lockedAsset.equals(btcCustodyPDA)).to.be.true;
+    expect(option.premiumAsset.equals(usdcCustodyPDA)).to.be.true;
+    expect(option.optionType).to.deep.equal({ call: {} });
+
+    // the entry price is the BTC spot the premium was priced at, not SOL's
+    const entryPrice = option.entryPrice.toNumber() / PRICE_SCALE;
+    expect(Math.abs(entryPrice - btcPrice) / btcPrice).to.be.lessThan(0.01);
+  });
+});
@@ -0,0 +1,170 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { OptionContract } from "../target/types/option_contract";
+import { expect } from "chai";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  getAccount,
+  getAssociatedTokenAddressSync,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+// A short future opened with USDC collateral must hand its remaining collateral back in USDC
+// on close, and never touch the SOL side.
+describe("Future Collateral", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+  const program = anchor.workspace.OptionContract as Program<OptionContract>;
+
+  const WSOLMint = new PublicKey("6fiDYq4uZgQQNUZVaBBcwu9jAUTWWBb7U8nmxt6BCaHY");
+  const USDCMint = new PublicKey("Fe7yM1wqx5ySZmSHJjNzkLuvBCU8BEnYpmxcpGwwBkZq");
+
+  const poolName = "SOL-USDC";
+  const FULL_CLOSE = new anchor.BN(100_000_000); // Future::FULL_CLOSE
+
+  let userWallet: Keypair;
+  let contractPDA: PublicKey;
+  let poolPDA: PublicKey;
+  let transferAuthorityPDA: PublicKey;
+  let userPDA: PublicKey;
+  let solCustodyPDA: PublicKey;
+  let usdcCustodyPDA: PublicKey;
+  let solCustodyTokenAccountPDA: PublicKey;
+  let usdcCustodyTokenAccountPDA: PublicKey;
+  let userUSDCAccount: PublicKey;
+  let userWSOLAccount: PublicKey;
+
+  before(async () => {
+    userWallet = provider.wallet.payer;
+
+    [contractPDA] = PublicKey.findProgramAddressSync([Buffer.from("contract")], program.programId);
+    [poolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), Buffer.from(poolName)],
+      program.programId
+    );
+    [transferAuthorityPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("transfer_authority")],
+      program.programId
+    );
+    [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_v3"), userWallet.publicKey.toBuffer()],
+      program.programId
+    );
+    [solCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), WSOLMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    [solCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), WSOLMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    userUSDCAccount = getAssociatedTokenAddressSync(USDCMint, userWallet.publicKey);
+    userWSOLAccount = getAssociatedTokenAddressSync(WSOLMint, userWallet.publicKey);
+  });
+
+  it("Should return USDC collateral of a short future in USDC", async () => {
+    const solCustody = await program.account.custody.fetch(solCustodyPDA);
+    const usdcCustody = await program.account.custody.fetch(usdcCustodyPDA);
+    const userData = await program.account.user.fetch(userPDA);
+    const futureIndex = userData.futureIndex;
+    const [futurePDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("future"),
+        userWallet.publicKey.toBuffer(),
+        futureIndex.toArrayLike(Buffer, "le", 8),
+        poolPDA.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const oracles = {
+      solOracleAccount: solCustody.oracle,
+      usdcOracleAccount: usdcCustody.oracle,
+      solOracleSecondary: null,
+      usdcOracleSecondary: null,
+    };
+    const custodies = {
+      transferAuthority: transferAuthorityPDA,
+      contract: contractPDA,
+      pool: poolPDA,
+      future: futurePDA,
+      solCustody: solCustodyPDA,
+      usdcCustody: usdcCustodyPDA,
+      solCustodyTokenAccount: solCustodyTokenAccountPDA,
+      usdcCustodyTokenAccount: usdcCustodyTokenAccountPDA,
+      solMint: WSOLMint,
+      usdcMint: USDCMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      ...oracles,
+    };
+
+    // STEP 1: open a 10x short with USDC collateral, settling in USDC
+    const expiryTimestamp = Math.floor(Date.now() / 1000) + 7 * 24 * 3600;
+    await program.methods
+      .openFuture({
+        side: { short: {} },
+        sizeUsd: new anchor.BN(100_000_000), // $100
+        collateralAmount: new anchor.BN(10_000_000), // 10 USDC
+        paySol: false,
+        payLp: false,
+        receiveSol: false,
+        expiryTimestamp: new anchor.BN(expiryTimestamp),
+        maxSlippageBps: new anchor.BN(500),
+        poolName,
+        referrer: null,
+      })
+      .accountsPartial({
+        ...custodies,
+        owner: userWallet.publicKey,
+        user: userPDA,
+        fundingAccount: userUSDCAccount,
+        lpTokenMint: null,
+        lpCollateralAccount: null,
+        referral: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    const opened = await program.account.future.fetch(futurePDA);
+    expect(opened.collateralCustody.equals(usdcCustodyPDA)).to.be.true;
+
+    const usdcBefore = (await getAccount(provider.connection, userUSDCAccount)).amount;
+    const wsolBefore = (await getAccount(provider.connection, userWSOLAccount)).amount;
+
+    // STEP 2: close it in full; no SOL receiving account is passed
+    await program.methods
+      .closeFuture({
+        futureIndex,
+        poolName,
+        closePercentage: FULL_CLOSE,
+        receiveSol: false,
+        maxSlippageBps: new anchor.BN(500),
+      })
+      .accountsPartial({
+        ...custodies,
+        owner: userWallet.publicKey,
+        receivingAccount: userUSDCAccount,
+        collateralReceivingAccount: null,
+        lpTokenMint: null,
+        lpCollateralAccount: null,
+        lpReceivingAccount: null,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    // STEP 3: the collateral came back in USDC, and SOL was left alone
+    const usdcAfter = (await getAccount(provider.connection, userUSDCAccount)).amount;
+    const wsolAfter = (await getAccount(provider.connection, userWSOLAccount)).amount;
+    expect(usdcAfter > usdcBefore).to.be.true;
+    expect(wsolAfter === wsolBefore).to.be.true;
+  });
+});
@@ -0,0 +1,146 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { OptionContract } from "../target/types/option_contract";
+import { expect } from "chai";
+import { PublicKey, Keypair, SystemProgram, SYSVAR_RENT_PUBKEY } from "@solana/web3.js";
+import {
+  getAssociatedTokenAddressSync,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+// LiquidityAdded carries the oracle price the deposit was valued at and the LP share price,
+// so the minted amount can be reproduced from the event alone.
+describe("Liquidity Events", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+  const program = anchor.workspace.OptionContract as Program<OptionContract>;
+
+  const USDCMint = new PublicKey("Fe7yM1wqx5ySZmSHJjNzkLuvBCU8BEnYpmxcpGwwBkZq");
+  const TOKEN_METADATA_PROGRAM_ID = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+  const poolName = "SOL-USDC";
+  const ONE_LP_TOKEN = 1_000_000; // Contract::LP_DECIMALS = 6
+
+  let userWallet: Keypair;
+  let contractPDA: PublicKey;
+  let poolPDA: PublicKey;
+  let transferAuthorityPDA: PublicKey;
+  let usdcCustodyPDA: PublicKey;
+  let usdcCustodyTokenAccountPDA: PublicKey;
+  let lpTokenMintPDA: PublicKey;
+  let lpTokenMetadataPDA: PublicKey;
+  let userUSDCAccount: PublicKey;
+
+  // Spot price and exponent of a Pyth PriceUpdateV2 account, skipping the discriminator,
+  // write authority and the verification level (Partial carries one extra byte)
+  const readPythPrice = async (oracle: PublicKey) => {
+    const data = (await provider.connection.getAccountInfo(oracle)).data;
+    let offset = 8 + 32;
+    offset += data.readUInt8(offset) === 0 ? 2 : 1;
+    offset += 32; // feed id
+    return {
+      price: Number(data.readBigInt64LE(offset)),
+      exponent: data.readInt32LE(offset + 16),
+    };
+  };
+
+  before(async () => {
+    userWallet = provider.wallet.payer;
+
+    [contractPDA] = PublicKey.findProgramAddressSync([Buffer.from("contract")], program.programId);
+    [poolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), Buffer.from(poolName)],
+      program.programId
+    );
+    [transferAuthorityPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("transfer_authority")],
+      program.programId
+    );
+    [usdcCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    [lpTokenMintPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp_token_mint"), Buffer.from(poolName)],
+      program.programId
+    );
+    [lpTokenMetadataPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("metadata"), TOKEN_METADATA_PROGRAM_ID.toBuffer(), lpTokenMintPDA.toBuffer()],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+    userUSDCAccount = getAssociatedTokenAddressSync(USDCMint, userWallet.publicKey);
+  });
+
+  it("Should emit the oracle price and LP share price a deposit was valued at", async () => {
+    const pool = await program.account.pool.fetch(poolPDA);
+    const usdcCustody = await program.account.custody.fetch(usdcCustodyPDA);
+
+    // every custody then every oracle, so a stale AUM can be refreshed
+    const custodies = await Promise.all(pool.custodies.map((key) => program.account.custody.fetch(key)));
+    const remainingAccounts = [
+      ...pool.custodies.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })),
+      ...custodies.map((custody) => ({ pubkey: custody.oracle, isSigner: false, isWritable: false })),
+    ];
+
+    const signature = await program.methods
+      .addLiquidity({
+        amountIn: new anchor.BN(1_000_000), // 1 USDC
+        minLpAmountOut: new anchor.BN(0),
+        poolName,
+      })
+      .accountsPartial({
+        owner: userWallet.publicKey,
+        fundingAccount: userUSDCAccount,
+        lpTokenAccount: getAssociatedTokenAddressSync(lpTokenMintPDA, userWallet.publicKey),
+        transferAuthority: transferAuthorityPDA,
+        contract: contractPDA,
+        pool: poolPDA,
+        custody: usdcCustodyPDA,
+        custodyOracleAccount: usdcCustody.oracle,
+        custodyTokenAccount: usdcCustodyTokenAccountPDA,
+        lpTokenMint: lpTokenMintPDA,
+        custodyMint: USDCMint,
+        lpTokenMetadata: lpTokenMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: SYSVAR_RENT_PUBKEY,
+        custodyOracleSecondary: null,
+      })
+      .remainingAccounts(remainingAccounts)
+      .signers([userWallet])
+      .rpc({ commitment: "confirmed" });
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+    const event = [...parser.parseLogs(tx.meta.logMessages)].find((e) => e.name.toLowerCase() === "liquidityadded");
+    expect(event).to.not.be.undefined;
+    const data = event.data as any;
+
+    // the emitted price is the oracle read, give or take a feed update since
+    const oracle = await readPythPrice(usdcCustody.oracle);
+    expect(data.tokenPriceExponent).to.equal(oracle.exponent);
+    const emitted = data.tokenPrice.toNumber() * Math.pow(10, data.tokenPriceExponent);
+    const read = oracle.price * Math.pow(10, oracle.exponent);
+    expect(Math.abs(emitted - read) / read).to.be.lessThan(0.005);
+
+    // the deposit's USD value follows from the emitted price ...
+    const depositUsd = Math.floor(
+      (data.depositAmount.toNumber() * emitted * 1_000_000) / Math.pow(10, usdcCustody.decimals)
+    );
+    expect(Math.abs(depositUsd - data.tokenAmountUsd.toNumber())).to.be.lessThanOrEqual(1);
+
+    // ... and the minted LP from the share price
+    const lpFromSharePrice = (data.tokenAmountUsd.toNumber() * ONE_LP_TOKEN) / data.lpSharePriceUsd.toNumber();
+    expect(Math.abs(lpFromSharePrice - data.lpAmount.toNumber()) / data.lpAmount.toNumber()).to.be.lessThan(0.001);
+  });
+});
@@ -0,0 +1,309 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { OptionContract } from "../target/types/option_contract";
+import { expect } from "chai";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  getAssociatedTokenAddressSync,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+// Open a perp, move the SOL custody to a new primary feed, then close the position with the
+// new oracle account. The feed change swaps primary and secondary in one set_custody_config
+// call, which also exercises the final-pair validation.
+describe("Oracle Migration", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+  const program = anchor.workspace.OptionContract as Program<OptionContract>;
+
+  const WSOLMint = new PublicKey("6fiDYq4uZgQQNUZVaBBcwu9jAUTWWBb7U8nmxt6BCaHY");
+  const USDCMint = new PublicKey("Fe7yM1wqx5ySZmSHJjNzkLuvBCU8BEnYpmxcpGwwBkZq");
+
+  const poolName = "SOL-USDC";
+  const ORACLE_TYPE_PYTH = 0;
+
+  let userWallet: Keypair;
+  let contractPDA: PublicKey;
+  let poolPDA: PublicKey;
+  let multisigPDA: PublicKey;
+  let transferAuthorityPDA: PublicKey;
+  let userPDA: PublicKey;
+  let solCustodyPDA: PublicKey;
+  let usdcCustodyPDA: PublicKey;
+  let solCustodyTokenAccountPDA: PublicKey;
+  let usdcCustodyTokenAccountPDA: PublicKey;
+  let userUSDCAccount: PublicKey;
+
+  let originalPrimary: PublicKey;
+  let originalSecondary: PublicKey;
+
+  const setSolOracles = async (primary: PublicKey, secondary: PublicKey) => {
+    await program.methods
+      .setCustodyConfig({
+        poolName,
+        optionBuyMarkupBps: null,
+        optionSellMarkdownBps: null,
+        optionCloseFeeTiers: null,
+        marginTiers: null,
+        oracle: primary,
+        oracleSecondary: secondary,
+        oracleTypePrimary: ORACLE_TYPE_PYTH,
+        oracleTypeSecondary: ORACLE_TYPE_PYTH,
+        reservedForSettlement: null,
+        tradingPaused: null,
+      })
+      .accounts({
+        signer: userWallet.publicKey,
+        multisig: multisigPDA,
+        contract: contractPDA,
+        pool: poolPDA,
+        custody: solCustodyPDA,
+        custodyMint: WSOLMint,
+      })
+      .signers([userWallet])
+      .rpc();
+  };
+
+  before(async () => {
+    userWallet = provider.wallet.payer;
+
+    [contractPDA] = PublicKey.findProgramAddressSync([Buffer.from("contract")], program.programId);
+    [multisigPDA] = PublicKey.findProgramAddressSync([Buffer.from("multisig")], program.programId);
+    [poolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), Buffer.from(poolName)],
+      program.programId
+    );
+    [transferAuthorityPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("transfer_authority")],
+      program.programId
+    );
+    [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_v3"), userWallet.publicKey.toBuffer()],
+      program.programId
+    );
+    [solCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), WSOLMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    [solCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), WSOLMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    userUSDCAccount = getAssociatedTokenAddressSync(USDCMint, userWallet.publicKey);
+
+    const solCustody = await program.account.custody.fetch(solCustodyPDA);
+    originalPrimary = solCustody.oracle;
+    originalSecondary = solCustody.oracleSecondary;
+  });
+
+  after(async () => {
+    // put the feeds back so the other suites see the original configuration
+    const solCustody = await program.account.custody.fetch(solCustodyPDA);
+    if (!solCustody.oracle.equals(originalPrimary)) {
+      await setSolOracles(originalPrimary, originalSecondary);
+    }
+  });
+
+  it("Should close a position opened before the custody oracle changed", async function () {
+    // the secondary feed stands in for the migrated primary, so it must exist
+    if (originalSecondary.equals(PublicKey.default)) {
+      this.skip();
+    }
+
+    const usdcCustody = await program.account.custody.fetch(usdcCustodyPDA);
+    const userData = await program.account.user.fetch(userPDA);
+    const positionIndex = userData.perpPositionIndex.addn(1);
+    const [positionPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("position"),
+        userWallet.publicKey.toBuffer(),
+        positionIndex.toArrayLike(Buffer, "le", 8),
+        poolPDA.toBuffer(),
+      ],
+      program.programId
+    );
+
+    // STEP 1: open a small long against the original feeds
+    await program.methods
+      .openPerpPosition({
+        sizeAmount: new anchor.BN(100_000_000), // 0.1 SOL
+        collateralAmount: new anchor.BN(10_000_000), // 10 USDC
+        side: { long: {} },
+        orderType: { market: {} },
+        triggerPrice: null,
+        triggerAboveThreshold: false,
+        stopPrice: null,
+        maxSlippage: new anchor.BN(500),
+        poolName,
+        paySol: false,
+        payLp: false,
+        referrer: null,
+        expiryTime: null,
+        maxLossUsd: null,
+      })
+      .accountsPartial({
+        owner: userWallet.publicKey,
+        fundingAccount: userUSDCAccount,
+        transferAuthority: transferAuthorityPDA,
+        contract: contractPDA,
+        pool: poolPDA,
+        user: userPDA,
+        position: positionPDA,
+        solCustody: solCustodyPDA,
+        usdcCustody: usdcCustodyPDA,
+        solCustodyTokenAccount: solCustodyTokenAccountPDA,
+        usdcCustodyTokenAccount: usdcCustodyTokenAccountPDA,
+        solOracleAccount: originalPrimary,
+        usdcOracleAccount: usdcCustody.oracle,
+        solMint: WSOLMint,
+        usdcMint: USDCMint,
+        limitOrderBook: null,
+        lpTokenMint: null,
+        lpCollateralAccount: null,
+        referral: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        solOracleSecondary: null,
+        usdcOracleSecondary: null,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    const opened = await program.account.position.fetch(positionPDA);
+    expect(opened.sizeUsd.toNumber()).to.be.greaterThan(0);
+
+    // STEP 2: swap primary and secondary in one call
+    await setSolOracles(originalSecondary, originalPrimary);
+
+    const migrated = await program.account.custody.fetch(solCustodyPDA);
+    expect(migrated.oracle.equals(originalSecondary)).to.be.true;
+    expect(migrated.oracleSecondary.equals(originalPrimary)).to.be.true;
+
+    // STEP 3: the old feed no longer matches the custody, so the close must be refused
+    let rejected = false;
+    try {
+      await program.methods
+        .closePerpPosition({
+          positionIndex,
+          poolName,
+          contractType: 0, // perp
+          closePercentage: new anchor.BN(100_000_000), // full close, 6 decimals
+          receiveSol: false,
+          receiveAsLp: false,
+        })
+        .accountsPartial({
+          owner: userWallet.publicKey,
+          user: userPDA,
+          receivingAccount: userUSDCAccount,
+          transferAuthority: transferAuthorityPDA,
+          contract: contractPDA,
+          pool: poolPDA,
+          position: positionPDA,
+          solCustody: solCustodyPDA,
+          usdcCustody: usdcCustodyPDA,
+          solCustodyTokenAccount: solCustodyTokenAccountPDA,
+          usdcCustodyTokenAccount: usdcCustodyTokenAccountPDA,
+          solOracleAccount: originalPrimary,
+          usdcOracleAccount: usdcCustody.oracle,
+          solMint: WSOLMint,
+          usdcMint: USDCMint,
+          tpSlOrderbook: null,
+          lpTokenMint: null,
+          lpCollateralAccount: null,
+          lpReceivingAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          solOracleSecondary: null,
+          usdcOracleSecondary: null,
+          referral: null,
+        })
+        .signers([userWallet])
+        .rpc();
+    } catch (error) {
+      rejected = true;
+    }
+    expect(rejected).to.be.true;
+
+    // STEP 4: close with the new primary feed
+    await program.methods
+      .closePerpPosition({
+        positionIndex,
+        poolName,
+        contractType: 0, // perp
+        closePercentage: new anchor.BN(100_000_000), // full close, 6 decimals
+        receiveSol: false,
+        receiveAsLp: false,
+      })
+      .accountsPartial({
+        owner: userWallet.publicKey,
+        user: userPDA,
+        receivingAccount: userUSDCAccount,
+        transferAuthority: transferAuthorityPDA,
+        contract: contractPDA,
+        pool: poolPDA,
+        position: positionPDA,
+        solCustody: solCustodyPDA,
+        usdcCustody: usdcCustodyPDA,
+        solCustodyTokenAccount: solCustodyTokenAccountPDA,
+        usdcCustodyTokenAccount: usdcCustodyTokenAccountPDA,
+        solOracleAccount: originalSecondary,
+        usdcOracleAccount: usdcCustody.oracle,
+        solMint: WSOLMint,
+        usdcMint: USDCMint,
+        tpSlOrderbook: null,
+        lpTokenMint: null,
+        lpCollateralAccount: null,
+        lpReceivingAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        solOracleSecondary: null,
+        usdcOracleSecondary: null,
+        referral: null,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    // full close reclaims the position account
+    const closed = await provider.connection.getAccountInfo(positionPDA);
+    expect(closed).to.be.null;
+  });
+
+  it("Should reject a feed change without its oracle type", async () => {
+    let rejected = false;
+    try {
+      await program.methods
+        .setCustodyConfig({
+          poolName,
+          optionBuyMarkupBps: null,
+          optionSellMarkdownBps: null,
+          optionCloseFeeTiers: null,
+          marginTiers: null,
+          oracle: Keypair.generate().publicKey,
+          oracleSecondary: null,
+          oracleTypePrimary: null,
+          oracleTypeSecondary: null,
+          reservedForSettlement: null,
+          tradingPaused: null,
+        })
+        .accounts({
+          signer: userWallet.publicKey,
+          multisig: multisigPDA,
+          contract: contractPDA,
+          pool: poolPDA,
+          custody: solCustodyPDA,
+          custodyMint: WSOLMint,
+        })
+        .signers([userWallet])
+        .rpc();
+    } catch (error) {
+      rejected = true;
+    }
+    expect(rejected).to.be.true;
+  });
+});
@@ -0,0 +1,356 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { OptionContract } from "../target/types/option_contract";
+import { expect } from "chai";
+import {
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  LAMPORTS_PER_SOL,
+} from "@solana/web3.js";
+import {
+  getAssociatedTokenAddressSync,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
+
+// Every close path returns the position rent (and the TP/SL orderbook rent, when one is
+// closed with it) to the position owner. The keeper paths are signed and paid for by a
+// separate keeper, so the owner's lamports move by exactly the reclaimed rent.
+describe("Rent Refund", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+  const program = anchor.workspace.OptionContract as Program<OptionContract>;
+
+  const WSOLMint = new PublicKey("6fiDYq4uZgQQNUZVaBBcwu9jAUTWWBb7U8nmxt6BCaHY");
+  const USDCMint = new PublicKey("Fe7yM1wqx5ySZmSHJjNzkLuvBCU8BEnYpmxcpGwwBkZq");
+
+  const poolName = "SOL-USDC";
+  const FULL_CLOSE = new anchor.BN(100_000_000); // 100%, 6 decimals
+  // keeper paths wait on the oracle moving past a trigger placed one tick from entry
+  const TRIGGER_WAIT_MS = 60_000;
+
+  let userWallet: Keypair;
+  let keeper: Keypair;
+  let contractPDA: PublicKey;
+  let poolPDA: PublicKey;
+  let transferAuthorityPDA: PublicKey;
+  let userPDA: PublicKey;
+  let solCustodyPDA: PublicKey;
+  let usdcCustodyPDA: PublicKey;
+  let solCustodyTokenAccountPDA: PublicKey;
+  let usdcCustodyTokenAccountPDA: PublicKey;
+  let userUSDCAccount: PublicKey;
+  let solOracle: PublicKey;
+  let usdcOracle: PublicKey;
+
+  const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  const lamportsOf = async (account: PublicKey) =>
+    (await provider.connection.getAccountInfo(account))?.lamports ?? 0;
+
+  const txFee = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    return tx.meta.fee;
+  };
+
+  const sharedAccounts = () => ({
+    transferAuthority: transferAuthorityPDA,
+    contract: contractPDA,
+    pool: poolPDA,
+    solCustody: solCustodyPDA,
+    usdcCustody: usdcCustodyPDA,
+    solCustodyTokenAccount: solCustodyTokenAccountPDA,
+    usdcCustodyTokenAccount: usdcCustodyTokenAccountPDA,
+    solOracleAccount: solOracle,
+    usdcOracleAccount: usdcOracle,
+    solMint: WSOLMint,
+    usdcMint: USDCMint,
+    tokenProgram: TOKEN_PROGRAM_ID,
+    solOracleSecondary: null,
+    usdcOracleSecondary: null,
+    referral: null,
+  });
+
+  const openPerp = async (side: "long" | "short", maxLossUsd: anchor.BN | null = null) => {
+    const userData = await program.account.user.fetch(userPDA);
+    const positionIndex = userData.perpPositionIndex.addn(1);
+    const [positionPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("position"),
+        userWallet.publicKey.toBuffer(),
+        positionIndex.toArrayLike(Buffer, "le", 8),
+        poolPDA.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .openPerpPosition({
+        sizeAmount: new anchor.BN(100_000_000), // 0.1 SOL
+        collateralAmount: new anchor.BN(10_000_000), // 10 USDC
+        side: side === "long" ? { long: {} } : { short: {} },
+        orderType: { market: {} },
+        triggerPrice: null,
+        triggerAboveThreshold: false,
+        stopPrice: null,
+        maxSlippage: new anchor.BN(500),
+        poolName,
+        paySol: false,
+        payLp: false,
+        referrer: null,
+        expiryTime: null,
+        maxLossUsd,
+      })
+      .accountsPartial({
+        ...sharedAccounts(),
+        owner: userWallet.publicKey,
+        fundingAccount: userUSDCAccount,
+        user: userPDA,
+        position: positionPDA,
+        limitOrderBook: null,
+        lpTokenMint: null,
+        lpCollateralAccount: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    return { positionIndex, positionPDA };
+  };
+
+  // Retry a keeper call until the oracle has crossed its trigger
+  const untilTriggered = async (attempts: (() => Promise<string>)[]) => {
+    const deadline = Date.now() + TRIGGER_WAIT_MS;
+    while (Date.now() < deadline) {
+      for (const attempt of attempts) {
+        try {
+          return await attempt();
+        } catch (error) {
+          // not triggered yet
+        }
+      }
+      await sleep(2_000);
+    }
+    return null;
+  };
+
+  before(async () => {
+    userWallet = provider.wallet.payer;
+    keeper = Keypair.generate();
+
+    [contractPDA] = PublicKey.findProgramAddressSync([Buffer.from("contract")], program.programId);
+    [poolPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), Buffer.from(poolName)],
+      program.programId
+    );
+    [transferAuthorityPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("transfer_authority")],
+      program.programId
+    );
+    [userPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("user_v3"), userWallet.publicKey.toBuffer()],
+      program.programId
+    );
+    [solCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), WSOLMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    [solCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), WSOLMint.toBuffer()],
+      program.programId
+    );
+    [usdcCustodyTokenAccountPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("custody_token_account"), poolPDA.toBuffer(), USDCMint.toBuffer()],
+      program.programId
+    );
+    userUSDCAccount = getAssociatedTokenAddressSync(USDCMint, userWallet.publicKey);
+
+    solOracle = (await program.account.custody.fetch(solCustodyPDA)).oracle;
+    usdcOracle = (await program.account.custody.fetch(usdcCustodyPDA)).oracle;
+
+    // the keeper pays its own fees so it never touches the owner's lamports
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: userWallet.publicKey,
+          toPubkey: keeper.publicKey,
+          lamports: 0.05 * LAMPORTS_PER_SOL,
+        })
+      ),
+      [userWallet]
+    );
+  });
+
+  it("Should return the position rent to the owner on close", async () => {
+    const { positionIndex, positionPDA } = await openPerp("long");
+    const positionRent = await lamportsOf(positionPDA);
+    const before = await lamportsOf(userWallet.publicKey);
+
+    const signature = await program.methods
+      .closePerpPosition({
+        positionIndex,
+        poolName,
+        contractType: 0, // perp
+        closePercentage: FULL_CLOSE,
+        receiveSol: false,
+        receiveAsLp: false,
+      })
+      .accountsPartial({
+        ...sharedAccounts(),
+        owner: userWallet.publicKey,
+        user: userPDA,
+        receivingAccount: userUSDCAccount,
+        position: positionPDA,
+        tpSlOrderbook: null,
+        lpTokenMint: null,
+        lpCollateralAccount: null,
+        lpReceivingAccount: null,
+      })
+      .signers([userWallet])
+      .rpc({ commitment: "confirmed" });
+
+    const after = await lamportsOf(userWallet.publicKey);
+    expect(await provider.connection.getAccountInfo(positionPDA)).to.be.null;
+    // the owner signed this one, so it also paid the fee
+    expect(after - before).to.equal(positionRent - (await txFee(signature)));
+  });
+
+  it("Should return the position and orderbook rent to the owner on a keeper TP/SL fill", async function () {
+    this.timeout(TRIGGER_WAIT_MS + 60_000);
+
+    const { positionIndex, positionPDA } = await openPerp("short");
+    const position = await program.account.position.fetch(positionPDA);
+    const [orderbookPDA] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("tp_sl_orderbook"),
+        userWallet.publicKey.toBuffer(),
+        positionIndex.toArrayLike(Buffer, "le", 8),
+        Buffer.from(poolName),
+        Buffer.from([0]), // perp
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .initTpSlOrderbook({ orderType: 0, positionIndex, poolName })
+      .accountsPartial({
+        owner: userWallet.publicKey,
+        tpSlOrderbook: orderbookPDA,
+        pool: poolPDA,
+        position: positionPDA,
+        optionDetail: null,
+        future: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([userWallet])
+      .rpc();
+
+    // one tick either side of entry, so any move fills one of them in full
+    const manage = (action: any) =>
+      program.methods
+        .manageTpSlOrders({ contractType: 0, positionIndex, poolName, action })
+        .accountsPartial({
+          owner: userWallet.publicKey,
+          tpSlOrderbook: orderbookPDA,
+          contract: contractPDA,
+          pool: poolPDA,
+          position: positionPDA,
+          optionDetail: null,
+          future: null,
+          solCustody: solCustodyPDA,
+          usdcCustody: usdcCustodyPDA,
+        })
+        .signers([userWallet])
+        .rpc();
+    await manage({
+      addTakeProfit: { price: position.entryPrice.subn(1), sizePercent: FULL_CLOSE, receiveSol: false },
+    });
+    await manage({
+      addStopLoss: { price: position.entryPrice.addn(1), sizePercent: FULL_CLOSE, receiveSol: false },
+    });
+
+    const reclaimed = (await lamportsOf(positionPDA)) + (await lamportsOf(orderbookPDA));
+    const before = await lamportsOf(userWallet.publicKey);
+
+    const execute = (triggerOrderType: number) => () =>
+      program.methods
+        .executeTpSlOrder({ positionIndex, poolName, contractType: 0, triggerOrderType, orderIndex: 0 })
+        .accountsPartial({
+          ...sharedAccounts(),
+          executor: keeper.publicKey,
+          owner: userWallet.publicKey,
+          receivingAccount: userUSDCAccount,
+          position: positionPDA,
+          tpSlOrderbook: orderbookPDA,
+        })
+        .signers([keeper])
+        .rpc({ commitment: "confirmed" });
+
+    const signature = await untilTriggered([execute(0), execute(1)]);
+    if (signature === null) {
+      console.log("Oracle did not move past either trigger, skipping");
+      this.skip();
+    }
+
+    const after = await lamportsOf(userWallet.publicKey);
+    expect(await provider.connection.getAccountInfo(positionPDA)).to.be.null;
+    expect(await provider.connection.getAccountInfo(orderbookPDA)).to.be.null;
+    expect(after - before).to.equal(reclaimed);
+  });
+
+  it("Should return the position rent to the owner on liquidation", async function () {
+    this.timeout(TRIGGER_WAIT_MS + 60_000);
+
+    // a max-loss floor one cent away on each side: the first move lets liquidate close one
+    const floored = [
+      await openPerp("long", new anchor.BN(10_000)),
+      await openPerp("short", new anchor.BN(10_000)),
+    ];
+    const rents = await Promise.all(floored.map(({ positionPDA }) => lamportsOf(positionPDA)));
+    const before = await lamportsOf(userWallet.publicKey);
+
+    let liquidated: number = -1;
+    const liquidate = (i: number) => async () => {
+      const { positionIndex, positionPDA } = floored[i];
+      const signature = await program.methods
+        .liquidate({
+          positionIndex,
+          poolName,
+          contractType: 0,
+          liquidatorRewardAccount: userUSDCAccount,
+        })
+        .accountsPartial({
+          ...sharedAccounts(),
+          liquidator: keeper.publicKey,
+          owner: userWallet.publicKey,
+          ownerSettlementAccount: userUSDCAccount,
+          liquidatorRewardAccount: userUSDCAccount,
+          position: positionPDA,
+          tpSlOrderbook: null,
+          lpTokenMint: null,
+          lpCollateralAccount: null,
+        })
+        .signers([keeper])
+        .rpc({ commitment: "confirmed" });
+      liquidated = i;
+      return signature;
+    };
+
+    const signature = await untilTriggered([liquidate(0), liquidate(1)]);
+    if (signature === null) {
+      console.log("Oracle did not reach either max-loss floor, skipping");
+      this.skip();
+    }
+
+    const after = await lamportsOf(userWallet.publicKey);
+    expect(await provider.connection.getAccountInfo(floored[liquidated].positionPDA)).to.be.null;
+    expect(after - before).to.equal(rents[liquidated]);
+  });
+});